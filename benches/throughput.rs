@@ -0,0 +1,62 @@
+//! Throughput of each stage of the tree-walking pipeline on the programs in
+//! `benches/programs`
+//!
+//! Run with `cargo bench --bench throughput`; `rinlox bench` gives a
+//! quicker, rougher look at the same numbers without criterion.
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rinlox::{Interpreter, Parser, Resolver, Scanner};
+
+const PROGRAMS: [(&str, &str); 3] = [
+    ("fib", include_str!("programs/fib.lox")),
+    ("strings", include_str!("programs/strings.lox")),
+    ("nesting", include_str!("programs/nesting.lox")),
+];
+
+fn scan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scan");
+    for (name, source) in PROGRAMS {
+        let tokens = Scanner::new(source.to_string()).count();
+        group.throughput(Throughput::Elements(tokens as u64));
+        group.bench_function(name, |b| {
+            b.iter(|| Scanner::new(black_box(source).to_string()).count())
+        });
+    }
+    group.finish();
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, source) in PROGRAMS {
+        // Scanned up front, so only the parser is measured
+        let tokens: Vec<_> = Scanner::new(source.to_string()).collect();
+        group.bench_function(name, |b| {
+            b.iter(|| Parser::new(black_box(&tokens).iter().cloned()).parse())
+        });
+    }
+    group.finish();
+}
+
+fn run(c: &mut Criterion) {
+    let mut group = c.benchmark_group("run");
+    for (name, source) in PROGRAMS {
+        let statements = Parser::new(Scanner::new(source.to_string()))
+            .parse()
+            .expect("the benchmarks should parse");
+        Resolver::default()
+            .resolve(&statements)
+            .expect("the benchmarks should resolve");
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut interpreter = Interpreter::default();
+                interpreter.set_output(std::io::sink());
+                interpreter.interpret(black_box(&statements))
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, scan, parse, run);
+criterion_main!(benches);
@@ -0,0 +1,23 @@
+//! Example of a rinlox plugin, defining the `twice` native
+//!
+//! Build it and load it into a script with:
+//!
+//! ```text
+//! cargo build --features plugins --example plugin
+//! cargo run --features plugins -- --plugin target/debug/examples/libplugin.so script.lox
+//! ```
+use rinlox::plugin::Registry;
+use rinlox::{RuntimeError, Value};
+
+fn register(registry: &mut Registry) {
+    registry.define_native("twice", 1, |_, paren, arguments| match &arguments[0] {
+        Value::Number(n) => Ok(Value::Number(n * 2.0)),
+        Value::String(s) => Ok(Value::String(s.repeat(2).into())),
+        _ => Err(RuntimeError::new(
+            paren,
+            "Can only double numbers and strings.",
+        )),
+    });
+}
+
+rinlox::declare_plugin!(register);
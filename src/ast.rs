@@ -0,0 +1,607 @@
+use crate::expr::{Expr, Object};
+/// Conversion of the AST back into Lox source code
+///
+/// The output is guaranteed to be valid Lox that parses back into the same
+/// tree, even for trees that were not produced by the parser (e.g. the
+/// desugared `for` loops, which are printed as the equivalent `while`).
+/// Parentheses are only added where the tree needs them to keep its shape.
+///
+/// The only exception are number literals with no syntax of their own
+/// (negative, infinite or NaN), which are written as the equivalent
+/// arithmetic expression.
+use crate::internal;
+use crate::lexer::{Token, TokenType};
+use crate::parser::{infix_precedence, Precedence};
+use crate::stmt::{ClassBody, Stmt};
+
+const INDENT: &str = "    ";
+
+/// Render a statement (and everything inside of it) as Lox source
+pub fn to_source(stmt: &Stmt) -> String {
+    let mut out = String::new();
+    write_stmt(&mut out, stmt, 0);
+    out
+}
+
+/// Render a whole program as Lox source, one statement after the other
+pub fn program_to_source(statements: &[Stmt]) -> String {
+    statements.iter().map(to_source).collect()
+}
+
+/// Render an expression as Lox source
+pub fn expr_to_source(expr: &Expr) -> String {
+    let mut out = String::new();
+    write_expr(&mut out, expr, Precedence::Comma);
+    out
+}
+
+fn precedence(expr: &Expr) -> Precedence {
+    match expr {
+        Expr::Assign { .. } | Expr::Set { .. } | Expr::SetIndex { .. } => Precedence::Assignment,
+        Expr::Conditional { .. } => Precedence::Conditional,
+        Expr::Binary { operator, .. } | Expr::Logical { operator, .. } => {
+            infix_precedence(&operator.typ).expect("operators should be in the parse table")
+        }
+        Expr::Unary { .. } => Precedence::Unary,
+        // These numbers can only be written as arithmetic (see `write_literal`)
+        Expr::Literal {
+            value: Object::Number(n),
+        } if !n.is_finite() => Precedence::Factor,
+        Expr::Literal {
+            value: Object::Number(n),
+        } if n.is_sign_negative() => Precedence::Unary,
+        Expr::Call { .. } | Expr::Get { .. } | Expr::Index { .. } => Precedence::Call,
+        Expr::Grouping { .. }
+        | Expr::Lambda { .. }
+        | Expr::List { .. }
+        | Expr::Literal { .. }
+        | Expr::Map { .. }
+        | Expr::Super { .. }
+        | Expr::This { .. }
+        | Expr::Variable { .. } => Precedence::Primary,
+    }
+}
+
+/// Write the `=` (or compound `+=`...) of an assignment and its value
+fn write_assignment(out: &mut String, operator: Option<&Token>, value: &Expr) {
+    out.push(' ');
+    if let Some(operator) = operator {
+        out.push_str(&operator.lexeme);
+    }
+    out.push_str("= ");
+    write_expr(out, value, Precedence::Assignment);
+}
+
+/// Write the expression, wrapping it in parentheses if it binds looser than
+/// the context it appears in (`min`) requires
+fn write_expr(out: &mut String, expr: &Expr, min: Precedence) {
+    // Nested code can take more stack than the thread has
+    internal::grow_stack(|| {
+        let needs_parens = precedence(expr) < min;
+        if needs_parens {
+            out.push('(');
+        }
+
+        match expr {
+            Expr::Assign { name, value, .. } => {
+                out.push_str(&name.lexeme);
+                out.push_str(" = ");
+                write_expr(out, value, Precedence::Assignment);
+            }
+            Expr::Set {
+                object,
+                name,
+                operator,
+                value,
+            } => {
+                write_expr(out, object, Precedence::Call);
+                out.push('.');
+                out.push_str(&name.lexeme);
+                write_assignment(out, operator.as_ref(), value);
+            }
+            Expr::SetIndex {
+                object,
+                index,
+                operator,
+                value,
+                ..
+            } => {
+                write_expr(out, object, Precedence::Call);
+                out.push('[');
+                write_expr(out, index, Precedence::Comma);
+                out.push(']');
+                write_assignment(out, operator.as_ref(), value);
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } if operator.typ == TokenType::Comma => {
+                write_expr(out, left, Precedence::Comma);
+                out.push_str(", ");
+                write_expr(out, right, Precedence::Assignment);
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            }
+            | Expr::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                let precedence = precedence(expr);
+                write_expr(out, left, precedence);
+                out.push(' ');
+                out.push_str(&operator.lexeme);
+                out.push(' ');
+                write_expr(out, right, precedence.next());
+            }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                write_expr(out, condition, Precedence::Or);
+                out.push_str(" ? ");
+                write_expr(out, then_branch, Precedence::Comma);
+                out.push_str(" : ");
+                write_expr(out, else_branch, Precedence::Conditional);
+            }
+            Expr::Unary { operator, right } => {
+                out.push_str(&operator.lexeme);
+                write_expr(out, right, Precedence::Unary);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                write_expr(out, callee, Precedence::Call);
+                out.push('(');
+                write_elements(out, arguments);
+                out.push(')');
+            }
+            Expr::Index { object, index, .. } => {
+                write_expr(out, object, Precedence::Call);
+                out.push('[');
+                write_expr(out, index, Precedence::Comma);
+                out.push(']');
+            }
+            Expr::List { elements, .. } => {
+                out.push('[');
+                write_elements(out, elements);
+                out.push(']');
+            }
+            Expr::Map { entries, .. } => {
+                out.push('{');
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    write_expr(out, key, Precedence::Assignment);
+                    out.push_str(": ");
+                    write_expr(out, value, Precedence::Assignment);
+                }
+                out.push('}');
+            }
+            Expr::Get { object, name } => {
+                write_expr(out, object, Precedence::Call);
+                out.push('.');
+                out.push_str(&name.lexeme);
+            }
+            Expr::Grouping { expression } => {
+                out.push('(');
+                write_expr(out, expression, Precedence::Comma);
+                out.push(')');
+            }
+            Expr::Lambda { params, body, .. } => {
+                out.push_str("fun ");
+                write_function(out, "", params, body, current_depth(out));
+            }
+            Expr::Literal { value } => write_literal(out, value),
+            Expr::Super { method, .. } => {
+                out.push_str("super.");
+                out.push_str(&method.lexeme);
+            }
+            Expr::This { .. } => out.push_str("this"),
+            Expr::Variable { name, .. } => out.push_str(&name.lexeme),
+        }
+
+        if needs_parens {
+            out.push(')');
+        }
+    })
+}
+
+/// Write the arguments of a call or the elements of a list, separated by
+/// commas
+fn write_elements(out: &mut String, elements: &[Expr]) {
+    for (i, element) in elements.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_expr(out, element, Precedence::Assignment);
+    }
+}
+
+fn write_literal(out: &mut String, value: &Object) {
+    match value {
+        Object::Nil => out.push_str("nil"),
+        Object::Boolean(b) => out.push_str(&b.to_string()),
+        // Lox has no literals for these, so write an expression producing them
+        Object::Number(n) if n.is_nan() => out.push_str("0 / 0"),
+        Object::Number(n) if n.is_infinite() => {
+            out.push_str(if *n > 0.0 { "1 / 0" } else { "-1 / 0" })
+        }
+        Object::Number(n) => out.push_str(&n.to_string()),
+        Object::String(s) => out.push_str(&quote_string(s)),
+    }
+}
+
+/// Quote the string as a Lox string literal, escaping the characters that
+/// can't appear in one as is
+pub fn quote_string(s: &str) -> String {
+    let mut out = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn write_indent(out: &mut String, depth: usize) {
+    for _ in 0..depth {
+        out.push_str(INDENT);
+    }
+}
+
+/// Write a statement on its own line(s), indented at `depth`
+fn write_stmt(out: &mut String, stmt: &Stmt, depth: usize) {
+    write_indent(out, depth);
+    write_stmt_inline(out, stmt, depth);
+    out.push('\n');
+}
+
+/// Write a statement starting at the current position of the output, with
+/// any nested lines indented relative to `depth`
+fn write_stmt_inline(out: &mut String, stmt: &Stmt, depth: usize) {
+    match stmt {
+        Stmt::Block { statements } => write_block(out, statements, depth),
+        Stmt::Break { .. } => out.push_str("break;"),
+        Stmt::Continue { .. } => out.push_str("continue;"),
+        Stmt::Class {
+            name,
+            superclass,
+            body,
+        } => {
+            out.push_str("class ");
+            out.push_str(&name.lexeme);
+            if let Some(superclass) = superclass {
+                out.push_str(" < ");
+                write_expr(out, superclass, Precedence::Primary);
+            }
+            out.push(' ');
+            write_class_body(out, body, depth);
+        }
+        Stmt::Defer { body, .. } => {
+            out.push_str("defer ");
+            write_block(out, body, depth);
+        }
+        Stmt::Enum { name, variants } => {
+            out.push_str("enum ");
+            out.push_str(&name.lexeme);
+            out.push_str(" {");
+            for (i, variant) in variants.iter().enumerate() {
+                out.push_str(if i > 0 { ", " } else { " " });
+                out.push_str(&variant.lexeme);
+            }
+            out.push_str(" }");
+        }
+        Stmt::Extend { class, body } => {
+            out.push_str("extend ");
+            write_expr(out, class, Precedence::Primary);
+            out.push(' ');
+            write_class_body(out, body, depth);
+        }
+        Stmt::Expression { expression } => {
+            let start = out.len();
+            write_expr(out, expression, Precedence::Comma);
+            // A statement starting with `{` is a block, so a map there needs
+            // parentheses
+            if out[start..].starts_with('{') {
+                out.insert(start, '(');
+                out.push(')');
+            }
+            out.push(';');
+        }
+        Stmt::Function { name, params, body } => {
+            out.push_str("fun ");
+            write_function(out, &name.lexeme, params, body, depth);
+        }
+        Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            out.push_str("if (");
+            write_expr(out, condition, Precedence::Comma);
+            out.push_str(") ");
+            match else_branch {
+                // An `else` would attach to the innermost `if` when parsing,
+                // so protect the `then` branch with a block
+                Some(_) if has_dangling_if(then_branch) => {
+                    write_block(out, std::slice::from_ref(then_branch.as_ref()), depth)
+                }
+                _ => write_stmt_inline(out, then_branch, depth),
+            }
+            if let Some(else_branch) = else_branch {
+                out.push_str(" else ");
+                write_stmt_inline(out, else_branch, depth);
+            }
+        }
+        Stmt::Import { path, .. } => {
+            out.push_str("import ");
+            out.push_str(&path.lexeme);
+            out.push(';');
+        }
+        Stmt::Print { expression, .. } => {
+            out.push_str("print ");
+            write_expr(out, expression, Precedence::Comma);
+            out.push(';');
+        }
+        Stmt::Return { value, .. } => {
+            out.push_str("return");
+            if let Some(value) = value {
+                out.push(' ');
+                write_expr(out, value, Precedence::Comma);
+            }
+            out.push(';');
+        }
+        Stmt::Var { name, initializer } => {
+            out.push_str("var ");
+            out.push_str(&name.lexeme);
+            if let Some(initializer) = initializer {
+                out.push_str(" = ");
+                write_expr(out, initializer, Precedence::Comma);
+            }
+            out.push(';');
+        }
+        // A `for` loop keeps its increment, so it is printed as one (with
+        // its initializer before it, in the block around it)
+        Stmt::While {
+            condition,
+            body,
+            increment: Some(increment),
+            ..
+        } => {
+            out.push_str("for (; ");
+            write_expr(out, condition, Precedence::Comma);
+            out.push_str("; ");
+            write_expr(out, increment, Precedence::Comma);
+            out.push_str(") ");
+            write_stmt_inline(out, body, depth);
+        }
+        Stmt::While {
+            condition, body, ..
+        } => {
+            out.push_str("while (");
+            write_expr(out, condition, Precedence::Comma);
+            out.push_str(") ");
+            write_stmt_inline(out, body, depth);
+        }
+    }
+}
+
+/// Indentation level of the line being written, for the expressions with
+/// blocks inside (lambdas) to line them up with it
+fn current_depth(out: &str) -> usize {
+    let line = &out[out.rfind('\n').map_or(0, |i| i + 1)..];
+    (line.len() - line.trim_start_matches(' ').len()) / INDENT.len()
+}
+
+/// Write a function (or method) from its name onwards
+fn write_function(out: &mut String, name: &str, params: &[Token], body: &[Stmt], depth: usize) {
+    out.push_str(name);
+    out.push('(');
+    for (i, param) in params.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        out.push_str(&param.lexeme);
+    }
+    out.push_str(") ");
+    write_block(out, body, depth);
+}
+
+/// Write the body of a class, from the opening to the closing brace
+fn write_class_body(out: &mut String, class_body: &ClassBody, depth: usize) {
+    out.push_str("{\n");
+    for method in &class_body.methods {
+        write_method(out, "", method, depth + 1);
+    }
+    for getter in &class_body.getters {
+        let Stmt::Function { name, body, .. } = getter else {
+            unreachable!("getters should be functions");
+        };
+        write_indent(out, depth + 1);
+        out.push_str(&name.lexeme);
+        out.push(' ');
+        write_block(out, body, depth + 1);
+        out.push('\n');
+    }
+    for method in &class_body.class_methods {
+        write_method(out, "class ", method, depth + 1);
+    }
+    write_indent(out, depth);
+    out.push('}');
+}
+
+/// Write a method on a line of its own, after `prefix`
+fn write_method(out: &mut String, prefix: &str, method: &Stmt, depth: usize) {
+    let Stmt::Function { name, params, body } = method else {
+        unreachable!("class methods should be functions");
+    };
+    write_indent(out, depth);
+    out.push_str(prefix);
+    write_function(out, &name.lexeme, params, body, depth);
+    out.push('\n');
+}
+
+fn write_block(out: &mut String, statements: &[Stmt], depth: usize) {
+    out.push_str("{\n");
+    for stmt in statements {
+        write_stmt(out, stmt, depth + 1);
+    }
+    write_indent(out, depth);
+    out.push('}');
+}
+
+/// Whether the statement ends in an `if` without an `else`, which would
+/// capture an `else` written right after it
+fn has_dangling_if(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::If {
+            else_branch: None, ..
+        } => true,
+        Stmt::If {
+            else_branch: Some(else_branch),
+            ..
+        } => has_dangling_if(else_branch),
+        Stmt::While { body, .. } => has_dangling_if(body),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::Features;
+    use crate::lexer::{Scanner, Span};
+    use crate::parser::Parser;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        let tokens = Scanner::new(source.to_string())
+            .with_features(Features::all())
+            .scan_all()
+            .expect("source should scan");
+        Parser::new(tokens.into_iter().map(Ok))
+            .parse()
+            .expect("source should parse")
+    }
+
+    /// parse → print → parse must give back the same tree
+    fn assert_round_trips(source: &str) {
+        let ast = parse(source);
+        let printed = program_to_source(&ast);
+        assert_eq!(parse(&printed), ast, "printed source:\n{}", printed);
+    }
+
+    fn token(typ: TokenType, lexeme: &str) -> Token {
+        Token::new(typ, lexeme, Span::default())
+    }
+
+    fn number(n: f64) -> Expr {
+        Expr::literal(Object::Number(n))
+    }
+
+    fn binary(left: Expr, typ: TokenType, lexeme: &str, right: Expr) -> Expr {
+        Expr::binary(left, token(typ, lexeme), right)
+    }
+
+    #[test]
+    fn examples_round_trip() {
+        assert_round_trips(include_str!("../examples/arithmetic.lox"));
+        assert_round_trips(include_str!("../examples/classes.lox"));
+        assert_round_trips(include_str!("../examples/conditionals.lox"));
+        assert_round_trips(include_str!("../examples/functions.lox"));
+        assert_round_trips(include_str!("../examples/hello.lox"));
+        assert_round_trips(include_str!("../examples/loops.lox"));
+        assert_round_trips(include_str!("../examples/scopes.lox"));
+        assert_round_trips(include_str!("../examples/variables.lox"));
+    }
+
+    #[test]
+    fn expressions_round_trip() {
+        assert_round_trips("a = b = 1 + 2 * -(3 - 4) / 5 >= 6 == !true;");
+        assert_round_trips("x or y and z or !w;");
+        assert_round_trips("a.b(c, d)(e).f = g.h;");
+        assert_round_trips("print ((1));");
+        assert_round_trips("a += b, c = d ? e, f : g ? h : i, j % 2;");
+        assert_round_trips("print (a ? b : c) ? f(d, (e, 1)) : (x.y *= 2);");
+        assert_round_trips("a[b, c][d] = [[], [e, (f, g)], h[0].i(j)[1]];");
+        assert_round_trips(r#"print {"a": {}, b ? c : d: [e], (f, g): h}[i];"#);
+        assert_round_trips("({a: 1})[a] = 2;");
+        assert_round_trips(
+            r#"print "tab\t \"quoted\" \\ \u{1b}[0m" + "multi
+line";"#,
+        );
+    }
+
+    #[test]
+    fn statements_round_trip() {
+        assert_round_trips("if (a) if (b) print 1; else print 2;");
+        assert_round_trips("if (a) { if (b) print 1; } else print 2;");
+        assert_round_trips("while (a) if (b) print 1; else { print 2; }");
+        assert_round_trips("for (;;) return;");
+        assert_round_trips("for (var i = 0; i < 3; i = i + 1) { if (i) continue; break; }");
+        assert_round_trips("class A < B { init() { super.init(); this.x = nil; } }");
+        assert_round_trips("class A { b { return 1; } class c(d) { return this; } e() {} }");
+        assert_round_trips("fun f() { defer { print 1; defer { print 2; } } return; }");
+        assert_round_trips("var f = fun (a, b) { return a + b; }; fun () {}(); print fun () {};");
+        assert_round_trips("import \"lib/a.lox\"; import b;");
+    }
+
+    #[test]
+    fn synthesized_trees_keep_their_shape() {
+        // (1 + 2) * 3 with no grouping node
+        let expr = binary(
+            binary(number(1.0), TokenType::Plus, "+", number(2.0)),
+            TokenType::Star,
+            "*",
+            number(3.0),
+        );
+        assert_eq!(expr_to_source(&expr), "(1 + 2) * 3");
+
+        // 1 - (2 - 3) with no grouping node
+        let expr = binary(
+            number(1.0),
+            TokenType::Minus,
+            "-",
+            binary(number(2.0), TokenType::Minus, "-", number(3.0)),
+        );
+        assert_eq!(expr_to_source(&expr), "1 - (2 - 3)");
+
+        // An `if` with an `else` whose `then` branch is an `if` without one
+        let stmt = Stmt::if_else(
+            Expr::variable(token(TokenType::Identifier, "a")),
+            Stmt::if_else(
+                Expr::variable(token(TokenType::Identifier, "b")),
+                Stmt::print(token(TokenType::Print, "print"), number(1.0)),
+                None,
+            ),
+            Some(Box::new(Stmt::print(
+                token(TokenType::Print, "print"),
+                number(2.0),
+            ))),
+        );
+        let printed = to_source(&stmt);
+        let Stmt::If { else_branch, .. } = &parse(&printed)[0] else {
+            panic!("expected an if statement:\n{}", printed);
+        };
+        assert!(else_branch.is_some(), "else moved:\n{}", printed);
+
+        // A map starting a statement with no grouping node
+        let stmt = Stmt::expression(Expr::get(
+            Expr::map(token(TokenType::LeftBrace, "{"), vec![]),
+            token(TokenType::Identifier, "a"),
+        ));
+        assert_eq!(to_source(&stmt), "({}.a);\n");
+    }
+}
@@ -0,0 +1,337 @@
+/// Debug view of the AST as the parenthesized, Lisp-like form from the book
+///
+/// Every node is written as `(name children...)`, so the shape the parser
+/// produced (precedence, associativity, desugaring) is explicit. Unlike
+/// `ast::to_source`, the output is not meant to be valid Lox.
+use std::rc::Rc;
+
+use crate::ast::quote_string;
+use crate::expr::{Depth, Expr, ExprVisitor, Object, Slot};
+use crate::lexer::Token;
+use crate::stmt::{ClassBody, Stmt, StmtVisitor};
+
+#[derive(Debug, Default)]
+pub struct AstPrinter;
+
+impl AstPrinter {
+    /// Render a whole program, one top-level statement per line
+    pub fn print_program(&mut self, statements: &[Stmt]) -> String {
+        statements
+            .iter()
+            .map(|stmt| format!("{}\n", stmt.accept(self)))
+            .collect()
+    }
+
+    fn parenthesize(&mut self, name: &str, exprs: &[&Expr]) -> String {
+        let parts: Vec<String> = exprs.iter().map(|expr| expr.accept(self)).collect();
+        wrap(name, &parts)
+    }
+
+    /// The members of a class body: its methods as `(fun ...)`, and then
+    /// its getters as `(get ...)` and its class methods as `(class ...)`
+    fn class_body(&mut self, body: &ClassBody) -> Vec<String> {
+        let mut parts: Vec<String> = body
+            .methods
+            .iter()
+            .map(|method| method.accept(self))
+            .collect();
+        for getter in &body.getters {
+            let Stmt::Function { name, body, .. } = getter else {
+                unreachable!("getters should be functions");
+            };
+            let mut getter = vec![name.lexeme.to_string()];
+            getter.extend(body.iter().map(|stmt| stmt.accept(self)));
+            parts.push(wrap("get", &getter));
+        }
+        for method in &body.class_methods {
+            parts.push(wrap("class", &[method.accept(self)]));
+        }
+        parts
+    }
+}
+
+/// Write `(name part part...)`
+fn wrap(name: &str, parts: &[String]) -> String {
+    let mut out = format!("({}", name);
+    for part in parts {
+        out.push(' ');
+        out.push_str(part);
+    }
+    out.push(')');
+    out
+}
+
+/// The operator of an assignment: `=`, or e.g. `+=` for a compound one
+fn assignment(operator: &Option<Token>) -> String {
+    match operator {
+        Some(operator) => format!("{}=", operator.lexeme),
+        None => "=".to_string(),
+    }
+}
+
+impl ExprVisitor<String> for AstPrinter {
+    fn visit_assign_expr(
+        &mut self,
+        name: &Token,
+        value: &Box<Expr>,
+        _depth: &Depth,
+        _slot: &Slot,
+    ) -> String {
+        wrap("=", &[name.lexeme.to_string(), value.accept(self)])
+    }
+
+    fn visit_binary_expr(
+        &mut self,
+        left: &Box<Expr>,
+        operator: &Token,
+        right: &Box<Expr>,
+    ) -> String {
+        self.parenthesize(&operator.lexeme, &[left, right])
+    }
+
+    fn visit_call_expr(
+        &mut self,
+        callee: &Box<Expr>,
+        _paren: &Token,
+        arguments: &Vec<Expr>,
+    ) -> String {
+        let mut exprs = vec![callee.as_ref()];
+        exprs.extend(arguments);
+        self.parenthesize("call", &exprs)
+    }
+
+    fn visit_conditional_expr(
+        &mut self,
+        condition: &Box<Expr>,
+        then_branch: &Box<Expr>,
+        else_branch: &Box<Expr>,
+    ) -> String {
+        self.parenthesize("?:", &[condition, then_branch, else_branch])
+    }
+
+    fn visit_get_expr(&mut self, object: &Box<Expr>, name: &Token) -> String {
+        wrap(".", &[object.accept(self), name.lexeme.to_string()])
+    }
+
+    fn visit_grouping_expr(&mut self, expression: &Box<Expr>) -> String {
+        self.parenthesize("group", &[expression])
+    }
+
+    fn visit_index_expr(
+        &mut self,
+        object: &Box<Expr>,
+        _bracket: &Token,
+        index: &Box<Expr>,
+    ) -> String {
+        self.parenthesize("[]", &[object, index])
+    }
+
+    fn visit_lambda_expr(
+        &mut self,
+        _keyword: &Token,
+        params: &Vec<Token>,
+        body: &Rc<Vec<Stmt>>,
+    ) -> String {
+        let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+        let mut parts = vec![format!("({})", params.join(" "))];
+        parts.extend(body.iter().map(|stmt| stmt.accept(self)));
+        wrap("lambda", &parts)
+    }
+
+    fn visit_list_expr(&mut self, _bracket: &Token, elements: &Vec<Expr>) -> String {
+        let elements: Vec<&Expr> = elements.iter().collect();
+        self.parenthesize("list", &elements)
+    }
+
+    fn visit_map_expr(&mut self, _brace: &Token, entries: &Vec<(Expr, Expr)>) -> String {
+        let entries: Vec<String> = entries
+            .iter()
+            .map(|(key, value)| format!(" ({} {})", key.accept(self), value.accept(self)))
+            .collect();
+        format!("(map{})", entries.concat())
+    }
+
+    fn visit_literal_expr(&mut self, value: &Object) -> String {
+        match value {
+            Object::Nil => "nil".to_string(),
+            Object::Boolean(b) => b.to_string(),
+            Object::Number(n) => n.to_string(),
+            Object::String(s) => quote_string(s),
+        }
+    }
+
+    fn visit_logical_expr(
+        &mut self,
+        left: &Box<Expr>,
+        operator: &Token,
+        right: &Box<Expr>,
+    ) -> String {
+        self.parenthesize(&operator.lexeme, &[left, right])
+    }
+
+    fn visit_set_expr(
+        &mut self,
+        object: &Box<Expr>,
+        name: &Token,
+        operator: &Option<Token>,
+        value: &Box<Expr>,
+    ) -> String {
+        wrap(
+            &assignment(operator),
+            &[
+                object.accept(self),
+                name.lexeme.to_string(),
+                value.accept(self),
+            ],
+        )
+    }
+
+    fn visit_set_index_expr(
+        &mut self,
+        object: &Box<Expr>,
+        _bracket: &Token,
+        index: &Box<Expr>,
+        operator: &Option<Token>,
+        value: &Box<Expr>,
+    ) -> String {
+        self.parenthesize(
+            &format!("[]{}", assignment(operator)),
+            &[object, index, value],
+        )
+    }
+
+    fn visit_super_expr(&mut self, _keyword: &Token, method: &Token, _depth: &Depth) -> String {
+        format!("(super {})", method.lexeme)
+    }
+
+    fn visit_this_expr(&mut self, _keyword: &Token, _depth: &Depth) -> String {
+        "this".to_string()
+    }
+
+    fn visit_unary_expr(&mut self, operator: &Token, right: &Box<Expr>) -> String {
+        self.parenthesize(&operator.lexeme, &[right])
+    }
+
+    fn visit_variable_expr(&mut self, name: &Token, _depth: &Depth, _slot: &Slot) -> String {
+        name.lexeme.to_string()
+    }
+}
+
+impl StmtVisitor<String> for AstPrinter {
+    fn visit_block_stmt(&mut self, statements: &Vec<Stmt>) -> String {
+        let parts: Vec<String> = statements.iter().map(|stmt| stmt.accept(self)).collect();
+        wrap("block", &parts)
+    }
+
+    fn visit_break_stmt(&mut self, _keyword: &Token) -> String {
+        "(break)".to_string()
+    }
+
+    fn visit_class_stmt(
+        &mut self,
+        name: &Token,
+        superclass: &Option<Expr>,
+        body: &ClassBody,
+    ) -> String {
+        let mut parts = vec![name.lexeme.to_string()];
+        if let Some(superclass) = superclass {
+            parts.push(format!("< {}", superclass.accept(self)));
+        }
+        parts.extend(self.class_body(body));
+        wrap("class", &parts)
+    }
+
+    fn visit_continue_stmt(&mut self, _keyword: &Token) -> String {
+        "(continue)".to_string()
+    }
+
+    fn visit_defer_stmt(&mut self, _keyword: &Token, body: &Rc<Vec<Stmt>>) -> String {
+        let parts: Vec<String> = body.iter().map(|stmt| stmt.accept(self)).collect();
+        wrap("defer", &parts)
+    }
+
+    fn visit_enum_stmt(&mut self, name: &Token, variants: &Vec<Token>) -> String {
+        let mut parts = vec![name.lexeme.to_string()];
+        parts.extend(variants.iter().map(|variant| variant.lexeme.to_string()));
+        wrap("enum", &parts)
+    }
+
+    fn visit_extend_stmt(&mut self, class: &Expr, body: &ClassBody) -> String {
+        let mut parts = vec![class.accept(self)];
+        parts.extend(self.class_body(body));
+        wrap("extend", &parts)
+    }
+
+    fn visit_expression_stmt(&mut self, expression: &Expr) -> String {
+        self.parenthesize(";", &[expression])
+    }
+
+    fn visit_function_stmt(
+        &mut self,
+        name: &Token,
+        params: &Vec<Token>,
+        body: &Rc<Vec<Stmt>>,
+    ) -> String {
+        let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+        let mut parts = vec![format!("{}({})", name.lexeme, params.join(" "))];
+        parts.extend(body.iter().map(|stmt| stmt.accept(self)));
+        wrap("fun", &parts)
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Box<Stmt>,
+        else_branch: &Option<Box<Stmt>>,
+    ) -> String {
+        let mut parts = vec![condition.accept(self), then_branch.accept(self)];
+        match else_branch {
+            Some(else_branch) => {
+                parts.push(else_branch.accept(self));
+                wrap("if-else", &parts)
+            }
+            None => wrap("if", &parts),
+        }
+    }
+
+    fn visit_import_stmt(&mut self, _keyword: &Token, path: &Token, _name: &Token) -> String {
+        wrap("import", &[path.lexeme.to_string()])
+    }
+
+    fn visit_print_stmt(&mut self, _keyword: &Token, expression: &Expr) -> String {
+        self.parenthesize("print", &[expression])
+    }
+
+    fn visit_return_stmt(&mut self, _keyword: &Token, value: &Option<Expr>) -> String {
+        match value {
+            Some(value) => self.parenthesize("return", &[value]),
+            None => "(return)".to_string(),
+        }
+    }
+
+    fn visit_var_stmt(&mut self, name: &Token, initializer: &Option<Expr>) -> String {
+        match initializer {
+            Some(initializer) => wrap(
+                "var",
+                &[
+                    name.lexeme.to_string(),
+                    "=".to_string(),
+                    initializer.accept(self),
+                ],
+            ),
+            None => format!("(var {})", name.lexeme),
+        }
+    }
+
+    fn visit_while_stmt(
+        &mut self,
+        _keyword: &Token,
+        condition: &Expr,
+        body: &Box<Stmt>,
+        increment: &Option<Expr>,
+    ) -> String {
+        let mut parts = vec![condition.accept(self), body.accept(self)];
+        parts.extend(increment.as_ref().map(|increment| increment.accept(self)));
+        wrap("while", &parts)
+    }
+}
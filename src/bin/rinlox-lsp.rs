@@ -0,0 +1,575 @@
+/// Language server for Lox, so editors can check `.lox` files as they are
+/// written
+///
+/// It talks the Language Server Protocol over the standard input and output,
+/// and gives:
+///
+/// - The errors of the scanner, the parser and the resolver, and the lints,
+///   every time a file changes.
+/// - The declaration of the variable under the cursor (go to definition).
+/// - The classes, functions, variables, enums and imports declared at the
+///   top level, with the members of the classes and enums (the outline).
+/// - What each identifier names, like a parameter or a method, for the
+///   editor to color them (semantic tokens, see `rinlox::semantic`).
+///
+/// Point the editor at the binary, built with `cargo build --features lsp`.
+use std::collections::HashMap;
+use std::error::Error;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as LspNotification, PublishDiagnostics,
+};
+use lsp_types::request::{
+    DocumentSymbolRequest, GotoDefinition, Request as LspRequest, SemanticTokensFullRequest,
+};
+use lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, DocumentSymbol, DocumentSymbolParams,
+    DocumentSymbolResponse, GotoDefinitionParams, GotoDefinitionResponse, Location, OneOf,
+    Position, PublishDiagnosticsParams, Range, SemanticToken, SemanticTokenModifier,
+    SemanticTokenType, SemanticTokens, SemanticTokensFullOptions, SemanticTokensLegend,
+    SemanticTokensOptions, SemanticTokensParams, SemanticTokensResult, ServerCapabilities,
+    SymbolKind, TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+};
+use rinlox::diagnostics::{Note, Severity};
+use rinlox::linter::Linter;
+use rinlox::semantic::{self, Kind};
+use rinlox::{Parser, Resolver, Scanner, Span, Stmt, Token};
+
+type LspResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Types of the semantic tokens, which they refer to by their index
+static TOKEN_TYPES: [SemanticTokenType; 6] = [
+    SemanticTokenType::PARAMETER,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::CLASS,
+    SemanticTokenType::METHOD,
+    SemanticTokenType::PROPERTY,
+];
+
+/// Modifiers of the semantic tokens, which they refer to by their bit.
+/// Globals are variables with the modifier of their own
+static TOKEN_MODIFIERS: [SemanticTokenModifier; 2] = [
+    SemanticTokenModifier::DECLARATION,
+    SemanticTokenModifier::new("global"),
+];
+
+/// The files open in the editor, by their URI
+#[derive(Debug, Default)]
+struct Server {
+    documents: HashMap<Uri, String>,
+}
+
+impl Server {
+    /// Answer the messages of the editor until it shuts the server down
+    fn serve(&mut self, connection: &Connection) -> LspResult<()> {
+        for message in &connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    if connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    connection.sender.send(self.respond(request).into())?;
+                }
+                Message::Notification(notification) => {
+                    if let Some(published) = self.update(notification)? {
+                        connection.sender.send(published.into())?;
+                    }
+                }
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn respond(&self, request: Request) -> Response {
+        let result = match request.method.as_str() {
+            GotoDefinition::METHOD => {
+                serde_json::from_value(request.params).map(|params: GotoDefinitionParams| {
+                    let position = params.text_document_position_params;
+                    let uri = position.text_document.uri;
+                    let definition = self.documents.get(&uri).and_then(|source| {
+                        let location = definition(&uri, source, position.position)?;
+                        Some(GotoDefinitionResponse::Scalar(location))
+                    });
+                    serde_json::to_value(definition)
+                })
+            }
+            DocumentSymbolRequest::METHOD => {
+                serde_json::from_value(request.params).map(|params: DocumentSymbolParams| {
+                    let symbols = self
+                        .documents
+                        .get(&params.text_document.uri)
+                        .map(|source| DocumentSymbolResponse::Nested(symbols(source)));
+                    serde_json::to_value(symbols)
+                })
+            }
+            SemanticTokensFullRequest::METHOD => {
+                serde_json::from_value(request.params).map(|params: SemanticTokensParams| {
+                    let tokens = self.documents.get(&params.text_document.uri).map(|source| {
+                        SemanticTokensResult::Tokens(SemanticTokens {
+                            result_id: None,
+                            data: semantic_tokens(source),
+                        })
+                    });
+                    serde_json::to_value(tokens)
+                })
+            }
+            method => {
+                return Response::new_err(
+                    request.id,
+                    ErrorCode::MethodNotFound as i32,
+                    format!("Unknown method '{}'.", method),
+                )
+            }
+        };
+        match result {
+            Ok(Ok(value)) => Response {
+                id: request.id,
+                result: Some(value),
+                error: None,
+            },
+            Ok(Err(err)) | Err(err) => {
+                Response::new_err(request.id, ErrorCode::InvalidParams as i32, err.to_string())
+            }
+        }
+    }
+
+    /// Keep track of the changes to the files, returning the diagnostics of
+    /// the file that changed to publish
+    fn update(&mut self, notification: Notification) -> LspResult<Option<Notification>> {
+        let (uri, diagnostics) = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let document = params.text_document;
+                let diagnostics = diagnostics(&document.uri, &document.text);
+                self.documents.insert(document.uri.clone(), document.text);
+                (document.uri, diagnostics)
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                // The whole text is sent on each change (see `main`)
+                let Some(change) = params.content_changes.into_iter().last() else {
+                    return Ok(None);
+                };
+                let uri = params.text_document.uri;
+                let diagnostics = diagnostics(&uri, &change.text);
+                self.documents.insert(uri.clone(), change.text);
+                (uri, diagnostics)
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                // Clear the diagnostics of the file
+                self.documents.remove(&params.text_document.uri);
+                (params.text_document.uri, Vec::new())
+            }
+            _ => return Ok(None),
+        };
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        Ok(Some(Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            params,
+        )))
+    }
+}
+
+/// The problems in the file: the errors stopping it from running if there
+/// are any, and the lints otherwise
+fn diagnostics(uri: &Uri, source: &str) -> Vec<Diagnostic> {
+    let diagnostic = |span: Span, severity: Severity, message: String, notes: &[Note]| {
+        let related: Vec<_> = notes
+            .iter()
+            .map(|note| DiagnosticRelatedInformation {
+                location: Location::new(uri.clone(), range(source, note.span)),
+                message: note.message.clone(),
+            })
+            .collect();
+        let severity = match severity {
+            Severity::Hint => DiagnosticSeverity::HINT,
+            Severity::Warning => DiagnosticSeverity::WARNING,
+            Severity::Error => DiagnosticSeverity::ERROR,
+        };
+        Diagnostic {
+            range: range(source, span),
+            severity: Some(severity),
+            source: Some("rinlox".to_string()),
+            message,
+            related_information: (!related.is_empty()).then_some(related),
+            ..Diagnostic::default()
+        }
+    };
+
+    let statements = match Parser::new(Scanner::new(source.to_string())).parse() {
+        Ok(statements) => statements,
+        Err(errors) => {
+            return errors
+                .iter()
+                .map(|error| {
+                    diagnostic(
+                        error.span(),
+                        Severity::Error,
+                        error.message(),
+                        error.notes(),
+                    )
+                })
+                .collect()
+        }
+    };
+    if let Err(errors) = Resolver::default().resolve(&statements) {
+        return errors
+            .into_iter()
+            .map(|error| {
+                diagnostic(
+                    error.token.span,
+                    Severity::Error,
+                    error.message,
+                    &error.notes,
+                )
+            })
+            .collect();
+    }
+    Linter::default()
+        .lint(&statements)
+        .into_iter()
+        .map(|lint| diagnostic(lint.token.span, lint.severity(), lint.message, &lint.notes))
+        .collect()
+}
+
+/// Where the variable at `position` is declared
+fn definition(uri: &Uri, source: &str, position: Position) -> Option<Location> {
+    let offset = offset(source, position)?;
+    let statements = Parser::new(Scanner::new(source.to_string())).parse().ok()?;
+    let mut resolver = Resolver::default().with_references();
+    // Variables resolve to their declaration even if the program has
+    // errors elsewhere
+    let _ = resolver.resolve(&statements);
+    let reference = resolver.references().iter().find(|reference| {
+        (reference.name.span.start..=reference.name.span.end).contains(&offset)
+    })?;
+    Some(Location::new(
+        uri.clone(),
+        range(source, reference.declaration.span),
+    ))
+}
+
+/// What the file declares at the top level, in order
+fn symbols(source: &str) -> Vec<DocumentSymbol> {
+    let Ok(statements) = Parser::new(Scanner::new(source.to_string())).parse() else {
+        return Vec::new();
+    };
+    statements
+        .iter()
+        .filter_map(|stmt| {
+            let symbol = match stmt {
+                Stmt::Class { name, body, .. } => {
+                    let members = [
+                        (&body.methods, SymbolKind::METHOD),
+                        (&body.getters, SymbolKind::PROPERTY),
+                        (&body.class_methods, SymbolKind::METHOD),
+                    ];
+                    let children = members
+                        .into_iter()
+                        .flat_map(|(methods, kind)| {
+                            methods.iter().map(move |method| (method, kind))
+                        })
+                        .filter_map(|(method, kind)| {
+                            let Stmt::Function { name, .. } = method else {
+                                return None;
+                            };
+                            let kind = if name.lexeme == "init" {
+                                SymbolKind::CONSTRUCTOR
+                            } else {
+                                kind
+                            };
+                            Some(symbol(source, name, kind, Vec::new()))
+                        })
+                        .collect();
+                    symbol(source, name, SymbolKind::CLASS, children)
+                }
+                Stmt::Enum { name, variants } => {
+                    let children = variants
+                        .iter()
+                        .map(|variant| symbol(source, variant, SymbolKind::ENUM_MEMBER, Vec::new()))
+                        .collect();
+                    symbol(source, name, SymbolKind::ENUM, children)
+                }
+                Stmt::Function { name, .. } => {
+                    symbol(source, name, SymbolKind::FUNCTION, Vec::new())
+                }
+                Stmt::Import { name, .. } => symbol(source, name, SymbolKind::MODULE, Vec::new()),
+                Stmt::Var { name, .. } => symbol(source, name, SymbolKind::VARIABLE, Vec::new()),
+                _ => return None,
+            };
+            Some(symbol)
+        })
+        .collect()
+}
+
+/// What each identifier in the file names, each token placed relative to
+/// the one before as the protocol wants them
+fn semantic_tokens(source: &str) -> Vec<SemanticToken> {
+    let Ok(statements) = Parser::new(Scanner::new(source.to_string())).parse() else {
+        return Vec::new();
+    };
+    let mut previous = Position::new(0, 0);
+    semantic::classify(&statements)
+        .into_iter()
+        .map(|token| {
+            let Range { start, end } = range(source, token.name.span);
+            let (token_type, global) = match token.kind {
+                Kind::Parameter => (0, false),
+                Kind::Local => (1, false),
+                Kind::Global => (1, true),
+                Kind::Function => (2, false),
+                Kind::Class => (3, false),
+                Kind::Method => (4, false),
+                Kind::Property => (5, false),
+            };
+            let delta_start = if start.line == previous.line {
+                start.character - previous.character
+            } else {
+                start.character
+            };
+            let encoded = SemanticToken {
+                delta_line: start.line - previous.line,
+                delta_start,
+                length: end.character - start.character,
+                token_type,
+                token_modifiers_bitset: u32::from(token.declaration) | u32::from(global) << 1,
+            };
+            previous = start;
+            encoded
+        })
+        .collect()
+}
+
+// NOTE(alvaro): `DocumentSymbol::deprecated` is deprecated in favor of
+// `tags`, but the struct can't be built without it
+#[allow(deprecated)]
+fn symbol(
+    source: &str,
+    name: &Token,
+    kind: SymbolKind,
+    children: Vec<DocumentSymbol>,
+) -> DocumentSymbol {
+    DocumentSymbol {
+        name: name.lexeme.to_string(),
+        detail: None,
+        kind,
+        tags: None,
+        deprecated: None,
+        range: range(source, name.span),
+        selection_range: range(source, name.span),
+        children: (!children.is_empty()).then_some(children),
+    }
+}
+
+/// The span in the positions of the protocol, which count lines from 0 and
+/// characters in UTF-16 code units
+fn range(source: &str, span: Span) -> Range {
+    Range::new(position(source, span.start), position(source, span.end))
+}
+
+fn position(source: &str, offset: usize) -> Position {
+    let before = &source[..offset.min(source.len())];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    let line = before.matches('\n').count();
+    let character = before[line_start..].encode_utf16().count();
+    Position::new(line as u32, character as u32)
+}
+
+/// Byte offset of the position in the source, if it is in it
+fn offset(source: &str, position: Position) -> Option<usize> {
+    let line_start = match position.line {
+        0 => 0,
+        line => {
+            source
+                .match_indices('\n')
+                .nth(line as usize - 1)
+                .map(|(newline, _)| newline)?
+                + 1
+        }
+    };
+    let mut units = 0;
+    for (i, c) in source[line_start..].char_indices() {
+        if units >= position.character as usize || c == '\n' {
+            return Some(line_start + i);
+        }
+        units += c.len_utf16();
+    }
+    Some(source.len())
+}
+
+fn main() -> LspResult<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        semantic_tokens_provider: Some(
+            SemanticTokensOptions {
+                legend: SemanticTokensLegend {
+                    token_types: TOKEN_TYPES.to_vec(),
+                    token_modifiers: TOKEN_MODIFIERS.to_vec(),
+                },
+                full: Some(SemanticTokensFullOptions::Bool(true)),
+                ..SemanticTokensOptions::default()
+            }
+            .into(),
+        ),
+        ..ServerCapabilities::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+    Server::default().serve(&connection)?;
+    // The threads stop once the connection is gone
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri() -> Uri {
+        "file:///test.lox".parse().unwrap()
+    }
+
+    #[test]
+    fn positions_count_utf16_code_units() {
+        let source = "var a = \"€𝄞\";\nprint a;";
+        let after = source.find(';').unwrap();
+        // `𝄞` takes two code units
+        assert_eq!(position(source, after), Position::new(0, 13));
+        assert_eq!(offset(source, Position::new(0, 13)), Some(after));
+        assert_eq!(position(source, source.len()), Position::new(1, 8));
+        assert_eq!(offset(source, Position::new(1, 6)), source.find("a;"));
+        assert_eq!(offset(source, Position::new(2, 0)), None);
+    }
+
+    #[test]
+    fn diagnostics_come_from_the_first_stage_that_fails() {
+        let messages = |source| -> Vec<_> {
+            diagnostics(&uri(), source)
+                .into_iter()
+                .map(|diagnostic| {
+                    (
+                        diagnostic.range.start,
+                        diagnostic.severity,
+                        diagnostic.message,
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(
+            messages("print ;\nvar 1;"),
+            [
+                (
+                    Position::new(0, 6),
+                    Some(DiagnosticSeverity::ERROR),
+                    "Expect expression.".to_string()
+                ),
+                (
+                    Position::new(1, 4),
+                    Some(DiagnosticSeverity::ERROR),
+                    "Expect variable name.".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            messages("return 1;"),
+            [(
+                Position::new(0, 0),
+                Some(DiagnosticSeverity::ERROR),
+                "Can't return from top-level code.".to_string()
+            )]
+        );
+        let lints = messages("{\n  var unused = 1;\n}");
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].0, Position::new(1, 6));
+        assert_eq!(lints[0].1, Some(DiagnosticSeverity::WARNING));
+    }
+
+    #[test]
+    fn definitions_are_found_for_locals_and_globals() {
+        let source = "fun f(n) {\n  return n + g();\n}\nfun g() { return 1; }";
+        let at = |line, character| {
+            definition(&uri(), source, Position::new(line, character))
+                .map(|location| location.range.start)
+        };
+        // The parameter, and a global declared further down
+        assert_eq!(at(1, 9), Some(Position::new(0, 6)));
+        assert_eq!(at(1, 13), Some(Position::new(3, 4)));
+        assert_eq!(at(1, 4), None);
+    }
+
+    #[test]
+    fn symbols_are_the_top_level_declarations() {
+        let source = "// rinlox: features=lox-enum\nclass A {\n  init() {}\n  get {}\n}\nenum E { X }\nfun f() { var local; }\nvar v;";
+        let outline: Vec<_> = symbols(source)
+            .into_iter()
+            .map(|symbol| {
+                let children: Vec<_> = symbol
+                    .children
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|child| (child.name, child.kind))
+                    .collect();
+                (symbol.name, symbol.kind, children)
+            })
+            .collect();
+        assert_eq!(
+            outline,
+            [
+                (
+                    "A".to_string(),
+                    SymbolKind::CLASS,
+                    vec![
+                        ("init".to_string(), SymbolKind::CONSTRUCTOR),
+                        ("get".to_string(), SymbolKind::PROPERTY)
+                    ]
+                ),
+                (
+                    "E".to_string(),
+                    SymbolKind::ENUM,
+                    vec![("X".to_string(), SymbolKind::ENUM_MEMBER)]
+                ),
+                ("f".to_string(), SymbolKind::FUNCTION, Vec::new()),
+                ("v".to_string(), SymbolKind::VARIABLE, Vec::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn semantic_tokens_are_relative_to_the_one_before() {
+        let source = "var total;\nfun f(n) {\n  return n + total;\n}";
+        let tokens: Vec<_> = semantic_tokens(source)
+            .into_iter()
+            .map(|token| {
+                (
+                    token.delta_line,
+                    token.delta_start,
+                    token.length,
+                    TOKEN_TYPES[token.token_type as usize].as_str(),
+                    token.token_modifiers_bitset,
+                )
+            })
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                (0, 4, 5, "variable", 0b11),
+                (1, 4, 1, "function", 0b01),
+                (0, 2, 1, "parameter", 0b01),
+                (1, 9, 1, "parameter", 0b00),
+                (0, 4, 5, "variable", 0b10),
+            ]
+        );
+    }
+}
@@ -0,0 +1,184 @@
+/// Bytecode for the virtual machine (`vm`), in the style of `clox`
+///
+/// A chunk is a flat sequence of bytes: each instruction is an opcode
+/// followed by its operands, if any. Constants live in a table next to the
+/// code and are referred to by their index.
+use std::fmt::Write;
+use std::rc::Rc;
+
+use crate::interpreter::Value;
+use crate::lexer::Token;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OpCode {
+    /// Push the constant at the index in the operand
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    /// Push the local in the stack slot in the operand
+    GetLocal,
+    SetLocal,
+    /// Push the global named by the constant in the operand
+    GetGlobal,
+    DefineGlobal,
+    SetGlobal,
+    Equal,
+    Greater,
+    Less,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Not,
+    Negate,
+    Print,
+    /// Jump forward by the 16 bit offset in the operands
+    Jump,
+    /// Jump forward by the 16 bit offset in the operands if the value on
+    /// top of the stack is falsey, leaving it there
+    JumpIfFalse,
+    /// Jump backward by the 16 bit offset in the operands
+    Loop,
+    Return,
+}
+
+impl OpCode {
+    /// Every opcode, in the order of their byte values
+    const ALL: [OpCode; 25] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::GetGlobal,
+        OpCode::DefineGlobal,
+        OpCode::SetGlobal,
+        OpCode::Equal,
+        OpCode::Greater,
+        OpCode::Less,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Modulo,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Loop,
+        OpCode::Return,
+    ];
+
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        OpCode::ALL.get(byte as usize).copied()
+    }
+
+    /// Number of bytes of operands following the opcode
+    pub fn operands(self) -> usize {
+        match self {
+            OpCode::Constant
+            | OpCode::GetLocal
+            | OpCode::SetLocal
+            | OpCode::GetGlobal
+            | OpCode::DefineGlobal
+            | OpCode::SetGlobal => 1,
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => 2,
+            _ => 0,
+        }
+    }
+}
+
+/// Compiled code, ready for the virtual machine to run
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    /// Token each byte was compiled from, to point errors at
+    pub tokens: Vec<Rc<Token>>,
+}
+
+impl Chunk {
+    pub fn write(&mut self, byte: u8, token: &Rc<Token>) {
+        self.code.push(byte);
+        self.tokens.push(Rc::clone(token));
+    }
+
+    /// Add a value to the constant table, returning its index
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    /// Human readable listing of the instructions, one per line with their
+    /// offset and source line, like `clox` prints them
+    pub fn disassemble(&self, name: &str) -> String {
+        let mut out = format!("== {} ==\n", name);
+        let mut offset = 0;
+        while offset < self.code.len() {
+            offset = self.disassemble_instruction(&mut out, offset);
+        }
+        out
+    }
+
+    /// Write the instruction at `offset`, returning the offset of the next
+    /// one
+    fn disassemble_instruction(&self, out: &mut String, offset: usize) -> usize {
+        let line = self.tokens[offset].span.line;
+        if offset > 0 && line == self.tokens[offset - 1].span.line {
+            let _ = write!(out, "{:04}    | ", offset);
+        } else {
+            let _ = write!(out, "{:04} {:4} ", offset, line);
+        }
+
+        let Some(op) = OpCode::from_byte(self.code[offset]) else {
+            let _ = writeln!(out, "Unknown opcode {}", self.code[offset]);
+            return offset + 1;
+        };
+        let name = format!("{:?}", op);
+        match op {
+            OpCode::Constant | OpCode::GetGlobal | OpCode::DefineGlobal | OpCode::SetGlobal => {
+                let index = self.code[offset + 1];
+                let constant = &self.constants[index as usize];
+                let _ = writeln!(out, "{:<16} {:4} '{}'", name, index, constant);
+            }
+            OpCode::GetLocal | OpCode::SetLocal => {
+                let _ = writeln!(out, "{:<16} {:4}", name, self.code[offset + 1]);
+            }
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+                let jump = u16::from_be_bytes([self.code[offset + 1], self.code[offset + 2]]);
+                let next = offset + 3;
+                let target = if op == OpCode::Loop {
+                    next - jump as usize
+                } else {
+                    next + jump as usize
+                };
+                let _ = writeln!(out, "{:<16} {:4} -> {}", name, offset, target);
+            }
+            _ => {
+                let _ = writeln!(out, "{}", name);
+            }
+        }
+        offset + 1 + op.operands()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opcodes_round_trip_through_bytes() {
+        for (byte, op) in OpCode::ALL.into_iter().enumerate() {
+            assert_eq!(op as u8 as usize, byte);
+            assert_eq!(OpCode::from_byte(byte as u8), Some(op));
+        }
+        assert_eq!(OpCode::from_byte(OpCode::ALL.len() as u8), None);
+    }
+}
@@ -0,0 +1,215 @@
+/// Classes and instances for the `Lox` interpreter
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::rc::Rc;
+
+use crate::function::{LoxCallable, LoxFunction};
+use crate::gc::{self, Trace};
+use crate::interpreter::{Interpreter, RuntimeError, Value};
+use crate::lexer::Token;
+use crate::symbol::Symbol;
+
+#[derive(Debug)]
+pub struct LoxClass {
+    pub name: String,
+    superclass: Option<Rc<LoxClass>>,
+    /// Methods can be added after the class is declared with `extend`,
+    /// which is why they are behind a `RefCell`. Getters are methods too
+    methods: RefCell<HashMap<Symbol, Rc<LoxFunction>>>,
+    /// Methods called on the class itself, with `this` bound to it
+    class_methods: RefCell<HashMap<Symbol, Rc<LoxFunction>>>,
+}
+
+impl LoxClass {
+    /// Class with no methods yet (see `define_method`)
+    pub fn new(name: &str, superclass: Option<Rc<LoxClass>>) -> Self {
+        Self {
+            name: name.to_string(),
+            superclass,
+            methods: RefCell::new(HashMap::new()),
+            class_methods: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Add a method to the class, replacing any existing one with the same
+    /// name. Instances created before see the change too
+    pub fn define_method(&self, name: Symbol, method: Rc<LoxFunction>) {
+        self.methods.borrow_mut().insert(name, method);
+    }
+
+    /// Add a method called on the class itself, like `define_method`
+    pub fn define_class_method(&self, name: Symbol, method: Rc<LoxFunction>) {
+        self.class_methods.borrow_mut().insert(name, method);
+    }
+
+    /// Find a method in this class or, failing that, in its superclasses
+    pub fn find_method(&self, name: Symbol) -> Option<Rc<LoxFunction>> {
+        self.methods.borrow().get(&name).cloned().or_else(|| {
+            self.superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name))
+        })
+    }
+
+    /// Find a class method in this class or, failing that, in its
+    /// superclasses
+    pub fn find_class_method(&self, name: Symbol) -> Option<Rc<LoxFunction>> {
+        self.class_methods.borrow().get(&name).cloned().or_else(|| {
+            self.superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_class_method(name))
+        })
+    }
+
+    /// Look up a class method, bound to the class
+    pub fn get(
+        self: &Rc<Self>,
+        interpreter: &mut Interpreter,
+        name: &Token,
+    ) -> Result<Value, RuntimeError> {
+        match self.find_class_method(name.lexeme.symbol()) {
+            Some(method) => {
+                let bound = method.bind(interpreter.heap(), Value::Class(Rc::clone(self)));
+                Ok(Value::Callable(bound))
+            }
+            None => Err(RuntimeError::new(
+                name,
+                &format!("Undefined property '{}'.", name.lexeme),
+            )),
+        }
+    }
+}
+
+impl Trace for LoxClass {
+    fn trace(&self, references: &mut Vec<usize>) -> bool {
+        let (Ok(methods), Ok(class_methods)) =
+            (self.methods.try_borrow(), self.class_methods.try_borrow())
+        else {
+            return false;
+        };
+        references.extend(self.superclass.as_ref().map(gc::address));
+        references.extend(
+            methods
+                .values()
+                .chain(class_methods.values())
+                .map(gc::address),
+        );
+        true
+    }
+
+    fn clear(&self) {
+        if let Ok(mut methods) = self.methods.try_borrow_mut() {
+            methods.clear();
+        }
+        if let Ok(mut class_methods) = self.class_methods.try_borrow_mut() {
+            class_methods.clear();
+        }
+    }
+}
+
+// NOTE(alvaro): Calling a class needs a handle to the class itself to store
+// in the new instance, so the callable is the `Rc` rather than the class
+impl LoxCallable for Rc<LoxClass> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn arity(&self) -> usize {
+        self.find_method(Symbol::INIT)
+            .map(|initializer| initializer.arity())
+            .unwrap_or(0)
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let instance = interpreter.heap().alloc(LoxInstance::new(Rc::clone(self)));
+        let instance = Value::Instance(instance);
+        if let Some(initializer) = self.find_method(Symbol::INIT) {
+            initializer
+                .bind(interpreter.heap(), instance.clone())
+                .call(interpreter, paren, arguments)?;
+        }
+        Ok(instance)
+    }
+}
+
+impl Display for LoxClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+#[derive(Debug)]
+pub struct LoxInstance {
+    class: Rc<LoxClass>,
+    fields: RefCell<HashMap<Symbol, Value>>,
+}
+
+impl LoxInstance {
+    fn new(class: Rc<LoxClass>) -> Self {
+        Self {
+            class,
+            fields: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Look up a property on the instance. Fields shadow methods, and methods
+    /// are returned bound to the instance, except for getters, which are
+    /// called to get the value
+    pub fn get(
+        self: &Rc<Self>,
+        interpreter: &mut Interpreter,
+        name: &Token,
+    ) -> Result<Value, RuntimeError> {
+        if let Some(value) = self.fields.borrow().get(&name.lexeme.symbol()) {
+            return Ok(value.clone());
+        }
+
+        if let Some(method) = self.class.find_method(name.lexeme.symbol()) {
+            let bound = method.bind(interpreter.heap(), Value::Instance(Rc::clone(self)));
+            if method.is_getter() {
+                return interpreter.call(bound, name, Vec::new());
+            }
+            return Ok(Value::Callable(bound));
+        }
+
+        Err(RuntimeError::new(
+            name,
+            &format!("Undefined property '{}'.", name.lexeme),
+        ))
+    }
+
+    pub fn set(&self, name: &Token, value: Value) {
+        self.fields.borrow_mut().insert(name.lexeme.symbol(), value);
+    }
+}
+
+impl Display for LoxInstance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
+
+impl Trace for LoxInstance {
+    fn trace(&self, references: &mut Vec<usize>) -> bool {
+        let Ok(fields) = self.fields.try_borrow() else {
+            return false;
+        };
+        references.push(gc::address(&self.class));
+        for value in fields.values() {
+            gc::trace_value(value, references);
+        }
+        true
+    }
+
+    fn clear(&self) {
+        if let Ok(mut fields) = self.fields.try_borrow_mut() {
+            fields.clear();
+        }
+    }
+}
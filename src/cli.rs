@@ -0,0 +1,646 @@
+/// Command line arguments of `rinlox`
+///
+/// The first argument can name a subcommand, which selects how far through
+/// the pipeline the input goes (`tokenize`, `parse`, `run`, ...). Without
+/// one, `rinlox` runs the script given, or starts the prompt.
+use std::str::FromStr;
+
+use rinlox::grammar::Format;
+use rinlox::Features;
+
+pub const USAGE: &str = "\
+usage: rinlox [options] [script | -]
+       rinlox [options] -e code
+       rinlox --emit=tokens-json|ast-json|semantic-json [--features=list] script
+       rinlox run [options] [script]
+       rinlox repl [options]
+       rinlox tokenize [options] [--baseline=dump] script
+       rinlox parse [options] script
+       rinlox transpile [--no-color] script [-o output]
+       rinlox test [options] [path]
+       rinlox debug [options] script
+       rinlox bench [--features=list] [path]
+       rinlox fmt [--check] [--features=list] script
+       rinlox lint [--no-color] [--features=list] script
+       rinlox grammar [--features=list] [--format=ebnf|railroad-html|precedence]
+
+commands:
+  run         run the script (- reads it from the standard input), or start
+              the prompt without one (the default)
+  repl        start the interactive prompt
+  tokenize    print the tokens of the script, or with --baseline=dump
+              compare them with a dump printed by tokenize before
+  parse       print the syntax tree of the script
+  transpile   translate the script into a Rust program
+  debug       run the script in the debugger, stopping at its first line
+              (type help there to see the commands)
+  test        run the *_test.lox files in a directory (lib by default)
+  fmt         format the script in place (- prints it instead), or with
+              --check only fail if it is not formatted
+  lint        warn about likely mistakes in the script (unused locals,
+              unreachable code, ...), failing if any of them is an error
+  bench       time scanning, parsing and running the .lox files in a
+              directory (benches/programs by default)
+  grammar     print the grammar of the language, or its operator
+              precedence table
+
+options:
+  -e code             run the code instead of a script
+  --print-ast         same as the parse command
+  --emit=format       print the tokens (tokens-json), the syntax tree
+                      (ast-json) or what each identifier names
+                      (semantic-json) of the script as JSON, if built with
+                      the serde feature
+  --features=list     enable experimental features (comma separated)
+  --extended          enable every experimental feature
+  --no-color          don't highlight errors
+  --precision digits  significant digits to print numbers with
+  --max-call-depth n  calls that can be nested before a stack overflow
+                      error (10000 by default)
+  --debug             define the natives inspecting the interpreter
+                      (stackDepth, callerName)
+  --plugin library    load the native functions of a plugin (a dynamic
+                      library), if built with the plugins feature
+  --trace             log each statement run, variable assigned and call
+                      made (with its arguments and result) to the errors
+  --opt               fold constant expressions and remove the code that
+                      never runs before running the script
+  --gc-stats          report how many objects the garbage collector freed
+                      and how many are left, once done running
+  --auto-semicolons   assume the ';' missing at the end of a line, with a
+                      hint instead of an error (always on in the prompt)
+  --strict-internal   report a crash in rinlox as an internal error (with
+                      where in rinlox it happened) instead of aborting
+  --backend=name      run the code on the tree-walker interpreter (the
+                      default) or compile it for the bytecode vm, which
+                      only supports expressions, variables and control flow
+  -h, --help          show this message";
+
+/// What `rinlox` was asked to do
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Run a script, or the prompt if there is none
+    Run(Option<String>),
+    Repl,
+    /// Run code given in the command line
+    Eval(String),
+    /// Stop after scanning the script, printing its tokens
+    Tokenize(String),
+    /// Compare the tokens of the script with a dump `Tokenize` printed
+    TokenDiff {
+        script: String,
+        baseline: String,
+    },
+    /// Stop after parsing the script, printing its syntax tree
+    Parse(String),
+    /// Print the tokens or syntax tree of the script for other tools
+    Emit {
+        script: String,
+        emit: Emit,
+    },
+    Transpile {
+        script: String,
+        output: Option<String>,
+    },
+    /// Run a script in the debugger
+    Debug(String),
+    /// Run the tests in a directory or file
+    Test(String),
+    /// Time each stage of the pipeline on the programs in a directory or
+    /// file
+    Bench(String),
+    /// Format the script, or check that it is formatted
+    Fmt {
+        script: String,
+        check: bool,
+    },
+    /// Check the script for likely mistakes
+    Lint(String),
+    Grammar(Format),
+    Help,
+}
+
+/// What runs the code
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    TreeWalker,
+    Vm,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tree-walker" => Ok(Backend::TreeWalker),
+            "vm" => Ok(Backend::Vm),
+            _ => Err(format!("unknown backend '{}'", s)),
+        }
+    }
+}
+
+/// What `--emit` writes as JSON
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    /// What each identifier names (see `rinlox::semantic`)
+    Semantic,
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tokens-json" => Ok(Emit::Tokens),
+            "ast-json" => Ok(Emit::Ast),
+            "semantic-json" => Ok(Emit::Semantic),
+            _ => Err(format!("unknown output format '{}'", s)),
+        }
+    }
+}
+
+/// Parsed command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cli {
+    pub command: Command,
+    pub features: Features,
+    pub color: bool,
+    /// Significant digits `print` uses for numbers
+    pub precision: Option<usize>,
+    /// Calls that can be nested before a stack overflow
+    pub max_call_depth: Option<usize>,
+    /// Whether to define the natives for debugging
+    pub debug: bool,
+    pub backend: Backend,
+    /// Whether to report panics as internal errors
+    pub strict_internal: bool,
+    /// Whether scripts are parsed with automatic semicolons
+    pub auto_semicolons: bool,
+    /// Whether the programs are optimized before running them
+    pub optimize: bool,
+    /// Whether to log what the interpreter does
+    pub trace: bool,
+    /// Whether to report what the garbage collector did
+    pub gc_stats: bool,
+    /// Dynamic libraries to load natives from
+    pub plugins: Vec<String>,
+}
+
+impl Cli {
+    /// Parse the arguments (without the program name), failing with the
+    /// usage message if they don't make sense, or with what is wrong if an
+    /// option doesn't fit the command
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut args = args.into_iter().peekable();
+        let command = args
+            .next_if(|arg| {
+                let commands = [
+                    "run",
+                    "repl",
+                    "tokenize",
+                    "parse",
+                    "transpile",
+                    "test",
+                    "debug",
+                    "bench",
+                    "fmt",
+                    "lint",
+                    "grammar",
+                ];
+                commands.contains(&arg.as_str())
+            })
+            .unwrap_or_else(|| "run".to_string());
+
+        let mut features = Features::default();
+        let mut color = true;
+        let mut precision = None;
+        let mut max_call_depth = None;
+        let mut debug = false;
+        let mut backend = Backend::TreeWalker;
+        let mut strict_internal = false;
+        let mut auto_semicolons = false;
+        let mut optimize = false;
+        let mut trace = false;
+        let mut gc_stats = false;
+        let mut plugins = Vec::new();
+        let mut print_ast = false;
+        let mut code = None;
+        let mut output = None;
+        let mut format = None;
+        let mut emit = None;
+        let mut baseline = None;
+        let mut check = false;
+        let mut positional = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(Cli::new(Command::Help)),
+                "-e" => code = Some(args.next().ok_or_else(usage)?),
+                "-o" => output = Some(args.next().ok_or_else(usage)?),
+                "--plugin" => plugins.push(args.next().ok_or_else(usage)?),
+                "--print-ast" => print_ast = true,
+                "--extended" => features = Features::all(),
+                "--no-color" => color = false,
+                "--debug" => debug = true,
+                "--strict-internal" => strict_internal = true,
+                "--auto-semicolons" => auto_semicolons = true,
+                "--opt" => optimize = true,
+                "--trace" => trace = true,
+                "--gc-stats" => gc_stats = true,
+                "--check" => check = true,
+                "--precision" => {
+                    let digits = args
+                        .next()
+                        .and_then(|digits| digits.parse::<usize>().ok())
+                        .filter(|&digits| digits > 0)
+                        .ok_or_else(usage)?;
+                    precision = Some(digits);
+                }
+                "--max-call-depth" => {
+                    let depth = args
+                        .next()
+                        .and_then(|depth| depth.parse::<usize>().ok())
+                        .filter(|&depth| depth > 0)
+                        .ok_or_else(usage)?;
+                    max_call_depth = Some(depth);
+                }
+                _ if arg.starts_with("--features=") => {
+                    features.enable_list(&arg["--features=".len()..])?;
+                }
+                _ if arg.starts_with("--backend=") => {
+                    backend = arg["--backend=".len()..].parse()?;
+                }
+                _ if arg.starts_with("--emit=") => {
+                    emit = Some(arg["--emit=".len()..].parse()?);
+                }
+                _ if arg.starts_with("--baseline=") => {
+                    baseline = Some(arg["--baseline=".len()..].to_string());
+                }
+                _ if arg.starts_with("--format=") => {
+                    format = Some(arg["--format=".len()..].parse()?);
+                }
+                // A lone `-` stands for the standard input
+                _ if positional.is_none() && (arg == "-" || !arg.starts_with('-')) => {
+                    positional = Some(arg)
+                }
+                _ => return Err(usage()),
+            }
+        }
+
+        // Options only some of the commands take
+        let command_is = |commands: &[&str]| commands.contains(&command.as_str());
+        let runs = ["run", "repl"];
+        let runs_code = ["run", "repl", "test", "debug"];
+        if output.is_some() && !command_is(&["transpile"]) {
+            return Err(only_applies_to("-o", &["transpile"]));
+        }
+        if format.is_some() && !command_is(&["grammar"]) {
+            return Err(only_applies_to("--format", &["grammar"]));
+        }
+        if check && !command_is(&["fmt"]) {
+            return Err(only_applies_to("--check", &["fmt"]));
+        }
+        if baseline.is_some() && !command_is(&["tokenize"]) {
+            return Err(only_applies_to("--baseline", &["tokenize"]));
+        }
+        if emit.is_some() && !command_is(&["run"]) {
+            return Err(only_applies_to("--emit", &["run"]));
+        }
+        if auto_semicolons && !command_is(&["run"]) {
+            return Err(only_applies_to("--auto-semicolons", &["run"]));
+        }
+        if optimize && !command_is(&runs) {
+            return Err(only_applies_to("--opt", &runs));
+        }
+        if trace && !command_is(&runs) {
+            return Err(only_applies_to("--trace", &runs));
+        }
+        if gc_stats && !command_is(&runs) {
+            return Err(only_applies_to("--gc-stats", &runs));
+        }
+        if !plugins.is_empty() && !command_is(&runs) {
+            return Err(only_applies_to("--plugin", &runs));
+        }
+        // The vm has none of the natives, and `test` needs them
+        if backend == Backend::Vm && !command_is(&runs) {
+            return Err(only_applies_to("--backend=vm", &runs));
+        }
+        if precision.is_some() && !command_is(&runs_code) {
+            return Err(only_applies_to("--precision", &runs_code));
+        }
+        if max_call_depth.is_some() && !command_is(&runs_code) {
+            return Err(only_applies_to("--max-call-depth", &runs_code));
+        }
+        if debug && !command_is(&runs_code) {
+            return Err(only_applies_to("--debug", &runs_code));
+        }
+        if strict_internal && command_is(&["transpile", "grammar", "bench", "fmt", "lint"]) {
+            return Err(format!("--strict-internal doesn't apply to {}", command));
+        }
+        if !features.is_empty() && command_is(&["transpile"]) {
+            return Err("--features and --extended don't apply to transpile".to_string());
+        }
+
+        // Options that don't go together
+        if emit.is_some() && code.is_some() {
+            return Err("--emit can't be used with -e".to_string());
+        }
+        if emit.is_some() || print_ast {
+            let stage = if print_ast { "--print-ast" } else { "--emit" };
+            for (option, given) in [
+                ("--auto-semicolons", auto_semicolons),
+                ("--opt", optimize),
+                ("--emit", emit.is_some()),
+            ] {
+                if given && option != stage {
+                    return Err(format!("{} can't be used with {}", option, stage));
+                }
+            }
+        }
+        if backend == Backend::Vm {
+            for (option, given) in [
+                ("--debug", debug),
+                ("--trace", trace),
+                ("--max-call-depth", max_call_depth.is_some()),
+                ("--auto-semicolons", auto_semicolons),
+                ("--opt", optimize),
+                ("--gc-stats", gc_stats),
+                ("--plugin", !plugins.is_empty()),
+            ] {
+                if given {
+                    return Err(format!("--backend=vm doesn't support {}", option));
+                }
+            }
+        }
+
+        let command = match (command.as_str(), positional, code, print_ast) {
+            ("run", None, Some(code), false) => Command::Eval(code),
+            ("run", Some(script), None, true) => Command::Parse(script),
+            ("run", script, None, false) => match emit {
+                Some(emit) => Command::Emit {
+                    script: script.ok_or_else(usage)?,
+                    emit,
+                },
+                None => Command::Run(script),
+            },
+            (_, _, Some(_), _) | (_, _, _, true) => return Err(usage()),
+            ("repl", None, ..) => Command::Repl,
+            ("tokenize", Some(script), ..) => match baseline {
+                Some(baseline) => Command::TokenDiff { script, baseline },
+                None => Command::Tokenize(script),
+            },
+            ("parse", Some(script), ..) => Command::Parse(script),
+            ("transpile", Some(script), ..) => Command::Transpile { script, output },
+            ("debug", Some(script), ..) => Command::Debug(script),
+            ("test", path, ..) => Command::Test(path.unwrap_or_else(|| "lib".to_string())),
+            ("fmt", Some(script), ..) => Command::Fmt { script, check },
+            ("lint", Some(script), ..) => Command::Lint(script),
+            ("bench", path, ..) => {
+                Command::Bench(path.unwrap_or_else(|| "benches/programs".to_string()))
+            }
+            ("grammar", None, ..) => Command::Grammar(format.unwrap_or(Format::Ebnf)),
+            _ => return Err(usage()),
+        };
+        Ok(Cli {
+            command,
+            features,
+            color,
+            precision,
+            max_call_depth,
+            debug,
+            backend,
+            strict_internal,
+            auto_semicolons,
+            optimize,
+            trace,
+            gc_stats,
+            plugins,
+        })
+    }
+
+    fn new(command: Command) -> Self {
+        Self {
+            command,
+            features: Features::default(),
+            color: true,
+            precision: None,
+            max_call_depth: None,
+            debug: false,
+            backend: Backend::TreeWalker,
+            strict_internal: false,
+            auto_semicolons: false,
+            optimize: false,
+            trace: false,
+            gc_stats: false,
+            plugins: Vec::new(),
+        }
+    }
+}
+
+fn usage() -> String {
+    USAGE.to_string()
+}
+
+/// Error for an option given to a command it doesn't apply to
+fn only_applies_to(option: &str, commands: &[&str]) -> String {
+    let commands = match commands {
+        [command] => command.to_string(),
+        [rest @ .., last] => format!("{} and {}", rest.join(", "), last),
+        [] => unreachable!("every option applies to some command"),
+    };
+    format!("{} only applies to {}", option, commands)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Cli, String> {
+        Cli::parse(args.split_whitespace().map(str::to_string))
+    }
+
+    fn command(args: &str) -> Command {
+        parse(args).expect("arguments should parse").command
+    }
+
+    #[test]
+    fn runs_a_script_or_the_prompt_by_default() {
+        assert_eq!(command(""), Command::Run(None));
+        assert_eq!(command("a.lox"), Command::Run(Some("a.lox".to_string())));
+        assert_eq!(
+            command("run a.lox"),
+            Command::Run(Some("a.lox".to_string()))
+        );
+        assert_eq!(command("repl"), Command::Repl);
+        assert_eq!(command("-e 1"), Command::Eval("1".to_string()));
+        assert_eq!(command("-"), Command::Run(Some("-".to_string())));
+    }
+
+    #[test]
+    fn selects_the_stage_to_stop_at() {
+        assert_eq!(
+            command("tokenize a.lox"),
+            Command::Tokenize("a.lox".to_string())
+        );
+        assert_eq!(command("parse a.lox"), Command::Parse("a.lox".to_string()));
+        assert_eq!(
+            command("--print-ast a.lox"),
+            Command::Parse("a.lox".to_string())
+        );
+        assert_eq!(
+            command("transpile a.lox -o a.rs"),
+            Command::Transpile {
+                script: "a.lox".to_string(),
+                output: Some("a.rs".to_string())
+            }
+        );
+        assert_eq!(
+            command("grammar --format=railroad-html"),
+            Command::Grammar(Format::RailroadHtml)
+        );
+        assert_eq!(command("test"), Command::Test("lib".to_string()));
+        assert_eq!(command("debug a.lox"), Command::Debug("a.lox".to_string()));
+        assert_eq!(
+            command("fmt --check a.lox"),
+            Command::Fmt {
+                script: "a.lox".to_string(),
+                check: true
+            }
+        );
+        assert_eq!(command("lint a.lox"), Command::Lint("a.lox".to_string()));
+        assert_eq!(
+            command("tokenize --baseline=a.tokens a.lox"),
+            Command::TokenDiff {
+                script: "a.lox".to_string(),
+                baseline: "a.tokens".to_string()
+            }
+        );
+        assert_eq!(
+            command("--emit=ast-json a.lox"),
+            Command::Emit {
+                script: "a.lox".to_string(),
+                emit: Emit::Ast
+            }
+        );
+        assert_eq!(
+            command("--emit=semantic-json a.lox"),
+            Command::Emit {
+                script: "a.lox".to_string(),
+                emit: Emit::Semantic
+            }
+        );
+        assert_eq!(
+            command("bench"),
+            Command::Bench("benches/programs".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_the_options() {
+        let cli = parse("--no-color --precision 3 --features=lox-enum --debug a.lox").unwrap();
+        assert!(!cli.color);
+        assert!(cli.debug);
+        assert_eq!(cli.precision, Some(3));
+        assert!(cli.features.is_enabled(rinlox::Feature::Enum));
+        assert!(!cli.features.is_enabled(rinlox::Feature::Defer));
+        assert_eq!(command("run --help"), Command::Help);
+        assert_eq!(parse("a.lox").unwrap().backend, Backend::TreeWalker);
+        assert_eq!(parse("--backend=vm -e 1").unwrap().backend, Backend::Vm);
+        assert!(parse("--strict-internal a.lox").unwrap().strict_internal);
+        assert!(parse("--auto-semicolons a.lox").unwrap().auto_semicolons);
+        assert!(parse("repl --opt").unwrap().optimize);
+        assert!(parse("repl --trace").unwrap().trace);
+        assert!(parse("--gc-stats a.lox").unwrap().gc_stats);
+        assert_eq!(
+            parse("--max-call-depth 100 a.lox").unwrap().max_call_depth,
+            Some(100)
+        );
+        assert_eq!(
+            parse("--plugin a.so --plugin b.so a.lox").unwrap().plugins,
+            ["a.so", "b.so"]
+        );
+    }
+
+    #[test]
+    fn rejects_options_the_command_does_not_take() {
+        for args in [
+            "a.lox b.lox",
+            "-e 1 a.lox",
+            "--precision 0",
+            "--precision",
+            "tokenize",
+            "repl a.lox",
+            "parse --precision 2 a.lox",
+            "tokenize --debug a.lox",
+            "grammar a.lox",
+            "run -o out a.lox",
+            "transpile --extended a.lox",
+            "--features=lox-nope",
+            "--backend=jit a.lox",
+            "--emit=tokens-json",
+            "--emit=tokens-json -e 1",
+            "--emit=yaml a.lox",
+            "test --opt",
+            "--backend=vm --opt a.lox",
+            "parse --emit=ast-json a.lox",
+            "test --backend=vm",
+            "bench --precision 2",
+            "--max-call-depth 0 a.lox",
+            "parse --max-call-depth 10 a.lox",
+            "--backend=vm --max-call-depth 10 a.lox",
+            "grammar --strict-internal",
+            "fmt",
+            "run --check a.lox",
+            "lint",
+            "lint --debug a.lox",
+            "parse --baseline=a.tokens a.lox",
+            "tokenize --auto-semicolons a.lox",
+            "--backend=vm --auto-semicolons a.lox",
+            "test --trace",
+            "fmt --gc-stats a.lox",
+            "--backend=vm --gc-stats a.lox",
+            "debug",
+            "debug --backend=vm a.lox",
+            "run --plugin",
+            "parse --plugin a.so a.lox",
+            "--backend=vm --debug a.lox",
+            "--bogus",
+        ] {
+            assert!(parse(args).is_err(), "'{}' should not parse", args);
+        }
+    }
+
+    #[test]
+    fn says_which_option_does_not_fit() {
+        for (args, message) in [
+            (
+                "fmt --gc-stats a.lox",
+                "--gc-stats only applies to run and repl",
+            ),
+            ("run -o out a.lox", "-o only applies to transpile"),
+            (
+                "bench --precision 2",
+                "--precision only applies to run, repl, test and debug",
+            ),
+            (
+                "grammar --strict-internal",
+                "--strict-internal doesn't apply to grammar",
+            ),
+            (
+                "transpile --extended a.lox",
+                "--features and --extended don't apply to transpile",
+            ),
+            (
+                "--emit=ast-json --opt a.lox",
+                "--opt can't be used with --emit",
+            ),
+            (
+                "--backend=vm --trace a.lox",
+                "--backend=vm doesn't support --trace",
+            ),
+        ] {
+            assert_eq!(parse(args).err().as_deref(), Some(message), "{}", args);
+        }
+    }
+}
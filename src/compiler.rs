@@ -0,0 +1,785 @@
+/// Single-pass compiler from tokens to bytecode for the virtual machine
+/// (`vm`), like the one in `clox`
+///
+/// There is no syntax tree: code is emitted as soon as each token is
+/// consumed. Expressions are compiled by precedence climbing with the same
+/// table the parser uses (`parser::PARSE_RULES`).
+///
+/// Only a subset of the language is supported for now: expressions, global
+/// and local variables, blocks and control flow. Functions and classes are
+/// reported as errors.
+use std::rc::Rc;
+
+use crate::chunk::{Chunk, OpCode};
+use crate::internal;
+use crate::interpreter::Value;
+use crate::lexer::{ScanError, Token, TokenType};
+use crate::parser::{parse_rule, Infix, ParseError, Precedence, Prefix, MAX_NESTING};
+use crate::symbol::Symbol;
+
+/// Maximum number of locals in scope at once, so their slots fit in a byte
+const MAX_LOCALS: usize = 256;
+/// Maximum number of constants in a chunk, so their indices fit in a byte
+const MAX_CONSTANTS: usize = 256;
+
+type CompileResult<T> = Result<T, ParseError>;
+
+/// Variable declared in a block, living in a slot of the stack
+#[derive(Debug)]
+struct Local {
+    name: Symbol,
+    /// How many blocks deep it was declared, or `None` while its
+    /// initializer is being compiled
+    depth: Option<usize>,
+}
+
+/// Loop being compiled, for the `break` and `continue` statements in it
+#[derive(Debug)]
+struct Loop {
+    /// Where a `continue` jumps back to: the condition, or the increment of
+    /// a `for` loop
+    start: usize,
+    /// Number of blocks the loop is in, so the locals declared deeper are
+    /// popped when leaving its body
+    depth: usize,
+    /// Offsets of the jumps of its `break`s, patched at the end of the loop
+    breaks: Vec<usize>,
+}
+
+pub struct Compiler<I>
+where
+    I: Iterator<Item = Result<Token, ScanError>>,
+{
+    tokens: I,
+    /// The next token to be consumed
+    current: Rc<Token>,
+    /// The last token consumed, which the code emitted is attributed to
+    previous: Rc<Token>,
+    chunk: Chunk,
+    /// Locals in scope, in the order of their stack slots
+    locals: Vec<Local>,
+    /// Number of blocks the code being compiled is in
+    scope_depth: usize,
+    /// Loops the code being compiled is in, innermost last
+    loops: Vec<Loop>,
+    /// How many statements and expressions the code being compiled is
+    /// nested in
+    nesting: usize,
+    /// Whether the rest of the source was skipped for being nested too
+    /// deep, so the errors following from that are not reported
+    gave_up: bool,
+    errors: Vec<ParseError>,
+}
+
+impl<I> Compiler<I>
+where
+    I: Iterator<Item = Result<Token, ScanError>>,
+{
+    pub fn new(tokens: I) -> Self {
+        let mut compiler = Self {
+            tokens,
+            current: Rc::new(Token::new(TokenType::Eof, "", Default::default())),
+            previous: Rc::new(Token::new(TokenType::Eof, "", Default::default())),
+            chunk: Chunk::default(),
+            locals: Vec::new(),
+            scope_depth: 0,
+            loops: Vec::new(),
+            nesting: 0,
+            gave_up: false,
+            errors: Vec::new(),
+        };
+        compiler.current = Rc::new(compiler.next_token());
+        compiler
+    }
+
+    /// Compile the whole program, returning its code or every error found
+    pub fn compile(mut self) -> Result<Chunk, Vec<ParseError>> {
+        while !self.check(&TokenType::Eof) {
+            self.declaration();
+        }
+        self.emit(OpCode::Return);
+
+        if self.errors.is_empty() {
+            Ok(self.chunk)
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    /// Compile a declaration, skipping to the next statement after an error
+    /// so the rest of the program is still checked
+    fn declaration(&mut self) {
+        let result = if self.next_matches(&TokenType::Var) {
+            self.var_declaration()
+        } else if let Some(keyword) = self.unsupported_statement() {
+            self.unsupported(&keyword);
+            self.skip_statement();
+            Ok(())
+        } else {
+            self.statement()
+        };
+        if result.is_err() {
+            self.synchronize();
+        }
+    }
+
+    /// The keyword starting the next statement, if it is one the virtual
+    /// machine can't run yet
+    fn unsupported_statement(&self) -> Option<Rc<Token>> {
+        match self.current.typ {
+            TokenType::Class
+            | TokenType::Defer
+            | TokenType::Enum
+            | TokenType::Extend
+            | TokenType::Fun
+            | TokenType::Import
+            | TokenType::Return => Some(Rc::clone(&self.current)),
+            _ => None,
+        }
+    }
+
+    /// Skip a whole statement, along with any blocks in it
+    fn skip_statement(&mut self) {
+        let mut depth = 0;
+        while !self.check(&TokenType::Eof) {
+            self.advance();
+            match self.previous.typ {
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace if depth <= 1 => return,
+                TokenType::RightBrace => depth -= 1,
+                TokenType::SemiColon if depth == 0 => return,
+                _ => {}
+            }
+        }
+    }
+
+    fn var_declaration(&mut self) -> CompileResult<()> {
+        self.consume(&TokenType::Identifier, "Expect variable name.")?;
+        let name = Rc::clone(&self.previous);
+        if self.scope_depth > 0 {
+            self.declare_local(&name);
+        }
+
+        if self.next_matches(&TokenType::Equal) {
+            self.expression()?;
+        } else {
+            self.emit(OpCode::Nil);
+        }
+        self.consume(
+            &TokenType::SemiColon,
+            "Expect ';' after variable declaration.",
+        )?;
+
+        if self.scope_depth > 0 {
+            // The value is already in the slot of the local, on the stack
+            if let Some(local) = self.locals.last_mut() {
+                local.depth = Some(self.scope_depth);
+            }
+        } else {
+            let constant = self.identifier_constant(&name);
+            self.emit_with_operand(OpCode::DefineGlobal, constant);
+        }
+        Ok(())
+    }
+
+    fn declare_local(&mut self, name: &Token) {
+        let redeclared = self
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth.is_none_or(|depth| depth == self.scope_depth))
+            .any(|local| local.name == name.lexeme.symbol());
+        if redeclared {
+            self.error(name, "Already a variable with this name in this scope.");
+        }
+        if self.locals.len() >= MAX_LOCALS {
+            self.error(name, "Too many local variables in function.");
+            return;
+        }
+        self.locals.push(Local {
+            name: name.lexeme.symbol(),
+            depth: None,
+        });
+    }
+
+    fn statement(&mut self) -> CompileResult<()> {
+        self.nested(Self::any_statement)
+    }
+
+    fn any_statement(&mut self) -> CompileResult<()> {
+        if self.next_matches(&TokenType::Print) {
+            self.expression()?;
+            self.consume(&TokenType::SemiColon, "Expect ';' after value.")?;
+            self.emit(OpCode::Print);
+        } else if self.next_matches(&TokenType::Break) {
+            self.break_statement()?;
+        } else if self.next_matches(&TokenType::Continue) {
+            self.continue_statement()?;
+        } else if self.next_matches(&TokenType::If) {
+            self.if_statement()?;
+        } else if self.next_matches(&TokenType::While) {
+            self.while_statement()?;
+        } else if self.next_matches(&TokenType::For) {
+            self.for_statement()?;
+        } else if self.next_matches(&TokenType::LeftBrace) {
+            self.begin_scope();
+            let result = self.block();
+            self.end_scope();
+            result?;
+        } else {
+            self.expression()?;
+            self.consume(&TokenType::SemiColon, "Expect ';' after expression.")?;
+            self.emit(OpCode::Pop);
+        }
+        Ok(())
+    }
+
+    /// Compile the declarations in a block, after the opening `{`
+    fn block(&mut self) -> CompileResult<()> {
+        while !self.check(&TokenType::RightBrace) && !self.check(&TokenType::Eof) {
+            self.declaration();
+        }
+        self.consume(&TokenType::RightBrace, "Expect '}' after block.")?;
+        Ok(())
+    }
+
+    fn begin_scope(&mut self) {
+        self.scope_depth += 1;
+    }
+
+    /// Leave a block, popping its locals off the stack
+    fn end_scope(&mut self) {
+        self.scope_depth -= 1;
+        while self
+            .locals
+            .last()
+            .is_some_and(|local| local.depth.is_none_or(|depth| depth > self.scope_depth))
+        {
+            self.locals.pop();
+            self.emit(OpCode::Pop);
+        }
+    }
+
+    fn if_statement(&mut self) -> CompileResult<()> {
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'if'.")?;
+        self.expression()?;
+        self.consume(&TokenType::RightParen, "Expect ')' after if condition.")?;
+
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit(OpCode::Pop);
+        self.statement()?;
+        let else_jump = self.emit_jump(OpCode::Jump);
+
+        self.patch_jump(then_jump);
+        self.emit(OpCode::Pop);
+        if self.next_matches(&TokenType::Else) {
+            self.statement()?;
+        }
+        self.patch_jump(else_jump);
+        Ok(())
+    }
+
+    fn while_statement(&mut self) -> CompileResult<()> {
+        let loop_start = self.chunk.code.len();
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'while'.")?;
+        self.expression()?;
+        self.consume(&TokenType::RightParen, "Expect ')' after condition.")?;
+
+        let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit(OpCode::Pop);
+        let breaks = self.loop_body(loop_start)?;
+        self.emit_loop(loop_start);
+
+        self.patch_jump(exit_jump);
+        self.emit(OpCode::Pop);
+        for jump in breaks {
+            self.patch_jump(jump);
+        }
+        Ok(())
+    }
+
+    /// Compile a `for` loop. The increment comes before the body in the
+    /// source but runs after it, so the body jumps back to it
+    fn for_statement(&mut self) -> CompileResult<()> {
+        self.begin_scope();
+        let result = self.for_clauses_and_body();
+        self.end_scope();
+        result
+    }
+
+    fn for_clauses_and_body(&mut self) -> CompileResult<()> {
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'for'.")?;
+        if self.next_matches(&TokenType::SemiColon) {
+            // No initializer
+        } else if self.next_matches(&TokenType::Var) {
+            self.var_declaration()?;
+        } else {
+            self.expression()?;
+            self.consume(&TokenType::SemiColon, "Expect ';' after expression.")?;
+            self.emit(OpCode::Pop);
+        }
+
+        let mut loop_start = self.chunk.code.len();
+        let mut exit_jump = None;
+        if !self.next_matches(&TokenType::SemiColon) {
+            self.expression()?;
+            self.consume(&TokenType::SemiColon, "Expect ';' after loop condition.")?;
+            exit_jump = Some(self.emit_jump(OpCode::JumpIfFalse));
+            self.emit(OpCode::Pop);
+        }
+
+        if !self.next_matches(&TokenType::RightParen) {
+            let body_jump = self.emit_jump(OpCode::Jump);
+            let increment_start = self.chunk.code.len();
+            self.expression()?;
+            self.emit(OpCode::Pop);
+            self.consume(&TokenType::RightParen, "Expect ')' after for clauses.")?;
+
+            self.emit_loop(loop_start);
+            loop_start = increment_start;
+            self.patch_jump(body_jump);
+        }
+
+        let breaks = self.loop_body(loop_start)?;
+        self.emit_loop(loop_start);
+
+        if let Some(exit_jump) = exit_jump {
+            self.patch_jump(exit_jump);
+            self.emit(OpCode::Pop);
+        }
+        for jump in breaks {
+            self.patch_jump(jump);
+        }
+        Ok(())
+    }
+
+    /// Compile the body of a loop continuing at `start`, returning the
+    /// jumps of the `break`s in it, which land after the loop
+    fn loop_body(&mut self, start: usize) -> CompileResult<Vec<usize>> {
+        self.loops.push(Loop {
+            start,
+            depth: self.scope_depth,
+            breaks: Vec::new(),
+        });
+        let result = self.statement();
+        let body = self.loops.pop().expect("the loop should still be open");
+        result.map(|()| body.breaks)
+    }
+
+    fn break_statement(&mut self) -> CompileResult<()> {
+        let keyword = Rc::clone(&self.previous);
+        match self.loops.last() {
+            Some(innermost) => {
+                self.pop_locals(innermost.depth);
+                let jump = self.emit_jump(OpCode::Jump);
+                if let Some(innermost) = self.loops.last_mut() {
+                    innermost.breaks.push(jump);
+                }
+            }
+            None => {
+                self.error(&keyword, "Can't use 'break' outside of a loop.");
+            }
+        }
+        self.consume(&TokenType::SemiColon, "Expect ';' after 'break'.")
+    }
+
+    fn continue_statement(&mut self) -> CompileResult<()> {
+        let keyword = Rc::clone(&self.previous);
+        match self.loops.last() {
+            Some(innermost) => {
+                let start = innermost.start;
+                self.pop_locals(innermost.depth);
+                self.emit_loop(start);
+            }
+            None => {
+                self.error(&keyword, "Can't use 'continue' outside of a loop.");
+            }
+        }
+        self.consume(&TokenType::SemiColon, "Expect ';' after 'continue'.")
+    }
+
+    /// Pop the locals declared more than `depth` blocks deep off the stack,
+    /// without ending their scopes, before jumping out of them
+    fn pop_locals(&mut self, depth: usize) {
+        let count = self
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth.is_none_or(|local_depth| local_depth > depth))
+            .count();
+        for _ in 0..count {
+            self.emit(OpCode::Pop);
+        }
+    }
+
+    fn expression(&mut self) -> CompileResult<()> {
+        self.parse_precedence(Precedence::Comma)
+    }
+
+    /// Compile an expression made of operators that bind at least as
+    /// tightly as `min`, the same way `Parser::parse_precedence` parses it
+    fn parse_precedence(&mut self, min: Precedence) -> CompileResult<()> {
+        self.nested(|compiler| compiler.operators(min))
+    }
+
+    fn operators(&mut self, min: Precedence) -> CompileResult<()> {
+        self.advance();
+        let Some(prefix) = parse_rule(&self.previous.typ).and_then(|rule| rule.prefix) else {
+            let previous = Rc::clone(&self.previous);
+            return Err(self.error(&previous, "Expect expression."));
+        };
+        // Only a variable on its own can be assigned to, not one that is
+        // the operand of something tighter (like `-a = 1`)
+        let can_assign = min <= Precedence::Assignment;
+        self.prefix(prefix, can_assign)?;
+
+        while let Some((infix, precedence)) =
+            parse_rule(&self.current.typ).and_then(|rule| rule.infix)
+        {
+            if precedence < min {
+                break;
+            }
+            self.advance();
+            self.infix(infix, precedence)?;
+        }
+        Ok(())
+    }
+
+    fn prefix(&mut self, prefix: Prefix, can_assign: bool) -> CompileResult<()> {
+        let token = Rc::clone(&self.previous);
+        match prefix {
+            Prefix::Grouping => {
+                self.expression()?;
+                self.consume(&TokenType::RightParen, "Expect ')' after expression.")?;
+            }
+            Prefix::Literal => match &token.typ {
+                TokenType::False => self.emit(OpCode::False),
+                TokenType::True => self.emit(OpCode::True),
+                TokenType::Nil => self.emit(OpCode::Nil),
+                TokenType::Number(n) => self.emit_constant(Value::Number(*n)),
+                TokenType::String(s) => self.emit_constant(Value::String(s.as_str().into())),
+                typ => unreachable!("invalid literal {}", typ),
+            },
+            Prefix::Unary => {
+                self.parse_precedence(Precedence::Unary)?;
+                match token.typ {
+                    TokenType::Minus => self.emit_at(OpCode::Negate, &token),
+                    TokenType::Bang => self.emit_at(OpCode::Not, &token),
+                    _ => unreachable!("invalid unary operator {}", token),
+                }
+            }
+            Prefix::Variable => self.variable(&token, can_assign)?,
+            Prefix::Lambda | Prefix::List | Prefix::Map | Prefix::Super | Prefix::This => {
+                return Err(self.unsupported(&token))
+            }
+        }
+        Ok(())
+    }
+
+    fn infix(&mut self, infix: Infix, precedence: Precedence) -> CompileResult<()> {
+        let operator = Rc::clone(&self.previous);
+        match infix {
+            Infix::Assign => {
+                // A valid target would have been compiled by `variable`
+                self.error(&operator, "Invalid assignment target.");
+                self.parse_precedence(Precedence::Assignment)?;
+            }
+            Infix::Binary => {
+                self.parse_precedence(precedence.next())?;
+                self.emit_binary(&operator.typ, &operator);
+            }
+            Infix::Comma => {
+                // The left side is only evaluated for its effects
+                self.emit(OpCode::Pop);
+                self.parse_precedence(precedence.next())?;
+            }
+            Infix::Conditional => {
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.expression()?;
+                let end_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(else_jump);
+                self.emit(OpCode::Pop);
+                self.consume(
+                    &TokenType::Colon,
+                    "Expect ':' after then branch of conditional expression.",
+                )?;
+                self.parse_precedence(precedence)?;
+                self.patch_jump(end_jump);
+            }
+            Infix::Logical if operator.typ == TokenType::And => {
+                // The left side is the result if it is falsey
+                let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.parse_precedence(precedence.next())?;
+                self.patch_jump(end_jump);
+            }
+            Infix::Logical => {
+                // The left side is the result if it is truthy
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                let end_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(else_jump);
+                self.emit(OpCode::Pop);
+                self.parse_precedence(precedence.next())?;
+                self.patch_jump(end_jump);
+            }
+            Infix::Call | Infix::Get | Infix::Index | Infix::Pipe => {
+                return Err(self.unsupported(&operator))
+            }
+        }
+        Ok(())
+    }
+
+    /// Compile a use of the variable, or an assignment to it (including the
+    /// compound ones like `+=`)
+    fn variable(&mut self, name: &Token, can_assign: bool) -> CompileResult<()> {
+        let (get, set, operand) = match self.resolve_local(name) {
+            Some(slot) => (OpCode::GetLocal, OpCode::SetLocal, slot),
+            None => {
+                let constant = self.identifier_constant(name);
+                (OpCode::GetGlobal, OpCode::SetGlobal, constant)
+            }
+        };
+
+        let compound = match self.current.typ {
+            TokenType::PlusEqual => Some(TokenType::Plus),
+            TokenType::MinusEqual => Some(TokenType::Minus),
+            TokenType::StarEqual => Some(TokenType::Star),
+            TokenType::SlashEqual => Some(TokenType::Slash),
+            _ => None,
+        };
+        if can_assign && self.next_matches(&TokenType::Equal) {
+            self.parse_precedence(Precedence::Assignment)?;
+            self.emit_with_operand(set, operand);
+        } else if let Some(operator) = compound.filter(|_| can_assign) {
+            self.advance();
+            let equals = Rc::clone(&self.previous);
+            self.emit_with_operand(get, operand);
+            self.parse_precedence(Precedence::Assignment)?;
+            self.emit_binary(&operator, &equals);
+            self.emit_with_operand(set, operand);
+        } else {
+            self.emit_with_operand(get, operand);
+        }
+        Ok(())
+    }
+
+    /// Stack slot of the innermost local with the name, if there is one
+    fn resolve_local(&mut self, name: &Token) -> Option<u8> {
+        let slot = self
+            .locals
+            .iter()
+            .rposition(|local| local.name == name.lexeme.symbol())?;
+        if self.locals[slot].depth.is_none() {
+            self.error(name, "Can't read local variable in its own initializer.");
+        }
+        Some(slot as u8)
+    }
+
+    /// Add the name of a global to the constants, returning its index
+    fn identifier_constant(&mut self, name: &Token) -> u8 {
+        self.make_constant(Value::String(name.lexeme.as_str().into()))
+    }
+
+    fn make_constant(&mut self, value: Value) -> u8 {
+        let index = self.chunk.add_constant(value);
+        if index >= MAX_CONSTANTS {
+            let previous = Rc::clone(&self.previous);
+            self.error(&previous, "Too many constants in one chunk.");
+            return 0;
+        }
+        index as u8
+    }
+
+    fn emit(&mut self, op: OpCode) {
+        self.chunk.write(op as u8, &self.previous);
+    }
+
+    /// Emit an instruction whose errors point at `token` rather than at the
+    /// last token compiled, like an operator after its operands
+    fn emit_at(&mut self, op: OpCode, token: &Rc<Token>) {
+        self.chunk.write(op as u8, token);
+    }
+
+    fn emit_with_operand(&mut self, op: OpCode, operand: u8) {
+        self.emit(op);
+        self.chunk.write(operand, &self.previous);
+    }
+
+    fn emit_constant(&mut self, value: Value) {
+        let constant = self.make_constant(value);
+        self.emit_with_operand(OpCode::Constant, constant);
+    }
+
+    /// Emit the instructions of the binary operator `typ`, located at the
+    /// `operator` token
+    fn emit_binary(&mut self, typ: &TokenType, operator: &Rc<Token>) {
+        let opcodes: &[OpCode] = match typ {
+            TokenType::Plus => &[OpCode::Add],
+            TokenType::Minus => &[OpCode::Subtract],
+            TokenType::Star => &[OpCode::Multiply],
+            TokenType::Slash => &[OpCode::Divide],
+            TokenType::Percent => &[OpCode::Modulo],
+            TokenType::EqualEqual => &[OpCode::Equal],
+            TokenType::BangEqual => &[OpCode::Equal, OpCode::Not],
+            TokenType::Greater => &[OpCode::Greater],
+            // `a >= b` is `!(a < b)`, and `a <= b` is `!(a > b)`
+            TokenType::GreaterEqual => &[OpCode::Less, OpCode::Not],
+            TokenType::Less => &[OpCode::Less],
+            TokenType::LessEqual => &[OpCode::Greater, OpCode::Not],
+            _ => unreachable!("invalid binary operator {}", typ),
+        };
+        for &op in opcodes {
+            self.emit_at(op, operator);
+        }
+    }
+
+    /// Emit a jump with a placeholder offset, returning where the offset is
+    /// to patch it once the target is known
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit(op);
+        self.chunk.write(0xff, &self.previous);
+        self.chunk.write(0xff, &self.previous);
+        self.chunk.code.len() - 2
+    }
+
+    /// Make the jump with its offset at `offset` land on the next
+    /// instruction emitted
+    fn patch_jump(&mut self, offset: usize) {
+        let jump = self.chunk.code.len() - offset - 2;
+        let Ok(jump) = u16::try_from(jump) else {
+            let previous = Rc::clone(&self.previous);
+            self.error(&previous, "Too much code to jump over.");
+            return;
+        };
+        self.chunk.code[offset..offset + 2].copy_from_slice(&jump.to_be_bytes());
+    }
+
+    /// Emit a jump back to `loop_start`
+    fn emit_loop(&mut self, loop_start: usize) {
+        self.emit(OpCode::Loop);
+        let jump = self.chunk.code.len() - loop_start + 2;
+        let jump = u16::try_from(jump).unwrap_or_else(|_| {
+            let previous = Rc::clone(&self.previous);
+            self.error(&previous, "Loop body too large.");
+            0
+        });
+        let [high, low] = jump.to_be_bytes();
+        self.chunk.write(high, &self.previous);
+        self.chunk.write(low, &self.previous);
+    }
+
+    fn unsupported(&mut self, token: &Token) -> ParseError {
+        let message = format!(
+            "'{}' is not supported by the bytecode backend yet.",
+            token.lexeme
+        );
+        self.error(token, &message)
+    }
+
+    /// Record an error at the token, returning it to unwind with
+    fn error(&mut self, token: &Token, message: &str) -> ParseError {
+        let error = ParseError::Syntax {
+            token: token.clone(),
+            message: message.to_string(),
+        };
+        if !self.gave_up {
+            self.errors.push(error.clone());
+        }
+        error
+    }
+
+    /// Compile code nested a level deeper than the code being compiled, on
+    /// a new stack segment if the thread's stack is running out
+    fn nested(
+        &mut self,
+        compile: impl FnOnce(&mut Self) -> CompileResult<()>,
+    ) -> CompileResult<()> {
+        if self.nesting >= MAX_NESTING {
+            let current = Rc::clone(&self.current);
+            let error = self.error(&current, "Too much nesting.");
+            // There is no telling where the nesting ends, like in the parser
+            self.gave_up = true;
+            while !self.check(&TokenType::Eof) {
+                self.advance();
+            }
+            return Err(error);
+        }
+        self.nesting += 1;
+        let result = internal::grow_stack(|| compile(self));
+        self.nesting -= 1;
+        result
+    }
+
+    /// Discard tokens until we are (probably) at the start of the next
+    /// statement, so we can keep compiling after an error
+    fn synchronize(&mut self) {
+        self.advance();
+        while !self.check(&TokenType::Eof) {
+            if self.previous.typ == TokenType::SemiColon {
+                return;
+            }
+            match self.current.typ {
+                TokenType::Break
+                | TokenType::Class
+                | TokenType::Continue
+                | TokenType::Defer
+                | TokenType::Enum
+                | TokenType::Extend
+                | TokenType::Fun
+                | TokenType::Import
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return => return,
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    fn consume(&mut self, typ: &TokenType, message: &str) -> CompileResult<()> {
+        if self.check(typ) {
+            self.advance();
+            Ok(())
+        } else {
+            let current = Rc::clone(&self.current);
+            Err(self.error(&current, message))
+        }
+    }
+
+    fn next_matches(&mut self, typ: &TokenType) -> bool {
+        let matches = self.check(typ);
+        if matches {
+            self.advance();
+        }
+        matches
+    }
+
+    fn check(&self, typ: &TokenType) -> bool {
+        self.current.typ == *typ
+    }
+
+    fn advance(&mut self) {
+        if self.current.typ != TokenType::Eof {
+            let next = Rc::new(self.next_token());
+            self.previous = std::mem::replace(&mut self.current, next);
+        }
+    }
+
+    /// The next token from the scanner, recording the scan errors before it
+    fn next_token(&mut self) -> Token {
+        loop {
+            match self
+                .tokens
+                .next()
+                .expect("the tokens should end with an `Eof`")
+            {
+                Ok(token) => return token,
+                Err(err) => self.errors.push(ParseError::Scan(err)),
+            }
+        }
+    }
+}
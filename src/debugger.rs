@@ -0,0 +1,341 @@
+/// Interactive debugger (`rinlox debug`), stopping a program to inspect it
+///
+/// It is an `Observer` on the interpreter, so it sees every statement
+/// before it runs. It stops at the first one, and then wherever the
+/// commands read from its input say:
+///
+/// - `break N` (`b`) and `delete N` (`d`) set and clear a breakpoint on
+///   line `N`.
+/// - `step` (`s`) goes to the next line run, into calls too, and `next`
+///   (`n`) to the next one in the same call (or the caller, after the last
+///   line of a function).
+/// - `continue` (`c`) runs until the next breakpoint.
+/// - `locals` (`l`) prints the variables of each local scope, innermost
+///   first, and `backtrace` (`bt`) the calls being executed.
+///
+/// Statements made only of literals have no position in the tree, so the
+/// debugger can't stop at them. The end of the input lets the program run
+/// to the end. When a call fails, the calls leading to the error are
+/// printed.
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+
+use crate::interpreter::{Frame, Interpreter, RuntimeError, Value};
+use crate::lexer::Span;
+use crate::observer::Observer;
+use crate::stmt::Stmt;
+
+const HELP: &str = "\
+break N      stop at line N (b)
+delete N     remove the breakpoint at line N (d)
+step         run until the next line, into calls too (s)
+next         run until the next line in this call (n)
+continue     run until the next breakpoint (c)
+locals       show the local variables, innermost scope first (l)
+backtrace    show the calls being executed (bt)
+help         show this message (h)";
+
+/// When the debugger stops next
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    /// At the next line
+    Step,
+    /// At the next line run with at most this many calls on the stack
+    Next(usize),
+    /// At the next breakpoint
+    Continue,
+    /// Never, as there are no more commands
+    Detached,
+}
+
+pub struct Debugger {
+    lines: Vec<String>,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    breakpoints: BTreeSet<usize>,
+    mode: Mode,
+    /// Line of the last statement seen, and the depth of the call stack
+    /// then, so a line with several statements is only stopped at once
+    last: Option<(usize, usize)>,
+    /// The last error a backtrace was printed for, which fails every call
+    /// it unwinds through
+    failure: Option<(Span, Box<str>)>,
+}
+
+impl Debugger {
+    /// Debugger for a program with the given source, reading commands from
+    /// `input` and writing to `output`
+    pub fn new(source: &str, input: impl BufRead + 'static, output: impl Write + 'static) -> Self {
+        Self {
+            lines: source.lines().map(str::to_string).collect(),
+            input: Box::new(input),
+            output: Box::new(output),
+            breakpoints: BTreeSet::new(),
+            mode: Mode::Step,
+            last: None,
+            failure: None,
+        }
+    }
+
+    /// Write a line for the user. If that fails there is nobody to debug
+    /// the program any more, so it runs on detached
+    fn print(&mut self, text: &str) {
+        if writeln!(self.output, "{}", text).is_err() {
+            self.mode = Mode::Detached;
+        }
+    }
+
+    /// Read and run commands until one of them resumes the program
+    fn pause(&mut self, interpreter: &Interpreter, line: usize) {
+        let function = frame_name(interpreter.frames().last());
+        let source = self.lines.get(line - 1).cloned().unwrap_or_default();
+        self.print(&format!("Stopped at line {} in {}", line, function));
+        self.print(&format!("{:>4} | {}", line, source));
+
+        loop {
+            if write!(self.output, "(debug) ")
+                .and_then(|()| self.output.flush())
+                .is_err()
+            {
+                self.mode = Mode::Detached;
+                return;
+            }
+            let mut command = String::new();
+            match self.input.read_line(&mut command) {
+                Ok(0) | Err(_) => {
+                    self.mode = Mode::Detached;
+                    return;
+                }
+                Ok(_) => {}
+            }
+
+            let mut words = command.split_whitespace();
+            let name = words.next();
+            let line_argument = words.next().and_then(|line| line.parse::<usize>().ok());
+            let resume = match name {
+                Some("s" | "step") => Some(Mode::Step),
+                Some("n" | "next") => Some(Mode::Next(interpreter.frames().len())),
+                Some("c" | "continue") => Some(Mode::Continue),
+                _ => None,
+            };
+            if let Some(mode) = resume {
+                self.mode = mode;
+                return;
+            }
+
+            match (name, line_argument) {
+                (Some("b" | "break"), Some(line)) => {
+                    self.breakpoints.insert(line);
+                    self.print(&format!("Breakpoint at line {}", line));
+                }
+                (Some("d" | "delete"), Some(line)) => {
+                    if self.breakpoints.remove(&line) {
+                        self.print(&format!("Removed the breakpoint at line {}", line));
+                    } else {
+                        self.print(&format!("There is no breakpoint at line {}", line));
+                    }
+                }
+                (Some("l" | "locals"), _) => self.locals(interpreter),
+                (Some("bt" | "backtrace"), _) => self.backtrace(interpreter, line),
+                (Some("h" | "help"), _) => self.print(HELP),
+                (None, _) => {}
+                (Some(_), _) => self.print(&format!(
+                    "Unknown command '{}'. Type help to see the commands.",
+                    command.trim()
+                )),
+            }
+        }
+    }
+
+    fn locals(&mut self, interpreter: &Interpreter) {
+        let scopes = interpreter.locals();
+        if scopes.iter().all(Vec::is_empty) {
+            return self.print("No local variables");
+        }
+        for (depth, scope) in scopes.iter().enumerate() {
+            for (name, value) in scope {
+                let value = stringify(interpreter, value);
+                self.print(&format!("{}{} = {}", "  ".repeat(depth), name, value));
+            }
+        }
+    }
+
+    /// Print the calls being executed, innermost first, given the line the
+    /// innermost one is at
+    fn backtrace(&mut self, interpreter: &Interpreter, line: usize) {
+        let frames = interpreter.frames();
+        let mut line = line;
+        for (i, frame) in frames.iter().enumerate().rev() {
+            self.print(&format!(
+                "#{} {} at line {}",
+                frames.len() - i - 1,
+                frame.name,
+                line
+            ));
+            line = frame.call.span.line;
+        }
+        self.print(&format!(
+            "#{} {} at line {}",
+            frames.len(),
+            frame_name(None),
+            line
+        ));
+    }
+}
+
+impl Observer for Debugger {
+    fn statement(&mut self, interpreter: &Interpreter, stmt: &Stmt) {
+        // The statements in a block are stopped at on their own
+        if matches!(stmt, Stmt::Block { .. }) {
+            return;
+        }
+        let Some(token) = stmt.first_token() else {
+            return;
+        };
+        let position = (token.span.line, interpreter.frames().len());
+        let new_line = self.last != Some(position);
+        self.last = Some(position);
+
+        let stop = new_line
+            && match self.mode {
+                Mode::Step => true,
+                Mode::Next(depth) => position.1 <= depth,
+                Mode::Continue => self.breakpoints.contains(&position.0),
+                Mode::Detached => false,
+            };
+        if stop {
+            self.pause(interpreter, position.0);
+        }
+    }
+
+    fn returned(
+        &mut self,
+        interpreter: &Interpreter,
+        _frame: &Frame,
+        result: &Result<Value, RuntimeError>,
+    ) {
+        let Err(error) = result else {
+            return;
+        };
+        let failure = Some((error.token.span, error.message.clone()));
+        if self.failure != failure {
+            self.failure = failure;
+            self.print(&format!("Error: {}", error.message));
+            self.backtrace(interpreter, error.token.span.line);
+        }
+    }
+}
+
+/// Name to show for the call in a frame, or for the top level
+fn frame_name(frame: Option<&Frame>) -> &str {
+    frame.map_or("script", |frame| &frame.name)
+}
+
+/// Show strings quoted, so they are not confused with other values
+fn stringify(interpreter: &Interpreter, value: &Value) -> String {
+    match value {
+        Value::String(s) => crate::ast::quote_string(s),
+        value => interpreter.stringify(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::features::Features;
+
+    /// Writer keeping what is written to it
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const PROGRAM: &str = "\
+fun add(a, b) {
+  var sum = a + b;
+  return sum;
+}
+var s = \"x\";
+print add(1, 2);
+print add(3, 4);";
+
+    /// Run the program under the debugger with the given commands,
+    /// returning what the debugger printed
+    fn debug(source: &str, commands: &str) -> String {
+        let output = Buffer::default();
+        let mut interpreter = Interpreter::default();
+        interpreter.set_output(std::io::sink());
+        let input = Cursor::new(commands.to_string());
+        interpreter.set_observer(Debugger::new(source, input, output.clone()));
+        let _ = crate::run(&mut interpreter, source, Features::default());
+        let output = String::from_utf8(output.0.take()).unwrap();
+        output.replace("(debug) ", "")
+    }
+
+    #[test]
+    fn stops_at_breakpoints_and_shows_the_locals() {
+        let output = debug(PROGRAM, "b 2\nc\nl\nbt\nd 2\nc\n");
+        assert_eq!(
+            output,
+            "\
+Stopped at line 1 in script
+   1 | fun add(a, b) {
+Breakpoint at line 2
+Stopped at line 2 in add
+   2 |   var sum = a + b;
+a = 1
+b = 2
+#0 add at line 2
+#1 script at line 6
+Removed the breakpoint at line 2
+"
+        );
+    }
+
+    #[test]
+    fn steps_into_calls_or_over_them() {
+        let output = debug(PROGRAM, "n\nn\ns\nn\nn\nl\n");
+        let stops: Vec<_> = output
+            .lines()
+            .filter(|line| line.starts_with("Stopped"))
+            .collect();
+        assert_eq!(
+            stops,
+            [
+                "Stopped at line 1 in script",
+                "Stopped at line 5 in script",
+                "Stopped at line 6 in script",
+                "Stopped at line 2 in add",
+                "Stopped at line 3 in add",
+                "Stopped at line 7 in script",
+            ]
+        );
+        assert!(output.ends_with("No local variables\n"));
+    }
+
+    #[test]
+    fn prints_the_calls_leading_to_an_error() {
+        let source = "fun f(x) {\n  return x + 1;\n}\nfun g() {\n  return f(nil);\n}\ng();";
+        let output = debug(source, "c\n");
+        assert!(output.ends_with(
+            "\
+Error: Operands must be two numbers or two strings.
+#0 f at line 2
+#1 g at line 5
+#2 script at line 7
+"
+        ));
+    }
+}
@@ -0,0 +1,243 @@
+/// Rendering of errors along with the source code they point at
+///
+/// ```text
+/// [line 2, col 11] Error at '+': Operands must be two numbers or two strings.
+///   |
+/// 2 |   print a + "x";
+///   |           ^
+/// ```
+use std::fmt::{Display, Write};
+
+use crate::lexer::{Origin, Span, Token, TokenType};
+use crate::symbol::Symbol;
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const CYAN: &str = "\x1b[1;36m";
+const RESET: &str = "\x1b[0m";
+
+/// Secondary location attached to an error, e.g. a previous declaration
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Note {
+    pub fn new(span: Span, message: &str) -> Self {
+        Self {
+            span,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// How bad a problem is: errors stop the program from running, warnings
+/// (from the linter) only point at code that is most likely a mistake, and
+/// hints at code that was accepted anyway (like a missing `;` with
+/// automatic semicolons)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Hint,
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Hint => write!(f, "Hint"),
+            Severity::Warning => write!(f, "Warning"),
+            Severity::Error => write!(f, "Error"),
+        }
+    }
+}
+
+/// How many diagnostics of each severity some code got
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub errors: usize,
+    pub warnings: usize,
+    pub hints: usize,
+}
+
+impl Summary {
+    /// Count `count` more diagnostics of the `severity`
+    pub fn add(&mut self, severity: Severity, count: usize) {
+        match severity {
+            Severity::Hint => self.hints += count,
+            Severity::Warning => self.warnings += count,
+            Severity::Error => self.errors += count,
+        }
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let counts = [
+            (self.errors, "error"),
+            (self.warnings, "warning"),
+            (self.hints, "hint"),
+        ];
+        let counts: Vec<_> = counts
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, name)| {
+                format!("{} {}{}", count, name, if *count == 1 { "" } else { "s" })
+            })
+            .collect();
+        if counts.is_empty() {
+            write!(f, "no diagnostics")
+        } else {
+            write!(f, "{}", counts.join(", "))
+        }
+    }
+}
+
+/// First line of an error found at `token`, like `[line 1, col 5] Error at
+/// 'x': message`
+pub fn error_header(token: &Token, message: &str) -> String {
+    header(Severity::Error, token, message)
+}
+
+/// First line of a problem found at `token`, like `[line 1, col 5] Warning
+/// at 'x': message`
+pub fn header(severity: Severity, token: &Token, message: &str) -> String {
+    let loc_str = if token.typ == TokenType::Eof {
+        " at end".to_string()
+    } else {
+        format!(" at '{}'", token.lexeme)
+    };
+    format!("[{}] {}{}: {}", token.span, severity, loc_str, message)
+}
+
+/// Keeps the source code being run, to show it in error messages
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    source: String,
+    /// Logical file the source is in, if it was given an `Origin`
+    file: Option<Symbol>,
+    /// Lines of the logical file before the source
+    line_offset: usize,
+    /// Whether to highlight the output with ANSI colors
+    color: bool,
+}
+
+impl Diagnostics {
+    pub fn new(color: bool) -> Self {
+        Self {
+            source: String::new(),
+            file: None,
+            line_offset: 0,
+            color,
+        }
+    }
+
+    pub fn set_source(&mut self, source: &str) {
+        self.source = source.to_string();
+        self.file = None;
+        self.line_offset = 0;
+    }
+
+    /// Show the source, which was scanned with the `origin`, for the errors
+    /// from now on
+    pub fn set_source_at(&mut self, source: &str, origin: &Origin) {
+        self.source = source.to_string();
+        self.file = Some(Symbol::intern(&origin.name));
+        self.line_offset = origin.line_offset;
+    }
+
+    pub fn set_color(&mut self, color: bool) {
+        self.color = color;
+    }
+
+    /// Format an error: the `header` with the message, the source line the
+    /// `span` is on with the span underlined, and then each of the notes
+    pub fn render(&self, header: &str, span: Span, notes: &[Note]) -> String {
+        self.render_as(Severity::Error, header, span, notes)
+    }
+
+    /// Format a problem like `render`, in the color of its severity
+    pub fn render_as(
+        &self,
+        severity: Severity,
+        header: &str,
+        span: Span,
+        notes: &[Note],
+    ) -> String {
+        let color = match severity {
+            Severity::Hint => CYAN,
+            Severity::Warning => YELLOW,
+            Severity::Error => RED,
+        };
+        let lines: Vec<usize> = std::iter::once(span.line)
+            .chain(notes.iter().map(|note| note.span.line))
+            .collect();
+        let width = lines.iter().max().unwrap_or(&0).to_string().len();
+
+        let mut out = String::new();
+        writeln!(out, "{}", self.paint(color, header)).unwrap();
+        self.write_snippet(&mut out, width, span, color, "");
+        for note in notes {
+            let label = format!("note: {}", note.message);
+            self.write_snippet(&mut out, width, note.span, CYAN, &label);
+        }
+        // Drop the last newline, since the caller prints it
+        out.pop();
+        out
+    }
+
+    /// Write the line the span starts on, underlined with `^` and followed
+    /// by the label. Nothing is written if the span is not in the source
+    /// (e.g. it is in a module the source imports)
+    fn write_snippet(&self, out: &mut String, width: usize, span: Span, color: &str, label: &str) {
+        if span.file != self.file {
+            return;
+        }
+        // NOTE(alvaro): Not using `lines()`, since an error at the end of a
+        // source ending in a newline is on the (empty) line after it
+        let index = span.line.wrapping_sub(self.line_offset + 1);
+        let Some(line) = self.source.split('\n').nth(index) else {
+            return;
+        };
+        let line = line.strip_suffix('\r').unwrap_or(line);
+
+        // Keep the tabs so the underline lines up with the source
+        let padding: String = line
+            .chars()
+            .take(span.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        // Underline up to the end of the span, or of the line if the span
+        // goes on to the next ones (at least one `^`, even at the end)
+        let available = line.chars().count().saturating_sub(padding.chars().count());
+        let length = self
+            .source
+            .get(span.start..span.end)
+            .map(|text| text.chars().count())
+            .unwrap_or(0)
+            .min(available)
+            .max(1);
+
+        let gutter = self.paint(BLUE, &format!("{:width$} |", ""));
+        writeln!(out, "{}", gutter).unwrap();
+        let number = self.paint(BLUE, &format!("{:>width$} |", span.line));
+        writeln!(out, "{} {}", number, line).unwrap();
+        let underline = self.paint(color, &"^".repeat(length));
+        let label = if label.is_empty() {
+            String::new()
+        } else {
+            format!(" {}", self.paint(color, label))
+        };
+        writeln!(out, "{} {}{}{}", gutter, padding, underline, label).unwrap();
+    }
+
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
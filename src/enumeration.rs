@@ -0,0 +1,52 @@
+/// Enums for the `Lox` interpreter (behind the `lox-enum` feature)
+use std::fmt::Display;
+use std::rc::Rc;
+
+use crate::interpreter::{RuntimeError, Value};
+use crate::lexer::Token;
+use crate::symbol::Symbol;
+
+/// A namespace of unique constants, declared with `enum Name { A, B }`.
+/// Each variant is only equal to itself, and is read as `Name.A`
+#[derive(Debug)]
+pub struct LoxEnum {
+    pub name: String,
+    variants: Vec<Symbol>,
+}
+
+impl LoxEnum {
+    pub fn new(name: &str, variants: &[Token]) -> Self {
+        Self {
+            name: name.to_string(),
+            variants: variants
+                .iter()
+                .map(|variant| variant.lexeme.symbol())
+                .collect(),
+        }
+    }
+
+    /// Look up one of the variants by name
+    pub fn get(self: &Rc<Self>, name: &Token) -> Result<Value, RuntimeError> {
+        match self
+            .variants
+            .iter()
+            .position(|variant| *variant == name.lexeme.symbol())
+        {
+            Some(index) => Ok(Value::Variant(Rc::clone(self), index)),
+            None => Err(RuntimeError::new(
+                name,
+                &format!("Undefined variant '{}'.", name.lexeme),
+            )),
+        }
+    }
+
+    pub fn variant_name(&self, index: usize) -> &str {
+        self.variants[index].as_str()
+    }
+}
+
+impl Display for LoxEnum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
@@ -0,0 +1,282 @@
+/// Variable bindings for the `Lox` interpreter
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::gc::{self, Trace};
+use crate::interpreter::{RuntimeError, Value};
+use crate::lexer::Token;
+use crate::resolver::GlobalSlots;
+use crate::symbol::Symbol;
+
+/// A scope holding variable bindings, linked to the scope that encloses it
+///
+/// Lookups and assignments walk the chain of enclosing scopes outwards until
+/// the variable is found, so inner scopes can shadow outer ones
+#[derive(Debug, Default)]
+pub struct Environment {
+    values: Values,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+/// How a scope keeps the values of its variables
+#[derive(Debug)]
+enum Values {
+    ByName(HashMap<Symbol, Value>),
+    /// In the slots the resolver gave the globals of a program, which the
+    /// variables resolved to them are read and assigned at directly
+    BySlot {
+        slots: GlobalSlots,
+        values: Vec<Option<Value>>,
+    },
+}
+
+impl Default for Values {
+    fn default() -> Self {
+        Values::ByName(HashMap::new())
+    }
+}
+
+impl Environment {
+    pub fn new(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            values: Values::default(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    /// Scope for the globals of a program, keeping them in their `slots`
+    pub fn with_slots(enclosing: Rc<RefCell<Environment>>, slots: GlobalSlots) -> Self {
+        Self {
+            values: Values::BySlot {
+                slots,
+                values: Vec::new(),
+            },
+            enclosing: Some(enclosing),
+        }
+    }
+
+    /// Bind a new variable in this scope. Redefining an existing variable
+    /// is allowed and simply overwrites it
+    pub fn define(&mut self, name: Symbol, value: Value) {
+        match &mut self.values {
+            Values::ByName(values) => {
+                values.insert(name, value);
+            }
+            Values::BySlot { slots, values } => {
+                let slot = slots.slot(name);
+                if slot >= values.len() {
+                    values.resize(slot + 1, None);
+                }
+                values[slot] = Some(value);
+            }
+        }
+    }
+
+    /// The variables bound in this scope (not the enclosing ones), sorted
+    /// by name
+    pub fn bindings(&self) -> Vec<(String, Value)> {
+        let mut bindings: Vec<_> = match &self.values {
+            Values::ByName(values) => values
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+            Values::BySlot { slots, values } => slots
+                .entries()
+                .into_iter()
+                .filter_map(|(name, slot)| {
+                    let value = values.get(slot)?.clone()?;
+                    Some((name.to_string(), value))
+                })
+                .collect(),
+        };
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        bindings
+    }
+
+    /// The scope this one is nested in, if it is not the outermost one
+    pub fn enclosing(&self) -> Option<Rc<RefCell<Environment>>> {
+        self.enclosing.clone()
+    }
+
+    /// Drop a variable bound in this scope, if it is there
+    pub fn remove(&mut self, name: Symbol) {
+        match &mut self.values {
+            Values::ByName(values) => {
+                values.remove(&name);
+            }
+            // The slot stays the global's, for the code resolved to it
+            Values::BySlot { slots, values } => {
+                if let Some(value) = slots.get(name).and_then(|slot| values.get_mut(slot)) {
+                    *value = None;
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Value, RuntimeError> {
+        self.lookup(name.lexeme.symbol())
+            .ok_or_else(|| undefined_variable(name))
+    }
+
+    /// Find the value of a variable by name, if it is defined
+    pub fn lookup(&self, name: Symbol) -> Option<Value> {
+        if let Some(value) = self.value(name) {
+            return Some(value.clone());
+        }
+
+        self.enclosing
+            .as_ref()
+            .and_then(|enclosing| enclosing.borrow().lookup(name))
+    }
+
+    /// Get the global in `slot` of this scope, looking it up by name in the
+    /// enclosing ones if it is not there (like the natives)
+    pub fn get_slot(&self, slot: usize, name: &Token) -> Result<Value, RuntimeError> {
+        match &self.values {
+            Values::BySlot { values, .. } => match values.get(slot) {
+                Some(Some(value)) => Ok(value.clone()),
+                _ => self
+                    .enclosing
+                    .as_ref()
+                    .and_then(|enclosing| enclosing.borrow().lookup(name.lexeme.symbol()))
+                    .ok_or_else(|| undefined_variable(name)),
+            },
+            // Code resolved with slots can run where there are none, like
+            // in a module
+            Values::ByName(_) => self.get(name),
+        }
+    }
+
+    /// Get a variable from the scope `distance` levels out from this one,
+    /// where the resolver found it to be declared
+    pub fn get_at(&self, distance: usize, name: &Token) -> Result<Value, RuntimeError> {
+        self.lookup_at(distance, name.lexeme.symbol())
+            .ok_or_else(|| undefined_variable(name))
+    }
+
+    /// Find the value of a variable by name in the scope `distance` levels
+    /// out from this one, if it is defined there
+    pub fn lookup_at(&self, distance: usize, name: Symbol) -> Option<Value> {
+        if distance == 0 {
+            return self.value(name).cloned();
+        }
+
+        self.enclosing
+            .as_ref()
+            .expect("resolved scope should exist")
+            .borrow()
+            .lookup_at(distance - 1, name)
+    }
+
+    /// Assign to a variable in the scope `distance` levels out from this one,
+    /// where the resolver found it to be declared
+    pub fn assign_at(
+        &mut self,
+        distance: usize,
+        name: &Token,
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        if distance == 0 {
+            return match self.value_mut(name.lexeme.symbol()) {
+                Some(slot) => {
+                    *slot = value;
+                    Ok(())
+                }
+                None => Err(undefined_variable(name)),
+            };
+        }
+
+        self.enclosing
+            .as_ref()
+            .expect("resolved scope should exist")
+            .borrow_mut()
+            .assign_at(distance - 1, name, value)
+    }
+
+    pub fn assign(&mut self, name: &Token, value: Value) -> Result<(), RuntimeError> {
+        if let Some(slot) = self.value_mut(name.lexeme.symbol()) {
+            *slot = value;
+            return Ok(());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+            None => Err(undefined_variable(name)),
+        }
+    }
+
+    /// Assign to the global in `slot` of this scope, or by name in the
+    /// enclosing ones if it is not there
+    pub fn assign_slot(
+        &mut self,
+        slot: usize,
+        name: &Token,
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        match &mut self.values {
+            Values::BySlot { values, .. } => match values.get_mut(slot) {
+                Some(Some(global)) => {
+                    *global = value;
+                    Ok(())
+                }
+                _ => match &self.enclosing {
+                    Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+                    None => Err(undefined_variable(name)),
+                },
+            },
+            Values::ByName(_) => self.assign(name, value),
+        }
+    }
+
+    /// The value of a variable bound in this scope
+    fn value(&self, name: Symbol) -> Option<&Value> {
+        match &self.values {
+            Values::ByName(values) => values.get(&name),
+            Values::BySlot { slots, values } => values.get(slots.get(name)?)?.as_ref(),
+        }
+    }
+
+    fn value_mut(&mut self, name: Symbol) -> Option<&mut Value> {
+        match &mut self.values {
+            Values::ByName(values) => values.get_mut(&name),
+            Values::BySlot { slots, values } => values.get_mut(slots.get(name)?)?.as_mut(),
+        }
+    }
+}
+
+impl Trace for RefCell<Environment> {
+    fn trace(&self, references: &mut Vec<usize>) -> bool {
+        let Ok(environment) = self.try_borrow() else {
+            return false;
+        };
+        match &environment.values {
+            Values::ByName(values) => {
+                for value in values.values() {
+                    gc::trace_value(value, references);
+                }
+            }
+            Values::BySlot { values, .. } => {
+                for value in values.iter().flatten() {
+                    gc::trace_value(value, references);
+                }
+            }
+        }
+        references.extend(environment.enclosing.as_ref().map(gc::address));
+        true
+    }
+
+    fn clear(&self) {
+        if let Ok(mut environment) = self.try_borrow_mut() {
+            match &mut environment.values {
+                Values::ByName(values) => values.clear(),
+                Values::BySlot { values, .. } => values.clear(),
+            }
+            environment.enclosing = None;
+        }
+    }
+}
+
+fn undefined_variable(name: &Token) -> RuntimeError {
+    RuntimeError::new(name, &format!("Undefined variable '{}'.", name.lexeme))
+}
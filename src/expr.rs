@@ -1,24 +1,73 @@
-/// AST Definition types (autogenerated by `generate-ast`)
+/// AST definition for expressions
+use std::cell::Cell;
+use std::rc::Rc;
+
 use crate::lexer::Token;
+use crate::stmt::Stmt;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(untagged))]
+pub enum Object {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+}
 
-#[derive(Debug, Copy, Clone)]
-pub struct Object {}
+/// Number of scopes between a variable use and its declaration, filled
+/// in by the resolver (`None` means it is a global)
+pub type Depth = Cell<Option<usize>>;
+
+/// Where a global is kept among the globals of the program (see
+/// `resolver::GlobalSlots`), filled in by the resolver when it is given
+/// their slots
+pub type Slot = Cell<Option<usize>>;
+
+define_ast! {
+    pub enum Expr: ExprVisitor {
+        Assign(assign, visit_assign_expr) { name: Token, value: Box<Expr>; depth: Depth, slot: Slot },
+        Binary(binary, visit_binary_expr) { left: Box<Expr>, operator: Token, right: Box<Expr> },
+        Call(call, visit_call_expr) { callee: Box<Expr>, paren: Token, arguments: Vec<Expr> },
+        Conditional(conditional, visit_conditional_expr) { condition: Box<Expr>, then_branch: Box<Expr>, else_branch: Box<Expr> },
+        Get(get, visit_get_expr) { object: Box<Expr>, name: Token },
+        Grouping(grouping, visit_grouping_expr) { expression: Box<Expr> },
+        Index(index, visit_index_expr) { object: Box<Expr>, bracket: Token, index: Box<Expr> },
+        Lambda(lambda, visit_lambda_expr) { keyword: Token, params: Vec<Token>, body: Rc<Vec<Stmt>> },
+        List(list, visit_list_expr) { bracket: Token, elements: Vec<Expr> },
+        Literal(literal, visit_literal_expr) { value: Object },
+        Logical(logical, visit_logical_expr) { left: Box<Expr>, operator: Token, right: Box<Expr> },
+        Map(map, visit_map_expr) { brace: Token, entries: Vec<(Expr, Expr)> },
+        // The `operator` of a compound assignment like `a.b += c` combines
+        // the property (or element) with the value, so `a` is evaluated once
+        Set(set, visit_set_expr) { object: Box<Expr>, name: Token, operator: Option<Token>, value: Box<Expr> },
+        SetIndex(set_index, visit_set_index_expr) { object: Box<Expr>, bracket: Token, index: Box<Expr>, operator: Option<Token>, value: Box<Expr> },
+        Super(super_method, visit_super_expr) { keyword: Token, method: Token; depth: Depth },
+        This(this, visit_this_expr) { keyword: Token; depth: Depth },
+        Unary(unary, visit_unary_expr) { operator: Token, right: Box<Expr> },
+        Variable(variable, visit_variable_expr) { name: Token; depth: Depth, slot: Slot },
+    }
+}
 
-#[derive(Debug, Clone)]
-pub enum Expr<'a> {
-    Binary {
-        left: &'a Expr<'a>,
-        operator: Token,
-        right: &'a Expr<'a>,
-    },
-    Grouping {
-        expression: &'a Expr<'a>,
-    },
-    Literal {
-        value: Object,
-    },
-    Unary {
-        operator: Token,
-        right: &'a Expr<'a>,
-    },
+impl Expr {
+    /// The leftmost token of the expression that is in the tree, if any
+    pub fn first_token(&self) -> Option<&Token> {
+        match self {
+            Expr::Assign { name, .. } | Expr::Variable { name, .. } => Some(name),
+            Expr::Binary { left, .. } | Expr::Logical { left, .. } => left.first_token(),
+            Expr::Call { callee, .. } => callee.first_token(),
+            Expr::Conditional { condition, .. } => condition.first_token(),
+            Expr::Get { object, .. }
+            | Expr::Index { object, .. }
+            | Expr::Set { object, .. }
+            | Expr::SetIndex { object, .. } => object.first_token(),
+            Expr::Grouping { expression } => expression.first_token(),
+            Expr::List { bracket, .. } => Some(bracket),
+            Expr::Map { brace, .. } => Some(brace),
+            Expr::Lambda { keyword, .. }
+            | Expr::Super { keyword, .. }
+            | Expr::This { keyword, .. } => Some(keyword),
+            Expr::Unary { operator, .. } => Some(operator),
+            Expr::Literal { .. } => None,
+        }
+    }
 }
@@ -0,0 +1,144 @@
+/// Experimental parts of the language, behind feature gates
+///
+/// Code using a feature has to enable its gate first, either from the
+/// command line (`--features=lox-enum,lox-defer`, or `--extended` for all
+/// of them) or with a comment in the file itself:
+///
+/// ```lox
+/// // rinlox: features=lox-enum
+/// ```
+///
+/// The keywords a feature introduces are only reserved while it is enabled,
+/// so programs using them as regular identifiers keep working by default.
+/// Operators of a feature are rejected while it is disabled.
+use std::fmt::Display;
+
+use crate::lexer::TokenType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    /// `defer { ... }` statements
+    Defer,
+    /// `enum` declarations
+    Enum,
+    /// `extend` declarations adding methods to existing classes
+    Extend,
+    /// The `|>` operator, calling its right side with its left one
+    Pipe,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 4] = [
+        Feature::Defer,
+        Feature::Enum,
+        Feature::Extend,
+        Feature::Pipe,
+    ];
+
+    /// Name of the gate enabling the feature
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::Defer => "lox-defer",
+            Feature::Enum => "lox-enum",
+            Feature::Extend => "lox-extend",
+            Feature::Pipe => "lox-pipe",
+        }
+    }
+
+    /// Message for `lexeme` being used without enabling the feature
+    pub fn gate_message(self, lexeme: &str) -> String {
+        format!(
+            "'{}' is behind the experimental feature '{}'. Enable it with \
+             '--features={}' or a '// rinlox: features={}' comment.",
+            lexeme, self, self, self
+        )
+    }
+
+    pub fn from_name(name: &str) -> Option<Feature> {
+        Feature::ALL
+            .into_iter()
+            .find(|feature| feature.name() == name)
+    }
+
+    /// Feature introducing the keyword, if it is behind a gate
+    pub fn of_keyword(typ: &TokenType) -> Option<Feature> {
+        match typ {
+            TokenType::Defer => Some(Feature::Defer),
+            TokenType::Enum => Some(Feature::Enum),
+            TokenType::Extend => Some(Feature::Extend),
+            _ => None,
+        }
+    }
+
+    /// Feature introducing the operator, if it is behind a gate
+    pub fn of_operator(typ: &TokenType) -> Option<Feature> {
+        match typ {
+            TokenType::Pipe => Some(Feature::Pipe),
+            _ => None,
+        }
+    }
+
+    /// Feature introducing the keyword spelled `lexeme`, if it is behind a
+    /// gate
+    pub fn of_lexeme(lexeme: &str) -> Option<Feature> {
+        match lexeme {
+            "defer" => Some(Feature::Defer),
+            "enum" => Some(Feature::Enum),
+            "extend" => Some(Feature::Extend),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Feature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Set of enabled features
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Features(u8);
+
+impl Features {
+    pub fn all() -> Self {
+        let mut features = Features::default();
+        for feature in Feature::ALL {
+            features.enable(feature);
+        }
+        features
+    }
+
+    pub fn enable(&mut self, feature: Feature) {
+        self.0 |= 1 << feature as u8;
+    }
+
+    pub fn is_enabled(self, feature: Feature) -> bool {
+        self.0 & (1 << feature as u8) != 0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Enable the gates in a comma separated list of names, like
+    /// `lox-enum,lox-defer`
+    pub fn enable_list(&mut self, list: &str) -> Result<(), String> {
+        for name in list.split(',').map(str::trim) {
+            let feature =
+                Feature::from_name(name).ok_or_else(|| format!("Unknown feature '{}'.", name))?;
+            self.enable(feature);
+        }
+        Ok(())
+    }
+}
+
+/// The list of features in a `// rinlox: features=...` directive, given
+/// the text of the comment after the slashes
+pub fn directive(comment: &str) -> Option<&str> {
+    comment
+        .trim()
+        .strip_prefix("rinlox:")?
+        .trim()
+        .strip_prefix("features=")
+}
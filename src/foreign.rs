@@ -0,0 +1,151 @@
+/// Values of the program embedding the interpreter, which Lox code can hold
+/// and call methods on
+///
+/// A Rust type implementing `LoxObject` becomes a Lox value with
+/// `Value::Foreign(Rc::new(object))`, e.g. to bind it as a global with
+/// `Interpreter::set_global`. Lox code can't see inside it: it can only pass
+/// it around, compare it (it is only equal to itself) and call the methods
+/// it has, like `counter.add(2)`. A native getting one back as an argument
+/// finds the Rust value with `downcast_ref`.
+use std::any::Any;
+use std::fmt::{Debug, Display};
+use std::rc::Rc;
+
+use crate::interpreter::{Interpreter, RuntimeError, Value};
+use crate::lexer::Token;
+use crate::native::NativeFunction;
+
+/// A Rust value exposed to Lox code, with named methods
+///
+/// Lox values are shared, so the methods take `&self`: an object that
+/// changes keeps its state behind a `Cell` or a `RefCell`.
+pub trait LoxObject: Any + Debug + Display {
+    /// Name of the type, which `type(object)` returns
+    fn type_name(&self) -> &str;
+
+    /// How many arguments the method `name` takes, or `None` if the object
+    /// has no method with that name
+    fn arity(&self, name: &str) -> Option<usize>;
+
+    /// Call the method `name`, which `arity` said the object has, with as
+    /// many arguments as it takes. `paren` is the closing parenthesis of the
+    /// call, to point errors at
+    fn call_method(
+        &self,
+        interpreter: &mut Interpreter,
+        name: &str,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError>;
+}
+
+impl dyn LoxObject {
+    /// The object as the Rust type it is, if it is a `T`
+    pub fn downcast_ref<T: LoxObject>(&self) -> Option<&T> {
+        (self as &dyn Any).downcast_ref()
+    }
+}
+
+/// The method `name` of the object, bound to it
+pub(crate) fn method(object: &Rc<dyn LoxObject>, name: &Token) -> Result<Value, RuntimeError> {
+    let Some(arity) = object.arity(&name.lexeme) else {
+        return Err(RuntimeError::new(
+            name,
+            &format!("Undefined property '{}'.", name.lexeme),
+        ));
+    };
+    let object = Rc::clone(object);
+    let method = name.lexeme.symbol();
+    let method = NativeFunction::new(
+        &name.lexeme,
+        arity,
+        Box::new(move |interpreter, paren, arguments| {
+            object.call_method(interpreter, &method, paren, arguments)
+        }),
+    );
+    Ok(Value::Callable(Rc::new(method)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::{run, Error, Features};
+
+    #[derive(Debug, Default)]
+    struct Counter {
+        count: Cell<f64>,
+    }
+
+    impl Display for Counter {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "<counter at {}>", self.count.get())
+        }
+    }
+
+    impl LoxObject for Counter {
+        fn type_name(&self) -> &str {
+            "counter"
+        }
+
+        fn arity(&self, name: &str) -> Option<usize> {
+            match name {
+                "add" => Some(1),
+                "count" => Some(0),
+                _ => None,
+            }
+        }
+
+        fn call_method(
+            &self,
+            _interpreter: &mut Interpreter,
+            name: &str,
+            paren: &Token,
+            arguments: Vec<Value>,
+        ) -> Result<Value, RuntimeError> {
+            match (name, arguments.as_slice()) {
+                ("add", [Value::Number(n)]) => {
+                    self.count.set(self.count.get() + n);
+                    Ok(Value::Nil)
+                }
+                ("add", _) => Err(RuntimeError::new(paren, "Can only add numbers.")),
+                _ => Ok(Value::Number(self.count.get())),
+            }
+        }
+    }
+
+    #[test]
+    fn lox_code_calls_the_methods_of_foreign_objects() {
+        let counter: Rc<dyn LoxObject> = Rc::new(Counter::default());
+        let mut interpreter = Interpreter::default();
+        interpreter.set_global("counter", Value::Foreign(Rc::clone(&counter)));
+        let source = "var add = counter.add; add(2); counter.add(3); counter.count();";
+        let count = run(&mut interpreter, source, Features::default()).unwrap();
+        assert_eq!(count, Some(Value::Number(5.0)));
+        assert_eq!(counter.downcast_ref::<Counter>().unwrap().count.get(), 5.0);
+
+        let mut value = |source| run(&mut interpreter, source, Features::default()).unwrap();
+        assert_eq!(
+            value("type(counter);"),
+            Some(Value::String("counter".into()))
+        );
+        assert_eq!(
+            value("str(counter);"),
+            Some(Value::String("<counter at 5>".into()))
+        );
+        assert_eq!(value("counter == counter;"), Some(Value::Boolean(true)));
+
+        for (source, message) in [
+            ("counter.reset();", "Undefined property 'reset'."),
+            ("counter.add(\"a\");", "Can only add numbers."),
+            ("counter.count = 1;", "Only instances have fields."),
+        ] {
+            let Err(Error::Runtime(error)) = run(&mut interpreter, source, Features::default())
+            else {
+                panic!("{} should fail", source);
+            };
+            assert_eq!(&*error.message, message);
+        }
+    }
+}
@@ -0,0 +1,634 @@
+/// Formatter for Lox source code (`rinlox fmt`)
+///
+/// The program is parsed first, so only valid code is formatted, but the
+/// formatting itself works on the tokens, comments included. The program
+/// keeps the exact tokens it had (a `for` loop is not printed as the
+/// `while` loop it desugars to, nor a pipe as a call), and only the
+/// whitespace between them changes:
+///
+/// - Each statement goes on its own line, indented by four spaces for each
+///   block it is in.
+/// - Binary operators get a space on each side, and unary ones none.
+/// - Map literals stay on the line of the expression they are in, like
+///   lists.
+/// - Comments stay where they were: at the end of the line of code before
+///   them, or on a line of their own. The ones at the end of consecutive
+///   lines are aligned.
+/// - A blank line is kept where the source had one or more.
+/// - Lines longer than `MAX_WIDTH` are broken after commas or before
+///   `and`, `or` and `|>`, with the rest indented one more level.
+///
+/// Formatting is idempotent: formatting the output again changes nothing.
+use crate::features::Features;
+use crate::lexer::{Scanner, Token, TokenType};
+use crate::parser::{ParseError, Parser};
+
+/// Width the lines are kept within, where they can be broken
+pub const MAX_WIDTH: usize = 100;
+
+const INDENT: &str = "    ";
+
+/// Format the program, or return its syntax errors
+pub fn format(source: &str, features: Features) -> Result<String, Vec<ParseError>> {
+    let scanner = || Scanner::new(source.to_string()).with_features(features);
+    Parser::new(scanner()).parse()?;
+    let tokens: Vec<_> = scanner()
+        .with_comments()
+        .map(|token| token.expect("the source scanned when it was parsed"))
+        .collect();
+
+    let mut formatter = Formatter::default();
+    // The scanner skips a `#!` line, so it is copied as is
+    if let Some(shebang) = source.lines().next().filter(|line| line.starts_with("#!")) {
+        formatter.lines.push(Line {
+            code: shebang.to_string(),
+            comment: None,
+        });
+    }
+    formatter.format(&tokens);
+    Ok(render(&formatter.lines))
+}
+
+/// Line of output, with the comment at its end apart so it can be aligned
+/// with the ones around it
+#[derive(Debug, Default)]
+struct Line {
+    code: String,
+    comment: Option<String>,
+}
+
+/// Join the lines, aligning the comments at the end of consecutive lines
+/// one space after the longest of their code
+fn render(lines: &[Line]) -> String {
+    let mut out = String::new();
+    let mut rest = lines;
+    while let Some(line) = rest.first() {
+        let commented = |line: &Line| line.comment.is_some() && !line.code.is_empty();
+        let group = if commented(line) {
+            rest.iter().take_while(|line| commented(line)).count()
+        } else {
+            1
+        };
+        let column = rest[..group]
+            .iter()
+            .map(|line| line.code.chars().count())
+            .max()
+            .unwrap_or(0);
+        for line in &rest[..group] {
+            match &line.comment {
+                Some(comment) if !line.code.is_empty() => {
+                    let padding = column - line.code.chars().count();
+                    out.push_str(&format!("{}{} {}", line.code, " ".repeat(padding), comment));
+                }
+                _ => out.push_str(&line.code),
+            }
+            out.push('\n');
+        }
+        rest = &rest[group..];
+    }
+    out
+}
+
+/// Piece of a line of code: the text of a token, and how it is joined
+/// to the one before
+#[derive(Debug)]
+struct Piece {
+    text: String,
+    /// Whether a space separates it from the piece before
+    space: bool,
+    /// Whether the line can be broken right before it
+    break_before: bool,
+    /// Parentheses it is in, so lines are broken at the outermost
+    /// places first
+    depth: usize,
+}
+
+/// Block (or map literal) open at some point of the program
+#[derive(Debug)]
+struct Block {
+    /// Whether it is the body of a lambda, after which the expression it is
+    /// in goes on in the same line
+    lambda: bool,
+    /// Whether it is a map literal instead, which is laid out like a list
+    map: bool,
+    /// The parentheses open around it, which its statements are not in
+    parens: usize,
+    /// The `?` open around it, whose `:` can't be in it
+    questions: usize,
+}
+
+#[derive(Debug, Default)]
+struct Formatter {
+    lines: Vec<Line>,
+    /// Blocks the current line is in
+    indent: usize,
+    /// Whether the line being built opens a block, so the lines after it
+    /// are indented one more level
+    opens_block: bool,
+    /// The line being built, kept apart from `out` until it is complete
+    /// so it can be broken if it ends up too long
+    line: Vec<Piece>,
+    /// Whether the line being built continues a statement started on an
+    /// earlier line (after a comment ended that one), so it is indented
+    /// one more level
+    continuation: bool,
+    /// Whether the next token goes on a new line
+    newline: bool,
+    /// Parentheses (and brackets) open at this point in the innermost
+    /// block, which keep the `;` in the header of a `for` loop from ending
+    /// the line
+    parens: usize,
+    /// The last token that was not a comment
+    last_code: Option<TokenType>,
+    /// Whether the last token was a unary operator, which is not spaced
+    /// from its operand
+    after_unary: bool,
+    /// Blocks open at this point
+    blocks: Vec<Block>,
+    /// Whether the next block opened is the body of a lambda
+    lambda: bool,
+    /// Whether the last token closed the body of a lambda or a map, which a
+    /// call or an index is not spaced from
+    after_braces: bool,
+    /// Conditional expressions whose `:` is still to come in the innermost
+    /// block, to tell it from the `:` after the key of a map
+    questions: usize,
+}
+
+impl Formatter {
+    fn format(&mut self, tokens: &[Token]) {
+        let mut previous: Option<&Token> = None;
+        for (i, token) in tokens.iter().enumerate() {
+            if token.typ == TokenType::Eof {
+                break;
+            }
+            let next = tokens.get(i + 1).map(|token| &token.typ);
+            let same_line =
+                previous.is_some_and(|previous| previous.span.end_line == token.span.line);
+            match &token.typ {
+                TokenType::Comment => self.comment(token, previous, same_line),
+                _ => {
+                    // Nothing but another comment follows a comment on its line
+                    if previous.is_some_and(|previous| previous.typ == TokenType::Comment)
+                        && !same_line
+                    {
+                        self.newline = true;
+                    }
+                    self.code(token, previous, next);
+                }
+            }
+            previous = Some(token);
+        }
+        self.end_line();
+    }
+
+    /// Place a comment after the code before it if they were on the same
+    /// line, or on a line of its own otherwise
+    fn comment(&mut self, token: &Token, previous: Option<&Token>, same_line: bool) {
+        if !same_line {
+            self.newline = false;
+            self.end_line();
+            self.blank_line(previous, token);
+        }
+        let space = !self.line.is_empty();
+        self.push(token.lexeme.trim_end(), space, false);
+        if token.lexeme.starts_with("//") {
+            self.newline = true;
+        }
+    }
+
+    fn code(&mut self, token: &Token, previous: Option<&Token>, next: Option<&TokenType>) {
+        if self.newline {
+            self.newline = false;
+            self.end_line();
+        }
+        let empty_block = previous.is_some_and(|previous| previous.typ == TokenType::LeftBrace)
+            && token.typ == TokenType::RightBrace;
+        let opens_map = token.typ == TokenType::LeftBrace && !starts_block(self.last_code.as_ref());
+        let in_map = self.blocks.last().is_some_and(|block| block.map);
+        let closes_map = token.typ == TokenType::RightBrace && in_map;
+        if token.typ == TokenType::RightBrace && !empty_block && !closes_map {
+            self.end_line();
+            self.indent = self.indent.saturating_sub(1);
+        }
+        if self.line.is_empty() {
+            self.blank_line(previous, token);
+        }
+
+        let space = !self.line.is_empty()
+            && match previous {
+                Some(previous) if previous.typ == TokenType::Comment => true,
+                Some(_) if self.after_braces && token.typ == TokenType::LeftParen => false,
+                Some(_) if self.after_braces && token.typ == TokenType::LeftBracket => false,
+                // Inside a map, like inside a list
+                Some(previous) if previous.typ == TokenType::LeftBrace && in_map => false,
+                Some(_) if closes_map => false,
+                Some(_) if token.typ == TokenType::Colon && self.questions == 0 => false,
+                Some(previous) => !self.after_unary && spaced(&previous.typ, &token.typ),
+                None => false,
+            };
+        let break_before = matches!(token.typ, TokenType::And | TokenType::Or | TokenType::Pipe)
+            || self.last_code == Some(TokenType::Comma);
+        self.push(&token.lexeme, space, break_before);
+        self.after_unary = match token.typ {
+            TokenType::Bang => true,
+            TokenType::Minus => {
+                !self.after_braces && !self.last_code.as_ref().is_some_and(ends_operand)
+            }
+            _ => false,
+        };
+        self.last_code = Some(token.typ.clone());
+        self.after_braces = false;
+
+        match token.typ {
+            TokenType::Fun => self.lambda = next == Some(&TokenType::LeftParen),
+            TokenType::LeftParen | TokenType::LeftBracket => self.parens += 1,
+            TokenType::RightParen | TokenType::RightBracket => {
+                self.parens = self.parens.saturating_sub(1)
+            }
+            TokenType::SemiColon if self.parens == 0 => self.newline = true,
+            TokenType::Question => self.questions += 1,
+            TokenType::Colon => self.questions = self.questions.saturating_sub(1),
+            TokenType::LeftBrace if opens_map => {
+                self.blocks.push(Block {
+                    lambda: false,
+                    map: true,
+                    parens: self.parens,
+                    questions: std::mem::take(&mut self.questions),
+                });
+                self.parens += 1;
+            }
+            TokenType::LeftBrace => {
+                self.blocks.push(Block {
+                    lambda: std::mem::take(&mut self.lambda),
+                    map: false,
+                    parens: std::mem::take(&mut self.parens),
+                    questions: std::mem::take(&mut self.questions),
+                });
+                if next != Some(&TokenType::RightBrace) {
+                    self.opens_block = true;
+                    self.newline = true;
+                }
+            }
+            TokenType::RightBrace => {
+                let block = self.blocks.pop();
+                self.parens = block.as_ref().map_or(0, |block| block.parens);
+                self.questions = block.as_ref().map_or(0, |block| block.questions);
+                if block.is_some_and(|block| block.lambda || block.map) {
+                    self.after_braces = true;
+                } else {
+                    // What continues the statement the block is part of
+                    // stays on its line
+                    self.newline = !matches!(
+                        next,
+                        Some(
+                            TokenType::Else
+                                | TokenType::SemiColon
+                                | TokenType::RightParen
+                                | TokenType::Comma
+                                | TokenType::Dot
+                        )
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn push(&mut self, text: &str, space: bool, break_before: bool) {
+        self.line.push(Piece {
+            text: text.to_string(),
+            space,
+            break_before,
+            depth: self.parens,
+        });
+    }
+
+    /// Keep a blank line before the token if there was at least one in the
+    /// source, except at the start or the end of a block
+    fn blank_line(&mut self, previous: Option<&Token>, token: &Token) {
+        let Some(previous) = previous else {
+            return;
+        };
+        let starts_block = self
+            .lines
+            .last()
+            .is_some_and(|line| line.code.ends_with('{'));
+        if token.span.line > previous.span.end_line + 1
+            && !starts_block
+            && token.typ != TokenType::RightBrace
+        {
+            self.lines.push(Line::default());
+        }
+    }
+
+    /// Write the line being built, broken in as many lines as it takes to
+    /// keep them within `MAX_WIDTH`
+    fn end_line(&mut self) {
+        if self.line.is_empty() {
+            return;
+        }
+        let indent = self.indent + usize::from(self.continuation);
+        let first = MAX_WIDTH.saturating_sub(INDENT.len() * indent);
+        let rest = first.saturating_sub(INDENT.len());
+        // A comment after the code is kept out of the way of breaking it
+        let comment = match self.line.last() {
+            Some(piece) if self.line.len() > 1 && piece.text.starts_with("//") => self.line.pop(),
+            _ => None,
+        };
+        for (i, line) in wrap(&self.line, first, rest).into_iter().enumerate() {
+            let mut code = INDENT.repeat(indent + usize::from(i > 0));
+            for (j, piece) in line.iter().enumerate() {
+                if piece.space && j > 0 {
+                    code.push(' ');
+                }
+                code.push_str(&piece.text);
+            }
+            self.lines.push(Line {
+                code,
+                comment: None,
+            });
+        }
+        if let Some(line) = self.lines.last_mut() {
+            line.comment = comment.map(|piece| piece.text);
+        }
+        self.line.clear();
+
+        if self.opens_block {
+            self.opens_block = false;
+            self.indent += 1;
+        }
+        // A statement broken by a comment goes on in the next line
+        self.continuation = !matches!(
+            self.last_code,
+            None | Some(TokenType::SemiColon | TokenType::LeftBrace | TokenType::RightBrace)
+        );
+    }
+}
+
+/// Break a line into lines `first` (the first one) and `rest` (the others)
+/// characters wide, filling them with the parts between the outermost
+/// places it can be broken at, and breaking the parts still too wide
+/// further
+fn wrap(line: &[Piece], first: usize, rest: usize) -> Vec<&[Piece]> {
+    let outermost = line[1..]
+        .iter()
+        .filter(|piece| piece.break_before)
+        .map(|piece| piece.depth)
+        .min();
+    let Some(depth) = outermost.filter(|_| width(line) > first) else {
+        return vec![line];
+    };
+
+    let mut lines = Vec::new();
+    let mut available = first;
+    // The parts from `start` up to `end` fit in the current line
+    let (mut start, mut end) = (0, 0);
+    let breaks = line.iter().enumerate().skip(1);
+    let breaks = breaks.filter(|(_, piece)| piece.break_before && piece.depth == depth);
+    for i in breaks.map(|(i, _)| i).chain([line.len()]) {
+        if end > start && width(&line[start..i]) > available {
+            lines.extend(wrap(&line[start..end], available, rest));
+            start = end;
+            available = rest;
+        }
+        end = i;
+    }
+    lines.extend(wrap(&line[start..], available, rest));
+    lines
+}
+
+/// Width of the pieces written on a line of their own
+fn width(pieces: &[Piece]) -> usize {
+    let spaces = pieces.iter().skip(1).filter(|piece| piece.space).count();
+    spaces
+        + pieces
+            .iter()
+            .map(|piece| piece.text.chars().count())
+            .sum::<usize>()
+}
+
+/// Whether a space goes between two tokens of code
+fn spaced(previous: &TokenType, token: &TokenType) -> bool {
+    use TokenType::*;
+    match (previous, token) {
+        (_, RightParen | RightBracket | Comma | SemiColon | Dot)
+        | (LeftParen | LeftBracket | Dot, _) => false,
+        (LeftBrace, RightBrace) => false,
+        // Calls, and the parameters of functions
+        (previous, LeftParen) => !ends_operand(previous),
+        // Indexing, and lists
+        (previous, LeftBracket) => !ends_operand(previous),
+        _ => true,
+    }
+}
+
+/// Whether a `{` after the token (the last one of code before it, if any)
+/// starts a block rather than a map
+fn starts_block(previous: Option<&TokenType>) -> bool {
+    // After the name of a class (or enum), the `)` of a header, or another
+    // statement
+    matches!(
+        previous,
+        None | Some(
+            TokenType::Identifier
+                | TokenType::RightParen
+                | TokenType::Else
+                | TokenType::Defer
+                | TokenType::SemiColon
+                | TokenType::LeftBrace
+                | TokenType::RightBrace
+        )
+    )
+}
+
+/// Whether the token can end an operand, so a `-` after it is a binary
+/// operator and a `(` a call
+fn ends_operand(typ: &TokenType) -> bool {
+    matches!(
+        typ,
+        TokenType::Identifier
+            | TokenType::String(_)
+            | TokenType::Number(_)
+            | TokenType::RightParen
+            | TokenType::RightBracket
+            | TokenType::This
+            | TokenType::True
+            | TokenType::False
+            | TokenType::Nil
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt(source: &str) -> String {
+        format(source, Features::all()).expect("source should parse")
+    }
+
+    /// The tokens of the source that are not comments
+    fn code_tokens(source: &str) -> Vec<Token> {
+        Scanner::new(source.to_string())
+            .with_features(Features::all())
+            .scan_all()
+            .expect("source should scan")
+    }
+
+    #[test]
+    fn lays_out_statements_and_blocks() {
+        let source = "var a=1;fun f(x,y){if(x>-y){return!x;}else print x-y;}\n\
+                      class A<B{init(){this.c=f (1,2);}}while(true){}";
+        assert_eq!(
+            fmt(source),
+            "\
+var a = 1;
+fun f(x, y) {
+    if (x > -y) {
+        return !x;
+    } else print x - y;
+}
+class A < B {
+    init() {
+        this.c = f(1, 2);
+    }
+}
+while (true) {}
+"
+        );
+        assert_eq!(
+            fmt("for(var i=0;i<3;i=i+1)print i;for(;;){}"),
+            "for (var i = 0; i < 3; i = i + 1) print i;\nfor (;;) {}\n"
+        );
+    }
+
+    #[test]
+    fn spaces_lists_and_indexing() {
+        assert_eq!(
+            fmt("var xs=[1,[2],[ ]];print xs [1][0]-xs[0];"),
+            "var xs = [1, [2], []];\nprint xs[1][0] - xs[0];\n"
+        );
+    }
+
+    #[test]
+    fn lays_out_maps_like_lists() {
+        assert_eq!(
+            fmt("var m={\"a\":1,b:{ },c:x?{ }:2};print {}[\"a\"] -m [b];\n{print m;}"),
+            "\
+var m = {\"a\": 1, b: {}, c: x ? {} : 2};
+print {}[\"a\"] - m[b];
+{
+    print m;
+}
+"
+        );
+    }
+
+    #[test]
+    fn keeps_what_follows_a_lambda_on_its_line() {
+        assert_eq!(
+            fmt("var f=fun(a){var b=a;return b;};fun(){print 1;}();print fun(){};"),
+            "\
+var f = fun (a) {
+    var b = a;
+    return b;
+};
+fun () {
+    print 1;
+}();
+print fun () {};
+"
+        );
+    }
+
+    #[test]
+    fn keeps_comments_and_blank_lines() {
+        let source = "\
+// header
+var a = 1;   // trailing
+
+
+/* own line */
+{
+
+    print a /* inline */ + 1;
+    // last
+
+}
+print a +  // broken
+ 2;
+";
+        assert_eq!(
+            fmt(source),
+            "\
+// header
+var a = 1; // trailing
+
+/* own line */
+{
+    print a /* inline */ + 1;
+    // last
+}
+print a + // broken
+    2;
+"
+        );
+    }
+
+    #[test]
+    fn aligns_the_comments_at_the_end_of_consecutive_lines() {
+        let source = "print 1; // one\nprint 100;   // hundred\n\nprint 10;      // ten\n";
+        assert_eq!(
+            fmt(source),
+            "print 1;   // one\nprint 100; // hundred\n\nprint 10; // ten\n"
+        );
+    }
+
+    #[test]
+    fn wraps_long_lines() {
+        let source = format!(
+            "print f({}) or g({});",
+            "aaaaaaaaaa, ".repeat(6) + "b",
+            "cccccccccc, ".repeat(3) + "d"
+        );
+        assert_eq!(
+            fmt(&source),
+            "\
+print f(aaaaaaaaaa, aaaaaaaaaa, aaaaaaaaaa, aaaaaaaaaa, aaaaaaaaaa, aaaaaaaaaa, b)
+    or g(cccccccccc, cccccccccc, cccccccccc, d);
+"
+        );
+    }
+
+    #[test]
+    fn refuses_invalid_code() {
+        assert!(format("print ;", Features::default()).is_err());
+    }
+
+    #[test]
+    fn keeps_the_tokens_and_is_idempotent() {
+        let mut paths = vec![];
+        for dir in std::fs::read_dir("tests/cases").expect("the cases should be readable") {
+            for file in std::fs::read_dir(dir.unwrap().path()).unwrap() {
+                let path = file.unwrap().path();
+                // Skip the modules the cases import
+                if path.is_file() {
+                    paths.push(path);
+                }
+            }
+        }
+        for path in paths {
+            let source = std::fs::read_to_string(&path).unwrap();
+            let Ok(formatted) = format(&source, Features::all()) else {
+                continue;
+            };
+            assert_eq!(
+                code_tokens(&formatted),
+                code_tokens(&source),
+                "{}",
+                path.display()
+            );
+            assert_eq!(fmt(&formatted), formatted, "{}", path.display());
+        }
+    }
+}
@@ -0,0 +1,179 @@
+/// Callable values for the `Lox` interpreter
+use std::cell::RefCell;
+use std::fmt::{Debug, Display};
+use std::rc::Rc;
+
+use crate::environment::Environment;
+use crate::gc::{self, Heap, Trace};
+use crate::interpreter::{Interpreter, RuntimeError, Unwind, Value};
+use crate::lexer::Token;
+use crate::stmt::Stmt;
+use crate::symbol::Symbol;
+
+/// Anything that can be called from Lox code with `callee(arguments)`
+pub trait LoxCallable: Debug + Display {
+    /// Name to show for the callable in the call stack
+    fn name(&self) -> &str;
+
+    /// Number of arguments the callable expects
+    fn arity(&self) -> usize;
+
+    /// Call with the given arguments, whose count has already been checked
+    /// against the `arity`. `paren` is the closing parenthesis of the call,
+    /// to point errors at
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError>;
+}
+
+/// What a function can see of where it was declared
+#[derive(Debug, Clone)]
+pub struct Closure {
+    /// The scope the function was declared in
+    pub environment: Rc<RefCell<Environment>>,
+    /// The globals of the module the function was declared in, which its
+    /// unresolved variables are looked up in wherever it is called from
+    pub globals: Rc<RefCell<Environment>>,
+}
+
+/// A function declared in Lox code, along with the environment it was
+/// declared in so it can access the variables around it (a closure)
+#[derive(Debug)]
+pub struct LoxFunction {
+    /// `None` for a lambda
+    name: Option<Token>,
+    params: Vec<Token>,
+    body: Rc<Vec<Stmt>>,
+    closure: Closure,
+    /// Whether this is the `init` method of a class, which always returns
+    /// the instance being initialized
+    is_initializer: bool,
+    /// Whether this is a getter, which is called when the property is read
+    is_getter: bool,
+}
+
+impl LoxFunction {
+    pub fn new(
+        name: &Token,
+        params: &[Token],
+        body: &Rc<Vec<Stmt>>,
+        closure: Closure,
+        is_initializer: bool,
+    ) -> Self {
+        Self {
+            name: Some(name.clone()),
+            params: params.to_vec(),
+            body: Rc::clone(body),
+            closure,
+            is_initializer,
+            is_getter: false,
+        }
+    }
+
+    /// Method declared with no parameter list, which runs when the property
+    /// is read
+    pub fn getter(name: &Token, body: &Rc<Vec<Stmt>>, closure: Closure) -> Self {
+        Self {
+            is_getter: true,
+            ..Self::new(name, &[], body, closure, false)
+        }
+    }
+
+    /// Function of a `fun (params) { body }` expression
+    pub fn lambda(params: &[Token], body: &Rc<Vec<Stmt>>, closure: Closure) -> Self {
+        Self {
+            name: None,
+            params: params.to_vec(),
+            body: Rc::clone(body),
+            closure,
+            is_initializer: false,
+            is_getter: false,
+        }
+    }
+
+    /// Create a copy of this method with `this` bound to the given instance
+    pub(crate) fn bind(&self, heap: &mut Heap, instance: Value) -> Rc<LoxFunction> {
+        let mut environment = Environment::new(Rc::clone(&self.closure.environment));
+        environment.define(Symbol::THIS, instance);
+        let environment = heap.alloc(RefCell::new(environment));
+        heap.alloc(LoxFunction {
+            name: self.name.clone(),
+            params: self.params.clone(),
+            body: Rc::clone(&self.body),
+            closure: Closure {
+                environment,
+                globals: Rc::clone(&self.closure.globals),
+            },
+            is_initializer: self.is_initializer,
+            is_getter: self.is_getter,
+        })
+    }
+
+    pub fn is_getter(&self) -> bool {
+        self.is_getter
+    }
+
+    fn this(&self) -> Value {
+        self.closure
+            .environment
+            .borrow()
+            .lookup(Symbol::THIS)
+            .expect("initializers should have `this` bound")
+    }
+}
+
+impl LoxCallable for LoxFunction {
+    fn name(&self) -> &str {
+        self.name.as_ref().map_or("lambda", |name| &name.lexeme)
+    }
+
+    fn arity(&self) -> usize {
+        self.params.len()
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        _paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let mut environment = Environment::new(Rc::clone(&self.closure.environment));
+        for (param, argument) in self.params.iter().zip(arguments) {
+            environment.define(param.lexeme.symbol(), argument);
+        }
+
+        let globals = interpreter.swap_globals(Rc::clone(&self.closure.globals));
+        let result = interpreter.execute_block(&self.body, environment);
+        interpreter.swap_globals(globals);
+        match result {
+            Ok(()) | Err(Unwind::Return(_)) if self.is_initializer => Ok(self.this()),
+            Ok(()) => Ok(Value::Nil),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(err)) => Err(err),
+            Err(Unwind::Break | Unwind::Continue) => {
+                unreachable!("the resolver rejects breaks outside of loops")
+            }
+        }
+    }
+}
+
+impl Trace for LoxFunction {
+    fn trace(&self, references: &mut Vec<usize>) -> bool {
+        references.push(gc::address(&self.closure.environment));
+        references.push(gc::address(&self.closure.globals));
+        true
+    }
+
+    // What a function holds never changes, and it can't be in a cycle of
+    // its own: the scopes it holds are what gets cleared
+    fn clear(&self) {}
+}
+
+impl Display for LoxFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<fn {}>", self.name())
+    }
+}
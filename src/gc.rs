@@ -0,0 +1,258 @@
+/// Collection of the garbage reference counting leaves behind
+///
+/// Runtime values are shared with `Rc`, which frees an object once nothing
+/// holds it, but not the objects holding each other in a cycle: a function
+/// and the scope it is declared in, an instance with a field holding
+/// itself... In a long session of the prompt those add up.
+///
+/// The `Heap` of the interpreter keeps a weak reference to each object it
+/// creates (scopes, functions, classes, instances, lists, maps and
+/// modules), and every so many of them it looks for the ones only the
+/// cycles keep alive. It empties them, which breaks the cycles so `Rc`
+/// frees them.
+///
+/// There are no roots to scan: an object is held from outside the heap (a
+/// variable of the interpreter, a value being evaluated, the program
+/// embedding it...) when it has more strong references than the objects
+/// of the heap account for. Everything reachable from those is alive, and
+/// the rest is garbage. An object the heap doesn't know of (e.g. one
+/// created by a native of a plugin) is as good as outside, so it never
+/// gets anything collected wrongly, at worst kept alive.
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::rc::{Rc, Weak};
+
+use crate::interpreter::Value;
+
+/// Objects to create before collecting the first time, or after a
+/// collection that left few alive
+const MIN_COLLECTION: usize = 10_000;
+
+/// Each collection waits for as many new objects as this many times the
+/// ones left alive by the last one, so big heaps aren't scanned too often
+const GROWTH: usize = 2;
+
+/// An object of the heap, which can tell which objects it holds
+pub(crate) trait Trace: Debug {
+    /// Push the address of each object it holds (see `address`), once for
+    /// each reference. Returns `false` without pushing them if it can't be
+    /// looked into, because it is being changed
+    fn trace(&self, references: &mut Vec<usize>) -> bool;
+
+    /// Let go of every object it holds, once it is known to be garbage
+    fn clear(&self);
+}
+
+/// What identifies an object held in an `Rc`: the address of its value
+pub(crate) fn address<T: ?Sized>(object: &Rc<T>) -> usize {
+    Rc::as_ptr(object).cast::<()>() as usize
+}
+
+/// Push the address of the object the value is, if it is one the heap
+/// could hold
+pub(crate) fn trace_value(value: &Value, references: &mut Vec<usize>) {
+    let object = match value {
+        Value::Callable(callable) => address(callable),
+        Value::Class(class) => address(class),
+        Value::Instance(instance) => address(instance),
+        Value::List(list) => address(list),
+        Value::Map(map) => address(map),
+        Value::Module(module) => address(module),
+        _ => return,
+    };
+    references.push(object);
+}
+
+/// What the collections did so far, and what is left
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GcStats {
+    pub collections: usize,
+    /// Objects freed by the collections (the ones `Rc` frees by itself are
+    /// not counted)
+    pub collected: usize,
+    /// Objects of the heap alive right now, counting the cycles not
+    /// collected yet
+    pub live: usize,
+}
+
+impl Display for GcStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} collections, {} objects collected, {} live",
+            self.collections, self.collected, self.live
+        )
+    }
+}
+
+/// The objects created by an interpreter
+#[derive(Debug)]
+pub(crate) struct Heap {
+    objects: Vec<Weak<dyn Trace>>,
+    /// Objects created since the last collection
+    allocated: usize,
+    /// Objects to create before the next collection
+    threshold: usize,
+    stats: GcStats,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self {
+            objects: Vec::new(),
+            allocated: 0,
+            threshold: MIN_COLLECTION,
+            stats: GcStats::default(),
+        }
+    }
+}
+
+impl Heap {
+    /// Put the object in an `Rc` the heap keeps track of, collecting the
+    /// garbage first if enough objects were created since the last time
+    pub(crate) fn alloc<T: Trace + 'static>(&mut self, object: T) -> Rc<T> {
+        if self.allocated >= self.threshold {
+            self.collect();
+        }
+        let object = Rc::new(object);
+        let weak: Weak<dyn Trace> = Rc::downgrade(&object) as Weak<dyn Trace>;
+        self.objects.push(weak);
+        self.allocated += 1;
+        object
+    }
+
+    pub fn stats(&self) -> GcStats {
+        GcStats {
+            live: self
+                .objects
+                .iter()
+                .filter(|object| object.strong_count() > 0)
+                .count(),
+            ..self.stats
+        }
+    }
+
+    /// Free the objects that are only alive because they hold each other
+    pub fn collect(&mut self) {
+        let objects: Vec<Rc<dyn Trace>> = self.objects.iter().filter_map(Weak::upgrade).collect();
+        let index: HashMap<usize, usize> = objects
+            .iter()
+            .enumerate()
+            .map(|(i, object)| (address(object), i))
+            .collect();
+
+        // Count the references from outside the heap: all of them (but the
+        // one in `objects`) minus those from the objects of the heap
+        let mut outside: Vec<usize> = objects
+            .iter()
+            .map(|object| Rc::strong_count(object) - 1)
+            .collect();
+        let mut held = Vec::with_capacity(objects.len());
+        for object in &objects {
+            let mut references = Vec::new();
+            if !object.trace(&mut references) {
+                // What it holds is unknown, so it must stay, and so must
+                // what it holds (which looks held from outside now)
+                held.push(None);
+                continue;
+            }
+            let references: Vec<usize> = references
+                .iter()
+                .filter_map(|reference| index.get(reference).copied())
+                .collect();
+            for &i in &references {
+                outside[i] -= 1;
+            }
+            held.push(Some(references));
+        }
+
+        // Keep everything reachable from outside
+        let mut alive = vec![false; objects.len()];
+        let mut pending: Vec<usize> = (0..objects.len())
+            .filter(|&i| outside[i] > 0 || held[i].is_none())
+            .collect();
+        while let Some(i) = pending.pop() {
+            if std::mem::replace(&mut alive[i], true) {
+                continue;
+            }
+            pending.extend(held[i].iter().flatten().filter(|&&j| !alive[j]));
+        }
+
+        let mut collected = 0;
+        for (object, &alive) in objects.iter().zip(&alive) {
+            if !alive {
+                object.clear();
+                collected += 1;
+            }
+        }
+        self.objects = objects
+            .iter()
+            .zip(&alive)
+            .filter(|(_, &alive)| alive)
+            .map(|(object, _)| Rc::downgrade(object))
+            .collect();
+        // The garbage is freed here, once nothing holds it anymore
+        drop(objects);
+
+        self.stats.collections += 1;
+        self.stats.collected += collected;
+        self.allocated = 0;
+        self.threshold = MIN_COLLECTION.max(self.objects.len() * GROWTH);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run, Features, Interpreter};
+
+    fn collected(interpreter: &mut Interpreter, source: &str) -> usize {
+        run(interpreter, source, Features::default()).unwrap();
+        let before = interpreter.gc_stats().collected;
+        interpreter.collect_garbage();
+        interpreter.gc_stats().collected - before
+    }
+
+    #[test]
+    fn cycles_left_behind_are_collected() {
+        let mut interpreter = Interpreter::default();
+        // The scope of each call and the function declared in it hold each
+        // other
+        let source = "
+            fun counter() { var n = 0; fun add() { n = n + 1; return n; } return add; }
+            for (var i = 0; i < 3; i = i + 1) counter()();
+        ";
+        assert!(collected(&mut interpreter, source) >= 6);
+
+        let source = "
+            class Node { init() { this.next = this; this.bound = this.init; } }
+            { var a = Node(); var l = [a]; l.push(l); var m = {}; m[1] = m; }
+        ";
+        assert!(collected(&mut interpreter, source) >= 4);
+        assert_eq!(collected(&mut interpreter, "1;"), 0);
+    }
+
+    #[test]
+    fn values_held_from_outside_are_kept() {
+        let mut interpreter = Interpreter::default();
+        let source = "
+            fun counter() { var n = 0; fun add() { n = n + 1; return n; } return add; }
+            var add = counter();
+            class A { init() { this.me = this; } }
+            var a = A();
+        ";
+        run(&mut interpreter, source, Features::default()).unwrap();
+        let add = run(&mut interpreter, "add;", Features::default()).unwrap();
+        run(&mut interpreter, "add = nil;", Features::default()).unwrap();
+        interpreter.collect_garbage();
+
+        interpreter.set_global("add", add.unwrap());
+        let source = "add(); add(); a.me.me == a;";
+        let value = run(&mut interpreter, source, Features::default()).unwrap();
+        assert_eq!(value, Some(Value::Boolean(true)));
+        assert_eq!(
+            run(&mut interpreter, "add();", Features::default()).unwrap(),
+            Some(Value::Number(3.0))
+        );
+    }
+}
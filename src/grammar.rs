@@ -0,0 +1,633 @@
+/// Printing the grammar the parser accepts (`parser::GRAMMAR`), either as
+/// text in the notation from the book or as railroad diagrams, and the
+/// operator table driving the expression parser (`parser::PARSE_RULES`)
+///
+/// The rules are parsed back from their notation into a tree, so the rules
+/// of features that are not enabled can be taken out of the rules referring
+/// to them before printing.
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::FromStr;
+
+use crate::features::{Feature, Features};
+use crate::parser::{Precedence, Prefix, GRAMMAR, PARSE_RULES};
+
+/// Output formats of `rinlox grammar`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Ebnf,
+    RailroadHtml,
+    /// The operators from loosest to tightest binding
+    Precedence,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ebnf" => Ok(Format::Ebnf),
+            "railroad-html" => Ok(Format::RailroadHtml),
+            "precedence" => Ok(Format::Precedence),
+            _ => Err(format!("unknown grammar format '{}'", s)),
+        }
+    }
+}
+
+/// Part of the body of a rule
+#[derive(Debug, Clone, PartialEq)]
+enum Expansion {
+    /// Literal text, like `"class"`
+    Literal(String),
+    /// A kind of token, like `IDENTIFIER`
+    Token(String),
+    /// Reference to another rule
+    Rule(String),
+    Sequence(Vec<Expansion>),
+    Choice(Vec<Expansion>),
+    Optional(Box<Expansion>),
+    ZeroOrMore(Box<Expansion>),
+    OneOrMore(Box<Expansion>),
+}
+
+/// Render the grammar, including the rules of the given experimental
+/// features
+pub fn render(format: Format, features: Features) -> String {
+    if format == Format::Precedence {
+        return precedence_table(features);
+    }
+    let enabled = |name: &str| {
+        GRAMMAR.iter().any(|rule| {
+            rule.name == name
+                && rule
+                    .feature
+                    .is_none_or(|feature| features.is_enabled(feature))
+        })
+    };
+    let rules: Vec<(&str, Expansion)> = GRAMMAR
+        .iter()
+        .filter(|rule| enabled(rule.name))
+        .filter_map(|rule| Some((rule.name, only_enabled(parse(rule.body), &enabled)?)))
+        .collect();
+
+    match format {
+        Format::Ebnf => rules
+            .iter()
+            .map(|(name, body)| format!("{}\n", ebnf_rule(name, body)))
+            .collect(),
+        Format::RailroadHtml => railroad_html(&rules),
+        Format::Precedence => unreachable!("rendered from the parse table"),
+    }
+}
+
+/// One line per precedence level, from the loosest to the tightest, with
+/// its associativity and operators
+fn precedence_table(features: Features) -> String {
+    let mut levels: Vec<(Precedence, &str, Vec<&str>)> = Vec::new();
+    let enabled = PARSE_RULES.iter().filter(|rule| {
+        Feature::of_operator(&rule.token).is_none_or(|feature| features.is_enabled(feature))
+    });
+    for rule in enabled {
+        let level = match (rule.prefix, rule.infix) {
+            (_, Some((infix, precedence))) if infix.is_right_associative() => (precedence, "right"),
+            (_, Some((_, precedence))) => (precedence, "left"),
+            (Some(Prefix::Unary), None) => (Precedence::Unary, "prefix"),
+            _ => continue,
+        };
+        let mut add = |(precedence, associativity): (Precedence, &'static str)| match levels
+            .iter_mut()
+            .find(|(level, ..)| *level == precedence)
+        {
+            Some((_, _, operators)) => operators.push(rule.text),
+            None => levels.push((precedence, associativity, vec![rule.text])),
+        };
+        add(level);
+        // `-` is both a binary and a prefix operator
+        if rule.infix.is_some() && rule.prefix == Some(Prefix::Unary) {
+            add((Precedence::Unary, "prefix"));
+        }
+    }
+    levels.sort_by_key(|(precedence, ..)| *precedence);
+
+    let mut out = String::new();
+    for (precedence, associativity, operators) in levels {
+        let name = format!("{:?}", precedence).to_lowercase();
+        let _ = writeln!(
+            out,
+            "{:<width$}{:<7}{}",
+            name,
+            associativity,
+            operators.join(" "),
+            width = NAME_WIDTH
+        );
+    }
+    out
+}
+
+/// Parse the body of a rule from its notation
+fn parse(body: &str) -> Expansion {
+    let tokens = tokenize(body);
+    let mut tokens = tokens.iter().map(String::as_str).peekable();
+    let expansion = parse_choice(&mut tokens);
+    assert!(tokens.next().is_none(), "unbalanced grammar rule: {}", body);
+    expansion
+}
+
+/// Split the notation into quoted literals, names and the operators
+fn tokenize(body: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' => {}
+            '(' | ')' | '|' | '*' | '?' | '+' => tokens.push(c.to_string()),
+            '"' => {
+                let mut literal = String::from('"');
+                for c in chars.by_ref() {
+                    literal.push(c);
+                    if c == '"' {
+                        break;
+                    }
+                }
+                tokens.push(literal);
+            }
+            _ => {
+                let mut name = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' {
+                        name.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(name);
+            }
+        }
+    }
+    tokens
+}
+
+fn parse_choice<'a, I: Iterator<Item = &'a str>>(tokens: &mut Peekable<I>) -> Expansion {
+    let mut alternatives = vec![parse_sequence(tokens)];
+    while tokens.next_if_eq(&"|").is_some() {
+        alternatives.push(parse_sequence(tokens));
+    }
+    if alternatives.len() == 1 {
+        alternatives.remove(0)
+    } else {
+        Expansion::Choice(alternatives)
+    }
+}
+
+fn parse_sequence<'a, I: Iterator<Item = &'a str>>(tokens: &mut Peekable<I>) -> Expansion {
+    let mut items = Vec::new();
+    while let Some(&token) = tokens.peek() {
+        if token == "|" || token == ")" {
+            break;
+        }
+        tokens.next();
+        let mut item = if token == "(" {
+            let inner = parse_choice(tokens);
+            assert_eq!(tokens.next(), Some(")"), "unclosed group in grammar rule");
+            inner
+        } else if token.starts_with('"') {
+            Expansion::Literal(token.to_string())
+        } else if token.chars().all(|c| c.is_ascii_uppercase()) {
+            Expansion::Token(token.to_string())
+        } else {
+            Expansion::Rule(token.to_string())
+        };
+
+        while let Some(&postfix) = tokens.peek() {
+            item = match postfix {
+                "?" => Expansion::Optional(Box::new(item)),
+                "*" => Expansion::ZeroOrMore(Box::new(item)),
+                "+" => Expansion::OneOrMore(Box::new(item)),
+                _ => break,
+            };
+            tokens.next();
+        }
+        items.push(item);
+    }
+    if items.len() == 1 {
+        items.remove(0)
+    } else {
+        Expansion::Sequence(items)
+    }
+}
+
+/// Take the references to rules that aren't enabled out of the expansion,
+/// returning `None` if nothing is left that could match
+fn only_enabled(expansion: Expansion, enabled: &impl Fn(&str) -> bool) -> Option<Expansion> {
+    let repeated = |inner: Box<Expansion>, wrap: fn(Box<Expansion>) -> Expansion| {
+        only_enabled(*inner, enabled).map(|inner| wrap(Box::new(inner)))
+    };
+    match expansion {
+        Expansion::Rule(ref name) if !enabled(name) => None,
+        Expansion::Sequence(items) => {
+            let mut items = items
+                .into_iter()
+                .map(|item| only_enabled(item, enabled))
+                .collect::<Option<Vec<_>>>()?;
+            items.retain(|item| *item != Expansion::Sequence(vec![]));
+            if items.len() == 1 {
+                items.pop()
+            } else {
+                Some(Expansion::Sequence(items))
+            }
+        }
+        Expansion::Choice(alternatives) => {
+            let mut alternatives: Vec<_> = alternatives
+                .into_iter()
+                .filter_map(|alternative| only_enabled(alternative, enabled))
+                .collect();
+            match alternatives.len() {
+                0 => None,
+                1 => alternatives.pop(),
+                _ => Some(Expansion::Choice(alternatives)),
+            }
+        }
+        // Something optional that can't match just goes away
+        Expansion::Optional(inner) => {
+            Some(repeated(inner, Expansion::Optional).unwrap_or(Expansion::Sequence(vec![])))
+        }
+        Expansion::ZeroOrMore(inner) => {
+            Some(repeated(inner, Expansion::ZeroOrMore).unwrap_or(Expansion::Sequence(vec![])))
+        }
+        Expansion::OneOrMore(inner) => repeated(inner, Expansion::OneOrMore),
+        expansion => Some(expansion),
+    }
+}
+
+/// Width of the rule names column in the text output
+const NAME_WIDTH: usize = 15;
+/// Maximum length of the lines of the text output, before wrapping the
+/// alternatives of a rule
+const LINE_WIDTH: usize = 80;
+
+/// Write a rule as `name → body ;`, with the alternatives of long rules
+/// wrapped into several lines
+fn ebnf_rule(name: &str, body: &Expansion) -> String {
+    let mut out = format!("{:<width$}→ ", name, width = NAME_WIDTH);
+    let Expansion::Choice(alternatives) = body else {
+        return format!("{}{} ;", out, ebnf(body, Context::Choice));
+    };
+
+    let mut line_start = 0;
+    for (i, alternative) in alternatives.iter().enumerate() {
+        let alternative = ebnf(alternative, Context::Sequence);
+        if i > 0 {
+            if out[line_start..].chars().count() + alternative.len() + 3 > LINE_WIDTH {
+                out.push('\n');
+                line_start = out.len();
+                out.push_str(&" ".repeat(NAME_WIDTH));
+                out.push_str("| ");
+            } else {
+                out.push_str(" | ");
+            }
+        }
+        out.push_str(&alternative);
+    }
+    out.push_str(" ;");
+    out
+}
+
+/// Where an expansion is written, to know when it needs parentheses
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Context {
+    Choice,
+    Sequence,
+    Postfix,
+}
+
+fn ebnf(expansion: &Expansion, context: Context) -> String {
+    let (text, binds) = match expansion {
+        Expansion::Literal(text) | Expansion::Token(text) | Expansion::Rule(text) => {
+            return text.clone()
+        }
+        Expansion::Sequence(items) => {
+            let items: Vec<_> = items
+                .iter()
+                .map(|item| ebnf(item, Context::Postfix))
+                .collect();
+            (items.join(" "), Context::Sequence)
+        }
+        Expansion::Choice(alternatives) => {
+            let alternatives: Vec<_> = alternatives
+                .iter()
+                .map(|alternative| ebnf(alternative, Context::Sequence))
+                .collect();
+            (alternatives.join(" | "), Context::Choice)
+        }
+        Expansion::Optional(inner) => return format!("{}?", ebnf(inner, Context::Postfix)),
+        Expansion::ZeroOrMore(inner) => return format!("{}*", ebnf(inner, Context::Postfix)),
+        Expansion::OneOrMore(inner) => return format!("{}+", ebnf(inner, Context::Postfix)),
+    };
+    if binds < context {
+        format!("( {} )", text)
+    } else {
+        text
+    }
+}
+
+// Measurements of the railroad diagrams, in pixels
+const BOX_HEIGHT: usize = 22;
+const CHAR_WIDTH: usize = 9;
+const PADDING: usize = 10;
+const GAP: usize = 10;
+const RAIL: usize = 20;
+
+/// Space taken by the diagram of an expansion: its track enters on the left
+/// and leaves on the right `up` pixels below the top
+#[derive(Debug, Clone, Copy)]
+struct Size {
+    width: usize,
+    up: usize,
+    down: usize,
+}
+
+/// Optional and repeated expansions are drawn as a choice to skip them
+fn as_choice(expansion: &Expansion) -> Option<Expansion> {
+    let skip = Expansion::Sequence(vec![]);
+    match expansion {
+        Expansion::Optional(inner) => Some(Expansion::Choice(vec![skip, (**inner).clone()])),
+        Expansion::ZeroOrMore(inner) => Some(Expansion::Choice(vec![
+            skip,
+            Expansion::OneOrMore(inner.clone()),
+        ])),
+        _ => None,
+    }
+}
+
+fn size(expansion: &Expansion) -> Size {
+    if let Some(choice) = as_choice(expansion) {
+        return size(&choice);
+    }
+    match expansion {
+        Expansion::Literal(text) | Expansion::Token(text) | Expansion::Rule(text) => Size {
+            width: text.chars().count() * CHAR_WIDTH + 2 * PADDING,
+            up: BOX_HEIGHT / 2,
+            down: BOX_HEIGHT / 2,
+        },
+        Expansion::Sequence(items) => {
+            let sizes: Vec<_> = items.iter().map(size).collect();
+            Size {
+                width: sizes.iter().map(|size| size.width).sum::<usize>()
+                    + GAP * items.len().saturating_sub(1),
+                up: sizes.iter().map(|size| size.up).max().unwrap_or(0),
+                down: sizes.iter().map(|size| size.down).max().unwrap_or(0),
+            }
+        }
+        Expansion::Choice(alternatives) => {
+            let sizes: Vec<_> = alternatives.iter().map(size).collect();
+            Size {
+                width: sizes.iter().map(|size| size.width).max().unwrap_or(0) + 2 * RAIL,
+                up: sizes[0].up,
+                down: sizes[0].down
+                    + sizes[1..]
+                        .iter()
+                        .map(|size| GAP + size.up + size.down)
+                        .sum::<usize>(),
+            }
+        }
+        Expansion::OneOrMore(inner) => {
+            let inner = size(inner);
+            Size {
+                width: inner.width + 2 * RAIL,
+                up: inner.up,
+                down: inner.down + GAP,
+            }
+        }
+        Expansion::Optional(_) | Expansion::ZeroOrMore(_) => unreachable!("drawn as choices"),
+    }
+}
+
+/// Draw the diagram of the expansion with its track entering at `(x, y)`
+fn draw(svg: &mut String, expansion: &Expansion, x: usize, y: usize) {
+    if let Some(choice) = as_choice(expansion) {
+        return draw(svg, &choice, x, y);
+    }
+    let width = size(expansion).width;
+    match expansion {
+        Expansion::Literal(text) | Expansion::Token(text) | Expansion::Rule(text) => {
+            let (class, radius) = match expansion {
+                Expansion::Rule(_) => ("rule", 0),
+                _ => ("terminal", BOX_HEIGHT / 2),
+            };
+            let text = escape_html(text);
+            let node = format!(
+                r#"<rect class="{}" x="{}" y="{}" width="{}" height="{}" rx="{}"/><text x="{}" y="{}">{}</text>"#,
+                class,
+                x,
+                y - BOX_HEIGHT / 2,
+                width,
+                BOX_HEIGHT,
+                radius,
+                x + width / 2,
+                y + 4,
+                text
+            );
+            if let Expansion::Rule(name) = expansion {
+                let _ = writeln!(svg, r##"<a href="#{}">{}</a>"##, name, node);
+            } else {
+                let _ = writeln!(svg, "{}", node);
+            }
+        }
+        Expansion::Sequence(items) => {
+            let mut x = x;
+            for (i, item) in items.iter().enumerate() {
+                draw(svg, item, x, y);
+                x += size(item).width;
+                if i + 1 < items.len() {
+                    line(svg, x, y, x + GAP, y);
+                    x += GAP;
+                }
+            }
+        }
+        Expansion::Choice(alternatives) => {
+            let inner_width = width - 2 * RAIL;
+            let (left, right) = (x + RAIL, x + RAIL + inner_width);
+            let mut branch_y = y;
+            let mut previous_down = 0;
+            for (i, alternative) in alternatives.iter().enumerate() {
+                let alternative_size = size(alternative);
+                if i == 0 {
+                    line(svg, x, y, left, y);
+                    line(svg, right, y, x + width, y);
+                } else {
+                    branch_y += previous_down + GAP + alternative_size.up;
+                    let _ = writeln!(
+                        svg,
+                        r#"<path d="M{} {} H{} V{} H{} M{} {} H{} V{} H{}"/>"#,
+                        x,
+                        y,
+                        x + RAIL / 2,
+                        branch_y,
+                        left,
+                        right,
+                        branch_y,
+                        right + RAIL / 2,
+                        y,
+                        x + width
+                    );
+                }
+                draw(svg, alternative, left, branch_y);
+                if alternative_size.width < inner_width {
+                    line(
+                        svg,
+                        left + alternative_size.width,
+                        branch_y,
+                        right,
+                        branch_y,
+                    );
+                }
+                previous_down = alternative_size.down;
+            }
+        }
+        Expansion::OneOrMore(inner) => {
+            let inner_size = size(inner);
+            let (left, right) = (x + RAIL, x + RAIL + inner_size.width);
+            line(svg, x, y, left, y);
+            draw(svg, inner, left, y);
+            line(svg, right, y, x + width, y);
+            // The track going back to repeat it
+            let _ = writeln!(
+                svg,
+                r#"<path d="M{} {} V{} H{} V{}"/>"#,
+                right + RAIL / 2,
+                y,
+                y + inner_size.down + GAP,
+                x + RAIL / 2,
+                y
+            );
+        }
+        Expansion::Optional(_) | Expansion::ZeroOrMore(_) => unreachable!("drawn as choices"),
+    }
+}
+
+fn line(svg: &mut String, x1: usize, y1: usize, x2: usize, y2: usize) {
+    let _ = writeln!(svg, r#"<path d="M{} {} L{} {}"/>"#, x1, y1, x2, y2);
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const STYLE: &str = "body { font-family: sans-serif; }
+svg path { stroke: #333; stroke-width: 2; fill: none; }
+svg rect { stroke: #333; stroke-width: 2; fill: #fff; }
+svg rect.terminal { fill: #e4f2e4; }
+svg a:hover rect { fill: #e4ecf8; }
+svg text { font-family: monospace; font-size: 14px; text-anchor: middle; }
+pre { color: #555; }";
+
+/// A standalone HTML page with the diagram of each rule, each one linking
+/// to the rules it uses
+fn railroad_html(rules: &[(&str, Expansion)]) -> String {
+    let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    let _ = writeln!(
+        html,
+        "<title>Lox grammar</title>\n<style>\n{}\n</style>",
+        STYLE
+    );
+    html.push_str("</head>\n<body>\n<h1>Lox grammar</h1>\n");
+    for (name, body) in rules {
+        let body_size = size(body);
+        let (width, height) = (
+            body_size.width + 2 * RAIL,
+            body_size.up + body_size.down + 2 * GAP,
+        );
+        let y = GAP + body_size.up;
+        let _ = writeln!(html, r#"<h2 id="{0}">{0}</h2>"#, name);
+        let _ = writeln!(
+            html,
+            r#"<svg width="{}" height="{}" viewBox="0 0 {} {}">"#,
+            width, height, width, height
+        );
+        // Bars marking where the rule starts and ends
+        let _ = writeln!(
+            html,
+            r#"<path d="M2 {} V{} M2 {} H{} M{} {} H{} M{} {} V{}"/>"#,
+            y - 8,
+            y + 8,
+            y,
+            RAIL,
+            width - RAIL,
+            y,
+            width - 2,
+            width - 2,
+            y - 8,
+            y + 8
+        );
+        draw(&mut html, body, RAIL, y);
+        html.push_str("</svg>\n");
+        let _ = writeln!(html, "<pre>{}</pre>", escape_html(&ebnf_rule(name, body)));
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::Feature;
+
+    #[test]
+    fn grammar_rules_are_well_formed() {
+        for rule in GRAMMAR {
+            let body = parse(rule.body);
+            // Every rule used is defined
+            let mut pending = vec![&body];
+            while let Some(expansion) = pending.pop() {
+                match expansion {
+                    Expansion::Rule(name) => assert!(
+                        GRAMMAR.iter().any(|rule| rule.name == name),
+                        "rule '{}' used by '{}' is not defined",
+                        name,
+                        rule.name
+                    ),
+                    Expansion::Sequence(items) | Expansion::Choice(items) => pending.extend(items),
+                    Expansion::Optional(inner)
+                    | Expansion::ZeroOrMore(inner)
+                    | Expansion::OneOrMore(inner) => pending.push(inner),
+                    Expansion::Literal(_) | Expansion::Token(_) => {}
+                }
+            }
+            // Printing it back gives the same rule
+            assert_eq!(parse(&ebnf(&body, Context::Choice)), body);
+        }
+    }
+
+    #[test]
+    fn gated_rules_are_left_out_by_default() {
+        let grammar = render(Format::Ebnf, Features::default());
+        assert!(grammar
+            .contains("declaration    → classDecl | funDecl | importDecl | varDecl | statement ;"));
+        assert!(!grammar.contains("enumDecl"));
+
+        let mut features = Features::default();
+        features.enable(Feature::Enum);
+        let grammar = render(Format::Ebnf, features);
+        assert!(grammar.contains("enumDecl       → \"enum\""));
+        assert!(!grammar.contains("deferStmt"));
+    }
+
+    #[test]
+    fn precedence_table_lists_operators_from_loosest_to_tightest() {
+        let table = render(Format::Precedence, Features::default());
+        let lines: Vec<_> = table.lines().collect();
+        assert_eq!(lines[0], "comma          left   ,");
+        assert_eq!(lines[1], "assignment     right  = += -= *= /=");
+        assert!(lines.contains(&"factor         left   / * %"));
+        assert_eq!(lines[lines.len() - 2], "unary          prefix - !");
+        assert_eq!(lines[lines.len() - 1], "call           left   ( [ .");
+        assert!(!table.contains("|>"));
+
+        let table = render(Format::Precedence, Features::all());
+        assert!(table.contains("comparison     left   > >= < <=\npipe           left   |>\n"));
+    }
+}
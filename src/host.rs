@@ -0,0 +1,81 @@
+/// What the interpreter asks of the system it runs on
+///
+/// The library itself never touches the file system, the standard input or
+/// the system clock: `readLine`, `clock`, `import` and the time limit of
+/// runs go through the `Host` of the interpreter instead. The default one,
+/// `System`, uses the standard library, which has no clock on `wasm32` (so
+/// there `clock` fails and runs can't be limited in time). An embedder
+/// without a standard library to rely on (like a browser) gives its own.
+use std::fmt::Debug;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub trait Host: Debug {
+    /// The next line of input for `readLine`, with its line break, or
+    /// `None` once there are no more
+    fn read_line(&mut self) -> io::Result<Option<String>>;
+
+    /// Seconds since the Unix epoch, for `clock`, or `None` if there is no
+    /// clock to read
+    fn clock(&self) -> Option<f64>;
+
+    /// Time since a point of the host's choosing, which the time limit of
+    /// runs (`Limits::time`) is measured with, or `None` if there is no
+    /// clock to measure it with
+    fn now(&self) -> Option<Duration>;
+
+    /// The canonical path of the file at `path`, the same for every way of
+    /// writing it, which the modules imported are remembered by
+    fn resolve(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// The source of the module in the file at `path` (as resolved)
+    fn load(&mut self, path: &Path) -> io::Result<String>;
+}
+
+/// The host of a program run from the command line: lines are read from
+/// the standard input and modules from the file system
+#[derive(Debug, Default, Clone, Copy)]
+pub struct System;
+
+impl Host for System {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line)? {
+            0 => Ok(None),
+            _ => Ok(Some(line)),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn clock(&self) -> Option<f64> {
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("the clock should be after the Unix epoch");
+        Some(time.as_secs_f64())
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn clock(&self) -> Option<f64> {
+        None
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn now(&self) -> Option<Duration> {
+        static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+        Some(START.get_or_init(std::time::Instant::now).elapsed())
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn now(&self) -> Option<Duration> {
+        None
+    }
+
+    fn resolve(&self, path: &Path) -> io::Result<PathBuf> {
+        path.canonicalize()
+    }
+
+    fn load(&mut self, path: &Path) -> io::Result<String> {
+        std::fs::read_to_string(path)
+    }
+}
@@ -0,0 +1,152 @@
+/// Internal errors: bugs in rinlox itself rather than in the program it
+/// runs
+///
+/// Nothing in the scanner, parser, resolver or interpreter is meant to
+/// panic on any input, but `catch` turns a panic that slips through into
+/// an `Error` pointing at the line of rinlox's source that panicked, so it
+/// can be reported like any other diagnostic. Overflowing the native stack
+/// aborts the process instead of panicking, so it can't be caught: the
+/// stages recursing as deep as the code is nested carry on in a segment on
+/// the heap when the stack runs low (see `grow_stack`), and the parser
+/// limits how deep that is.
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::fmt::Display;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+
+use crate::Error;
+
+thread_local! {
+    /// Whether the thread is running a stage in `catch`, so its panics are
+    /// not printed
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+    /// Where the last panic caught happened
+    static LOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// A panic caught while running a stage of the pipeline
+#[derive(Debug, Clone, PartialEq)]
+pub struct InternalError {
+    pub message: String,
+    /// Where in rinlox's source the panic happened, as `file:line:column`
+    pub location: Option<String>,
+}
+
+impl Display for InternalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Internal error: {}", self.message)?;
+        if let Some(location) = &self.location {
+            write!(f, "\n[rinlox {}]", location)?;
+        }
+        Ok(())
+    }
+}
+
+/// Stack left when a recursion moves on to a new stack segment on the heap,
+/// which has to fit anything it can run before the next check
+#[cfg(not(target_arch = "wasm32"))]
+const STACK_RED_ZONE: usize = 256 * 1024;
+
+/// Size of the stack segments recursions move on to
+#[cfg(not(target_arch = "wasm32"))]
+const STACK_SEGMENT: usize = 8 * 1024 * 1024;
+
+/// Run a step of a recursion (like a call in the interpreter, or an
+/// expression in the parser), on a new stack segment if the thread's stack
+/// is running out. There is no switching stacks in WebAssembly, where the
+/// depth has to be kept under what the stack fits instead
+pub(crate) fn grow_stack<T>(step: impl FnOnce() -> T) -> T {
+    #[cfg(not(target_arch = "wasm32"))]
+    return stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, step);
+    #[cfg(target_arch = "wasm32")]
+    step()
+}
+
+/// Serialize a field of a node of the tree, which can take as much stack
+/// as walking it
+#[cfg(feature = "serde")]
+pub(crate) fn serialize_nested<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: serde::Serialize,
+    S: serde::Serializer,
+{
+    grow_stack(|| value.serialize(serializer))
+}
+
+/// Run `stage`, turning a panic in it into an `Error::Internal`
+///
+/// The panic is not printed as well. Whatever state the stage was changing
+/// may have been left half way through.
+pub fn catch<T>(stage: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+    install_hook();
+    let was_catching = CATCHING.replace(true);
+    let result = panic::catch_unwind(AssertUnwindSafe(stage));
+    CATCHING.set(was_catching);
+
+    result.unwrap_or_else(|payload| {
+        Err(Error::Internal(InternalError {
+            message: panic_message(payload.as_ref()),
+            location: LOCATION.take(),
+        }))
+    })
+}
+
+/// Wrap the panic hook, once, so it records where the panics in `catch`
+/// happen instead of printing them
+///
+/// The hook is shared by every thread, so it is never swapped back: a
+/// thread restoring it could drop the one another thread is relying on.
+fn install_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CATCHING.get() {
+                let location = info
+                    .location()
+                    .map(|at| format!("{}:{}:{}", at.file(), at.line(), at.column()));
+                LOCATION.set(location);
+            } else {
+                previous(info);
+            }
+        }));
+    });
+}
+
+/// The message `panic!` was given, when it is text
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match (
+        payload.downcast_ref::<&str>(),
+        payload.downcast_ref::<String>(),
+    ) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "the interpreter panicked".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catches_panics_with_their_location() {
+        let result: Result<(), Error> = catch(|| panic!("the {} broke", "scanner"));
+        let Err(Error::Internal(error)) = result else {
+            panic!("the panic should be caught, not {:?}", result);
+        };
+        assert_eq!(error.message, "the scanner broke");
+        let location = error.location.expect("the location should be known");
+        assert!(location.starts_with("src/internal.rs:"), "{}", location);
+    }
+
+    #[test]
+    fn passes_through_what_the_stage_returns() {
+        assert!(matches!(catch(|| Ok(1)), Ok(1)));
+        assert!(matches!(
+            catch(|| crate::run_source("print ;")),
+            Err(Error::Parse(_))
+        ));
+    }
+}
@@ -0,0 +1,1401 @@
+/// Tree-walking interpreter for the `Lox` programming language
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Debug, Display};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::class::{LoxClass, LoxInstance};
+use crate::enumeration::LoxEnum;
+use crate::environment::Environment;
+use crate::expr::{Depth, Expr, Object};
+use crate::features::Features;
+use crate::foreign::{self, LoxObject};
+use crate::function::{Closure, LoxCallable, LoxFunction};
+use crate::gc::{GcStats, Heap};
+use crate::host::{Host, System};
+use crate::internal;
+use crate::lexer::{Origin, Scanner, Span, Token, TokenType};
+use crate::list::LoxList;
+use crate::map::LoxMap;
+use crate::module::LoxModule;
+use crate::native::{self, NativeFunction};
+use crate::observer::Observer;
+use crate::parser::Parser;
+use crate::resolver::{GlobalSlots, Resolver};
+use crate::stmt::{ClassBody, Stmt};
+use crate::string;
+use crate::symbol::Symbol;
+
+/// How many calls can be nested by default before failing with a `Stack
+/// overflow.` error
+pub const MAX_CALL_DEPTH: usize = 10_000;
+
+/// Runtime values in Lox
+#[derive(Debug, Clone)]
+pub enum Value {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(Rc<str>),
+    Callable(Rc<dyn LoxCallable>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<LoxInstance>),
+    List(Rc<LoxList>),
+    Map(Rc<LoxMap>),
+    Enum(Rc<LoxEnum>),
+    /// One of the variants of an enum, by its position in the declaration
+    Variant(Rc<LoxEnum>, usize),
+    Module(Rc<LoxModule>),
+    /// A value of the program embedding the interpreter
+    Foreign(Rc<dyn LoxObject>),
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Boolean(l), Value::Boolean(r)) => l == r,
+            (Value::Number(l), Value::Number(r)) => l == r,
+            (Value::String(l), Value::String(r)) => l == r,
+            // Callables are only equal to themselves
+            (Value::Callable(l), Value::Callable(r)) => Rc::ptr_eq(l, r),
+            (Value::Class(l), Value::Class(r)) => Rc::ptr_eq(l, r),
+            (Value::Instance(l), Value::Instance(r)) => Rc::ptr_eq(l, r),
+            (Value::List(l), Value::List(r)) => Rc::ptr_eq(l, r),
+            (Value::Map(l), Value::Map(r)) => Rc::ptr_eq(l, r),
+            (Value::Enum(l), Value::Enum(r)) => Rc::ptr_eq(l, r),
+            (Value::Variant(l, i), Value::Variant(r, j)) => Rc::ptr_eq(l, r) && i == j,
+            (Value::Module(l), Value::Module(r)) => Rc::ptr_eq(l, r),
+            (Value::Foreign(l), Value::Foreign(r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        }
+    }
+}
+
+impl Value {
+    /// `false` and `nil` are falsey, and everything else is truthy
+    pub(crate) fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Boolean(false))
+    }
+}
+
+impl From<&Object> for Value {
+    fn from(object: &Object) -> Self {
+        match object {
+            Object::Nil => Value::Nil,
+            Object::Boolean(b) => Value::Boolean(*b),
+            Object::Number(n) => Value::Number(*n),
+            Object::String(s) => Value::String(s.as_str().into()),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", format_number(*n, None)),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Callable(c) => write!(f, "{}", c),
+            Value::Class(c) => write!(f, "{}", c),
+            Value::Instance(i) => write!(f, "{}", i),
+            Value::List(l) => write!(f, "{}", l),
+            Value::Map(m) => write!(f, "{}", m),
+            Value::Enum(e) => write!(f, "{}", e),
+            Value::Variant(e, index) => write!(f, "{}.{}", e.name, e.variant_name(*index)),
+            Value::Module(m) => write!(f, "{}", m),
+            Value::Foreign(o) => write!(f, "{}", o),
+        }
+    }
+}
+
+/// Write a number the way Lox shows it. Integral numbers never have a
+/// fractional part, and other numbers are rounded to `digits` significant
+/// digits if given (dropping any trailing zeros)
+pub fn format_number(n: f64, digits: Option<usize>) -> String {
+    match digits {
+        Some(digits) if n.is_finite() && n.fract() != 0.0 => {
+            // An `f64` never has more than 17 meaningful significant digits
+            let digits = digits.min(17) as i32;
+            let magnitude = n.abs().log10().floor() as i32;
+            let decimals = digits - 1 - magnitude;
+            if decimals <= 0 {
+                // Rounding happens in the integral part
+                let scale = 10f64.powi(-decimals);
+                return ((n / scale).round() * scale).to_string();
+            }
+            format!("{:.*}", decimals as usize, n)
+                .trim_end_matches('0')
+                .trim_end_matches('.')
+                .to_string()
+        }
+        // NOTE(alvaro): Rust already prints integral floats without the
+        // trailing `.0`, which is what Lox expects
+        _ => n.to_string(),
+    }
+}
+
+/// Error raised while evaluating, pointing to the token that caused it
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub token: Token,
+    /// Boxed rather than a `String`, as every `Result` carrying the error
+    /// is as big as it is
+    pub message: Box<str>,
+}
+
+/// What stopped a program with a `RuntimeError`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeErrorKind {
+    /// A mistake in the program, like calling something that can't be
+    /// called
+    Program,
+    /// The run went over one of its `Limits`
+    LimitExceeded(Limit),
+    /// What the program printed couldn't be written to its output, for
+    /// this reason (like a closed pipe)
+    Output(io::ErrorKind),
+}
+
+impl RuntimeError {
+    pub fn new(token: &Token, message: &str) -> Self {
+        Self {
+            kind: RuntimeErrorKind::Program,
+            token: token.clone(),
+            message: message.into(),
+        }
+    }
+
+    /// The program went over the `limit` at `token`
+    pub fn limit_exceeded(token: &Token, limit: Limit) -> Self {
+        Self {
+            kind: RuntimeErrorKind::LimitExceeded(limit),
+            ..Self::new(token, limit.message())
+        }
+    }
+
+    /// What the program printed at `token` couldn't be written
+    pub fn output_failed(token: &Token, error: &io::Error) -> Self {
+        let message = format!("Could not write the output: {}.", error);
+        Self {
+            kind: RuntimeErrorKind::Output(error.kind()),
+            ..Self::new(token, &message)
+        }
+    }
+
+    /// The limit the program went over, if that is what stopped it rather
+    /// than a mistake in it
+    pub fn limit(&self) -> Option<Limit> {
+        match self.kind {
+            RuntimeErrorKind::LimitExceeded(limit) => Some(limit),
+            _ => None,
+        }
+    }
+}
+
+/// Caps on how much a run can do, for hosts running programs they can't
+/// trust to end (e.g. a server, or a grader). Each run (`interpret`) starts
+/// with the whole budget
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Statements executed and expressions evaluated
+    pub operations: Option<u64>,
+    /// Wall-clock time, measured with the `Host::now` of the interpreter
+    /// (runs on a host without a clock can only be limited by `operations`)
+    pub time: Option<Duration>,
+}
+
+/// Which of the `Limits` a program went over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Operations,
+    Time,
+}
+
+impl Limit {
+    fn message(self) -> &'static str {
+        match self {
+            Limit::Operations => "Operation limit exceeded.",
+            Limit::Time => "Time limit exceeded.",
+        }
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\n[{}]", self.message, self.token.span)
+    }
+}
+
+type RuntimeResult<T> = Result<T, RuntimeError>;
+
+/// Reasons for the execution of statements to stop before reaching the end
+#[derive(Debug)]
+pub enum Unwind {
+    /// A `return` statement, carrying the returned value up to the call
+    Return(Value),
+    /// A `break` statement, leaving the innermost loop
+    Break,
+    /// A `continue` statement, going on to the next iteration of the
+    /// innermost loop
+    Continue,
+    Error(RuntimeError),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(err: RuntimeError) -> Self {
+        Unwind::Error(err)
+    }
+}
+
+/// Where `print` writes to
+pub(crate) struct Output(pub(crate) Box<dyn Write>);
+
+impl Debug for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Output")
+    }
+}
+
+/// Output handing each line printed (without its line break) to a callback
+struct Lines<F> {
+    line: Vec<u8>,
+    print: F,
+}
+
+impl<F: FnMut(&str)> Write for Lines<F> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for &byte in buf {
+            if byte == b'\n' {
+                (self.print)(&String::from_utf8_lossy(&self.line));
+                self.line.clear();
+            } else {
+                self.line.push(byte);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A call being executed
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// Name of the function (or class) called
+    pub name: String,
+    /// Closing parenthesis of the call (or the name of the property, for a
+    /// getter)
+    pub call: Token,
+}
+
+/// Interpreter state that persists across runs (e.g. REPL lines)
+#[derive(Debug)]
+pub struct Interpreter {
+    /// The natives, which every module sees (around its globals)
+    builtins: Rc<RefCell<Environment>>,
+    /// The outermost scope of the module being run, where the variables not
+    /// resolved to a local scope live
+    globals: Rc<RefCell<Environment>>,
+    /// Globals of the program itself, which are `globals` unless code from
+    /// an imported module is running
+    program: Rc<RefCell<Environment>>,
+    /// Slots of the globals of the program, which the code run is resolved
+    /// to
+    global_slots: GlobalSlots,
+    /// File of the program, which its imports are relative to (the current
+    /// directory if not known)
+    path: Option<PathBuf>,
+    /// Modules imported so far, by the canonical path of their file
+    modules: HashMap<PathBuf, Rc<LoxModule>>,
+    /// Files of the modules being imported, innermost last
+    importing: Vec<PathBuf>,
+    /// The innermost scope at the current point of execution
+    environment: Rc<RefCell<Environment>>,
+    /// Significant digits `print` uses for non-integral numbers (`None`
+    /// prints them with as many digits as needed to be exact)
+    precision: Option<usize>,
+    /// Bodies of the `defer` statements run so far in each block being
+    /// executed, innermost block last
+    deferred: Vec<Vec<Rc<Vec<Stmt>>>>,
+    /// Calls being executed, innermost last
+    frames: Vec<Frame>,
+    /// Calls the last runtime error unwound through, innermost first
+    unwound: Vec<Frame>,
+    /// Calls that can be nested before failing with a stack overflow
+    max_call_depth: usize,
+    limits: Limits,
+    /// Statements executed and expressions evaluated in this run
+    operations: u64,
+    /// When this run goes over the time limit, by the clock of the host
+    deadline: Option<Duration>,
+    /// Whether the natives inspecting the interpreter itself are defined
+    debug: bool,
+    output: Output,
+    /// Where input, time and modules come from
+    host: Box<dyn Host>,
+    /// Tool watching the execution, if any
+    observer: Option<Box<dyn Observer>>,
+    /// The objects created so far, to collect the cycles among them
+    heap: Heap,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::with_heap(Heap::default())
+    }
+}
+
+impl Interpreter {
+    /// Interpreter creating its objects in `heap`
+    fn with_heap(mut heap: Heap) -> Self {
+        let builtins = heap.alloc(RefCell::new(Environment::default()));
+        let global_slots = GlobalSlots::default();
+        let program = Environment::with_slots(Rc::clone(&builtins), global_slots.clone());
+        let program = heap.alloc(RefCell::new(program));
+        let mut interpreter = Self {
+            builtins,
+            environment: Rc::clone(&program),
+            globals: Rc::clone(&program),
+            program,
+            global_slots,
+            path: None,
+            modules: HashMap::new(),
+            importing: Vec::new(),
+            precision: None,
+            deferred: Vec::new(),
+            frames: Vec::new(),
+            unwound: Vec::new(),
+            max_call_depth: MAX_CALL_DEPTH,
+            limits: Limits::default(),
+            operations: 0,
+            deadline: None,
+            debug: false,
+            output: Output(Box::new(std::io::stdout())),
+            host: Box::new(System),
+            observer: None,
+            heap,
+        };
+        interpreter.define_native("assert", 2, native::assert);
+        interpreter.define_native("clock", 0, native::clock);
+        interpreter.define_native("len", 1, native::len);
+        interpreter.define_native("num", 1, native::num);
+        interpreter.define_native("numberToString", 2, native::number_to_string);
+        interpreter.define_native("readLine", 0, native::read_line);
+        interpreter.define_native("str", 1, native::str);
+        interpreter.define_native("type", 1, native::type_of);
+        interpreter
+    }
+
+    pub fn set_precision(&mut self, precision: Option<usize>) {
+        self.precision = precision;
+    }
+
+    /// Fail calls nested deeper than `depth` with a `Stack overflow.` error
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    /// Stop the runs from now on with a `RuntimeError::limit_exceeded` when
+    /// they go over the `limits`
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Make `print` write to `output` instead of the standard output
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Output(Box::new(output));
+    }
+
+    /// Hand each line `print` writes to `print`, without its line break,
+    /// instead of writing it to the standard output
+    pub fn set_print(&mut self, print: impl FnMut(&str) + 'static) {
+        self.set_output(Lines {
+            line: Vec::new(),
+            print,
+        });
+    }
+
+    /// Get input, time and modules from `host` instead of the system
+    pub fn set_host(&mut self, host: impl Host + 'static) {
+        self.host = Box::new(host);
+    }
+
+    pub(crate) fn host(&mut self) -> &mut dyn Host {
+        self.host.as_mut()
+    }
+
+    /// Tell `observer` about everything that happens while running code
+    /// from now on
+    pub fn set_observer(&mut self, observer: impl Observer + 'static) {
+        self.observer = Some(Box::new(observer));
+    }
+
+    /// Define the natives for debugging, which look into the interpreter
+    /// itself (like the call stack)
+    pub fn enable_debug(&mut self) {
+        self.debug = true;
+        self.define_native("callerName", 1, native::caller_name);
+        self.define_native("stackDepth", 0, native::stack_depth);
+    }
+
+    /// Forget every global defined so far, keeping the settings
+    pub fn reset(&mut self) {
+        let debug = self.debug;
+        let output = std::mem::replace(&mut self.output, Output(Box::new(std::io::sink())));
+        let host = std::mem::replace(&mut self.host, Box::new(System));
+        let heap = std::mem::take(&mut self.heap);
+        *self = Self {
+            precision: self.precision,
+            max_call_depth: self.max_call_depth,
+            limits: self.limits,
+            path: self.path.take(),
+            output,
+            host,
+            observer: self.observer.take(),
+            ..Self::with_heap(heap)
+        };
+        if debug {
+            self.enable_debug();
+        }
+        // The globals forgotten are likely in cycles with their functions
+        self.collect_garbage();
+    }
+
+    /// Free the objects only kept alive by cycles among them now, rather
+    /// than when enough objects were created since the last time
+    pub fn collect_garbage(&mut self) {
+        self.heap.collect();
+    }
+
+    /// How many objects the collections freed so far, and how many are
+    /// still alive
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.stats()
+    }
+
+    pub(crate) fn heap(&mut self) -> &mut Heap {
+        &mut self.heap
+    }
+
+    /// Get back to the top level after a panic (see `internal::catch`) left
+    /// the execution of a program half way through, keeping the globals
+    pub fn recover(&mut self) {
+        self.globals = Rc::clone(&self.program);
+        self.environment = Rc::clone(&self.globals);
+        self.deferred.clear();
+        self.frames.clear();
+        self.importing.clear();
+    }
+
+    /// Set the file the program comes from, so its imports are relative to
+    /// it rather than to the current directory
+    pub fn set_path(&mut self, path: &Path) {
+        self.path = Some(
+            self.host
+                .resolve(path)
+                .unwrap_or_else(|_| path.to_path_buf()),
+        );
+    }
+
+    /// The calls being executed, innermost last
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Where the last run failed with `error`: the function each call it
+    /// unwound through was in and where it was at in it, innermost first
+    /// and ending with the top level (`script`)
+    pub fn stack_trace(&self, error: &RuntimeError) -> Vec<(&str, Span)> {
+        let mut span = error.token.span;
+        let mut trace = Vec::new();
+        for frame in &self.unwound {
+            trace.push((frame.name.as_str(), span));
+            span = frame.call.span;
+        }
+        trace.push(("script", span));
+        trace
+    }
+
+    /// Bind a global variable, as if declared with `var`, e.g. to give a
+    /// script its configuration before running it
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.program
+            .borrow_mut()
+            .define(Symbol::intern(name), value);
+    }
+
+    pub fn remove_global(&mut self, name: &str) {
+        self.program.borrow_mut().remove(Symbol::intern(name));
+    }
+
+    /// Slots of the globals of the program, to resolve the code run next
+    /// with (see `Resolver::with_globals`). They stay the same until the
+    /// interpreter is `reset`
+    pub fn global_slots(&self) -> GlobalSlots {
+        self.global_slots.clone()
+    }
+
+    /// The global variables (natives included), sorted by name
+    ///
+    /// The values are a snapshot: running more code afterwards does not
+    /// change them.
+    pub fn globals(&self) -> impl Iterator<Item = (String, Value)> {
+        let builtins = self.builtins.borrow().bindings();
+        let globals = self.program.borrow().bindings();
+        // Globals shadow the natives with the same name
+        let globals: BTreeMap<_, _> = builtins.into_iter().chain(globals).collect();
+        globals.into_iter()
+    }
+
+    /// The variables of each local scope at the current point of execution
+    /// (the innermost first), which are not globals
+    pub fn locals(&self) -> Vec<Vec<(String, Value)>> {
+        let mut scopes = Vec::new();
+        let mut scope = Rc::clone(&self.environment);
+        while !Rc::ptr_eq(&scope, &self.globals) {
+            scopes.push(scope.borrow().bindings());
+            let Some(enclosing) = scope.borrow().enclosing() else {
+                break;
+            };
+            scope = enclosing;
+        }
+        scopes
+    }
+
+    /// Bind a function implemented in Rust as a global variable, so Lox code
+    /// can call it with `arity` arguments
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&mut Interpreter, &Token, Vec<Value>) -> Result<Value, RuntimeError> + 'static,
+    ) {
+        let native = NativeFunction::new(name, arity, Box::new(function));
+        self.builtins
+            .borrow_mut()
+            .define(Symbol::intern(name), Value::Callable(Rc::new(native)));
+    }
+
+    /// Text `print` shows for a value
+    pub fn stringify(&self, value: &Value) -> String {
+        match value {
+            Value::Number(n) => format_number(*n, self.precision),
+            Value::List(list) => list.show(|element| self.stringify(element)),
+            Value::Map(map) => map.show(|value| self.stringify(value)),
+            value => value.to_string(),
+        }
+    }
+
+    /// Run a fragment of a bigger program, like a cell of a notebook or a
+    /// selection in an editor, returning the value of its last statement if
+    /// it is an expression statement (`nil` otherwise)
+    ///
+    /// The fragments see what the ones run before declared, and the `;` at
+    /// the end of a line can be left out, as with `eval`. A fragment that
+    /// makes rinlox panic fails with an `Error::Internal`, leaving the
+    /// interpreter ready for the next one.
+    pub fn eval_fragment(&mut self, source: &str) -> Result<Value, crate::Error> {
+        let result = crate::internal::catch(|| crate::eval(self, source, Features::default()));
+        if let Err(crate::Error::Internal(_)) = result {
+            self.recover();
+        }
+        result.map(|evaluation| evaluation.value.unwrap_or(Value::Nil))
+    }
+
+    /// Run a program, returning the value of its last statement if it is
+    /// an expression statement
+    pub fn interpret(&mut self, statements: &[Stmt]) -> RuntimeResult<Option<Value>> {
+        self.unwound.clear();
+        self.operations = 0;
+        self.deadline = self
+            .limits
+            .time
+            .and_then(|time| Some(self.host.now()? + time));
+        // Top-level `defer`s run once the whole program is done
+        self.deferred.push(Vec::new());
+        let mut value = None;
+        let result = statements.iter().try_for_each(|stmt| {
+            value = match stmt {
+                Stmt::Expression { expression } => {
+                    self.notify(|observer, interpreter| observer.statement(interpreter, stmt));
+                    Some(self.evaluate(expression)?)
+                }
+                stmt => {
+                    self.execute(stmt)?;
+                    None
+                }
+            };
+            Ok(())
+        });
+        match self.run_deferred(result) {
+            Ok(()) => Ok(value),
+            Err(Unwind::Return(_)) => unreachable!("the resolver rejects top-level returns"),
+            Err(Unwind::Break | Unwind::Continue) => {
+                unreachable!("the resolver rejects breaks outside of loops")
+            }
+            Err(Unwind::Error(err)) => Err(err),
+        }
+    }
+
+    /// Let the observer (if any) know about something that happened
+    fn notify(&mut self, event: impl FnOnce(&mut dyn Observer, &Interpreter)) {
+        if let Some(mut observer) = self.observer.take() {
+            event(observer.as_mut(), self);
+            self.observer = Some(observer);
+        }
+    }
+
+    /// Fail if the run went over its limits, at `token`. Only loops and
+    /// calls check them, as nothing else can keep a program running
+    fn check_limits(&self, token: &Token) -> RuntimeResult<()> {
+        if self
+            .limits
+            .operations
+            .is_some_and(|operations| self.operations > operations)
+        {
+            return Err(RuntimeError::limit_exceeded(token, Limit::Operations));
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| self.host.now().is_some_and(|now| now > deadline))
+        {
+            return Err(RuntimeError::limit_exceeded(token, Limit::Time));
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, stmt: &Stmt) -> Result<(), Unwind> {
+        // Nested code can take more stack than the thread has
+        internal::grow_stack(|| {
+            self.operations += 1;
+            self.notify(|observer, interpreter| observer.statement(interpreter, stmt));
+            match stmt {
+                Stmt::Block { statements } => {
+                    let scope = Environment::new(Rc::clone(&self.environment));
+                    self.execute_block(statements, scope)?;
+                }
+                Stmt::Break { .. } => return Err(Unwind::Break),
+                Stmt::Continue { .. } => return Err(Unwind::Continue),
+                Stmt::Class {
+                    name,
+                    superclass,
+                    body,
+                } => self.class_declaration(name, superclass.as_ref(), body)?,
+                Stmt::Defer { body, .. } => self
+                    .deferred
+                    .last_mut()
+                    .expect("statements should run inside a block")
+                    .push(Rc::clone(body)),
+                Stmt::Enum { name, variants } => {
+                    let enumeration = LoxEnum::new(&name.lexeme, variants);
+                    self.environment
+                        .borrow_mut()
+                        .define(name.lexeme.symbol(), Value::Enum(Rc::new(enumeration)));
+                }
+                Stmt::Extend { class, body } => self.extend_class(class, body)?,
+                Stmt::Expression { expression } => {
+                    self.evaluate(expression)?;
+                }
+                Stmt::Function { name, params, body } => {
+                    let function = LoxFunction::new(name, params, body, self.closure(), false);
+                    let function = self.heap.alloc(function);
+                    self.environment
+                        .borrow_mut()
+                        .define(name.lexeme.symbol(), Value::Callable(function));
+                }
+                Stmt::If {
+                    condition,
+                    then_branch,
+                    else_branch,
+                } => {
+                    if self.evaluate(condition)?.is_truthy() {
+                        self.execute(then_branch)?;
+                    } else if let Some(else_branch) = else_branch {
+                        self.execute(else_branch)?;
+                    }
+                }
+                Stmt::While {
+                    keyword,
+                    condition,
+                    body,
+                    increment,
+                } => {
+                    while self.evaluate(condition)?.is_truthy() {
+                        self.check_limits(keyword)?;
+                        match self.execute(body) {
+                            Ok(()) | Err(Unwind::Continue) => {}
+                            Err(Unwind::Break) => break,
+                            Err(unwind) => return Err(unwind),
+                        }
+                        if let Some(increment) = increment {
+                            self.evaluate(increment)?;
+                        }
+                    }
+                }
+                Stmt::Import { path, name, .. } => {
+                    let module = self.import(path, name)?;
+                    self.environment
+                        .borrow_mut()
+                        .define(name.lexeme.symbol(), Value::Module(module));
+                }
+                Stmt::Print {
+                    keyword,
+                    expression,
+                } => {
+                    let value = self.evaluate(expression)?;
+                    let text = self.stringify(&value);
+                    writeln!(self.output.0, "{}", text)
+                        .map_err(|err| RuntimeError::output_failed(keyword, &err))?;
+                }
+                Stmt::Return { value, .. } => {
+                    let value = match value {
+                        Some(value) => self.evaluate(value)?,
+                        None => Value::Nil,
+                    };
+                    return Err(Unwind::Return(value));
+                }
+                Stmt::Var { name, initializer } => {
+                    let value = match initializer {
+                        Some(initializer) => self.evaluate(initializer)?,
+                        None => Value::Nil,
+                    };
+                    self.notify(|observer, interpreter| {
+                        observer.assignment(interpreter, name, &value)
+                    });
+                    self.environment
+                        .borrow_mut()
+                        .define(name.lexeme.symbol(), value);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn class_declaration(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Expr>,
+        body: &ClassBody,
+    ) -> RuntimeResult<()> {
+        let superclass = match superclass {
+            Some(expr) => match self.evaluate(expr)? {
+                Value::Class(class) => Some(class),
+                _ => {
+                    let Expr::Variable { name, .. } = expr else {
+                        unreachable!("superclass should be a variable");
+                    };
+                    return Err(RuntimeError::new(name, "Superclass must be a class."));
+                }
+            },
+            None => None,
+        };
+
+        self.environment
+            .borrow_mut()
+            .define(name.lexeme.symbol(), Value::Nil);
+
+        // Methods of a subclass close over an extra scope where `super` is
+        // bound to the superclass
+        let previous = if let Some(superclass) = &superclass {
+            let mut scope = Environment::new(Rc::clone(&self.environment));
+            scope.define(Symbol::SUPER, Value::Class(Rc::clone(superclass)));
+            let scope = self.heap.alloc(RefCell::new(scope));
+            Some(std::mem::replace(&mut self.environment, scope))
+        } else {
+            None
+        };
+
+        let class = LoxClass::new(&name.lexeme, superclass);
+        self.define_members(&class, body);
+
+        if let Some(previous) = previous {
+            self.environment = previous;
+        }
+
+        let class = self.heap.alloc(class);
+        self.environment
+            .borrow_mut()
+            .assign(name, Value::Class(class))
+    }
+
+    /// Add the methods of an `extend` declaration to the class
+    fn extend_class(&mut self, class: &Expr, body: &ClassBody) -> RuntimeResult<()> {
+        let Value::Class(class) = self.evaluate(class)? else {
+            let Expr::Variable { name, .. } = class else {
+                unreachable!("extended class should be a variable");
+            };
+            return Err(RuntimeError::new(name, "Can only extend classes."));
+        };
+
+        self.define_members(&class, body);
+        Ok(())
+    }
+
+    /// Add the methods in a class body to the class, closing over the
+    /// current scope
+    fn define_members(&mut self, class: &LoxClass, class_body: &ClassBody) {
+        for method in &class_body.methods {
+            let Stmt::Function { name, params, body } = method else {
+                unreachable!("class methods should be functions");
+            };
+            let method =
+                LoxFunction::new(name, params, body, self.closure(), name.lexeme == "init");
+            class.define_method(name.lexeme.symbol(), self.heap.alloc(method));
+        }
+        for getter in &class_body.getters {
+            let Stmt::Function { name, body, .. } = getter else {
+                unreachable!("getters should be functions");
+            };
+            let getter = LoxFunction::getter(name, body, self.closure());
+            class.define_method(name.lexeme.symbol(), self.heap.alloc(getter));
+        }
+        for method in &class_body.class_methods {
+            let Stmt::Function { name, params, body } = method else {
+                unreachable!("class methods should be functions");
+            };
+            let method = LoxFunction::new(name, params, body, self.closure(), false);
+            class.define_class_method(name.lexeme.symbol(), self.heap.alloc(method));
+        }
+    }
+
+    /// Load the module at `path` (a string, or an identifier naming a
+    /// `.lox` file), relative to the file importing it. Each file is run
+    /// once, the first time it is imported, and later imports get the same
+    /// module
+    fn import(&mut self, path: &Token, name: &Token) -> RuntimeResult<Rc<LoxModule>> {
+        let written = match &path.typ {
+            TokenType::String(file) => file.clone(),
+            _ => format!("{}.lox", path.lexeme),
+        };
+        let error = |message: &str| {
+            RuntimeError::new(
+                path,
+                &format!("Could not import '{}': {}", written, message),
+            )
+        };
+
+        let directory = self
+            .importing
+            .last()
+            .or(self.path.as_ref())
+            .and_then(|file| file.parent())
+            .unwrap_or(Path::new(""));
+        let file = self
+            .host
+            .resolve(&directory.join(&written))
+            .map_err(|err| error(&err.to_string()))?;
+        if let Some(module) = self.modules.get(&file) {
+            return Ok(Rc::clone(module));
+        }
+        let running = self.path.iter().chain(&self.importing);
+        if let Some(start) = running.clone().position(|running| *running == file) {
+            let cycle: Vec<_> = running
+                .skip(start)
+                .chain([&file])
+                .map(|file| file.file_name().unwrap_or_default().to_string_lossy())
+                .collect();
+            return Err(error(&format!(
+                "it is part of the cycle {}.",
+                cycle.join(" -> ")
+            )));
+        }
+
+        let source = self
+            .host
+            .load(&file)
+            .map_err(|err| error(&err.to_string()))?;
+        // The errors in the module point at its file, as it was written
+        let scanner = Scanner::new(source).with_origin(&Origin::new(&written, 0));
+        let statements = Parser::new(scanner)
+            .parse()
+            .map_err(|errors| error(&format!("it has errors:\n{}", join_lines(&errors))))?;
+        Resolver::default()
+            .resolve(&statements)
+            .map_err(|errors| error(&format!("it has errors:\n{}", join_lines(&errors))))?;
+
+        // The module runs at its own top level, with globals of its own
+        let globals = Environment::new(Rc::clone(&self.builtins));
+        let globals = self.heap.alloc(RefCell::new(globals));
+        let enclosing = self.swap_globals(Rc::clone(&globals));
+        let environment = std::mem::replace(&mut self.environment, Rc::clone(&globals));
+        self.importing.push(file.clone());
+        self.deferred.push(Vec::new());
+        let result = statements.iter().try_for_each(|stmt| self.execute(stmt));
+        let result = self.run_deferred(result);
+        self.importing.pop();
+        self.environment = environment;
+        self.swap_globals(enclosing);
+
+        match result {
+            Ok(()) => {}
+            Err(Unwind::Error(err)) => {
+                if let Some(limit) = err.limit() {
+                    return Err(RuntimeError::limit_exceeded(path, limit));
+                }
+                let message = format!(
+                    "Error in module '{}' at line {}: {}",
+                    written, err.token.span.line, err.message
+                );
+                return Err(RuntimeError::new(path, &message));
+            }
+            Err(Unwind::Return(_) | Unwind::Break | Unwind::Continue) => {
+                unreachable!("the resolver rejects them at the top level")
+            }
+        }
+        let module = self.heap.alloc(LoxModule::new(&name.lexeme, globals));
+        self.modules.insert(file, Rc::clone(&module));
+        Ok(module)
+    }
+
+    /// What a function declared at this point closes over: the current
+    /// scope, and the globals of the module it is in
+    fn closure(&self) -> Closure {
+        Closure {
+            environment: Rc::clone(&self.environment),
+            globals: Rc::clone(&self.globals),
+        }
+    }
+
+    /// Make `globals` the ones unresolved variables are looked up in (those
+    /// of the module a function was declared in, while it runs), returning
+    /// the previous ones
+    pub(crate) fn swap_globals(
+        &mut self,
+        globals: Rc<RefCell<Environment>>,
+    ) -> Rc<RefCell<Environment>> {
+        std::mem::replace(&mut self.globals, globals)
+    }
+
+    /// Call the callable with arguments whose count was already checked,
+    /// keeping track of it in the call stack. `paren` is the token of the
+    /// call to point errors at
+    pub(crate) fn call(
+        &mut self,
+        callable: Rc<dyn LoxCallable>,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> RuntimeResult<Value> {
+        if self.frames.len() >= self.max_call_depth {
+            return Err(RuntimeError::new(paren, "Stack overflow."));
+        }
+        self.check_limits(paren)?;
+        self.frames.push(Frame {
+            name: callable.name().to_string(),
+            call: paren.clone(),
+        });
+        let traced = self.observer.is_some().then(|| arguments.clone());
+        if let Some(arguments) = &traced {
+            self.notify(|observer, interpreter| {
+                let frame = interpreter.frames.last().expect("the call is on the stack");
+                observer.call(interpreter, frame, arguments)
+            });
+        }
+        // `execute` and `evaluate` see to the stack deep recursion takes
+        let result = callable.call(self, paren, arguments);
+        if traced.is_some() {
+            self.notify(|observer, interpreter| {
+                let frame = interpreter.frames.last().expect("the call is on the stack");
+                observer.returned(interpreter, frame, &result)
+            });
+        }
+        let frame = self.frames.pop().expect("the call is on the stack");
+        if result.is_err() {
+            self.unwound.push(frame);
+        }
+        result
+    }
+
+    /// Read a variable from the scope the resolver found it in, or from the
+    /// globals (by its slot, if it has one) if it was not found in any local
+    /// scope
+    fn look_up_variable(
+        &self,
+        name: &Token,
+        depth: &Depth,
+        slot: Option<usize>,
+    ) -> RuntimeResult<Value> {
+        match (depth.get(), slot) {
+            (Some(distance), _) => self.environment.borrow().get_at(distance, name),
+            (None, Some(slot)) => self.globals.borrow().get_slot(slot, name),
+            (None, None) => self.globals.borrow().get(name),
+        }
+    }
+
+    /// Execute the statements inside of the given scope, restoring the
+    /// current one afterwards (even if there was an error)
+    pub fn execute_block(&mut self, statements: &[Stmt], scope: Environment) -> Result<(), Unwind> {
+        let scope = self.heap.alloc(RefCell::new(scope));
+        let previous = std::mem::replace(&mut self.environment, scope);
+        self.deferred.push(Vec::new());
+        let result = statements.iter().try_for_each(|stmt| self.execute(stmt));
+        let result = self.run_deferred(result);
+        self.environment = previous;
+        result
+    }
+
+    /// Run the bodies deferred in the block that just finished with
+    /// `result`, last one first. They run however the block was left, and
+    /// an error in them takes over unless the block already had one
+    fn run_deferred(&mut self, mut result: Result<(), Unwind>) -> Result<(), Unwind> {
+        let deferred = self
+            .deferred
+            .pop()
+            .expect("blocks should push their deferred list");
+        for body in deferred.iter().rev() {
+            let scope = Environment::new(Rc::clone(&self.environment));
+            if let Err(err @ Unwind::Error(_)) = self.execute_block(body, scope) {
+                if !matches!(result, Err(Unwind::Error(_))) {
+                    result = Err(err);
+                }
+            }
+        }
+        result
+    }
+
+    /// Evaluate the expression in the current scope (it must have been
+    /// resolved first)
+    pub fn evaluate(&mut self, expr: &Expr) -> RuntimeResult<Value> {
+        // Nested code can take more stack than the thread has
+        internal::grow_stack(|| {
+            self.operations += 1;
+            match expr {
+                Expr::Literal { value } => Ok(value.into()),
+                Expr::Grouping { expression } => self.evaluate(expression),
+                Expr::List { elements, .. } => {
+                    let elements = elements
+                        .iter()
+                        .map(|element| self.evaluate(element))
+                        .collect::<RuntimeResult<Vec<_>>>()?;
+                    Ok(Value::List(self.heap.alloc(LoxList::new(elements))))
+                }
+                Expr::Map { brace, entries } => {
+                    let entries = entries
+                        .iter()
+                        .map(|(key, value)| Ok((self.evaluate(key)?, self.evaluate(value)?)))
+                        .collect::<RuntimeResult<Vec<_>>>()?;
+                    let map = LoxMap::new(brace, entries)?;
+                    Ok(Value::Map(self.heap.alloc(map)))
+                }
+                Expr::Index {
+                    object,
+                    bracket,
+                    index,
+                } => {
+                    let object = self.evaluate(object)?;
+                    let index = self.evaluate(index)?;
+                    match object {
+                        Value::List(list) => list.get(bracket, &index),
+                        Value::Map(map) => map.get(bracket, &index),
+                        Value::String(s) => string::get(bracket, &s, &index),
+                        _ => Err(RuntimeError::new(
+                            bracket,
+                            "Only lists, maps and strings can be indexed.",
+                        )),
+                    }
+                }
+                Expr::SetIndex {
+                    object,
+                    bracket,
+                    index,
+                    operator,
+                    value,
+                } => {
+                    let object = self.evaluate(object)?;
+                    match object {
+                        Value::List(_) | Value::Map(_) => {}
+                        Value::String(_) => {
+                            return Err(RuntimeError::new(bracket, "Strings can't be modified."))
+                        }
+                        _ => {
+                            return Err(RuntimeError::new(
+                                bracket,
+                                "Only lists, maps and strings can be indexed.",
+                            ))
+                        }
+                    }
+                    let index = self.evaluate(index)?;
+                    // The element is read before the value is evaluated, like
+                    // in `a[i] = a[i] + b`
+                    let current = match (operator, &object) {
+                        (None, _) => None,
+                        (Some(_), Value::List(list)) => Some(list.get(bracket, &index)?),
+                        (Some(_), Value::Map(map)) => Some(map.get(bracket, &index)?),
+                        _ => unreachable!("only lists and maps get this far"),
+                    };
+                    let mut value = self.evaluate(value)?;
+                    if let (Some(operator), Some(current)) = (operator, current) {
+                        value = binary(operator, current, value)?;
+                    }
+                    match object {
+                        Value::List(list) => list.set(bracket, &index, value.clone())?,
+                        Value::Map(map) => map.insert(bracket, index, value.clone())?,
+                        _ => unreachable!("only lists and maps get this far"),
+                    }
+                    Ok(value)
+                }
+                Expr::Lambda { params, body, .. } => {
+                    let function = LoxFunction::lambda(params, body, self.closure());
+                    Ok(Value::Callable(self.heap.alloc(function)))
+                }
+                Expr::Variable { name, depth, slot } => {
+                    self.look_up_variable(name, depth, slot.get())
+                }
+                Expr::Assign {
+                    name,
+                    value,
+                    depth,
+                    slot,
+                } => {
+                    let value = self.evaluate(value)?;
+                    match (depth.get(), slot.get()) {
+                        (Some(distance), _) => self.environment.borrow_mut().assign_at(
+                            distance,
+                            name,
+                            value.clone(),
+                        )?,
+                        (None, Some(slot)) => {
+                            self.globals
+                                .borrow_mut()
+                                .assign_slot(slot, name, value.clone())?
+                        }
+                        (None, None) => self.globals.borrow_mut().assign(name, value.clone())?,
+                    }
+                    self.notify(|observer, interpreter| {
+                        observer.assignment(interpreter, name, &value)
+                    });
+                    Ok(value)
+                }
+                Expr::Logical {
+                    left,
+                    operator,
+                    right,
+                } => {
+                    // Short-circuit, returning the operand that decided the
+                    // result (not a coerced boolean)
+                    let left = self.evaluate(left)?;
+                    let short_circuits = match operator.typ {
+                        TokenType::Or => left.is_truthy(),
+                        TokenType::And => !left.is_truthy(),
+                        _ => unreachable!("invalid logical operator {}", operator),
+                    };
+                    if short_circuits {
+                        Ok(left)
+                    } else {
+                        self.evaluate(right)
+                    }
+                }
+                Expr::Conditional {
+                    condition,
+                    then_branch,
+                    else_branch,
+                } => {
+                    if self.evaluate(condition)?.is_truthy() {
+                        self.evaluate(then_branch)
+                    } else {
+                        self.evaluate(else_branch)
+                    }
+                }
+                Expr::Call {
+                    callee,
+                    paren,
+                    arguments,
+                } => {
+                    let callee = self.evaluate(callee)?;
+                    let arguments = arguments
+                        .iter()
+                        .map(|argument| self.evaluate(argument))
+                        .collect::<RuntimeResult<Vec<_>>>()?;
+
+                    let callable: Rc<dyn LoxCallable> = match callee {
+                        Value::Callable(callable) => callable,
+                        Value::Class(class) => Rc::new(class),
+                        _ => {
+                            return Err(RuntimeError::new(
+                                paren,
+                                "Can only call functions and classes.",
+                            ))
+                        }
+                    };
+                    if arguments.len() != callable.arity() {
+                        return Err(RuntimeError::new(
+                            paren,
+                            &format!(
+                                "Expected {} arguments but got {}.",
+                                callable.arity(),
+                                arguments.len()
+                            ),
+                        ));
+                    }
+                    self.call(callable, paren, arguments)
+                }
+                Expr::Get { object, name } => match self.evaluate(object)? {
+                    Value::Instance(instance) => instance.get(self, name),
+                    Value::Class(class) => class.get(self, name),
+                    Value::Enum(enumeration) => enumeration.get(name),
+                    Value::Module(module) => module.get(name),
+                    Value::Foreign(object) => foreign::method(&object, name),
+                    Value::List(list) => list.method(name),
+                    Value::Map(map) => map.method(name),
+                    Value::String(s) => string::method(&s, name),
+                    _ => Err(RuntimeError::new(name, "Only instances have properties.")),
+                },
+                Expr::Set {
+                    object,
+                    name,
+                    operator,
+                    value,
+                } => {
+                    let Value::Instance(instance) = self.evaluate(object)? else {
+                        return Err(RuntimeError::new(name, "Only instances have fields."));
+                    };
+                    let current = match operator {
+                        Some(_) => Some(instance.get(self, name)?),
+                        None => None,
+                    };
+                    let mut value = self.evaluate(value)?;
+                    if let (Some(operator), Some(current)) = (operator, current) {
+                        value = binary(operator, current, value)?;
+                    }
+                    instance.set(name, value.clone());
+                    Ok(value)
+                }
+                Expr::This { keyword, depth } => self.look_up_variable(keyword, depth, None),
+                Expr::Super { method, depth, .. } => {
+                    let distance = depth.get().expect("`super` should be resolved to a scope");
+                    let environment = self.environment.borrow();
+                    let Some(Value::Class(superclass)) =
+                        environment.lookup_at(distance, Symbol::SUPER)
+                    else {
+                        unreachable!("`super` should be bound to a class");
+                    };
+                    // `this` is always bound in the scope right inside the one
+                    // binding `super`
+                    let instance = environment
+                        .lookup_at(distance - 1, Symbol::THIS)
+                        .expect("`this` should be bound inside methods");
+                    // Inside a class method `this` is the class, whose
+                    // superclass has the class methods
+                    let found = match instance {
+                        Value::Class(_) => superclass.find_class_method(method.lexeme.symbol()),
+                        _ => superclass.find_method(method.lexeme.symbol()),
+                    };
+                    let Some(found) = found else {
+                        let message = format!("Undefined property '{}'.", method.lexeme);
+                        return Err(RuntimeError::new(method, &message));
+                    };
+                    let bound = found.bind(&mut self.heap, instance);
+                    drop(environment);
+                    if found.is_getter() {
+                        return self.call(bound, method, Vec::new());
+                    }
+                    Ok(Value::Callable(bound))
+                }
+                Expr::Unary { operator, right } => {
+                    let right = self.evaluate(right)?;
+                    match operator.typ {
+                        TokenType::Minus => {
+                            let n = check_number_operand(operator, &right)?;
+                            Ok(Value::Number(-n))
+                        }
+                        TokenType::Bang => Ok(Value::Boolean(!right.is_truthy())),
+                        _ => unreachable!("invalid unary operator {}", operator),
+                    }
+                }
+                Expr::Binary {
+                    left,
+                    operator,
+                    right,
+                } => {
+                    let left = self.evaluate(left)?;
+                    let right = self.evaluate(right)?;
+                    binary(operator, left, right)
+                }
+            }
+        })
+    }
+}
+
+/// Apply a binary operator (but `and` and `or`, which don't evaluate
+/// both sides) to the values of its operands
+fn binary(operator: &Token, left: Value, right: Value) -> RuntimeResult<Value> {
+    match operator.typ {
+        TokenType::Minus => {
+            let (l, r) = check_number_operands(operator, &left, &right)?;
+            Ok(Value::Number(l - r))
+        }
+        TokenType::Slash => {
+            let (l, r) = check_number_operands(operator, &left, &right)?;
+            Ok(Value::Number(l / r))
+        }
+        TokenType::Star => {
+            let (l, r) = check_number_operands(operator, &left, &right)?;
+            Ok(Value::Number(l * r))
+        }
+        TokenType::Percent => {
+            let (l, r) = check_number_operands(operator, &left, &right)?;
+            Ok(Value::Number(l % r))
+        }
+        // Both sides are evaluated for their effects, and the
+        // right one is the result
+        TokenType::Comma => Ok(right),
+        TokenType::Plus => match (left, right) {
+            (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l + r)),
+            (Value::String(l), Value::String(r)) => Ok(Value::String(format!("{}{}", l, r).into())),
+            _ => Err(RuntimeError::new(
+                operator,
+                "Operands must be two numbers or two strings.",
+            )),
+        },
+        TokenType::Greater => {
+            let (l, r) = check_number_operands(operator, &left, &right)?;
+            Ok(Value::Boolean(l > r))
+        }
+        TokenType::GreaterEqual => {
+            let (l, r) = check_number_operands(operator, &left, &right)?;
+            Ok(Value::Boolean(l >= r))
+        }
+        TokenType::Less => {
+            let (l, r) = check_number_operands(operator, &left, &right)?;
+            Ok(Value::Boolean(l < r))
+        }
+        TokenType::LessEqual => {
+            let (l, r) = check_number_operands(operator, &left, &right)?;
+            Ok(Value::Boolean(l <= r))
+        }
+        TokenType::BangEqual => Ok(Value::Boolean(left != right)),
+        TokenType::EqualEqual => Ok(Value::Boolean(left == right)),
+        _ => unreachable!("invalid binary operator {}", operator),
+    }
+}
+
+/// The errors, one per line
+fn join_lines<T: Display>(errors: &[T]) -> String {
+    let lines: Vec<_> = errors.iter().map(ToString::to_string).collect();
+    lines.join("\n")
+}
+
+fn check_number_operand(operator: &Token, operand: &Value) -> RuntimeResult<f64> {
+    match operand {
+        Value::Number(n) => Ok(*n),
+        _ => Err(RuntimeError::new(operator, "Operand must be a number.")),
+    }
+}
+
+fn check_number_operands(
+    operator: &Token,
+    left: &Value,
+    right: &Value,
+) -> RuntimeResult<(f64, f64)> {
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => Ok((*l, *r)),
+        _ => Err(RuntimeError::new(operator, "Operands must be numbers.")),
+    }
+}
@@ -0,0 +1,149 @@
+/// JSON output of the front end, for tools that consume the tokens or the
+/// syntax tree without linking to the crate
+///
+/// The documents are objects with the `version` of their schema, bumped
+/// whenever a change could break a consumer, and the tokens, statements or
+/// identifiers in an array:
+///
+/// ```text
+/// {"version": 1, "tokens": [{"type": "Number", "literal": 1.0, "lexeme": "1", "span": {...}}, ...]}
+/// {"version": 1, "statements": [{"kind": "Print", "expression": {"kind": "Literal", "value": 1.0}}, ...]}
+/// {"version": 1, "identifiers": [{"name": {"type": "Identifier", ...}, "kind": "global", "declaration": true}, ...]}
+/// ```
+///
+/// Tokens have their `type`, the value of literals, their `lexeme` and
+/// their `span`. Nodes have their `kind` and their fields, named as in
+/// `expr` and `stmt`. Identifiers have their token as `name`, what they
+/// name as `kind` (see `semantic::Kind`) and whether they are where it is
+/// declared.
+use serde::Serialize;
+
+use crate::lexer::Token;
+use crate::semantic::SemanticToken;
+use crate::stmt::Stmt;
+
+/// Version of the schema of the documents
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Tokens<'a> {
+    version: u32,
+    tokens: &'a [Token],
+}
+
+#[derive(Serialize)]
+struct Ast<'a> {
+    version: u32,
+    statements: &'a [Stmt],
+}
+
+#[derive(Serialize)]
+struct Identifiers<'a> {
+    version: u32,
+    identifiers: &'a [SemanticToken],
+}
+
+/// The token stream (including the final `Eof`) as a JSON document
+pub fn tokens(tokens: &[Token]) -> String {
+    to_json(&Tokens {
+        version: SCHEMA_VERSION,
+        tokens,
+    })
+}
+
+/// The statements of a program as a JSON document
+pub fn ast(statements: &[Stmt]) -> String {
+    to_json(&Ast {
+        version: SCHEMA_VERSION,
+        statements,
+    })
+}
+
+/// What each identifier of a program names (see `semantic::classify`) as
+/// a JSON document
+pub fn identifiers(identifiers: &[SemanticToken]) -> String {
+    to_json(&Identifiers {
+        version: SCHEMA_VERSION,
+        identifiers,
+    })
+}
+
+fn to_json(document: &impl Serialize) -> String {
+    serde_json::to_string_pretty(document).expect("the front end should serialize to JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+
+    fn parse_json(text: &str) -> Value {
+        serde_json::from_str(text).expect("the output should be valid JSON")
+    }
+
+    #[test]
+    fn serializes_tokens() {
+        let tokens: Vec<_> = Scanner::new("x = \"a\";".to_string())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let document = parse_json(&super::tokens(&tokens));
+        assert_eq!(document["version"], json!(SCHEMA_VERSION));
+        assert_eq!(
+            document["tokens"][0],
+            json!({
+                "type": "Identifier",
+                "lexeme": "x",
+                "span": {
+                    "start": 0, "end": 1, "line": 1, "column": 1, "end_line": 1, "end_column": 2
+                }
+            })
+        );
+        assert_eq!(document["tokens"][2]["type"], json!("String"));
+        assert_eq!(document["tokens"][2]["literal"], json!("a"));
+        assert_eq!(document["tokens"][4]["type"], json!("Eof"));
+    }
+
+    #[test]
+    fn serializes_the_syntax_tree() {
+        let statements = Parser::new(Scanner::new("print -x;".to_string()))
+            .parse()
+            .unwrap();
+        let document = parse_json(&ast(&statements));
+        let print = &document["statements"][0];
+        assert_eq!(print["kind"], json!("Print"));
+        assert_eq!(print["expression"]["kind"], json!("Unary"));
+        assert_eq!(print["expression"]["operator"]["lexeme"], json!("-"));
+        // The resolver's depths are not part of the tree
+        assert_eq!(
+            print["expression"]["right"],
+            json!({
+                "kind": "Variable",
+                "name": {
+                    "type": "Identifier",
+                    "lexeme": "x",
+                    "span": {
+                        "start": 7, "end": 8, "line": 1, "column": 8, "end_line": 1, "end_column": 9
+                    }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn serializes_what_the_identifiers_name() {
+        let statements = Parser::new(Scanner::new("fun f(n) { return n; }".to_string()))
+            .parse()
+            .unwrap();
+        let document = parse_json(&identifiers(&crate::semantic::classify(&statements)));
+        let identifiers = document["identifiers"].as_array().unwrap();
+        assert_eq!(identifiers.len(), 3);
+        assert_eq!(identifiers[0]["kind"], json!("function"));
+        assert_eq!(identifiers[0]["declaration"], json!(true));
+        assert_eq!(identifiers[2]["name"]["lexeme"], json!("n"));
+        assert_eq!(identifiers[2]["kind"], json!("parameter"));
+        assert_eq!(identifiers[2]["declaration"], json!(false));
+    }
+}
@@ -1,17 +1,26 @@
 /// Lexer for the `Lox` programming language
-use std::collections::HashMap;
-use std::fmt::Display;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Debug, Display};
+use std::io::{BufRead, ErrorKind};
+use std::ops::Deref;
+use std::rc::Rc;
+use std::sync::OnceLock;
 
-use crate::Lox;
+use crate::diagnostics::Note;
+use crate::features::{self, Feature, Features};
+use crate::symbol::Symbol;
 
 static KEYWORDS_PAIRS: &[(&str, TokenType)] = &[
     ("and", TokenType::And),
+    ("break", TokenType::Break),
     ("class", TokenType::Class),
+    ("continue", TokenType::Continue),
     ("else", TokenType::Else),
     ("false", TokenType::False),
     ("for", TokenType::For),
     ("fun", TokenType::Fun),
     ("if", TokenType::If),
+    ("import", TokenType::Import),
     ("nil", TokenType::Nil),
     ("or", TokenType::Or),
     ("print", TokenType::Print),
@@ -23,18 +32,51 @@ static KEYWORDS_PAIRS: &[(&str, TokenType)] = &[
     ("while", TokenType::While),
 ];
 
+/// Keywords of the experimental features, only reserved when their feature
+/// is enabled (see `Feature::of_keyword`)
+static GATED_KEYWORDS_PAIRS: &[(&str, TokenType)] = &[
+    ("defer", TokenType::Defer),
+    ("enum", TokenType::Enum),
+    ("extend", TokenType::Extend),
+];
+
+type Keywords = HashMap<&'static str, TokenType>;
+
+/// Keyword table, built once and shared by every scanner (e.g. one per
+/// REPL line)
+fn keywords() -> &'static Keywords {
+    static KEYWORDS: OnceLock<Keywords> = OnceLock::new();
+    KEYWORDS.get_or_init(|| {
+        KEYWORDS_PAIRS
+            .iter()
+            .chain(GATED_KEYWORDS_PAIRS)
+            .cloned()
+            .collect()
+    })
+}
+
 /// Type of Tokens existing in Lox
-#[derive(Debug, Clone)]
-enum TokenType {
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(tag = "type", content = "literal")
+)]
+pub enum TokenType {
     // Single character
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
+    Colon,
     Comma,
     Dot,
     Minus,
+    Percent,
     Plus,
+    Question,
     SemiColon,
     Slash,
     Star,
@@ -48,6 +90,11 @@ enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    MinusEqual,
+    Pipe,
+    PlusEqual,
+    SlashEqual,
+    StarEqual,
 
     // Literals
     Identifier,
@@ -56,12 +103,15 @@ enum TokenType {
 
     // Keywords
     And,
+    Break,
     Class,
+    Continue,
     Else,
     False,
     Fun,
     For,
     If,
+    Import,
     Nil,
     Or,
     Print,
@@ -72,25 +122,218 @@ enum TokenType {
     Var,
     While,
 
+    // Extended mode keywords
+    Defer,
+    Enum,
+    Extend,
+
+    /// Only scanned for the tools keeping comments (see
+    /// `Scanner::with_comments`)
+    Comment,
+
     Eof,
 }
 
+impl TokenType {
+    /// Whether tokens of this type are names, identifiers or keywords,
+    /// whose lexemes are interned
+    pub fn is_name(&self) -> bool {
+        use TokenType::*;
+        matches!(
+            self,
+            Identifier
+                | And
+                | Break
+                | Class
+                | Continue
+                | Else
+                | False
+                | Fun
+                | For
+                | If
+                | Import
+                | Nil
+                | Or
+                | Print
+                | Return
+                | Super
+                | This
+                | True
+                | Var
+                | While
+                | Defer
+                | Enum
+                | Extend
+        )
+    }
+}
+
 impl Display for TokenType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
     }
 }
 
+/// Location of a piece of source code
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Span {
+    /// Byte offset of the first character
+    pub start: usize,
+    /// Byte offset right after the last character
+    pub end: usize,
+    /// Line of the first character, starting at 1
+    pub line: usize,
+    /// Column of the first character within its line, counted in
+    /// characters and starting at 1
+    pub column: usize,
+    /// Line and column right after the last character, on a later line
+    /// than the start for tokens spanning several lines
+    pub end_line: usize,
+    pub end_column: usize,
+    /// Logical file the source is part of, if it was scanned with an
+    /// `Origin` (whose line offset the lines already count)
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub file: Option<Symbol>,
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.file {
+            Some(file) => write!(f, "{}:{}, col {}", file, self.line, self.column),
+            None => write!(f, "line {}, col {}", self.line, self.column),
+        }
+    }
+}
+
+/// Where a source comes from, when it is a piece of something larger (the
+/// session of a prompt, a snippet embedded in a document...) or a file of
+/// its own (an imported module): the name of the logical file it is in,
+/// and how many lines of that file come before it
+///
+/// A source scanned with an origin has its locations reported in the
+/// logical file, like `[repl:3, col 5]`, in every error and stack trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    pub name: String,
+    pub line_offset: usize,
+}
+
+impl Origin {
+    pub fn new(name: &str, line_offset: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            line_offset,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Token {
-    typ: TokenType,
-    lexeme: String,
-    line: usize,
+    /// Serialized as `type`, with the value of literals in `literal`
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub typ: TokenType,
+    pub lexeme: Lexeme,
+    pub span: Span,
 }
 
 impl Token {
-    fn new(typ: TokenType, lexeme: String, line: usize) -> Self {
-        Self { typ, lexeme, line }
+    pub fn new(typ: TokenType, lexeme: &str, span: Span) -> Self {
+        let lexeme = if typ.is_name() {
+            Lexeme::Name(Symbol::intern(lexeme))
+        } else {
+            Lexeme::Text(lexeme.into())
+        };
+        Self { typ, lexeme, span }
+    }
+}
+
+/// Text of a token, as written in the source
+///
+/// Names are interned, as the interpreter looks them up by their symbol.
+/// The rest (literals, comments, punctuation) keep a copy of their own
+/// instead, freed along with their tokens: a long session at the prompt
+/// would otherwise keep every string and number it ever scanned.
+#[derive(Clone)]
+pub enum Lexeme {
+    Name(Symbol),
+    Text(Rc<str>),
+}
+
+impl Lexeme {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Lexeme::Name(symbol) => symbol.as_str(),
+            Lexeme::Text(text) => text,
+        }
+    }
+
+    /// The symbol of a name. Other lexemes are never looked up, but would
+    /// be interned here if they were
+    pub fn symbol(&self) -> Symbol {
+        match self {
+            Lexeme::Name(symbol) => *symbol,
+            Lexeme::Text(text) => Symbol::intern(text),
+        }
+    }
+}
+
+impl Deref for Lexeme {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq for Lexeme {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Lexeme::Name(a), Lexeme::Name(b)) => a == b,
+            _ => self.as_str() == other.as_str(),
+        }
+    }
+}
+
+impl Eq for Lexeme {}
+
+impl PartialEq<str> for Lexeme {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Lexeme {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Display for Lexeme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Debug for Lexeme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Lexeme {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// Tokens are equal if they are the same lexeme, regardless of where they
+/// appear in the source, so that ASTs can be compared structurally
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        self.typ == other.typ && self.lexeme == other.lexeme
     }
 }
 
@@ -100,81 +343,255 @@ impl Display for Token {
     }
 }
 
-// FIXME(alvaro): Make this Scanner work with a single-pass Iterator
-// over the tokens (with a peekable method) so that avoid unnecessary
-// loops over the source characters (see `peek` and `advance`)
+/// What is wrong in a `ScanError`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanErrorKind {
+    UnexpectedCharacter,
+    /// The source ends inside a string, so more of it could close it
+    UnterminatedString,
+    /// The source ends inside a block comment, like a string
+    UnterminatedComment,
+    InvalidEscape,
+    /// Syntax of an experimental feature not enabled, or a directive
+    /// naming a feature that doesn't exist
+    Feature,
+    /// The source read by the scanner could not be read, or is not UTF-8
+    Read,
+}
+
+/// Error found while scanning, such as an unexpected character
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanError {
+    pub kind: ScanErrorKind,
+    pub span: Span,
+    pub message: String,
+    /// Other places in the source the error is about, like the end of the
+    /// source for an unterminated string
+    pub notes: Vec<Note>,
+}
+
+impl Display for ScanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] Error: {}", self.span, self.message)
+    }
+}
+
+type ScanResult<T> = Result<T, ScanError>;
+
+/// How much of the source already scanned a reading scanner keeps before
+/// dropping it
+const DROP_AFTER: usize = 64 * 1024;
+
+/// Same message as `std::io::read_to_string` for a source that is not UTF-8
+const INVALID_UTF8: &str = "stream did not contain valid UTF-8";
+
+/// Turns source code into tokens, one at a time
+///
+/// The scanner is an iterator, so tokens can be consumed as they are
+/// scanned without storing them all first. It always ends with a single
+/// `Eof` token, and errors are yielded in place of the offending token so
+/// scanning can go on after them.
+///
+/// The source can also be read as it is scanned (see `from_reader`), so
+/// big files don't have to fit in memory.
 pub struct Scanner {
+    /// The part of the source that may still be needed: from the current
+    /// lexeme on, when the source is being read
     source: String,
-    pub tokens: Vec<Token>,
+    /// Byte offset in the whole source where `source` starts
+    base: usize,
+    /// Where the rest of the source is read from, until it ends
+    reader: Option<Box<dyn BufRead>>,
+    /// Bytes read that don't make a whole character yet
+    undecoded: Vec<u8>,
+    /// Why reading the source stopped early, to report before the end
+    read_error: Option<String>,
+    /// Byte offset of the first character of the current lexeme
     start: usize,
+    /// Byte offset of the next character to consume. It always lies on a
+    /// character boundary, so looking at the next character is O(1)
     current: usize,
+    /// Line and column of the next character to consume
     line: usize,
-    keywords: HashMap<&'static str, TokenType>,
+    column: usize,
+    /// Line and column of the first character of the current lexeme
+    start_line: usize,
+    start_column: usize,
+    /// Last character consumed, and where the line it is on ends (before
+    /// its line break) if it ends one
+    last: Option<char>,
+    line_end: (usize, usize),
+    /// Logical file of the source, for the spans
+    file: Option<Symbol>,
+    /// Experimental features whose keywords are recognized
+    features: Features,
+    /// Whether comments are returned as tokens instead of skipped
+    comments: bool,
+    /// Whether the `Eof` token was already returned
+    finished: bool,
+    /// Results already scanned, to return before scanning more (e.g. the
+    /// token of a string after the errors of its bad escapes)
+    pending: VecDeque<ScanResult<Token>>,
 }
 
 impl Scanner {
     pub fn new(source: String) -> Self {
-        Scanner {
+        Self::scanning(source, None)
+    }
+
+    /// Scan the source as it is read, keeping only what the current lexeme
+    /// needs. If reading fails, or the source is not valid UTF-8, the error
+    /// is returned after the tokens read until then
+    pub fn from_reader(reader: impl BufRead + 'static) -> Self {
+        Self::scanning(String::new(), Some(Box::new(reader)))
+    }
+
+    fn scanning(source: String, reader: Option<Box<dyn BufRead>>) -> Self {
+        let mut scanner = Scanner {
             source,
-            tokens: Vec::new(),
+            base: 0,
+            reader,
+            undecoded: Vec::new(),
+            read_error: None,
             start: 0,
             current: 0,
             line: 1,
-            keywords: KEYWORDS_PAIRS.iter().cloned().collect(),
-        }
+            column: 1,
+            start_line: 1,
+            start_column: 1,
+            last: None,
+            line_end: (0, 1),
+            file: None,
+            features: Features::default(),
+            comments: false,
+            finished: false,
+            pending: VecDeque::new(),
+        };
+        scanner.skip_shebang();
+        scanner
     }
 
-    pub fn scan_tokens(&mut self, interpreter: &Lox) -> &[Token] {
-        while !self.is_at_end() {
-            self.start = self.current;
-            self.scan_token(interpreter);
+    /// Skip a `#!` line at the start, so scripts can be run directly on
+    /// Unix. The line break after it is scanned as usual to keep the line
+    /// numbers right
+    fn skip_shebang(&mut self) {
+        while self.source.len() < 2 && self.fill() {}
+        if !self.source.starts_with("#!") {
+            return;
         }
+        while !self.source.contains('\n') && self.fill() {}
+        let shebang = self.source.find('\n').unwrap_or(self.source.len());
+        self.start = shebang;
+        self.current = shebang;
+        self.column = self.source[..shebang].chars().count() + 1;
+        self.start_column = self.column;
+    }
 
-        self.tokens
-            .push(Token::new(TokenType::Eof, "".to_string(), self.line));
-        &self.tokens
+    /// Also recognize the keywords of the given experimental features
+    pub fn with_features(mut self, features: Features) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// Report the locations of the tokens in the logical file the source
+    /// comes from (see `Origin`)
+    pub fn with_origin(mut self, origin: &Origin) -> Self {
+        self.file = Some(Symbol::intern(&origin.name));
+        self.line += origin.line_offset;
+        self.start_line += origin.line_offset;
+        self
+    }
+
+    /// Return the comments as `Comment` tokens, for tools that need to keep
+    /// them (like the formatter)
+    pub fn with_comments(mut self) -> Self {
+        self.comments = true;
+        self
+    }
+
+    /// Scan the whole source at once, returning all the tokens or every
+    /// error found along the way
+    pub fn scan_all(self) -> Result<Vec<Token>, Vec<ScanError>> {
+        let (tokens, errors): (Vec<_>, Vec<_>) = self.partition(Result::is_ok);
+        if errors.is_empty() {
+            Ok(tokens.into_iter().map(Result::unwrap).collect())
+        } else {
+            Err(errors.into_iter().map(Result::unwrap_err).collect())
+        }
     }
 
+    /// Scan the lexeme starting at `start`, returning `None` if it does not
+    /// produce a token (whitespace and comments)
     // FIXME(alvaro): This could probably be done in a `From` implementation?
-    fn scan_token(&mut self, interpreter: &Lox) {
-        match self.advance() {
-            '(' => self.add_token(TokenType::LeftParen),
-            ')' => self.add_token(TokenType::RightParen),
-            '{' => self.add_token(TokenType::LeftBrace),
-            '}' => self.add_token(TokenType::RightBrace),
-            ',' => self.add_token(TokenType::Comma),
-            '.' => self.add_token(TokenType::Dot),
-            '-' => self.add_token(TokenType::Minus),
-            '+' => self.add_token(TokenType::Plus),
-            ';' => self.add_token(TokenType::SemiColon),
-            '*' => self.add_token(TokenType::Star),
-            '"' => self.string(interpreter),
+    fn scan_token(&mut self) -> Option<ScanResult<Token>> {
+        let typ = match self.advance() {
+            '(' => TokenType::LeftParen,
+            ')' => TokenType::RightParen,
+            '{' => TokenType::LeftBrace,
+            '}' => TokenType::RightBrace,
+            '[' => TokenType::LeftBracket,
+            ']' => TokenType::RightBracket,
+            ':' => TokenType::Colon,
+            ',' => TokenType::Comma,
+            '.' => TokenType::Dot,
+            '%' => TokenType::Percent,
+            '?' => TokenType::Question,
+            ';' => TokenType::SemiColon,
+            '-' => {
+                if self.next_match('=') {
+                    TokenType::MinusEqual
+                } else {
+                    TokenType::Minus
+                }
+            }
+            '+' => {
+                if self.next_match('=') {
+                    TokenType::PlusEqual
+                } else {
+                    TokenType::Plus
+                }
+            }
+            '*' => {
+                if self.next_match('=') {
+                    TokenType::StarEqual
+                } else {
+                    TokenType::Star
+                }
+            }
+            '|' if self.next_match('>') => {
+                if !self.features.is_enabled(Feature::Pipe) {
+                    let message = Feature::Pipe.gate_message("|>");
+                    return Some(Err(self.error(ScanErrorKind::Feature, &message)));
+                }
+                TokenType::Pipe
+            }
+            '"' => return Some(self.string()),
             '!' => {
                 if self.next_match('=') {
-                    self.add_token(TokenType::BangEqual)
+                    TokenType::BangEqual
                 } else {
-                    self.add_token(TokenType::Bang)
+                    TokenType::Bang
                 }
             }
             '=' => {
                 if self.next_match('=') {
-                    self.add_token(TokenType::EqualEqual)
+                    TokenType::EqualEqual
                 } else {
-                    self.add_token(TokenType::Equal)
+                    TokenType::Equal
                 }
             }
             '<' => {
                 if self.next_match('=') {
-                    self.add_token(TokenType::LessEqual)
+                    TokenType::LessEqual
                 } else {
-                    self.add_token(TokenType::Less)
+                    TokenType::Less
                 }
             }
             '>' => {
                 if self.next_match('=') {
-                    self.add_token(TokenType::GreaterEqual)
+                    TokenType::GreaterEqual
                 } else {
-                    self.add_token(TokenType::Greater)
+                    TokenType::Greater
                 }
             }
             '/' => {
@@ -183,45 +600,180 @@ impl Scanner {
                     while self.peek().map(|c| c != '\n').unwrap_or(false) {
                         self.advance();
                     }
+                    let comment =
+                        &self.source[self.start + 2 - self.base..self.current - self.base];
+                    // A directive enables features for the rest of the file
+                    if let Some(list) = features::directive(comment) {
+                        if let Err(message) = self.features.enable_list(list) {
+                            return Some(Err(self.error(ScanErrorKind::Feature, &message)));
+                        }
+                    }
+                    return self.comment();
+                } else if self.next_match('*') {
+                    if let Err(error) = self.block_comment() {
+                        return Some(Err(error));
+                    }
+                    return self.comment();
+                } else if self.next_match('=') {
+                    TokenType::SlashEqual
                 } else {
-                    self.add_token(TokenType::Slash)
+                    TokenType::Slash
                 }
             }
-            ' ' | '\r' | '\t' => {}
-            '\n' => self.line += 1,
+            ' ' | '\r' | '\t' | '\n' => return None,
             c if is_digit(c) => self.number(),
             c if is_alpha(c) => self.identifier(),
-            // FIXME(alvaro): We should try to coallesce a string of invalid characters into a
-            // single error message
-            c => interpreter.error(self.line, format!("Unexpected character '{}'", c).as_ref()),
+            _ => return Some(Err(self.unexpected_characters())),
+        };
+        Some(Ok(self.make_token(typ)))
+    }
+
+    /// The comment just scanned, if they are being kept
+    fn comment(&self) -> Option<ScanResult<Token>> {
+        self.comments
+            .then(|| Ok(self.make_token(TokenType::Comment)))
+    }
+
+    /// Consume the rest of a `/* ... */` comment, which can have other
+    /// block comments nested inside
+    fn block_comment(&mut self) -> ScanResult<()> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.peek() {
+                Some('/') if self.peek_next() == Some('*') => {
+                    self.advance();
+                    depth += 1;
+                }
+                Some('*') if self.peek_next() == Some('/') => {
+                    self.advance();
+                    depth -= 1;
+                }
+                Some(_) => {}
+                None => return Err(self.unterminated(ScanErrorKind::UnterminatedComment, "/*")),
+            }
+            self.advance();
         }
+        Ok(())
     }
 
-    /// Try to consume a string literal
-    fn string(&mut self, interpreter: &Lox) {
+    /// Consume the rest of a run of characters that can't start a token, so
+    /// it is reported as a single error
+    fn unexpected_characters(&mut self) -> ScanError {
+        while self.peek().map(|c| !starts_token(c)).unwrap_or(false) {
+            self.advance();
+        }
+
+        let text = self.text(self.start, self.current);
+        let message = if text.chars().count() == 1 {
+            format!("Unexpected character '{}'", text)
+        } else {
+            format!("Unexpected characters '{}'", text)
+        };
+        self.error(ScanErrorKind::UnexpectedCharacter, &message)
+    }
+
+    /// Try to consume a string literal, processing its escape sequences
+    ///
+    /// Strings can span several lines: a raw newline is kept in the value
+    /// as is, same as writing `\n`
+    fn string(&mut self) -> ScanResult<Token> {
+        let mut literal = String::new();
+        // Keep going after a bad escape so the rest of the string isn't
+        // scanned as code, and report all of them
+        let mut errors = Vec::new();
         while let Some(c) = self.peek() {
-            if c == '"' {
-                break;
-            } else if c == '\n' {
-                self.line += 1;
+            match c {
+                '"' => break,
+                '\\' => match self.escape() {
+                    Ok(c) => literal.push(c),
+                    Err(err) => errors.push(err),
+                },
+                _ => literal.push(self.advance()),
             }
-            self.advance();
         }
 
         if self.is_at_end() {
-            interpreter.error(self.line, "Unterminated string");
-            return;
+            return Err(self.unterminated(ScanErrorKind::UnterminatedString, "\""));
         }
         // Consume the closing '"'
         self.advance();
 
-        // Trim the surrounding quotes
-        let literal = self.source[self.start + 1..self.current - 1].to_string();
-        self.add_token(TokenType::String(literal))
+        // The string is still returned after its errors, so the parser
+        // doesn't report the missing expression too
+        let token = self.make_token(TokenType::String(literal));
+        let mut errors = errors.into_iter();
+        match errors.next() {
+            Some(first) => {
+                self.pending.extend(errors.map(Err));
+                self.pending.push_back(Ok(token));
+                Err(first)
+            }
+            None => Ok(token),
+        }
+    }
+
+    /// Consume an escape sequence inside a string, returning the character
+    /// it stands for
+    fn escape(&mut self) -> ScanResult<char> {
+        let (start, line, column) = (self.current, self.line, self.column);
+        let error = |scanner: &Self, message: String| ScanError {
+            kind: ScanErrorKind::InvalidEscape,
+            span: Span {
+                start,
+                end: scanner.current,
+                line,
+                column,
+                end_line: scanner.line,
+                end_column: scanner.column,
+                file: scanner.file,
+            },
+            message,
+            notes: Vec::new(),
+        };
+
+        // Consume the '\'
+        self.advance();
+        let Some(c) = self.peek() else {
+            // The string is reported as unterminated
+            return Ok('\\');
+        };
+        self.advance();
+        match c {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            '\\' => Ok('\\'),
+            '"' => Ok('"'),
+            'u' => self.unicode_escape().ok_or_else(|| {
+                let escaped = self.text(start, self.current);
+                error(self, format!("Invalid unicode escape '{}'", escaped))
+            }),
+            c => Err(error(self, format!("Unknown escape sequence '\\{}'", c))),
+        }
+    }
+
+    /// Consume the `{XXXX}` part of a `\u{XXXX}` escape, with 1 to 6 hex
+    /// digits naming a Unicode scalar value
+    fn unicode_escape(&mut self) -> Option<char> {
+        if !self.next_match('{') {
+            return None;
+        }
+        let digits_start = self.current;
+        while self.peek().map(|c| c.is_ascii_hexdigit()).unwrap_or(false) {
+            self.advance();
+        }
+        let digits_end = self.current;
+        if !self.next_match('}') {
+            return None;
+        }
+        let digits = self.text(digits_start, digits_end);
+        if digits.is_empty() || digits.len() > 6 {
+            return None;
+        }
+        char::from_u32(u32::from_str_radix(digits, 16).ok()?)
     }
 
     /// Consume a number literal
-    fn number(&mut self) {
+    fn number(&mut self) -> TokenType {
         while let Some(c) = self.peek() {
             if is_digit(c) {
                 self.advance();
@@ -239,20 +791,26 @@ impl Scanner {
                 while let Some(c) = self.peek() {
                     if is_digit(c) {
                         self.advance();
+                    } else {
+                        break;
                     }
                 }
+                break;
             } else {
                 break;
             }
         }
-        let number = self.source[self.start..self.current]
+        // NOTE(alvaro): `f64::from_str` always expects a `.` as the decimal
+        // separator, independently of the system locale
+        let number = self
+            .text(self.start, self.current)
             .parse::<f64>()
             .expect("it should be a valid number format");
-        self.add_token(TokenType::Number(number))
+        TokenType::Number(number)
     }
 
     /// Consume an identifier
-    fn identifier(&mut self) {
+    fn identifier(&mut self) -> TokenType {
         while let Some(c) = self.peek() {
             if is_alphanumeric(c) {
                 self.advance();
@@ -260,60 +818,703 @@ impl Scanner {
                 break;
             }
         }
-        let ident_text = &self.source[self.start..self.current];
-        let token_type = self
-            .keywords
-            .get(ident_text)
-            .unwrap_or(&TokenType::Identifier)
-            .clone();
-        self.add_token(token_type);
+        let ident_text = self.text(self.start, self.current);
+        match keywords().get(ident_text) {
+            Some(typ) => match Feature::of_keyword(typ) {
+                Some(feature) if !self.features.is_enabled(feature) => TokenType::Identifier,
+                _ => typ.clone(),
+            },
+            None => TokenType::Identifier,
+        }
     }
 
-    fn is_at_end(&self) -> bool {
-        self.current >= self.source.len()
+    fn is_at_end(&mut self) -> bool {
+        self.peek().is_none()
     }
 
-    // FIXME(alvaro): This is very inefficient, and should instead use an
-    // iterator over the characters
     fn advance(&mut self) -> char {
         let next_char = self.peek().expect("current should be a valid index");
-        self.current += 1;
+        // A `\r\n` line ends before the `\r`
+        if next_char == '\r' || (next_char == '\n' && self.last != Some('\r')) {
+            self.line_end = (self.current, self.column);
+        }
+        self.current += next_char.len_utf8();
+        self.last = Some(next_char);
+        if next_char == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
         next_char
     }
 
-    fn add_token(&mut self, typ: TokenType) {
-        let text = &self.source[self.start..self.current];
-        let token = Token::new(typ, text.to_string(), self.line);
-        self.tokens.push(token);
+    /// Start a new lexeme at the next character
+    fn begin_lexeme(&mut self) {
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.column;
+        // Drop the source before the lexeme once there is enough of it, so
+        // the source read stays about the size of a chunk
+        let scanned = self.start - self.base;
+        if self.reader.is_some() && scanned >= DROP_AFTER {
+            self.source.drain(..scanned);
+            self.base = self.start;
+        }
+    }
+
+    /// The source between the byte offsets `start` and `end`
+    fn text(&self, start: usize, end: usize) -> &str {
+        &self.source[start - self.base..end - self.base]
+    }
+
+    /// Read the next chunk of the source, returning whether there may be
+    /// more to read
+    fn fill(&mut self) -> bool {
+        let Some(reader) = self.reader.as_mut() else {
+            return false;
+        };
+        let error = match reader.fill_buf() {
+            Ok([]) if self.undecoded.is_empty() => None,
+            // The source ends in the middle of a character
+            Ok([]) => Some(INVALID_UTF8.to_string()),
+            Ok(chunk) => {
+                let length = chunk.len();
+                self.undecoded.extend_from_slice(chunk);
+                reader.consume(length);
+                if self.decode() {
+                    return true;
+                }
+                Some(INVALID_UTF8.to_string())
+            }
+            Err(err) if err.kind() == ErrorKind::Interrupted => return true,
+            Err(err) => Some(err.to_string()),
+        };
+        self.reader = None;
+        self.read_error = error;
+        false
+    }
+
+    /// Move the whole characters read to the source, returning whether the
+    /// bytes left can still start one (the next chunk can cut a character)
+    fn decode(&mut self) -> bool {
+        let (valid, invalid) = match std::str::from_utf8(&self.undecoded) {
+            Ok(text) => (text.len(), false),
+            Err(err) => (err.valid_up_to(), err.error_len().is_some()),
+        };
+        let text = std::str::from_utf8(&self.undecoded[..valid])
+            .expect("the bytes up to the first error should be UTF-8");
+        self.source.push_str(text);
+        self.undecoded.drain(..valid);
+        !invalid
+    }
+
+    /// Location of the current lexeme
+    fn span(&self) -> Span {
+        Span {
+            start: self.start,
+            end: self.current,
+            line: self.start_line,
+            column: self.start_column,
+            end_line: self.line,
+            end_column: self.column,
+            file: self.file,
+        }
+    }
+
+    /// Build a token of the given type from the current lexeme
+    fn make_token(&self, typ: TokenType) -> Token {
+        let text = self.text(self.start, self.current);
+        Token::new(typ, text, self.span())
+    }
+
+    /// The `Eof` token. A line break ending the source doesn't start a line
+    /// of code, so the end is located right after the last line instead
+    /// (which is where a line typed into the prompt ends)
+    fn eof(&self) -> Token {
+        let mut token = self.make_token(TokenType::Eof);
+        if self.last != Some('\n') {
+            return token;
+        }
+        let (end, column) = self.line_end;
+        token.span.start = end;
+        token.span.end = end;
+        token.span.line = self.line - 1;
+        token.span.end_line = self.line - 1;
+        token.span.column = column;
+        token.span.end_column = column;
+        token
+    }
+
+    fn error(&self, kind: ScanErrorKind, message: &str) -> ScanError {
+        ScanError {
+            kind,
+            span: self.span(),
+            message: message.to_string(),
+            notes: Vec::new(),
+        }
+    }
+
+    /// Error for a string or comment still open at the end of the source,
+    /// pointing at its opening `delimiter` with a note at the end
+    fn unterminated(&self, kind: ScanErrorKind, delimiter: &str) -> ScanError {
+        let message = match kind {
+            ScanErrorKind::UnterminatedComment => "Unterminated block comment",
+            _ => "Unterminated string",
+        };
+        let mut error = self.error(kind, message);
+        error.span.end = error.span.start + delimiter.len();
+        error.span.end_line = error.span.line;
+        error.span.end_column = error.span.column + delimiter.chars().count();
+
+        let end = Span {
+            start: self.current,
+            end: self.current,
+            line: self.line,
+            column: self.column,
+            end_line: self.line,
+            end_column: self.column,
+            file: self.file,
+        };
+        let closing = if delimiter == "/*" { "*/" } else { delimiter };
+        let note = format!("the source ends here, without a closing '{}'", closing);
+        error.notes.push(Note::new(end, &note));
+        error
     }
 
     fn next_match(&mut self, expected: char) -> bool {
         let next_matches = self.peek().map(|c| c == expected).unwrap_or(false);
         if next_matches {
-            self.current += 1;
-            true
-        } else {
-            false
+            self.advance();
         }
+        next_matches
     }
 
-    fn peek(&self) -> Option<char> {
-        self.source.chars().nth(self.current)
+    fn peek(&mut self) -> Option<char> {
+        self.peek_nth(0)
     }
 
-    fn peek_next(&self) -> Option<char> {
-        self.source.chars().nth(self.current + 1)
+    fn peek_next(&mut self) -> Option<char> {
+        self.peek_nth(1)
+    }
+
+    /// The character `n` places after the next one to consume, reading
+    /// more of the source if needed
+    fn peek_nth(&mut self, n: usize) -> Option<char> {
+        loop {
+            let next = self.source[self.current - self.base..].chars().nth(n);
+            if next.is_some() || self.reader.is_none() {
+                return next;
+            }
+            // Even the last chunk read, with an error, can add characters
+            self.fill();
+        }
     }
 }
 
+impl Iterator for Scanner {
+    type Item = ScanResult<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(result) = self.pending.pop_front() {
+            return Some(result);
+        }
+        while !self.is_at_end() {
+            self.begin_lexeme();
+            if let Some(token) = self.scan_token() {
+                return Some(token);
+            }
+        }
+
+        if let Some(message) = self.read_error.take() {
+            self.begin_lexeme();
+            let message = format!("Could not read the source: {}", message);
+            return Some(Err(self.error(ScanErrorKind::Read, &message)));
+        }
+        if self.finished {
+            return None;
+        }
+        self.finished = true;
+        self.begin_lexeme();
+        Some(Ok(self.eof()))
+    }
+}
+
+/// Whether the character can start a token (or be skipped, as whitespace)
+fn starts_token(c: char) -> bool {
+    is_alphanumeric(c) || "(){}:,.-%+?;*\"!=<>/| \r\t\n".contains(c)
+}
+
 fn is_digit(c: char) -> bool {
-    c.is_digit(10)
+    c.is_ascii_digit()
 }
 
+/// Identifiers start with a letter of any script (or `_`), so `café` and
+/// `π` are names, but symbols like emoji are still unexpected characters
 fn is_alpha(c: char) -> bool {
-    c.is_ascii_alphabetic() || c == '_'
+    c.is_alphabetic() || c == '_'
 }
 
 fn is_alphanumeric(c: char) -> bool {
     is_alpha(c) || is_digit(c)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn types(source: &str) -> Vec<TokenType> {
+        Scanner::new(source.to_string())
+            .scan_all()
+            .expect("source should scan")
+            .into_iter()
+            .map(|token| token.typ)
+            .collect()
+    }
+
+    #[test]
+    fn scans_lazily_and_ends_with_eof() {
+        let mut scanner = Scanner::new("var x = 1; // done\n".to_string());
+        assert_eq!(scanner.next().unwrap().unwrap().typ, TokenType::Var);
+        assert_eq!(scanner.next().unwrap().unwrap().lexeme, "x");
+
+        let rest: Vec<_> = scanner.map(|token| token.unwrap().typ).collect();
+        assert_eq!(
+            rest,
+            [
+                TokenType::Equal,
+                TokenType::Number(1.0),
+                TokenType::SemiColon,
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn interns_only_names() {
+        let tokens: Vec<_> = Scanner::new("print x + \"text\" * 2;".to_string())
+            .map(|token| token.unwrap())
+            .collect();
+        let interned: Vec<_> = tokens
+            .iter()
+            .map(|token| matches!(token.lexeme, Lexeme::Name(_)))
+            .collect();
+        assert_eq!(
+            interned,
+            [true, true, false, false, false, false, false, false]
+        );
+        assert_eq!(tokens[1].lexeme.symbol(), Symbol::intern("x"));
+        assert_eq!(tokens[3].lexeme, "\"text\"");
+    }
+
+    #[test]
+    fn scans_operators_and_literals() {
+        assert_eq!(
+            types("a <= \"ü\" != 1.5."),
+            [
+                TokenType::Identifier,
+                TokenType::LessEqual,
+                TokenType::String("ü".to_string()),
+                TokenType::BangEqual,
+                TokenType::Number(1.5),
+                TokenType::Dot,
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn scans_c_style_operators() {
+        assert_eq!(
+            types("a %= b ? c : d += e -= f *= g /= h % i, j"),
+            [
+                TokenType::Identifier,
+                TokenType::Percent,
+                TokenType::Equal,
+                TokenType::Identifier,
+                TokenType::Question,
+                TokenType::Identifier,
+                TokenType::Colon,
+                TokenType::Identifier,
+                TokenType::PlusEqual,
+                TokenType::Identifier,
+                TokenType::MinusEqual,
+                TokenType::Identifier,
+                TokenType::StarEqual,
+                TokenType::Identifier,
+                TokenType::SlashEqual,
+                TokenType::Identifier,
+                TokenType::Percent,
+                TokenType::Identifier,
+                TokenType::Comma,
+                TokenType::Identifier,
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn tracks_spans() {
+        let tokens = Scanner::new("\"a\nb\" \"ü\"\n  x".to_string())
+            .scan_all()
+            .expect("source should scan");
+        let spans: Vec<_> = tokens.iter().map(|token| token.span).collect();
+        assert_eq!(
+            spans,
+            [
+                Span {
+                    start: 0,
+                    end: 5,
+                    line: 1,
+                    column: 1,
+                    end_line: 2,
+                    end_column: 3,
+                    file: None,
+                },
+                Span {
+                    start: 6,
+                    end: 10,
+                    line: 2,
+                    column: 4,
+                    end_line: 2,
+                    end_column: 7,
+                    file: None,
+                },
+                Span {
+                    start: 13,
+                    end: 14,
+                    line: 3,
+                    column: 3,
+                    end_line: 3,
+                    end_column: 4,
+                    file: None,
+                },
+                Span {
+                    start: 14,
+                    end: 14,
+                    line: 3,
+                    column: 4,
+                    end_line: 3,
+                    end_column: 4,
+                    file: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn spans_are_located_in_the_origin() {
+        let origin = Origin::new("notes.md", 4);
+        let tokens = Scanner::new("a\n  b".to_string())
+            .with_origin(&origin)
+            .scan_all()
+            .expect("source should scan");
+        let spans: Vec<_> = tokens.iter().map(|token| token.span.to_string()).collect();
+        assert_eq!(
+            spans,
+            [
+                "notes.md:5, col 1",
+                "notes.md:6, col 3",
+                "notes.md:6, col 4"
+            ]
+        );
+        assert_eq!(tokens[1].span.end_line, 6);
+    }
+
+    #[test]
+    fn ends_after_the_last_line() {
+        let eof = |source: &str| {
+            let tokens = Scanner::new(source.to_string())
+                .with_origin(&Origin::new("repl", 9))
+                .scan_all()
+                .expect("source should scan");
+            tokens
+                .last()
+                .expect("there should be an `Eof`")
+                .span
+                .to_string()
+        };
+        assert_eq!(eof("print x +\n"), "repl:10, col 10");
+        assert_eq!(eof("a\r\nbc\r\n"), "repl:11, col 3");
+        assert_eq!(eof("a\n\n"), "repl:11, col 1");
+        assert_eq!(eof("a"), "repl:10, col 2");
+    }
+
+    #[test]
+    fn keeps_scanning_after_errors() {
+        let mut scanner = Scanner::new("@ 1\n\"open".to_string());
+        assert_eq!(
+            scanner.next(),
+            Some(Err(ScanError {
+                kind: ScanErrorKind::UnexpectedCharacter,
+                span: Span {
+                    start: 0,
+                    end: 1,
+                    line: 1,
+                    column: 1,
+                    end_line: 1,
+                    end_column: 2,
+                    file: None,
+                },
+                message: "Unexpected character '@'".to_string(),
+                notes: Vec::new(),
+            }))
+        );
+        assert_eq!(scanner.next().unwrap().unwrap().typ, TokenType::Number(1.0));
+
+        let errors = Scanner::new("@ 1\n\"open".to_string())
+            .scan_all()
+            .unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[1].message, "Unterminated string");
+        assert_eq!(errors[1].kind, ScanErrorKind::UnterminatedString);
+        // Only the opening quote, with a note where the source ends
+        let span = errors[1].span;
+        assert_eq!((span.start, span.end, span.line, span.column), (4, 5, 2, 1));
+        assert_eq!((span.end_line, span.end_column), (2, 2));
+        let end = errors[1].notes[0].span;
+        assert_eq!((end.start, end.line, end.column), (9, 2, 6));
+    }
+
+    #[test]
+    fn coalesces_unexpected_characters() {
+        let errors = Scanner::new("a @#$ b €".to_string())
+            .scan_all()
+            .unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|err| err.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            ["Unexpected characters '@#$'", "Unexpected character '€'"]
+        );
+        assert_eq!((errors[0].span.start, errors[0].span.end), (2, 5));
+    }
+
+    #[test]
+    fn scans_non_ascii_source() {
+        let source = "// ¿qué? 🦀\nvar café = \"ñ 🎉\"; π2_ /* ✓ */ x🦀y";
+        let errors = Scanner::new(source.to_string()).scan_all().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Unexpected character '🦀'");
+        let span = errors[0].span;
+        assert_eq!(&source[span.start..span.end], "🦀");
+        assert_eq!((span.line, span.column, span.end_column), (2, 32, 33));
+
+        let tokens = Scanner::new(source.replace("x🦀y", ""))
+            .scan_all()
+            .expect("source should scan");
+        let lexemes: Vec<_> = tokens.iter().map(|token| token.lexeme.as_str()).collect();
+        assert_eq!(lexemes, ["var", "café", "=", "\"ñ 🎉\"", ";", "π2_", ""]);
+        assert_eq!(tokens[3].typ, TokenType::String("ñ 🎉".to_string()));
+        assert_eq!((tokens[5].span.column, tokens[5].span.end_column), (19, 22));
+    }
+
+    #[test]
+    fn processes_escape_sequences() {
+        assert_eq!(
+            types(
+                r#""a\tb\n\\ \"q\" \u{e9}\u{1F600}" "two
+lines""#
+            ),
+            [
+                TokenType::String("a\tb\n\\ \"q\" \u{e9}\u{1F600}".to_string()),
+                TokenType::String("two\nlines".to_string()),
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_bad_escapes_at_their_column() {
+        let errors = Scanner::new(r#"x = "ok\q" + "\u{110000}" + "\u{}";"#.to_string())
+            .scan_all()
+            .unwrap_err();
+        let reported: Vec<_> = errors
+            .iter()
+            .map(|err| (err.span.column, err.message.as_str()))
+            .collect();
+        assert_eq!(
+            reported,
+            [
+                (8, r"Unknown escape sequence '\q'"),
+                (15, r"Invalid unicode escape '\u{110000}'"),
+                (30, r"Invalid unicode escape '\u{}'"),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_strings_with_bad_escapes() {
+        let results: Vec<_> = Scanner::new(r#""\q\z";"#.to_string()).collect();
+        let errors: Vec<_> = results
+            .iter()
+            .filter_map(|result| result.as_ref().err())
+            .map(|err| err.span.column)
+            .collect();
+        assert_eq!(errors, [2, 4]);
+        let tokens: Vec<_> = results
+            .into_iter()
+            .filter_map(Result::ok)
+            .map(|token| token.typ)
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                TokenType::String(String::new()),
+                TokenType::SemiColon,
+                TokenType::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_nested_block_comments_unless_kept() {
+        assert_eq!(
+            types("1 /* a /* b\n */ c */ 2 /**/ / 3"),
+            [
+                TokenType::Number(1.0),
+                TokenType::Number(2.0),
+                TokenType::Slash,
+                TokenType::Number(3.0),
+                TokenType::Eof,
+            ]
+        );
+
+        let comments: Vec<_> = Scanner::new("1 /* a /* b */ */ // c\n".to_string())
+            .with_comments()
+            .scan_all()
+            .expect("source should scan")
+            .into_iter()
+            .filter(|token| token.typ == TokenType::Comment)
+            .map(|token| token.lexeme)
+            .collect();
+        assert_eq!(comments, ["/* a /* b */ */", "// c"]);
+
+        let errors = Scanner::new("1;\n  /* a /* b */\n".to_string())
+            .scan_all()
+            .unwrap_err();
+        // Pointing at the opening `/*`, and at where the source ends
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Unterminated block comment");
+        assert_eq!(errors[0].kind, ScanErrorKind::UnterminatedComment);
+        let span = errors[0].span;
+        assert_eq!((span.start, span.end), (5, 7));
+        assert_eq!(
+            (span.line, span.column, span.end_line, span.end_column),
+            (2, 3, 2, 5)
+        );
+        let note = &errors[0].notes[0];
+        assert_eq!(
+            (note.span.start, note.span.line, note.span.column),
+            (18, 3, 1)
+        );
+        assert_eq!(note.message, "the source ends here, without a closing '*/'");
+    }
+
+    #[test]
+    fn skips_a_shebang_line() {
+        let tokens = Scanner::new("#!/usr/bin/env rinlox\nprint 1;".to_string())
+            .scan_all()
+            .unwrap();
+        assert_eq!(tokens[0].typ, TokenType::Print);
+        assert_eq!((tokens[0].span.line, tokens[0].span.column), (2, 1));
+        assert_eq!(types("#!rinlox"), [TokenType::Eof]);
+        // Only on the first line
+        assert!(Scanner::new("print 1;\n#!x".to_string())
+            .scan_all()
+            .is_err());
+    }
+
+    #[test]
+    fn gated_keywords_need_their_feature() {
+        assert_eq!(
+            types("enum defer"),
+            [TokenType::Identifier, TokenType::Identifier, TokenType::Eof]
+        );
+        assert_eq!(
+            types("// rinlox: features=lox-enum\nenum defer"),
+            [TokenType::Enum, TokenType::Identifier, TokenType::Eof]
+        );
+
+        let errors = Scanner::new("// rinlox: features=lox-nope\n".to_string())
+            .scan_all()
+            .unwrap_err();
+        assert_eq!(errors[0].message, "Unknown feature 'lox-nope'.");
+    }
+
+    /// Scan the source read a few bytes at a time, cutting characters
+    fn scan_read(source: &[u8], chunk: usize) -> Vec<ScanResult<Token>> {
+        let reader =
+            std::io::BufReader::with_capacity(chunk, std::io::Cursor::new(source.to_vec()));
+        Scanner::from_reader(reader).collect()
+    }
+
+    #[test]
+    fn scans_a_reader_like_the_whole_source() {
+        let sources = [
+            "#!/usr/bin/env rinlox\nprint \"ü\\u{1F600}\" + café;\r\n",
+            "// rinlox: features=lox-enum\nenum π { A } /* ∑ */ 1.5 >= 2\n",
+            "var s = \"unterminated\n\n",
+            "#!x",
+            "",
+        ];
+        for source in sources {
+            let whole: Vec<_> = Scanner::new(source.to_string()).collect();
+            for chunk in 1..=4 {
+                let read = scan_read(source.as_bytes(), chunk);
+                assert_eq!(
+                    format!("{:?}", read),
+                    format!("{:?}", whole),
+                    "{:?}",
+                    source
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_only_the_source_still_needed() {
+        let source = "var x = \"a\" + 1;\n".repeat(20_000);
+        let reader = std::io::BufReader::new(std::io::Cursor::new(source.into_bytes()));
+        let mut scanner = Scanner::from_reader(reader);
+        let mut kept = 0;
+        let mut eof = None;
+        while let Some(token) = scanner.next() {
+            kept = kept.max(scanner.source.len());
+            eof = Some(token.unwrap());
+        }
+        assert!(kept < 2 * DROP_AFTER, "kept {} bytes", kept);
+        let eof = eof.unwrap();
+        assert_eq!(eof.typ, TokenType::Eof);
+        assert_eq!((eof.span.line, eof.span.column), (20_000, 17));
+    }
+
+    #[test]
+    fn reports_a_source_that_is_not_utf8() {
+        let results = scan_read(b"print 1; \xff print 2;", 4);
+        let kinds: Vec<_> = results
+            .iter()
+            .map(|result| {
+                result
+                    .as_ref()
+                    .map(|token| token.typ.clone())
+                    .map_err(|err| err.kind)
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                Ok(TokenType::Print),
+                Ok(TokenType::Number(1.0)),
+                Ok(TokenType::SemiColon),
+                Err(ScanErrorKind::Read),
+                Ok(TokenType::Eof),
+            ]
+        );
+        let error = results[3].as_ref().unwrap_err();
+        assert_eq!(
+            error.message,
+            "Could not read the source: stream did not contain valid UTF-8"
+        );
+        assert_eq!(error.span.column, 10);
+    }
+}
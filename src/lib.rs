@@ -0,0 +1,769 @@
+//! Interpreter for the Lox programming language from the
+//! "Crafting Interpreters" book
+//!
+//! The simplest way to embed it is `run_source`, which runs a whole program
+//! on a fresh interpreter. Keeping an `Interpreter` around and calling `run`
+//! instead keeps the globals from one call to the next, the way a REPL does,
+//! and gives back the value of the last statement if it is an expression.
+//! `eval` does the same for a prompt, where semicolons are optional, also
+//! giving back the hints about the code. The stages (`Scanner`, `Parser`,
+//! `Resolver` and `Interpreter`) can also be driven one by one.
+//!
+//! Code that is a piece of something larger, like a snippet embedded in a
+//! document, runs with `run_named` (or `eval_named`) instead, giving the
+//! `Origin` its errors and stack traces report locations in.
+//!
+//! Rust code gives Lox code values to work with by binding them as globals
+//! (`Interpreter::set_global`), functions with `Interpreter::define_native`
+//! and objects of its own types with methods through `LoxObject`.
+//!
+//! Values are reference counted, and the interpreter collects the cycles
+//! that leaves behind now and then (see `gc`), so a long-lived one doesn't
+//! leak them.
+//!
+//! The library does not use the file system, the standard input or the
+//! clock itself, but the `host::Host` of the interpreter, so it also builds
+//! and runs on `wasm32-unknown-unknown`. `run_source_with` hands the lines
+//! printed to a callback, and the `wasm` feature adds the bindings for a
+//! playground in a browser (see `wasm::Playground`).
+//!
+//! `run_vm` runs programs on a second backend instead, compiling them to
+//! bytecode for the `Vm`. It only supports a subset of the language so far.
+#[macro_use]
+mod macros;
+
+pub mod ast;
+pub mod ast_printer;
+pub mod chunk;
+mod class;
+pub mod compiler;
+pub mod debugger;
+pub mod diagnostics;
+mod enumeration;
+mod environment;
+pub mod expr;
+pub mod features;
+pub mod foreign;
+pub mod formatter;
+mod function;
+pub mod gc;
+pub mod grammar;
+pub mod host;
+pub mod internal;
+pub mod interpreter;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod lexer;
+pub mod linter;
+mod list;
+mod map;
+mod module;
+mod native;
+pub mod observer;
+pub mod optimizer;
+pub mod parser;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+pub mod resolver;
+pub mod semantic;
+pub mod stmt;
+mod string;
+pub mod symbol;
+pub mod token_diff;
+pub mod transpiler;
+pub mod vm;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+use std::fmt::Display;
+use std::time::Duration;
+
+use compiler::Compiler;
+use diagnostics::{Diagnostics, Severity, Summary};
+use optimizer::Optimizer;
+
+pub use features::{Feature, Features};
+pub use foreign::LoxObject;
+pub use gc::GcStats;
+pub use internal::InternalError;
+pub use interpreter::{Interpreter, Limit, Limits, RuntimeError, RuntimeErrorKind, Value};
+pub use lexer::{Lexeme, Origin, ScanError, ScanErrorKind, Scanner, Span, Token, TokenType};
+pub use parser::{Hint, ParseError, Parser};
+pub use resolver::{GlobalSlots, ResolveError, Resolver};
+pub use stmt::Stmt;
+pub use vm::Vm;
+
+/// Errors stopping a program, from whichever stage found them
+#[derive(Debug)]
+pub enum Error {
+    /// The source could not be scanned or parsed, so nothing was run
+    Parse(Vec<ParseError>),
+    /// The program was parsed but is not valid, so nothing was run
+    Resolve(Vec<ResolveError>),
+    /// The program failed while running
+    Runtime(RuntimeError),
+    /// rinlox itself failed, which is a bug (see `internal::catch`)
+    Internal(InternalError),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parse(errors) => write_all(f, errors),
+            Error::Resolve(errors) => write_all(f, errors),
+            Error::Runtime(error) => write!(f, "{}", error),
+            Error::Internal(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+fn write_all<T: Display>(f: &mut std::fmt::Formatter<'_>, errors: &[T]) -> std::fmt::Result {
+    for (i, error) in errors.iter().enumerate() {
+        if i > 0 {
+            writeln!(f)?;
+        }
+        write!(f, "{}", error)?;
+    }
+    Ok(())
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    /// Format the errors like the command line does, each with the line of
+    /// the source it is on (see `Diagnostics::render`)
+    pub fn render(&self, diagnostics: &Diagnostics) -> String {
+        let rendered: Vec<_> = match self {
+            Error::Parse(errors) => errors
+                .iter()
+                .map(|error| diagnostics.render(&error.to_string(), error.span(), error.notes()))
+                .collect(),
+            Error::Resolve(errors) => errors
+                .iter()
+                .map(|error| diagnostics.render(&error.to_string(), error.token.span, &error.notes))
+                .collect(),
+            Error::Runtime(error) => {
+                vec![diagnostics.render(&error.to_string(), error.token.span, &[])]
+            }
+            Error::Internal(error) => vec![error.to_string()],
+        };
+        rendered.join("\n")
+    }
+}
+
+/// Run the program in `source` on the `interpreter`, with the given
+/// experimental features enabled, returning the value of its last
+/// statement if it is an expression statement
+pub fn run(
+    interpreter: &mut Interpreter,
+    source: &str,
+    features: Features,
+) -> Result<Option<Value>, Error> {
+    let scanner = Scanner::new(source.to_string()).with_features(features);
+    run_scanned(interpreter, scanner)
+}
+
+/// Run the program in `source` like `run`, when it comes from somewhere
+/// else than a file of its own (see `Origin`): its errors and stack traces
+/// point at the logical file it is in
+pub fn run_named(
+    interpreter: &mut Interpreter,
+    origin: &Origin,
+    source: &str,
+    features: Features,
+) -> Result<Option<Value>, Error> {
+    let scanner = Scanner::new(source.to_string())
+        .with_features(features)
+        .with_origin(origin);
+    run_scanned(interpreter, scanner)
+}
+
+fn run_scanned(interpreter: &mut Interpreter, scanner: Scanner) -> Result<Option<Value>, Error> {
+    let statements = Parser::new(scanner).parse().map_err(Error::Parse)?;
+    run_statements(interpreter, &statements)
+}
+
+/// Run a program parsed already (e.g. with automatic semicolons) on the
+/// `interpreter`, like `run`
+pub fn run_statements(
+    interpreter: &mut Interpreter,
+    statements: &[Stmt],
+) -> Result<Option<Value>, Error> {
+    Resolver::default()
+        .with_globals(interpreter.global_slots())
+        .resolve(statements)
+        .map_err(Error::Resolve)?;
+    interpreter.interpret(statements).map_err(Error::Runtime)
+}
+
+/// Run a program parsed already like `run_statements`, optimizing it once
+/// it is resolved (see `optimizer`)
+pub fn run_optimized(
+    interpreter: &mut Interpreter,
+    statements: Vec<Stmt>,
+) -> Result<Option<Value>, Error> {
+    Resolver::default()
+        .with_globals(interpreter.global_slots())
+        .resolve(&statements)
+        .map_err(Error::Resolve)?;
+    let statements = Optimizer::default().optimize(statements);
+    interpreter.interpret(&statements).map_err(Error::Runtime)
+}
+
+/// Run the program in `source` on the virtual machine, compiling it to
+/// bytecode first
+pub fn run_vm(vm: &mut Vm, source: &str, features: Features) -> Result<(), Error> {
+    let scanner = Scanner::new(source.to_string()).with_features(features);
+    let chunk = Compiler::new(scanner).compile().map_err(Error::Parse)?;
+    vm.interpret(&chunk).map_err(Error::Runtime)
+}
+
+/// What `eval` gave back besides the output of the code
+#[derive(Debug, Clone)]
+pub struct Evaluation {
+    /// Value of the last statement, if it is an expression statement
+    pub value: Option<Value>,
+    /// Where the parser assumed a `;` at the end of a line
+    pub hints: Vec<Hint>,
+    /// How long each stage took, if the host has a clock
+    pub timings: Option<Timings>,
+}
+
+/// How long each stage of running some code took, by the clock of the
+/// host of the interpreter
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Timings {
+    /// Scanning and parsing the source, which go together
+    pub parse: Duration,
+    pub resolve: Duration,
+    pub run: Duration,
+}
+
+impl Evaluation {
+    /// How many diagnostics of each severity the code got
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary::default();
+        summary.add(Severity::Hint, self.hints.len());
+        summary
+    }
+}
+
+/// Run the source typed in a prompt like `run`, also giving back the hints
+/// about it
+///
+/// The source is parsed with automatic semicolons, so the `;` at the end
+/// of a line can be left out (see `Parser::with_auto_semicolons`)
+pub fn eval(
+    interpreter: &mut Interpreter,
+    source: &str,
+    features: Features,
+) -> Result<Evaluation, Error> {
+    let scanner = Scanner::new(source.to_string()).with_features(features);
+    eval_scanned(interpreter, scanner)
+}
+
+/// Run the source typed in a prompt like `eval`, pointing its errors and
+/// stack traces at the logical file of `origin`, e.g. the line of the
+/// session it was typed on
+pub fn eval_named(
+    interpreter: &mut Interpreter,
+    origin: &Origin,
+    source: &str,
+    features: Features,
+) -> Result<Evaluation, Error> {
+    let scanner = Scanner::new(source.to_string())
+        .with_features(features)
+        .with_origin(origin);
+    eval_scanned(interpreter, scanner)
+}
+
+fn eval_scanned(interpreter: &mut Interpreter, scanner: Scanner) -> Result<Evaluation, Error> {
+    let start = interpreter.host().now();
+    let mut parser = Parser::new(scanner).with_auto_semicolons();
+    let statements = parser.parse().map_err(Error::Parse)?;
+    let parsed = interpreter.host().now();
+    Resolver::default()
+        .with_globals(interpreter.global_slots())
+        .resolve(&statements)
+        .map_err(Error::Resolve)?;
+    let resolved = interpreter.host().now();
+    let value = interpreter.interpret(&statements).map_err(Error::Runtime)?;
+    let ran = interpreter.host().now();
+    let timings = match (start, parsed, resolved, ran) {
+        (Some(start), Some(parsed), Some(resolved), Some(ran)) => Some(Timings {
+            parse: parsed - start,
+            resolve: resolved - parsed,
+            run: ran - resolved,
+        }),
+        _ => None,
+    };
+    Ok(Evaluation {
+        value,
+        hints: parser.hints().to_vec(),
+        timings,
+    })
+}
+
+/// Whether the source stops in the middle of a statement: inside a string
+/// or a block comment, or before closing all its brackets. A prompt can
+/// keep reading lines until it is complete
+pub fn is_incomplete(source: &str) -> bool {
+    let mut depth = 0;
+    for token in Scanner::new(source.to_string()) {
+        match token {
+            Ok(token) => match token.typ {
+                TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => depth += 1,
+                TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => {
+                    depth -= 1
+                }
+                _ => {}
+            },
+            Err(error) => {
+                if matches!(
+                    error.kind,
+                    ScanErrorKind::UnterminatedString | ScanErrorKind::UnterminatedComment
+                ) {
+                    return true;
+                }
+            }
+        }
+    }
+    depth > 0
+}
+
+/// Run the program in `source` on a fresh interpreter
+pub fn run_source(source: &str) -> Result<(), Error> {
+    run(&mut Interpreter::default(), source, Features::default()).map(|_| ())
+}
+
+/// Run the program in `source` on a fresh interpreter like `run_source`,
+/// handing each line it prints to `print` instead of writing it to the
+/// standard output
+pub fn run_source_with(source: &str, print: impl FnMut(&str) + 'static) -> Result<(), Error> {
+    let mut interpreter = Interpreter::default();
+    interpreter.set_print(print);
+    run(&mut interpreter, source, Features::default()).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn run_source_reports_the_failing_stage() {
+        assert!(run_source("var a = 1; a = a + 1;").is_ok());
+        assert!(matches!(run_source("print ;"), Err(Error::Parse(_))));
+        assert!(matches!(run_source("return 1;"), Err(Error::Resolve(_))));
+        assert!(matches!(run_source("nil();"), Err(Error::Runtime(_))));
+    }
+
+    #[test]
+    fn run_source_with_hands_over_each_line_printed() {
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let printed = Rc::clone(&lines);
+        let source = "print 1; print \"a\\nb\"; print nil;";
+        run_source_with(source, move |line| {
+            printed.borrow_mut().push(line.to_string())
+        })
+        .unwrap();
+        assert_eq!(*lines.borrow(), ["1", "a", "b", "nil"]);
+    }
+
+    #[test]
+    fn run_keeps_globals_between_calls() {
+        let mut interpreter = Interpreter::default();
+        run(&mut interpreter, "var a = 1;", Features::default()).unwrap();
+        run(&mut interpreter, "a = a + 1;", Features::default()).unwrap();
+        let error = run(&mut interpreter, "b;", Features::default()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Undefined variable 'b'.\n[line 1, col 1]"
+        );
+    }
+
+    #[test]
+    fn the_interpreter_keeps_the_calls_an_error_unwound_through() {
+        let mut interpreter = Interpreter::default();
+        let source = "fun f(n) {\n  if (n == 0) return nil + 1;\n  return f(n - 1);\n}\nfun g() {\n  return f(1);\n}\ng();";
+        let Err(Error::Runtime(error)) = run(&mut interpreter, source, Features::default()) else {
+            panic!("the call should fail");
+        };
+        assert_eq!(
+            lines(interpreter.stack_trace(&error)),
+            [("f", 2), ("f", 3), ("g", 6), ("script", 8)]
+        );
+        let Err(Error::Runtime(error)) = run(&mut interpreter, "\nnil + 1;", Features::default())
+        else {
+            panic!("the addition should fail");
+        };
+        assert_eq!(lines(interpreter.stack_trace(&error)), [("script", 2)]);
+    }
+
+    /// The function of each call in a stack trace, with its line
+    fn lines(trace: Vec<(&str, Span)>) -> Vec<(&str, usize)> {
+        trace
+            .into_iter()
+            .map(|(name, span)| (name, span.line))
+            .collect()
+    }
+
+    #[test]
+    fn named_sources_report_locations_in_their_origin() {
+        let mut interpreter = Interpreter::default();
+        let origin = Origin::new("page.html", 10);
+        let source = "fun f() {\n  return nil + 1;\n}\nf();";
+        let Err(Error::Runtime(error)) =
+            run_named(&mut interpreter, &origin, source, Features::default())
+        else {
+            panic!("the call should fail");
+        };
+        assert_eq!(
+            error.to_string(),
+            "Operands must be two numbers or two strings.\n[page.html:12, col 14]"
+        );
+        assert_eq!(
+            lines(interpreter.stack_trace(&error)),
+            [("f", 12), ("script", 14)]
+        );
+
+        let mut diagnostics = Diagnostics::new(false);
+        diagnostics.set_source_at(source, &origin);
+        assert_eq!(
+            Error::Runtime(error).render(&diagnostics),
+            "Operands must be two numbers or two strings.\n[page.html:12, col 14]\n   |\n12 |   return nil + 1;\n   |              ^"
+        );
+        let evaluation = eval_named(&mut interpreter, &origin, "f", Features::default());
+        assert!(evaluation.unwrap().value.is_some());
+    }
+
+    #[test]
+    fn deep_recursion_fails_with_a_stack_overflow() {
+        let source = "fun f(n) { if (n == 0) return 0; return f(n - 1) + 1; }";
+        let mut interpreter = Interpreter::default();
+        run(&mut interpreter, source, Features::default()).unwrap();
+        // Deeper than the stack of the thread would take
+        let depth = run(&mut interpreter, "f(5000);", Features::default()).unwrap();
+        assert_eq!(depth, Some(Value::Number(5000.0)));
+
+        interpreter.set_max_call_depth(100);
+        assert!(run(&mut interpreter, "f(99);", Features::default()).is_ok());
+        let error = run(&mut interpreter, "f(100);", Features::default()).unwrap_err();
+        assert!(error.to_string().starts_with("Stack overflow."));
+    }
+
+    #[test]
+    fn runs_stop_when_they_go_over_their_limits() {
+        let mut interpreter = Interpreter::default();
+        interpreter.set_limits(Limits {
+            operations: Some(1000),
+            ..Limits::default()
+        });
+        let source = "var i = 0; while (i < 10) i = i + 1;";
+        run(&mut interpreter, source, Features::default()).unwrap();
+        let Err(Error::Runtime(error)) =
+            run(&mut interpreter, "while (true) {}", Features::default())
+        else {
+            panic!("the loop should be stopped");
+        };
+        assert_eq!(error.limit(), Some(Limit::Operations));
+        assert_eq!(
+            error.to_string(),
+            "Operation limit exceeded.\n[line 1, col 1]"
+        );
+
+        interpreter.set_limits(Limits {
+            time: Some(Duration::from_millis(20)),
+            ..Limits::default()
+        });
+        let source = "fun f() { for (;;) {} }\nf();";
+        let Err(Error::Runtime(error)) = run(&mut interpreter, source, Features::default()) else {
+            panic!("the loop should be stopped");
+        };
+        assert_eq!(error.limit(), Some(Limit::Time));
+        let Err(Error::Runtime(error)) = run(&mut interpreter, "nil();", Features::default())
+        else {
+            panic!("the call should fail");
+        };
+        assert_eq!(error.limit(), None);
+
+        // Errors are told apart by their kind, not by their message
+        interpreter.define_native("fail", 0, |_, paren, _| {
+            Err(RuntimeError::new(paren, "Time limit exceeded."))
+        });
+        let Err(Error::Runtime(error)) = run(&mut interpreter, "fail();", Features::default())
+        else {
+            panic!("the call should fail");
+        };
+        assert_eq!(error.kind, RuntimeErrorKind::Program);
+        assert_eq!(error.limit(), None);
+    }
+
+    /// Output whose reader is gone, like a pipe `head` closed
+    struct Closed;
+
+    impl io::Write for Closed {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn failed_writes_stop_the_run_with_an_error() {
+        let output_failed = |error: Error| match error {
+            Error::Runtime(error) => {
+                error.kind == RuntimeErrorKind::Output(io::ErrorKind::BrokenPipe)
+            }
+            _ => false,
+        };
+        let mut interpreter = Interpreter::default();
+        interpreter.set_output(Closed);
+        interpreter.set_observer(observer::Tracer::new(Closed));
+        let error = run(
+            &mut interpreter,
+            "var a = 1;\nprint a;",
+            Features::default(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().starts_with("Could not write the output:"));
+        assert!(output_failed(error));
+
+        let mut vm = Vm::default();
+        vm.set_output(Closed);
+        assert!(output_failed(
+            run_vm(&mut vm, "print 1;", Features::default()).unwrap_err()
+        ));
+    }
+
+    /// A host whose clock moves a second each time it is read, if it has one
+    #[derive(Debug)]
+    struct Ticking(Option<Cell<u64>>);
+
+    impl host::Host for Ticking {
+        fn read_line(&mut self) -> io::Result<Option<String>> {
+            Ok(None)
+        }
+
+        fn clock(&self) -> Option<f64> {
+            self.now().map(|now| now.as_secs_f64())
+        }
+
+        fn now(&self) -> Option<Duration> {
+            let seconds = self.0.as_ref()?;
+            seconds.set(seconds.get() + 1);
+            Some(Duration::from_secs(seconds.get()))
+        }
+
+        fn resolve(&self, path: &Path) -> io::Result<PathBuf> {
+            Ok(path.to_path_buf())
+        }
+
+        fn load(&mut self, _path: &Path) -> io::Result<String> {
+            Err(io::ErrorKind::NotFound.into())
+        }
+    }
+
+    #[test]
+    fn time_is_measured_by_the_host() {
+        let limits = Limits {
+            time: Some(Duration::from_secs(10)),
+            ..Limits::default()
+        };
+        let mut interpreter = Interpreter::default();
+        interpreter.set_host(Ticking(Some(Cell::new(0))));
+        interpreter.set_limits(limits);
+        let Err(Error::Runtime(error)) =
+            run(&mut interpreter, "while (true) {}", Features::default())
+        else {
+            panic!("the loop should be stopped");
+        };
+        assert_eq!(error.limit(), Some(Limit::Time));
+
+        let mut interpreter = Interpreter::default();
+        interpreter.set_host(Ticking(None));
+        interpreter.set_limits(limits);
+        let source = "var i = 0; while (i < 100) i = i + 1;";
+        assert!(run(&mut interpreter, source, Features::default()).is_ok());
+        let error = run(&mut interpreter, "clock();", Features::default()).unwrap_err();
+        assert!(error.to_string().starts_with("There is no clock to read."));
+    }
+
+    #[test]
+    fn globals_can_be_set_before_a_run_and_read_after() {
+        let mut interpreter = Interpreter::default();
+        interpreter.set_global("limit", Value::Number(3.0));
+        let source = "var total = 0; for (var i = 1; i <= limit; i = i + 1) total = total + i;";
+        run(&mut interpreter, source, Features::default()).unwrap();
+        let globals: Vec<_> = interpreter
+            .globals()
+            .filter(|(_, value)| !matches!(value, Value::Callable(_)))
+            .collect();
+        assert_eq!(
+            globals,
+            [
+                ("limit".to_string(), Value::Number(3.0)),
+                ("total".to_string(), Value::Number(6.0))
+            ]
+        );
+    }
+
+    #[test]
+    fn imports_report_modules_that_fail_to_load() {
+        let directory = std::env::temp_dir().join(format!("rinlox-imports-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("bad.lox"), "print ;\nvar 1;").unwrap();
+        let mut interpreter = Interpreter::default();
+        interpreter.set_path(&directory.join("main.lox"));
+
+        let error = run(&mut interpreter, "import bad;", Features::default()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Could not import 'bad.lox': it has errors:\n\
+             [bad.lox:1, col 7] Error at ';': Expect expression.\n\
+             [bad.lox:2, col 5] Error at '1': Expect variable name.\n\
+             [line 1, col 8]"
+        );
+        let error = run(&mut interpreter, "import missing;", Features::default()).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Could not import 'missing.lox': "));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn run_vm_keeps_globals_between_calls() {
+        let mut vm = Vm::default();
+        run_vm(&mut vm, "var a = 1;", Features::default()).unwrap();
+        run_vm(&mut vm, "a = a + 1;", Features::default()).unwrap();
+        assert!(matches!(
+            run_vm(&mut vm, "print ;", Features::default()),
+            Err(Error::Parse(_))
+        ));
+        let error = run_vm(&mut vm, "b;", Features::default()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Undefined variable 'b'.\n[line 1, col 1]"
+        );
+    }
+
+    #[test]
+    fn eval_returns_the_value_of_expressions() {
+        let mut interpreter = Interpreter::default();
+        let mut eval = |source| eval(&mut interpreter, source, Features::default()).unwrap();
+        assert_eq!(eval("1 + 2").value, Some(Value::Number(3.0)));
+        assert_eq!(eval("var a = \"x\";").value, None);
+        assert_eq!(eval("a + a;").value, Some(Value::String("xx".into())));
+        assert_eq!(eval("a; var b = a;").value, None);
+        let evaluation = eval("var c = a\nc + a");
+        assert_eq!(evaluation.value, Some(Value::String("xx".into())));
+        assert_eq!(evaluation.summary().to_string(), "2 hints");
+        assert_eq!(eval("1;").summary().to_string(), "no diagnostics");
+    }
+
+    #[test]
+    fn globals_keep_their_slots_from_one_run_to_the_next() {
+        let mut interpreter = Interpreter::default();
+        let slots = interpreter.global_slots();
+        let mut eval = |source| eval(&mut interpreter, source, Features::default()).unwrap();
+        eval("var a = 1\nfun inc() { a = a + 1; }");
+        let a = slots.get(symbol::Symbol::intern("a"));
+        assert!(a.is_some());
+        eval("inc()\nvar b = a");
+        assert_eq!(slots.get(symbol::Symbol::intern("a")), a);
+        assert_eq!(eval("a + b").value, Some(Value::Number(4.0)));
+        // Globals without a value in their slot are the natives
+        assert!(matches!(eval("clock").value, Some(Value::Callable(_))));
+
+        interpreter.remove_global("b");
+        let error = run(&mut interpreter, "b;", Features::default()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Undefined variable 'b'.\n[line 1, col 1]"
+        );
+        interpreter.set_global("b", Value::Number(5.0));
+        let value = run(&mut interpreter, "a = a + b; a;", Features::default()).unwrap();
+        assert_eq!(value, Some(Value::Number(7.0)));
+    }
+
+    #[test]
+    fn eval_times_each_stage_by_the_clock_of_the_host() {
+        let mut interpreter = Interpreter::default();
+        interpreter.set_host(Ticking(Some(Cell::new(0))));
+        let evaluation = eval(&mut interpreter, "1 + 2", Features::default()).unwrap();
+        let second = Duration::from_secs(1);
+        assert_eq!(
+            evaluation.timings,
+            Some(Timings {
+                parse: second,
+                resolve: second,
+                run: second
+            })
+        );
+
+        interpreter.set_host(Ticking(None));
+        let evaluation = eval(&mut interpreter, "1 + 2", Features::default()).unwrap();
+        assert_eq!(evaluation.timings, None);
+    }
+
+    #[test]
+    fn fragments_share_their_globals() {
+        let mut interpreter = Interpreter::default();
+        assert_eq!(
+            interpreter.eval_fragment("var a = 1\n").unwrap(),
+            Value::Nil
+        );
+        assert_eq!(
+            interpreter.eval_fragment("a = a + 1\na * 10").unwrap(),
+            Value::Number(20.0)
+        );
+        let error = interpreter.eval_fragment("{\n  a +\n}").unwrap_err();
+        let mut diagnostics = Diagnostics::new(false);
+        diagnostics.set_source("{\n  a +\n}");
+        assert_eq!(
+            error.render(&diagnostics),
+            "[line 3, col 1] Error at '}': Expect expression.\n  |\n3 | }\n  | ^"
+        );
+        assert_eq!(interpreter.eval_fragment("a").unwrap(), Value::Number(2.0));
+    }
+
+    #[test]
+    fn debug_natives_see_the_call_stack() {
+        let mut interpreter = Interpreter::default();
+        interpreter.enable_debug();
+        let source = "fun f() { return g(); } fun g() { return callerName(1); }";
+        run(&mut interpreter, source, Features::default()).unwrap();
+        let mut eval = |source| {
+            eval(&mut interpreter, source, Features::default())
+                .unwrap()
+                .value
+        };
+        assert_eq!(eval("f()"), Some(Value::String("f".into())));
+        assert_eq!(eval("callerName(0)"), Some(Value::Nil));
+        assert_eq!(eval("stackDepth()"), Some(Value::Number(0.0)));
+        assert!(interpreter.frames().is_empty());
+    }
+
+    #[test]
+    fn unclosed_brackets_strings_and_comments_are_incomplete() {
+        assert!(is_incomplete("fun f() {"));
+        assert!(is_incomplete("print (1 +"));
+        assert!(is_incomplete("var s = \"two\nlines"));
+        assert!(is_incomplete("/* comment"));
+        assert!(is_incomplete("var l = [1,"));
+        assert!(is_incomplete("print m[l[0]"));
+        assert!(!is_incomplete("var l = [1, [2]];"));
+        assert!(!is_incomplete("print \"\\q\" @"));
+        assert!(!is_incomplete("fun f() {}"));
+        assert!(!is_incomplete("print 1"));
+        assert!(!is_incomplete("print 1);"));
+    }
+}
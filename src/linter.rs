@@ -0,0 +1,543 @@
+/// Static checks for code that is valid Lox but most likely a mistake
+///
+/// The linter runs on programs the resolver accepted, and finds:
+///
+/// - Local variables that are never read (unless their name starts with
+///   `_`).
+/// - Code after a `return`, `break` or `continue`, which never runs.
+/// - Assignments used as the condition of an `if` or a loop, which were
+///   probably meant to be comparisons. Wrapping them in parentheses
+///   keeps them quiet.
+/// - Local declarations shadowing a local of an enclosing scope.
+/// - Comparisons of something with itself, like `a == a`.
+///
+/// Each finding is a `Lint`, with the severity of its `Rule`.
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::ast::expr_to_source;
+use crate::diagnostics::{header, Note, Severity};
+use crate::expr::Expr;
+use crate::internal;
+use crate::lexer::{Token, TokenType};
+use crate::stmt::{ClassBody, Stmt};
+use crate::symbol::Symbol;
+
+/// Kind of mistake a lint is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    UnusedVariable,
+    UnreachableCode,
+    AssignmentInCondition,
+    Shadowing,
+    SelfComparison,
+}
+
+impl Rule {
+    /// Any of them can be on purpose, even comparing something with itself
+    /// (`x != x` is how a NaN is told apart), so none fails the lint
+    pub fn severity(self) -> Severity {
+        Severity::Warning
+    }
+}
+
+/// Mistake found at `token`, possibly pointing at other related places in
+/// the source with the `notes`
+#[derive(Debug, Clone)]
+pub struct Lint {
+    pub rule: Rule,
+    pub token: Token,
+    pub message: String,
+    pub notes: Vec<Note>,
+}
+
+impl Lint {
+    pub fn severity(&self) -> Severity {
+        self.rule.severity()
+    }
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", header(self.severity(), &self.token, &self.message))
+    }
+}
+
+/// Local declared in a scope being checked
+#[derive(Debug)]
+struct Local {
+    name: Token,
+    /// Whether it must be read at some point (parameters and functions
+    /// don't have to be)
+    must_be_read: bool,
+    read: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Linter {
+    /// Stack of the local scopes being checked. Globals are not tracked,
+    /// since other code (e.g. the next line in the prompt) can use them
+    scopes: Vec<HashMap<Symbol, Local>>,
+    lints: Vec<Lint>,
+}
+
+impl Linter {
+    /// Check the program, returning what was found in the order it appears
+    /// in the source
+    pub fn lint(&mut self, statements: &[Stmt]) -> Vec<Lint> {
+        self.lint_statements(statements);
+        let mut lints = std::mem::take(&mut self.lints);
+        lints.sort_by_key(|lint| lint.token.span.start);
+        lints
+    }
+
+    fn lint_statements(&mut self, statements: &[Stmt]) {
+        for (i, stmt) in statements.iter().enumerate() {
+            self.lint_stmt(stmt);
+            if let Stmt::Return { keyword, .. }
+            | Stmt::Break { keyword }
+            | Stmt::Continue { keyword } = stmt
+            {
+                if let Some(unreachable) = statements.get(i + 1) {
+                    self.unreachable(unreachable, keyword);
+                }
+                break;
+            }
+        }
+    }
+
+    fn lint_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block { statements } => {
+                self.begin_scope();
+                self.lint_statements(statements);
+                self.end_scope();
+            }
+            Stmt::Break { .. } | Stmt::Continue { .. } => {}
+            Stmt::Class {
+                name,
+                superclass,
+                body,
+            } => {
+                self.declare(name, false);
+                if let Some(superclass) = superclass {
+                    self.lint_expr(superclass);
+                }
+                self.lint_class_body(body);
+            }
+            Stmt::Defer { body, .. } => {
+                self.begin_scope();
+                self.lint_statements(body);
+                self.end_scope();
+            }
+            Stmt::Enum { name, .. } | Stmt::Import { name, .. } => self.declare(name, false),
+            Stmt::Extend { class, body } => {
+                self.lint_expr(class);
+                self.lint_class_body(body);
+            }
+            Stmt::Expression { expression } | Stmt::Print { expression, .. } => {
+                self.lint_expr(expression)
+            }
+            Stmt::Function { name, params, body } => {
+                self.declare(name, false);
+                self.lint_function(params, body);
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.lint_condition(condition);
+                self.lint_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.lint_stmt(else_branch);
+                }
+            }
+            Stmt::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.lint_expr(value);
+                }
+            }
+            Stmt::Var { name, initializer } => {
+                if let Some(initializer) = initializer {
+                    self.lint_expr(initializer);
+                }
+                self.declare(name, true);
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+                ..
+            } => {
+                self.lint_condition(condition);
+                self.lint_stmt(body);
+                if let Some(increment) = increment {
+                    self.lint_expr(increment);
+                }
+            }
+        }
+    }
+
+    fn lint_class_body(&mut self, body: &ClassBody) {
+        let methods = [&body.methods, &body.getters, &body.class_methods];
+        for method in methods.into_iter().flatten() {
+            let Stmt::Function { params, body, .. } = method else {
+                unreachable!("class methods should be functions");
+            };
+            self.lint_function(params, body);
+        }
+    }
+
+    fn lint_function(&mut self, params: &[Token], body: &[Stmt]) {
+        self.begin_scope();
+        for param in params {
+            self.declare(param, false);
+        }
+        self.lint_statements(body);
+        self.end_scope();
+    }
+
+    fn lint_condition(&mut self, condition: &Expr) {
+        if let Expr::Assign { name, .. } | Expr::Set { name, .. } = condition {
+            self.report(
+                Rule::AssignmentInCondition,
+                name,
+                "Assignment used as a condition, did you mean '=='?",
+                Vec::new(),
+            );
+        }
+        self.lint_expr(condition);
+    }
+
+    fn lint_expr(&mut self, expr: &Expr) {
+        // Nested code can take more stack than the thread has
+        internal::grow_stack(|| {
+            match expr {
+                // Assigning a variable is not reading it
+                Expr::Assign { value, .. } => self.lint_expr(value),
+                Expr::Binary {
+                    left,
+                    operator,
+                    right,
+                } => {
+                    if ungrouped(left) == ungrouped(right) && is_place(left) {
+                        self.self_comparison(left, operator);
+                    }
+                    self.lint_expr(left);
+                    self.lint_expr(right);
+                }
+                Expr::Logical { left, right, .. } => {
+                    self.lint_expr(left);
+                    self.lint_expr(right);
+                }
+                Expr::Call {
+                    callee, arguments, ..
+                } => {
+                    self.lint_expr(callee);
+                    for argument in arguments {
+                        self.lint_expr(argument);
+                    }
+                }
+                Expr::Conditional {
+                    condition,
+                    then_branch,
+                    else_branch,
+                } => {
+                    self.lint_expr(condition);
+                    self.lint_expr(then_branch);
+                    self.lint_expr(else_branch);
+                }
+                Expr::Get { object, .. } => self.lint_expr(object),
+                Expr::Set { object, value, .. } => {
+                    self.lint_expr(value);
+                    self.lint_expr(object);
+                }
+                Expr::Index { object, index, .. } => {
+                    self.lint_expr(object);
+                    self.lint_expr(index);
+                }
+                Expr::SetIndex {
+                    object,
+                    index,
+                    value,
+                    ..
+                } => {
+                    self.lint_expr(value);
+                    self.lint_expr(object);
+                    self.lint_expr(index);
+                }
+                Expr::List { elements, .. } => {
+                    for element in elements {
+                        self.lint_expr(element);
+                    }
+                }
+                Expr::Map { entries, .. } => {
+                    for (key, value) in entries {
+                        self.lint_expr(key);
+                        self.lint_expr(value);
+                    }
+                }
+                Expr::Grouping { expression } => self.lint_expr(expression),
+                Expr::Lambda { params, body, .. } => self.lint_function(params, body),
+                Expr::Unary { right, .. } => self.lint_expr(right),
+                Expr::Variable { name, .. } => {
+                    let local = self
+                        .scopes
+                        .iter_mut()
+                        .rev()
+                        .find_map(|scope| scope.get_mut(&name.lexeme.symbol()));
+                    if let Some(local) = local {
+                        local.read = true;
+                    }
+                }
+                Expr::Literal { .. } | Expr::Super { .. } | Expr::This { .. } => {}
+            }
+        })
+    }
+
+    fn self_comparison(&mut self, operand: &Expr, operator: &Token) {
+        let outcome = match operator.typ {
+            TokenType::EqualEqual | TokenType::LessEqual | TokenType::GreaterEqual => {
+                "true (unless it is NaN)"
+            }
+            TokenType::BangEqual => "false (unless it is NaN)",
+            TokenType::Less | TokenType::Greater => "false",
+            _ => return,
+        };
+        let message = format!(
+            "'{}' is compared with itself, which is always {}.",
+            expr_to_source(operand),
+            outcome
+        );
+        self.report(Rule::SelfComparison, operator, &message, Vec::new());
+    }
+
+    /// Report the statement after a `return`, pointing at its first token
+    /// if it has one
+    /// Report the statement after a `return`, `break` or `continue`
+    fn unreachable(&mut self, stmt: &Stmt, keyword: &Token) {
+        let message = format!("Unreachable code after '{}'.", keyword.lexeme);
+        match stmt.first_token() {
+            Some(token) => {
+                let note = match keyword.typ {
+                    TokenType::Break => "the loop ends here",
+                    TokenType::Continue => "the loop goes on to the next iteration here",
+                    _ => "the function returns here",
+                };
+                let note = Note::new(keyword.span, note);
+                self.report(Rule::UnreachableCode, token, &message, vec![note]);
+            }
+            None => self.report(Rule::UnreachableCode, keyword, &message, Vec::new()),
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// Close the innermost scope, reporting the variables in it that were
+    /// never read
+    fn end_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        for local in scope.into_values() {
+            if local.must_be_read && !local.read && !local.name.lexeme.starts_with('_') {
+                let message = format!("Local variable '{}' is never read.", local.name.lexeme);
+                self.report(Rule::UnusedVariable, &local.name, &message, Vec::new());
+            }
+        }
+    }
+
+    /// Add a local to the innermost scope (if there is one), reporting it
+    /// if it shadows one of an enclosing scope
+    fn declare(&mut self, name: &Token, must_be_read: bool) {
+        let Some((scope, enclosing)) = self.scopes.split_last_mut() else {
+            return;
+        };
+        let shadowed = enclosing
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.lexeme.symbol()))
+            .map(|local| local.name.span);
+        scope.insert(
+            name.lexeme.symbol(),
+            Local {
+                name: name.clone(),
+                must_be_read,
+                read: false,
+            },
+        );
+
+        if let Some(shadowed) = shadowed {
+            let message = format!("'{}' shadows a local of an enclosing scope.", name.lexeme);
+            let note = Note::new(shadowed, "the shadowed local is declared here");
+            self.report(Rule::Shadowing, name, &message, vec![note]);
+        }
+    }
+
+    fn report(&mut self, rule: Rule, token: &Token, message: &str, notes: Vec<Note>) {
+        self.lints.push(Lint {
+            rule,
+            token: token.clone(),
+            message: message.to_string(),
+            notes,
+        });
+    }
+}
+
+/// Whether the expression names a place (a variable or a field), which
+/// always has the same value when it is read twice in a row
+fn is_place(expr: &Expr) -> bool {
+    match expr {
+        Expr::Variable { .. } | Expr::This { .. } => true,
+        Expr::Get { object, .. } => is_place(object),
+        Expr::Grouping { expression } => is_place(expression),
+        _ => false,
+    }
+}
+
+fn ungrouped(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Grouping { expression } => ungrouped(expression),
+        _ => expr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+
+    /// The rule, lexeme and line of each lint found in the source
+    fn lint(source: &str) -> Vec<(Rule, String, usize)> {
+        let statements = Parser::new(Scanner::new(source.to_string()))
+            .parse()
+            .expect("source should parse");
+        Resolver::default()
+            .resolve(&statements)
+            .expect("source should resolve");
+        Linter::default()
+            .lint(&statements)
+            .into_iter()
+            .map(|lint| {
+                (
+                    lint.rule,
+                    lint.token.lexeme.to_string(),
+                    lint.token.span.line,
+                )
+            })
+            .collect()
+    }
+
+    fn found(rule: Rule, lexeme: &str, line: usize) -> (Rule, String, usize) {
+        (rule, lexeme.to_string(), line)
+    }
+
+    #[test]
+    fn finds_unread_locals() {
+        let source = "
+            var global;
+            fun f(unused) {
+                var a = 1;
+                var _b = 2;
+                var c;
+                c = 3;
+                var d = 4;
+                return d;
+            }";
+        assert_eq!(
+            lint(source),
+            [
+                found(Rule::UnusedVariable, "a", 4),
+                found(Rule::UnusedVariable, "c", 6)
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_code_after_return() {
+        let source = "
+            fun f() {
+                return 1;
+                g();
+                print 2;
+            }
+            fun h() { if (true) return; print 3; }";
+        assert_eq!(lint(source), [found(Rule::UnreachableCode, "g", 4)]);
+        assert_eq!(
+            lint("fun f() { return; \"done\"; }"),
+            [found(Rule::UnreachableCode, "return", 1)]
+        );
+        assert_eq!(
+            lint(
+                "while (true) { break; print 1; }
+while (true) { continue; print 2; }"
+            ),
+            [
+                found(Rule::UnreachableCode, "print", 1),
+                found(Rule::UnreachableCode, "print", 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_assignments_in_conditions() {
+        let source = "
+            var a;
+            if (a = 1) print a;
+            while ((a = false)) {}
+            if (a.b = 2) print a;";
+        assert_eq!(
+            lint(source),
+            [
+                found(Rule::AssignmentInCondition, "a", 3),
+                found(Rule::AssignmentInCondition, "b", 5)
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_shadowed_locals() {
+        let source = "
+            var a;
+            fun f(a) {
+                for (var i = 0; i < a; i = i + 1) {
+                    var a = i;
+                    print a;
+                    for (var i = 0; i < 1; i = i + 1) print i;
+                }
+            }";
+        assert_eq!(
+            lint(source),
+            [
+                found(Rule::Shadowing, "a", 5),
+                found(Rule::Shadowing, "i", 7)
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_comparisons_with_itself() {
+        let source = "
+            var a;
+            print a == a;
+            print a.b < (a.b);
+            print a == -a;
+            print f() == f();
+            fun isNaN(x) { return x != x; }";
+        let lints = lint(source);
+        assert_eq!(
+            lints,
+            [
+                found(Rule::SelfComparison, "==", 3),
+                found(Rule::SelfComparison, "<", 4),
+                found(Rule::SelfComparison, "!=", 7)
+            ]
+        );
+        assert_eq!(Rule::SelfComparison.severity(), Severity::Warning);
+    }
+}
@@ -0,0 +1,137 @@
+/// Lists for the `Lox` interpreter, written `[1, 2, 3]`
+use std::cell::{Cell, RefCell};
+use std::fmt::Display;
+use std::rc::Rc;
+
+use crate::gc::{self, Trace};
+use crate::interpreter::{RuntimeError, Value};
+use crate::lexer::Token;
+use crate::native::NativeFunction;
+
+/// A growable sequence of values, read and written with `list[index]`.
+/// Like instances, a list is shared by everything holding it, and only
+/// equal to itself
+#[derive(Debug, Default)]
+pub struct LoxList {
+    elements: RefCell<Vec<Value>>,
+    /// Whether the list is being shown, so a list inside itself is not
+    /// shown forever
+    showing: Cell<bool>,
+}
+
+impl LoxList {
+    pub fn new(elements: Vec<Value>) -> Self {
+        Self {
+            elements: RefCell::new(elements),
+            showing: Cell::new(false),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.borrow().len()
+    }
+
+    /// The element at `index`, which the `bracket` of the expression reading
+    /// it points errors at
+    pub fn get(&self, bracket: &Token, index: &Value) -> Result<Value, RuntimeError> {
+        let position = self.position(bracket, index)?;
+        Ok(self.elements.borrow()[position].clone())
+    }
+
+    /// Replace the element at `index`
+    pub fn set(&self, bracket: &Token, index: &Value, value: Value) -> Result<(), RuntimeError> {
+        let position = self.position(bracket, index)?;
+        self.elements.borrow_mut()[position] = value;
+        Ok(())
+    }
+
+    fn position(&self, bracket: &Token, index: &Value) -> Result<usize, RuntimeError> {
+        let Value::Number(n) = *index else {
+            return Err(RuntimeError::new(bracket, "List index must be a number."));
+        };
+        if n.fract() != 0.0 {
+            return Err(RuntimeError::new(bracket, "List index must be an integer."));
+        }
+        if n < 0.0 || n >= self.len() as f64 {
+            return Err(RuntimeError::new(
+                bracket,
+                &format!(
+                    "List index {} out of range for a list of {} elements.",
+                    n,
+                    self.len()
+                ),
+            ));
+        }
+        Ok(n as usize)
+    }
+
+    /// Look up one of the methods by name, bound to the list:
+    ///
+    /// - `push(value)` adds the value at the end.
+    /// - `pop()` removes the last element and returns it.
+    pub fn method(self: &Rc<Self>, name: &Token) -> Result<Value, RuntimeError> {
+        let list = Rc::clone(self);
+        let method = match name.lexeme.as_str() {
+            "push" => NativeFunction::new(
+                "push",
+                1,
+                Box::new(move |_, _, mut arguments| {
+                    list.elements.borrow_mut().push(arguments.remove(0));
+                    Ok(Value::Nil)
+                }),
+            ),
+            "pop" => NativeFunction::new(
+                "pop",
+                0,
+                Box::new(move |_, paren, _| {
+                    list.elements
+                        .borrow_mut()
+                        .pop()
+                        .ok_or_else(|| RuntimeError::new(paren, "Can't pop from an empty list."))
+                }),
+            ),
+            _ => {
+                return Err(RuntimeError::new(
+                    name,
+                    &format!("Undefined property '{}'.", name.lexeme),
+                ))
+            }
+        };
+        Ok(Value::Callable(Rc::new(method)))
+    }
+
+    /// Show the list with each element shown by `show`, like `[1, 2]`. A
+    /// list inside itself is shown as `[...]`
+    pub fn show(&self, show: impl Fn(&Value) -> String) -> String {
+        if self.showing.replace(true) {
+            return "[...]".to_string();
+        }
+        let elements: Vec<String> = self.elements.borrow().iter().map(show).collect();
+        self.showing.set(false);
+        format!("[{}]", elements.join(", "))
+    }
+}
+
+impl Trace for LoxList {
+    fn trace(&self, references: &mut Vec<usize>) -> bool {
+        let Ok(elements) = self.elements.try_borrow() else {
+            return false;
+        };
+        for element in elements.iter() {
+            gc::trace_value(element, references);
+        }
+        true
+    }
+
+    fn clear(&self) {
+        if let Ok(mut elements) = self.elements.try_borrow_mut() {
+            elements.clear();
+        }
+    }
+}
+
+impl Display for LoxList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.show(Value::to_string))
+    }
+}
@@ -0,0 +1,96 @@
+/// Define an AST node type as an enum with a struct-like variant per kind of
+/// node, along with a constructor function for each of the variants and a
+/// visitor trait to walk the tree with
+///
+/// Each variant is declared as `Variant(constructor, visit_method) { fields }`:
+///
+/// ```ignore
+/// define_ast! {
+///     pub enum Expr: ExprVisitor {
+///         Binary(binary, visit_binary_expr) { left: Box<Expr>, operator: Token, right: Box<Expr> },
+///         Variable(variable, visit_variable_expr) { name: Token; depth: Depth },
+///     }
+/// }
+/// ```
+///
+/// The constructor arguments accept anything that converts into the field
+/// type, so `Expr::binary(left, operator, right)` can take the operands
+/// directly instead of boxing them first. Fields listed after a `;` are
+/// not arguments of the constructor, and start with their `Default` value.
+///
+/// With the `serde` feature the nodes serialize as objects with their
+/// fields and the name of their variant as `kind`. The default fields are
+/// left out, since they are filled in after parsing.
+///
+/// `accept` dispatches a node to the visit method of its variant, which
+/// receives a reference to each of the fields in order.
+macro_rules! define_ast {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident : $visitor:ident {
+            $(
+                $variant:ident ( $constructor:ident , $visit:ident ) {
+                    $( $field:ident : $typ:ty ),* $(,)?
+                    $( ; $( $default_field:ident : $default_typ:ty ),* $(,)? )?
+                }
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "kind"))]
+        $vis enum $name {
+            $(
+                $variant {
+                    $(
+                        #[cfg_attr(
+                            feature = "serde",
+                            serde(serialize_with = "crate::internal::serialize_nested")
+                        )]
+                        $field: $typ,
+                    )*
+                    $(
+                        $(
+                            #[cfg_attr(feature = "serde", serde(skip))]
+                            $default_field: $default_typ,
+                        )*
+                    )?
+                },
+            )*
+        }
+
+        impl $name {
+            $(
+                pub fn $constructor($( $field: impl Into<$typ> ),*) -> Self {
+                    $name::$variant {
+                        $( $field: $field.into(), )*
+                        $( $( $default_field: Default::default(), )* )?
+                    }
+                }
+            )*
+
+            pub fn accept<R>(&self, visitor: &mut impl $visitor<R>) -> R {
+                // Nested code can take more stack than the thread has
+                crate::internal::grow_stack(|| match self {
+                    $(
+                        $name::$variant {
+                            $( $field, )*
+                            $( $( $default_field, )* )?
+                        } => visitor.$visit($( $field, )* $( $( $default_field, )* )?),
+                    )*
+                })
+            }
+        }
+
+        #[allow(clippy::borrowed_box, clippy::ptr_arg)]
+        $vis trait $visitor<R> {
+            $(
+                fn $visit(
+                    &mut self,
+                    $( $field: &$typ, )*
+                    $( $( $default_field: &$default_typ, )* )?
+                ) -> R;
+            )*
+        }
+    };
+}
@@ -1,17 +1,42 @@
-mod expr;
-/// Interpreter for the Lox programming language from the
-/// "Crafting Interpreters" book
-mod lexer;
+/// Command line interface for `rinlox`, running scripts or a prompt on top
+/// of the library
+mod cli;
 
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::fmt::{Debug, Display};
-use std::io::BufRead;
+use std::io::{BufRead, BufReader, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
-use lexer::Scanner;
+use cli::{Backend, Cli, Command, Emit, USAGE};
+use rinlox::ast_printer::AstPrinter;
+use rinlox::debugger::Debugger;
+use rinlox::diagnostics::{error_header, Diagnostics, Severity, Summary};
+#[cfg(feature = "serde")]
+use rinlox::json;
+use rinlox::linter::Linter;
+use rinlox::observer::Tracer;
+#[cfg(feature = "plugins")]
+use rinlox::plugin;
+#[cfg(feature = "serde")]
+use rinlox::semantic;
+use rinlox::token_diff::{self, DumpedToken};
+use rinlox::transpiler::Transpiler;
+use rinlox::{formatter, grammar};
+use rinlox::{
+    Error, Features, Hint, InternalError, Interpreter, Origin, ParseError, Parser, ResolveError,
+    Resolver, RuntimeError, RuntimeErrorKind, ScanError, Scanner, Token, Value, Vm,
+};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
 
 // TODO(alvaro): Look into `thiserror` for hanlding this boilerplate
 #[derive(Debug)]
 enum LoxError {
     IOError(std::io::Error),
+    ReadlineError(ReadlineError),
     Generic(String),
 }
 
@@ -19,6 +44,7 @@ impl Display for LoxError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             LoxError::IOError(e) => write!(f, "IOError: {}", e),
+            LoxError::ReadlineError(e) => write!(f, "ReadlineError: {}", e),
             LoxError::Generic(msg) => write!(f, "{}", msg),
         }
     }
@@ -30,75 +56,1159 @@ impl From<std::io::Error> for LoxError {
     }
 }
 
+impl From<ReadlineError> for LoxError {
+    fn from(e: ReadlineError) -> Self {
+        LoxError::ReadlineError(e)
+    }
+}
+
 impl From<String> for LoxError {
     fn from(e: String) -> Self {
         LoxError::Generic(e)
     }
 }
 
+/// Where `Lox` writes, shared with the interpreter it runs so everything
+/// written comes out in order
+#[derive(Clone)]
+struct Sink(Rc<RefCell<Box<dyn Write>>>);
+
+impl Sink {
+    fn new(writer: impl Write + 'static) -> Self {
+        Self(Rc::new(RefCell::new(Box::new(writer))))
+    }
+
+    /// Write the text followed by a line break, like `println!`
+    fn line(&self, text: impl Display) {
+        if writeln!(self.0.borrow_mut(), "{}", text).is_err() {
+            stop_writing();
+        }
+    }
+
+    /// Write the text as is, like `print!`
+    fn text(&self, text: impl Display) {
+        if write!(self.0.borrow_mut(), "{}", text).is_err() {
+            stop_writing();
+        }
+    }
+}
+
+/// Stop right away once the output or the errors can't be written (e.g.
+/// the pipe to `head` was closed), as there is nobody left to tell about
+/// anything else. Exits with 74 (`EX_IOERR`)
+fn stop_writing() -> ! {
+    std::process::exit(74)
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+impl Debug for Sink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Sink")
+    }
+}
+
 #[derive(Debug)]
-pub struct Lox {}
+struct Lox {
+    interpreter: Interpreter,
+    /// Virtual machine running the code instead of the interpreter, when
+    /// the bytecode backend is selected
+    vm: Option<Vm>,
+    /// Experimental features enabled from the command line (scripts can
+    /// enable more with a directive)
+    features: Features,
+    diagnostics: Diagnostics,
+    /// Where the output of the commands goes, including what the program
+    /// prints
+    output: Sink,
+    /// Where the errors in the code and while running it are reported
+    errors: Sink,
+    /// Whether the last run had errors in the code (scanning, parsing or
+    /// resolving it), so it was not executed
+    had_error: bool,
+    /// Whether the last run stopped because of an error while executing
+    had_runtime_error: bool,
+    /// Diagnostics reported since the last run started
+    reported: Summary,
+    /// Whether panics in rinlox are reported as internal errors instead of
+    /// aborting
+    strict_internal: bool,
+    /// Name of the file being run, for the stack traces (`<prompt>` for
+    /// code typed in the prompt or given in the command line)
+    source_name: String,
+    /// Whether scripts are parsed with automatic semicolons (the prompt
+    /// always is)
+    auto_semicolons: bool,
+    /// Whether programs are optimized before running them
+    optimize: bool,
+    /// Whether to report what the garbage collector did once done running
+    gc_stats: bool,
+    /// Values of the expressions evaluated in the prompt, bound to `_1`,
+    /// `_2`... (only the last `MAX_VALUES` are kept)
+    values: VecDeque<(usize, Value)>,
+    /// Number of values evaluated in the prompt so far
+    value_count: usize,
+    /// Whether to print how long each stage of the code typed in the
+    /// prompt took
+    timings: bool,
+    /// File being scanned as it is read (see `stream`), which is only read
+    /// whole to quote it once there are errors
+    streamed: Option<String>,
+}
+
+/// What running some code gave back, besides its output
+struct Outcome {
+    /// Value of the last statement, if it is an expression statement
+    value: Option<Value>,
+    /// Diagnostics reported about the code
+    diagnostics: Summary,
+}
+
+/// How many of the values evaluated in the prompt stay bound
+const MAX_VALUES: usize = 100;
 
 impl Lox {
-    fn new() -> Self {
-        Self {}
+    /// Prompt writing its output (including what the programs it runs
+    /// print) and its errors to the given sinks, which can be the same one
+    fn new(output: Sink, errors: Sink) -> Self {
+        // Only use colors when writing to a terminal, unless the user has
+        // opted out (see https://no-color.org)
+        let color = std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        let mut interpreter = Interpreter::default();
+        interpreter.set_output(output.clone());
+        Self {
+            interpreter,
+            vm: None,
+            features: Features::default(),
+            diagnostics: Diagnostics::new(color),
+            output,
+            errors,
+            had_error: false,
+            had_runtime_error: false,
+            reported: Summary::default(),
+            strict_internal: false,
+            source_name: "<prompt>".to_string(),
+            auto_semicolons: false,
+            optimize: false,
+            gc_stats: false,
+            values: VecDeque::new(),
+            value_count: 0,
+            timings: false,
+            streamed: None,
+        }
+    }
+
+    /// Run the script in the file (or in the standard input if it is `-`)
+    fn run_file(&mut self, script_name: String) -> Result<(), LoxError> {
+        let contents = read_source(&script_name)?;
+        self.set_path(&script_name);
+        self.source_name = script_name;
+        self.run(contents);
+        self.exit_on_error();
+        Ok(())
+    }
+
+    fn run_prompt(&mut self) -> Result<(), LoxError> {
+        // Without a terminal there is nobody to type into the prompt, so
+        // whatever is piped in is a whole program
+        if !std::io::stdin().is_terminal() {
+            return self.run_file("-".to_string());
+        }
+        self.output.line("Running from prompt");
+
+        let mut editor = DefaultEditor::new()?;
+        let history = history_path();
+        if let Some(history) = &history {
+            // There is no history yet the first time
+            let _ = editor.load_history(history);
+        }
+        let mut source = String::new();
+        // The last code run, for the meta-commands inspecting it
+        let mut last = String::new();
+        // Lines of code typed so far, so the errors are located in the
+        // whole session (e.g. `repl:12`) rather than in each input
+        let mut session_lines = 0;
+        loop {
+            let prompt = if source.is_empty() { "> " } else { "... " };
+            match editor.readline(prompt) {
+                Ok(line) if source.is_empty() && line.trim_start().starts_with(':') => {
+                    editor.add_history_entry(line.trim())?;
+                    self.meta_command(line.trim(), &last);
+                    self.reset_errors();
+                }
+                Ok(line) => {
+                    source.push_str(&line);
+                    source.push('\n');
+                    if rinlox::is_incomplete(&source) {
+                        continue;
+                    }
+                    let source = std::mem::take(&mut source);
+                    if source.trim().is_empty() {
+                        continue;
+                    }
+                    editor.add_history_entry(source.trim_end())?;
+                    let origin = Origin::new("repl", session_lines);
+                    if let Some(value) = self.eval(source.clone(), Some(&origin)).value {
+                        self.remember(value);
+                    }
+                    session_lines += source.lines().count();
+                    self.reset_errors();
+                    last = source;
+                }
+                // Ctrl-C drops whatever was typed so far, like in a shell
+                Err(ReadlineError::Interrupted) => source.clear(),
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if let Some(history) = &history {
+            editor.save_history(history)?;
+        }
+        self.report_gc();
+        Ok(())
     }
 
-    fn run_file(&self, script_name: String) -> Result<(), LoxError> {
-        println!("Running from script {}", script_name);
-        let contents = std::fs::read_to_string(script_name)?;
-        match self.run(contents) {
-            Ok(_) => Ok(()),
+    /// Run source typed in the prompt, printing the value of the last
+    /// statement if it is an expression statement. With an `origin`, its
+    /// errors are located in it instead of in the source alone
+    fn eval(&mut self, source: String, origin: Option<&Origin>) -> Outcome {
+        // NOTE(alvaro): The vm can't give back the value of an expression
+        // yet, so it runs the source as a program
+        if self.vm.is_some() {
+            return self.run(source);
+        }
+        match origin {
+            Some(origin) => self.diagnostics.set_source_at(&source, origin),
+            None => self.diagnostics.set_source(&source),
+        }
+        self.reported = Summary::default();
+        let features = self.features;
+        let value = match guard(self.strict_internal, || match origin {
+            Some(origin) => rinlox::eval_named(&mut self.interpreter, origin, &source, features),
+            None => rinlox::eval(&mut self.interpreter, &source, features),
+        }) {
+            Ok(evaluation) => {
+                // Semicolons are optional in the prompt, so leaving them
+                // out is not worth a hint on every line
+                self.reported.add(Severity::Hint, evaluation.hints.len());
+                if let Some(timings) = evaluation.timings.filter(|_| self.timings) {
+                    self.errors.line(format!(
+                        "timings: parse {:.2?}, resolve {:.2?}, run {:.2?}",
+                        timings.parse, timings.resolve, timings.run
+                    ));
+                }
+                evaluation.value
+            }
             Err(err) => {
-                self.error(0, format!("{}", err).as_ref());
-                Ok(())
+                self.report(err);
+                None
+            }
+        };
+        if let Some(value) = &value {
+            self.output.line(self.interpreter.stringify(value));
+        }
+        Outcome {
+            value,
+            diagnostics: std::mem::take(&mut self.reported),
+        }
+    }
+
+    /// Bind a value evaluated in the prompt to `_` and to the next `_N`,
+    /// forgetting the oldest one once there are too many
+    fn remember(&mut self, value: Value) {
+        self.value_count += 1;
+        let name = format!("_{}", self.value_count);
+        self.interpreter.set_global("_", value.clone());
+        self.interpreter.set_global(&name, value.clone());
+        self.values.push_back((self.value_count, value));
+        if self.values.len() > MAX_VALUES {
+            if let Some((n, _)) = self.values.pop_front() {
+                self.interpreter.remove_global(&format!("_{}", n));
+            }
+        }
+    }
+
+    /// Run a command typed in the prompt starting with `:`. The ones
+    /// looking at code take it after the command, or use the `last` code
+    /// run
+    fn meta_command(&mut self, line: &str, last: &str) {
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+        let argument = argument.trim();
+        let code = if argument.is_empty() { last } else { argument };
+        match command {
+            ":tokens" => {
+                self.diagnostics.set_source(code);
+                self.print_tokens(self.scanner(code.to_string()));
+            }
+            ":ast" => {
+                self.diagnostics.set_source(code);
+                self.print_ast(self.scanner(code.to_string()));
+            }
+            ":env" => {
+                for (name, value) in self.interpreter.globals() {
+                    let value = self.interpreter.stringify(&value);
+                    self.output.line(format!("{} = {}", name, value));
+                }
             }
+            ":load" if !argument.is_empty() => match std::fs::read_to_string(argument) {
+                Ok(source) => {
+                    self.run(source);
+                }
+                Err(e) => self
+                    .output
+                    .line(format!("Could not read '{}': {}", argument, e)),
+            },
+            ":load" => self.output.line("Missing the file to load: :load file"),
+            ":history" if argument == "values" => {
+                for (n, value) in &self.values {
+                    let value = self.interpreter.stringify(value);
+                    self.output.line(format!("_{} = {}", n, value));
+                }
+            }
+            ":history" => self
+                .output
+                .line("Only the values have a history: :history values"),
+            ":reset" => {
+                self.interpreter.reset();
+                self.values.clear();
+                self.value_count = 0;
+            }
+            ":timings" => {
+                self.timings = !self.timings;
+                let state = if self.timings { "on" } else { "off" };
+                self.output.line(format!("Timings {}", state));
+            }
+            ":help" => self.output.line(REPL_HELP),
+            _ => self.output.line(format!(
+                "Unknown command '{}'. Type :help to see the commands.",
+                line
+            )),
+        }
+    }
+
+    /// An error in one statement of a prompt session shouldn't end it
+    fn reset_errors(&mut self) {
+        self.had_error = false;
+        self.had_runtime_error = false;
+    }
+
+    /// Print the tokens of the source instead of running it, as they are
+    /// scanned
+    fn print_tokens(&mut self, scanner: Scanner) {
+        let printed = guard(self.strict_internal, || {
+            for token in scanner {
+                match token {
+                    Ok(token) => self.output.line(format!("[{}] {}", token.span, token)),
+                    Err(error) => self.scan_error(&error),
+                }
+            }
+            Ok(())
+        });
+        if let Err(err) = printed {
+            self.report(err);
+        }
+    }
+
+    /// Compare the tokens of the script with the dump of them in
+    /// `baseline`, printed by `tokenize` before, exiting with 1 if they are
+    /// different
+    fn token_diff(&mut self, script: String, baseline: String) -> Result<(), LoxError> {
+        let dump = std::fs::read_to_string(&baseline)?;
+        let old = token_diff::parse_dump(&dump).map_err(|err| format!("{}: {}", baseline, err))?;
+        let mut new = Vec::new();
+        for token in self.stream(&script)? {
+            match token {
+                Ok(token) => new.push(DumpedToken::new(&token)),
+                Err(error) => self.scan_error(&error),
+            }
+        }
+
+        let changes = token_diff::diff(&old, &new);
+        for change in &changes {
+            self.output.line(change.to_string());
+        }
+        self.exit_on_error();
+        if !changes.is_empty() {
+            self.errors.line(format!(
+                "{} tokens of {} differ from {}",
+                changes.len(),
+                script,
+                baseline
+            ));
+            let _ = self.errors.flush();
+            std::process::exit(1);
         }
+        Ok(())
     }
 
-    fn run_prompt(&self) -> Result<(), LoxError> {
-        println!("Running from prompt");
-        let stdin = std::io::stdin();
-        for line in stdin.lock().lines().flatten() {
-            if let Err(err) = self.run(line) {
-                self.error(0, format!("{}", err).as_ref());
+    /// Parse the source and print its syntax tree instead of running it
+    fn print_ast(&mut self, scanner: Scanner) {
+        match guard(self.strict_internal, || {
+            Parser::new(scanner).parse().map_err(Error::Parse)
+        }) {
+            Ok(statements) => self.output.text(AstPrinter.print_program(&statements)),
+            Err(err) => self.report(err),
+        }
+    }
+
+    /// Format the script in place, or print it formatted if it is the
+    /// standard input. With `check` only report whether it is formatted,
+    /// exiting with 1 if it is not
+    fn format(&mut self, script: String, check: bool) -> Result<(), LoxError> {
+        let source = read_source(&script)?;
+        self.diagnostics.set_source(&source);
+        let formatted = match formatter::format(&source, self.features) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                self.parse_errors(&errors);
+                self.exit_on_error();
+                return Ok(());
+            }
+        };
+
+        if check {
+            if formatted != source {
+                self.errors.line(format!("{} is not formatted", script));
+                let _ = self.errors.flush();
+                std::process::exit(1);
             }
+        } else if script == "-" {
+            self.output.text(formatted);
+        } else if formatted != source {
+            std::fs::write(&script, formatted)?;
         }
         Ok(())
     }
 
-    fn run(&self, source: String) -> Result<(), LoxError> {
-        let mut scanner = Scanner::new(source);
-        scanner.scan_tokens(self);
-        for (i, token) in scanner.tokens.iter().enumerate() {
-            println!("Token {}: {}", i, token)
+    /// Report the likely mistakes in the script without running it, exiting
+    /// with an error if any of them is an error
+    fn lint(&mut self, script: String) -> Result<(), LoxError> {
+        let source = read_source(&script)?;
+        self.diagnostics.set_source(&source);
+        let statements = match Parser::new(self.scanner(source)).parse() {
+            Ok(statements) => statements,
+            Err(errors) => {
+                self.parse_errors(&errors);
+                self.exit_on_error();
+                return Ok(());
+            }
+        };
+        if let Err(errors) = Resolver::default().resolve(&statements) {
+            self.resolve_errors(&errors);
+            self.exit_on_error();
         }
+
+        for lint in Linter::default().lint(&statements) {
+            let header = lint.to_string();
+            self.errors.line(self.diagnostics.render_as(
+                lint.severity(),
+                &header,
+                lint.token.span,
+                &lint.notes,
+            ));
+            if lint.severity() == Severity::Error {
+                self.had_error = true;
+            }
+        }
+        self.exit_on_error();
         Ok(())
     }
 
-    fn error(&self, line: usize, msg: &str) {
-        self.report(line, "", msg)
+    /// Print the tokens, the syntax tree or the identifiers of the source
+    /// as JSON instead of running it
+    #[cfg(feature = "serde")]
+    fn emit(&mut self, source: String, emit: Emit) {
+        self.diagnostics.set_source(&source);
+        match emit {
+            Emit::Tokens => {
+                let mut tokens = Vec::new();
+                for token in self.scanner(source) {
+                    match token {
+                        Ok(token) => tokens.push(token),
+                        Err(error) => self.scan_error(&error),
+                    }
+                }
+                if !self.had_error {
+                    self.output.line(json::tokens(&tokens));
+                }
+            }
+            Emit::Ast => match Parser::new(self.scanner(source)).parse() {
+                Ok(statements) => self.output.line(json::ast(&statements)),
+                Err(errors) => self.parse_errors(&errors),
+            },
+            // The identifiers are classified even if the program doesn't
+            // resolve, like an editor would need while it is being written
+            Emit::Semantic => match Parser::new(self.scanner(source)).parse() {
+                Ok(statements) => {
+                    let identifiers = semantic::classify(&statements);
+                    self.output.line(json::identifiers(&identifiers))
+                }
+                Err(errors) => self.parse_errors(&errors),
+            },
+        }
     }
 
-    fn report(&self, line: usize, loc_str: &str, msg: &str) {
-        println!("[line {}] Error{}: {}", line, loc_str, msg);
+    #[cfg(not(feature = "serde"))]
+    fn emit(&mut self, _source: String, _emit: Emit) {
+        self.errors
+            .line("--emit needs rinlox to be built with the 'serde' feature");
+        let _ = self.errors.flush();
+        std::process::exit(64);
     }
+
+    /// Define the natives of the plugin in the interpreter
+    #[cfg(feature = "plugins")]
+    fn load_plugin(&mut self, path: &str) -> Result<(), LoxError> {
+        Ok(plugin::load(&mut self.interpreter, path.as_ref())?)
+    }
+
+    #[cfg(not(feature = "plugins"))]
+    fn load_plugin(&mut self, _path: &str) -> Result<(), LoxError> {
+        self.errors
+            .line("--plugin needs rinlox to be built with the 'plugins' feature");
+        let _ = self.errors.flush();
+        std::process::exit(64);
+    }
+
+    /// Translate the script into a Rust program, written to `output` (or
+    /// printed if not given)
+    fn transpile(&mut self, script_name: String, output: Option<String>) -> Result<(), LoxError> {
+        let contents = read_source(&script_name)?;
+        self.diagnostics.set_source(&contents);
+        let statements = match Parser::new(self.scanner(contents)).parse() {
+            Ok(statements) => statements,
+            Err(errors) => {
+                self.parse_errors(&errors);
+                self.exit_on_error();
+                return Ok(());
+            }
+        };
+        if let Err(errors) = Resolver::default().resolve(&statements) {
+            self.resolve_errors(&errors);
+            self.exit_on_error();
+        }
+
+        match Transpiler::default().transpile(&statements) {
+            Ok(program) => match output {
+                Some(output) => std::fs::write(output, program)?,
+                None => self.output.text(program),
+            },
+            Err(err) => {
+                self.token_error(&err.token, &err.message);
+                self.had_error = true;
+            }
+        }
+        self.exit_on_error();
+        Ok(())
+    }
+
+    /// Run the script in the debugger, which reads its commands from the
+    /// standard input
+    fn debug(&mut self, script: String) -> Result<(), LoxError> {
+        let source = read_source(&script)?;
+        self.set_path(&script);
+        self.source_name = script;
+        let input = std::io::BufReader::new(std::io::stdin());
+        let debugger = Debugger::new(&source, input, self.output.clone());
+        self.interpreter.set_observer(debugger);
+        self.run(source);
+        self.exit_on_error();
+        Ok(())
+    }
+
+    /// Run the `*_test.lox` files in `path` (a directory, or a single test
+    /// file), each on a fresh interpreter, and exit with an error if any of
+    /// them fails
+    fn test(&mut self, path: String) -> Result<(), LoxError> {
+        let tests = list_files(path, "_test.lox")?;
+
+        let mut failed = 0;
+        for test in &tests {
+            // NOTE(alvaro): The module a test file is for runs first in the
+            // same interpreter, so its declarations are there for the tests
+            // without importing it
+            let name = test.to_string_lossy();
+            let module = PathBuf::from(name.replace("_test.lox", ".lox"));
+            let files = [module, test.clone()];
+
+            self.interpreter.reset();
+            self.interpreter.enable_debug();
+            let mut errors = 0;
+            for file in files.iter().filter(|file| file.is_file()) {
+                self.source_name = file.display().to_string();
+                self.interpreter.set_path(file);
+                errors = self.run(std::fs::read_to_string(file)?).diagnostics.errors;
+                if errors > 0 {
+                    break;
+                }
+            }
+            if errors > 0 {
+                failed += 1;
+                self.output.line(format!("FAIL {}", test.display()));
+            } else {
+                self.output.line(format!("ok   {}", test.display()));
+            }
+            self.reset_errors();
+        }
+
+        self.output.line(format!(
+            "\n{} passed, {} failed",
+            tests.len() - failed,
+            failed
+        ));
+        if failed > 0 {
+            std::process::exit(1);
+        }
+        Ok(())
+    }
+
+    /// Time scanning, parsing and running the `.lox` files in `path` (a
+    /// directory, or a single file), printing the best of a few rounds of
+    /// each stage
+    fn bench(&mut self, path: String) -> Result<(), LoxError> {
+        const ROUNDS: usize = 5;
+
+        self.output.line(format!(
+            "{:<24} {:>8} {:>12} {:>14} {:>12} {:>12}",
+            "program", "tokens", "scan", "tokens/s", "parse", "run"
+        ));
+        for program in list_files(path, ".lox")? {
+            let source = std::fs::read_to_string(&program)?;
+            self.diagnostics.set_source(&source);
+            let tokens = match self.scanner(source.clone()).collect::<Result<Vec<_>, _>>() {
+                Ok(tokens) => tokens,
+                Err(error) => {
+                    self.scan_error(&error);
+                    continue;
+                }
+            };
+            let statements = match Parser::new(tokens.iter().cloned().map(Ok)).parse() {
+                Ok(statements) => statements,
+                Err(errors) => {
+                    self.parse_errors(&errors);
+                    continue;
+                }
+            };
+            if let Err(errors) = Resolver::default().resolve(&statements) {
+                self.resolve_errors(&errors);
+                continue;
+            }
+
+            let (scan, _) = best_of(ROUNDS, || self.scanner(source.clone()).count());
+            // Scanned up front, so only the parser is measured
+            let (parse, _) = best_of(ROUNDS, || {
+                Parser::new(tokens.iter().cloned().map(Ok)).parse()
+            });
+            let (run, result) = best_of(ROUNDS, || {
+                let mut interpreter = Interpreter::default();
+                interpreter.set_output(std::io::sink());
+                interpreter.interpret(&statements)
+            });
+            if let Err(err) = result {
+                self.runtime_error(&err);
+                continue;
+            }
+
+            self.output.line(format!(
+                "{:<24} {:>8} {:>12.2?} {:>14.0} {:>12.2?} {:>12.2?}",
+                program.file_name().unwrap_or_default().to_string_lossy(),
+                tokens.len(),
+                scan,
+                tokens.len() as f64 / scan.as_secs_f64(),
+                parse,
+                run
+            ));
+        }
+        self.exit_on_error();
+        Ok(())
+    }
+
+    fn scanner(&self, source: String) -> Scanner {
+        Scanner::new(source).with_features(self.features)
+    }
+
+    /// Scanner reading the script (or the standard input if it is `-`) as
+    /// it scans it, so big files don't have to fit in memory
+    fn stream(&mut self, script: &str) -> std::io::Result<Scanner> {
+        let reader: Box<dyn BufRead> = if script == "-" {
+            Box::new(std::io::stdin().lock())
+        } else {
+            Box::new(BufReader::new(std::fs::File::open(script)?))
+        };
+        self.diagnostics.set_source("");
+        // The standard input can't be read again to quote it
+        self.streamed = (script != "-").then(|| script.to_string());
+        Ok(Scanner::from_reader(reader).with_features(self.features))
+    }
+
+    /// Read the file being streamed whole after all, to quote it in the
+    /// errors about to be reported
+    fn quote_streamed(&mut self) {
+        let Some(script) = self.streamed.take() else {
+            return;
+        };
+        if let Ok(source) = std::fs::read_to_string(script) {
+            self.diagnostics.set_source(&source);
+        }
+    }
+
+    /// Make the imports of the script relative to its file (unless it is
+    /// the standard input)
+    fn set_path(&mut self, script: &str) {
+        if script != "-" {
+            self.interpreter.set_path(Path::new(script));
+        }
+    }
+
+    /// Run the source, reporting any errors and recording them in the
+    /// `had_error` and `had_runtime_error` flags
+    fn run(&mut self, source: String) -> Outcome {
+        self.diagnostics.set_source(&source);
+        self.reported = Summary::default();
+        let (strict, features) = (self.strict_internal, self.features);
+        let result = match &mut self.vm {
+            Some(vm) => guard(strict, || rinlox::run_vm(vm, &source, features)).map(|()| None),
+            None if self.auto_semicolons || self.optimize => {
+                let mut parser = Parser::new(self.scanner(source));
+                if self.auto_semicolons {
+                    parser = parser.with_auto_semicolons();
+                }
+                let parsed = guard(strict, || parser.parse().map_err(Error::Parse));
+                self.hints(parser.hints());
+                let optimize = self.optimize;
+                parsed.and_then(|statements| {
+                    guard(strict, || {
+                        if optimize {
+                            rinlox::run_optimized(&mut self.interpreter, statements)
+                        } else {
+                            rinlox::run_statements(&mut self.interpreter, &statements)
+                        }
+                    })
+                })
+            }
+            None => guard(strict, || {
+                rinlox::run(&mut self.interpreter, &source, features)
+            }),
+        };
+        let value = result.unwrap_or_else(|err| {
+            self.report(err);
+            None
+        });
+        Outcome {
+            value,
+            diagnostics: std::mem::take(&mut self.reported),
+        }
+    }
+
+    fn report(&mut self, err: Error) {
+        match err {
+            Error::Parse(errors) => self.parse_errors(&errors),
+            Error::Resolve(errors) => self.resolve_errors(&errors),
+            Error::Runtime(err) => {
+                self.runtime_error(&err);
+                // The bytecode VM doesn't keep track of the calls
+                if self.vm.is_none() {
+                    self.stack_trace(&err);
+                }
+            }
+            Error::Internal(err) => self.internal_error(&err),
+        }
+    }
+
+    fn internal_error(&mut self, err: &InternalError) {
+        // Exits with 70 (`EX_SOFTWARE`) too, as the fault is in rinlox
+        self.had_runtime_error = true;
+        self.reported.add(Severity::Error, 1);
+        self.errors.line(err);
+        self.interpreter.recover();
+    }
+
+    /// Exit the way `jlox` does if there were errors: with 65 (`EX_DATAERR`)
+    /// for errors in the code, and 70 (`EX_SOFTWARE`) for errors while
+    /// running it
+    fn exit_on_error(&mut self) {
+        self.report_gc();
+        // Exiting skips the destructors, so nothing buffered would be
+        // written otherwise
+        let _ = self.output.flush();
+        let _ = self.errors.flush();
+        if self.had_error {
+            std::process::exit(65);
+        }
+        if self.had_runtime_error {
+            std::process::exit(70);
+        }
+    }
+
+    /// Write what the garbage collector did to the errors, if asked to
+    fn report_gc(&self) {
+        if self.gc_stats {
+            self.errors
+                .line(format!("gc: {}", self.interpreter.gc_stats()));
+        }
+    }
+
+    fn scan_error(&mut self, error: &ScanError) {
+        self.quote_streamed();
+        let header = error.to_string();
+        self.errors
+            .line(self.diagnostics.render(&header, error.span, &error.notes));
+        self.had_error = true;
+        self.reported.add(Severity::Error, 1);
+    }
+
+    fn parse_errors(&mut self, errors: &[ParseError]) {
+        self.quote_streamed();
+        for error in errors {
+            let header = error.to_string();
+            self.errors.line(
+                self.diagnostics
+                    .render(&header, error.span(), error.notes()),
+            );
+        }
+        self.had_error = true;
+        self.reported.add(Severity::Error, errors.len());
+    }
+
+    fn resolve_errors(&mut self, errors: &[ResolveError]) {
+        for error in errors {
+            let header = error.to_string();
+            self.errors.line(
+                self.diagnostics
+                    .render(&header, error.token.span, &error.notes),
+            );
+        }
+        self.had_error = true;
+        self.reported.add(Severity::Error, errors.len());
+    }
+
+    fn hints(&mut self, hints: &[Hint]) {
+        self.reported.add(Severity::Hint, hints.len());
+        for hint in hints {
+            let header = hint.to_string();
+            self.errors.line(self.diagnostics.render_as(
+                Severity::Hint,
+                &header,
+                hint.token.span,
+                &[],
+            ));
+        }
+    }
+
+    fn token_error(&self, token: &Token, msg: &str) {
+        let header = error_header(token, msg);
+        self.errors
+            .line(self.diagnostics.render(&header, token.span, &[]));
+    }
+
+    fn runtime_error(&mut self, err: &RuntimeError) {
+        if let RuntimeErrorKind::Output(_) = err.kind {
+            stop_writing();
+        }
+        self.had_runtime_error = true;
+        self.reported.add(Severity::Error, 1);
+        let header = err.to_string();
+        self.errors
+            .line(self.diagnostics.render(&header, err.token.span, &[]));
+    }
+
+    /// Print where each call the error unwound through was, like `at fib
+    /// (script.lox:12)`, if it happened in one. The same call repeated
+    /// (e.g. by a recursion) is only printed once, with how many times it
+    /// repeats
+    fn stack_trace(&self, err: &RuntimeError) {
+        let trace = self.interpreter.stack_trace(err);
+        if trace.len() == 1 {
+            return;
+        }
+        let mut i = 0;
+        while i < trace.len() {
+            let repeated = trace[i..]
+                .iter()
+                .take_while(|&&call| call == trace[i])
+                .count();
+            let (name, span) = trace[i];
+            // Code in modules (and typed in the prompt) has its own file
+            let file = match span.file {
+                Some(file) => file.to_string(),
+                None => self.source_name.clone(),
+            };
+            self.errors
+                .line(format!("  at {} ({}:{})", name, file, span.line));
+            if repeated > 1 {
+                self.errors
+                    .line(format!("  ... repeated {} more times", repeated - 1));
+            }
+            i += repeated;
+        }
+    }
+}
+
+/// Run a stage of the pipeline, reporting a panic in it as an internal
+/// error if `strict_internal` is set (`--strict-internal`)
+fn guard<T>(strict_internal: bool, stage: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+    if strict_internal {
+        rinlox::internal::catch(stage)
+    } else {
+        stage()
+    }
+}
+
+/// Read the source code in the file, or in the standard input if the name
+/// is `-`
+fn read_source(name: &str) -> std::io::Result<String> {
+    if name == "-" {
+        std::io::read_to_string(std::io::stdin())
+    } else {
+        std::fs::read_to_string(name)
+    }
+}
+
+/// The files in `path` whose names end with `suffix`, in order, or `path`
+/// itself if it is not a directory
+fn list_files(path: String, suffix: &str) -> std::io::Result<Vec<PathBuf>> {
+    let path = PathBuf::from(path);
+    let mut files = if path.is_dir() {
+        std::fs::read_dir(&path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .filter(|path| match path {
+                Ok(path) => path.to_string_lossy().ends_with(suffix),
+                Err(_) => true,
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        vec![path]
+    };
+    files.sort();
+    Ok(files)
+}
+
+/// Shortest time `stage` took out of a number of rounds, with what it
+/// returned the last time
+fn best_of<T>(rounds: usize, mut stage: impl FnMut() -> T) -> (Duration, T) {
+    let mut best = Duration::MAX;
+    let mut last = None;
+    for _ in 0..rounds {
+        let start = Instant::now();
+        last = Some(stage());
+        best = best.min(start.elapsed());
+    }
+    (best, last.expect("there should be at least one round"))
+}
+
+/// File keeping the lines typed in the prompt between sessions
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".rinlox_history"))
 }
 
+const REPL_HELP: &str = "\
+:tokens [code]  show the tokens of the code, or of the last input
+:ast [code]     show the syntax tree of the code, or of the last input
+:env            show the global variables
+:history values show the values bound to _1, _2... (the last is also _)
+:load file      run a script in this session
+:reset          forget every variable defined in this session
+:timings        show (or stop showing) how long parsing, resolving and
+                running each input takes
+:help           show this message";
+
 fn main() -> Result<(), LoxError> {
-    let args = std::env::args();
+    let cli = match Cli::parse(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(message) => {
+            // Exit with 64 (`EX_USAGE`), like `jlox`
+            eprintln!("{}", message);
+            std::process::exit(64);
+        }
+    };
 
-    match args.len() {
-        1 => {
-            let lox = Lox::new();
-            lox.run_prompt()?
+    let mut lox = Lox::new(Sink::new(std::io::stdout()), Sink::new(std::io::stderr()));
+    lox.interpreter.set_precision(cli.precision);
+    if let Some(depth) = cli.max_call_depth {
+        lox.interpreter.set_max_call_depth(depth);
+    }
+    if cli.debug {
+        lox.interpreter.enable_debug();
+    }
+    if cli.trace {
+        lox.interpreter
+            .set_observer(Tracer::new(lox.errors.clone()));
+    }
+    for plugin in &cli.plugins {
+        lox.load_plugin(plugin)?;
+    }
+    if cli.backend == Backend::Vm {
+        let mut vm = Vm::default();
+        vm.set_precision(cli.precision);
+        vm.set_output(lox.output.clone());
+        lox.vm = Some(vm);
+    }
+    lox.features = cli.features;
+    lox.strict_internal = cli.strict_internal;
+    lox.auto_semicolons = cli.auto_semicolons;
+    lox.optimize = cli.optimize;
+    lox.gc_stats = cli.gc_stats;
+    if !cli.color {
+        lox.diagnostics.set_color(false);
+    }
+    match cli.command {
+        Command::Run(Some(script)) => lox.run_file(script)?,
+        Command::Run(None) | Command::Repl => lox.run_prompt()?,
+        Command::Eval(code) => {
+            lox.eval(code, None);
+            lox.exit_on_error();
+        }
+        Command::Tokenize(script) => {
+            let scanner = lox.stream(&script)?;
+            lox.print_tokens(scanner);
+            lox.exit_on_error();
+        }
+        Command::TokenDiff { script, baseline } => lox.token_diff(script, baseline)?,
+        Command::Parse(script) => {
+            let scanner = lox.stream(&script)?;
+            lox.print_ast(scanner);
+            lox.exit_on_error();
         }
-        2 => {
-            let lox = Lox::new();
-            lox.run_file(args.into_iter().nth(1).unwrap())?
+        Command::Emit { script, emit } => {
+            lox.emit(read_source(&script)?, emit);
+            lox.exit_on_error();
         }
-        _ => return Err("usage: rinlox [script]".to_string().into()),
+        Command::Transpile { script, output } => lox.transpile(script, output)?,
+        Command::Debug(script) => lox.debug(script)?,
+        Command::Test(path) => lox.test(path)?,
+        Command::Bench(path) => lox.bench(path)?,
+        Command::Fmt { script, check } => lox.format(script, check)?,
+        Command::Lint(script) => lox.lint(script)?,
+        Command::Grammar(format) => lox.output.text(grammar::render(format, cli.features)),
+        Command::Help => lox.output.line(USAGE),
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writer keeping what is written to it, for the tests to look at
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Buffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).expect("output should be UTF-8")
+        }
+    }
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn lox() -> (Lox, Buffer, Buffer) {
+        let (output, errors) = (Buffer::default(), Buffer::default());
+        let mut lox = Lox::new(Sink::new(output.clone()), Sink::new(errors.clone()));
+        lox.diagnostics.set_color(false);
+        (lox, output, errors)
+    }
+
+    #[test]
+    fn output_and_errors_go_to_their_sinks() {
+        let (mut lox, output, errors) = lox();
+        lox.run("print 1; var a = 2;".to_string());
+        lox.eval("a + 1".to_string(), None);
+        lox.meta_command(":load", "");
+        let expected = "1\n3\nMissing the file to load: :load file\n";
+        assert_eq!(output.contents(), expected);
+        assert_eq!(errors.contents(), "");
+
+        lox.eval("a +".to_string(), None);
+        lox.run("nil();".to_string());
+        assert_eq!(output.contents(), expected);
+        assert!(errors
+            .contents()
+            .starts_with("[line 1, col 4] Error at end: Expect expression."));
+        assert!(errors
+            .contents()
+            .contains("Can only call functions and classes."));
+    }
+
+    #[test]
+    fn runs_give_back_the_last_value_and_the_diagnostics() {
+        let (mut lox, _, _) = lox();
+        let outcome = lox.run("var a = 2; a * 3;".to_string());
+        assert_eq!(outcome.value, Some(Value::Number(6.0)));
+        assert_eq!(outcome.diagnostics, Summary::default());
+
+        let outcome = lox.run("print a; nil();".to_string());
+        assert_eq!(outcome.value, None);
+        assert_eq!(outcome.diagnostics.to_string(), "1 error");
+
+        lox.auto_semicolons = true;
+        let outcome = lox.run("var b = a\nb +".to_string());
+        assert_eq!(outcome.diagnostics.to_string(), "1 error, 1 hint");
+
+        let outcome = lox.eval("a = a + 1\na".to_string(), None);
+        assert_eq!(outcome.value, Some(Value::Number(3.0)));
+        assert_eq!(outcome.diagnostics.to_string(), "2 hints");
+    }
+
+    #[test]
+    fn timings_are_shown_once_turned_on() {
+        let (mut lox, output, errors) = lox();
+        lox.eval("1".to_string(), None);
+        assert_eq!(errors.contents(), "");
+
+        lox.meta_command(":timings", "");
+        lox.eval("2".to_string(), None);
+        assert_eq!(output.contents(), "1\nTimings on\n2\n");
+        assert!(errors.contents().starts_with("timings: parse "));
+
+        lox.meta_command(":timings", "");
+        lox.eval("3".to_string(), None);
+        assert_eq!(errors.contents().lines().count(), 1);
+    }
+
+    #[test]
+    fn streamed_files_are_quoted_once_there_are_errors() {
+        let script = std::env::temp_dir().join(format!("rinlox-stream-{}.lox", std::process::id()));
+        std::fs::write(&script, "print 1;\nprint @;\n").unwrap();
+        let (mut lox, output, errors) = lox();
+        let scanner = lox.stream(script.to_str().unwrap()).unwrap();
+        lox.print_tokens(scanner);
+        std::fs::remove_file(&script).unwrap();
+
+        assert!(output
+            .contents()
+            .starts_with("[line 1, col 1] Print print\n"));
+        assert_eq!(
+            errors.contents(),
+            "[line 2, col 7] Error: Unexpected character '@'\n  |\n2 | print @;\n  |       ^\n"
+        );
+    }
+
+    #[test]
+    fn sinks_can_be_shared_to_keep_the_order() {
+        let output = Buffer::default();
+        let sink = Sink::new(output.clone());
+        let mut lox = Lox::new(sink.clone(), sink);
+        lox.diagnostics.set_color(false);
+        lox.run("print \"before\"; nil();".to_string());
+        assert!(output
+            .contents()
+            .starts_with("before\nCan only call functions and classes.\n"));
+    }
+}
@@ -0,0 +1,214 @@
+/// Maps for the `Lox` interpreter, written `{"a": 1, "b": 2}`
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::rc::Rc;
+
+use crate::gc::{self, Trace};
+use crate::interpreter::{RuntimeError, Value};
+use crate::lexer::Token;
+use crate::list::LoxList;
+use crate::native::NativeFunction;
+
+/// What a key is looked up by: two values are the same key exactly when
+/// they are equal in Lox
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Nil,
+    Boolean(bool),
+    /// The bits of the number, with `-0` turned into `0`
+    Number(u64),
+    String(Rc<str>),
+    /// The address of a value only equal to itself
+    Object(usize),
+    Variant(usize, usize),
+}
+
+impl Key {
+    /// The key of the value, or `None` for NaN, which is equal to nothing
+    fn new(value: &Value) -> Option<Self> {
+        let key = match value {
+            Value::Nil => Key::Nil,
+            Value::Boolean(b) => Key::Boolean(*b),
+            Value::Number(n) if n.is_nan() => return None,
+            Value::Number(n) => Key::Number(if *n == 0.0 { 0.0f64 } else { *n }.to_bits()),
+            Value::String(s) => Key::String(s.clone()),
+            Value::Callable(c) => Key::Object(Rc::as_ptr(c).cast::<()>() as usize),
+            Value::Class(c) => Key::Object(Rc::as_ptr(c) as usize),
+            Value::Instance(i) => Key::Object(Rc::as_ptr(i) as usize),
+            Value::List(l) => Key::Object(Rc::as_ptr(l) as usize),
+            Value::Map(m) => Key::Object(Rc::as_ptr(m) as usize),
+            Value::Enum(e) => Key::Object(Rc::as_ptr(e) as usize),
+            Value::Variant(e, index) => Key::Variant(Rc::as_ptr(e) as usize, *index),
+            Value::Module(m) => Key::Object(Rc::as_ptr(m) as usize),
+            Value::Foreign(o) => Key::Object(Rc::as_ptr(o).cast::<()>() as usize),
+        };
+        Some(key)
+    }
+}
+
+/// A table from keys to values, read and written with `map[key]`. The
+/// entries are kept in the order they were added. Like lists, a map is
+/// shared by everything holding it, and only equal to itself
+#[derive(Debug, Default)]
+pub struct LoxMap {
+    entries: RefCell<Vec<(Value, Value)>>,
+    /// Position of each key in `entries`. The entries hold the values
+    /// their keys are the addresses of, so those stay unique
+    positions: RefCell<HashMap<Key, usize>>,
+    /// Whether the map is being shown, so a map inside itself is not shown
+    /// forever
+    showing: Cell<bool>,
+}
+
+impl LoxMap {
+    /// Map with the entries, where later ones replace earlier ones with the
+    /// same key. The `brace` of the literal points errors at
+    pub fn new(brace: &Token, entries: Vec<(Value, Value)>) -> Result<Self, RuntimeError> {
+        let map = Self::default();
+        for (key, value) in entries {
+            map.insert(brace, key, value)?;
+        }
+        Ok(map)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    /// The value for `key`, which the `bracket` of the expression reading
+    /// it points errors at
+    pub fn get(&self, bracket: &Token, key: &Value) -> Result<Value, RuntimeError> {
+        let position = Key::new(key).and_then(|key| self.positions.borrow().get(&key).copied());
+        match position {
+            Some(position) => Ok(self.entries.borrow()[position].1.clone()),
+            None => Err(RuntimeError::new(
+                bracket,
+                &format!("Undefined key '{}'.", key),
+            )),
+        }
+    }
+
+    /// Set the value for `key`, adding it if it is not in the map yet
+    pub fn insert(&self, bracket: &Token, key: Value, value: Value) -> Result<(), RuntimeError> {
+        let Some(hashed) = Key::new(&key) else {
+            return Err(RuntimeError::new(bracket, "Map keys can't be NaN."));
+        };
+        let mut entries = self.entries.borrow_mut();
+        let mut positions = self.positions.borrow_mut();
+        match positions.get(&hashed) {
+            Some(&position) => entries[position].1 = value,
+            None => {
+                positions.insert(hashed, entries.len());
+                entries.push((key, value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the entry for `key`, returning its value if there was one
+    fn remove(&self, key: &Value) -> Option<Value> {
+        let position = self.positions.borrow_mut().remove(&Key::new(key)?)?;
+        let (_, value) = self.entries.borrow_mut().remove(position);
+        // The entries after it moved one position back
+        for later in self.positions.borrow_mut().values_mut() {
+            if *later > position {
+                *later -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    /// Look up one of the methods by name, bound to the map:
+    ///
+    /// - `keys()` returns a list of the keys.
+    /// - `has(key)` tells if there is an entry for the key.
+    /// - `remove(key)` removes the entry for the key, returning its value
+    ///   (or `nil` if there was none).
+    pub fn method(self: &Rc<Self>, name: &Token) -> Result<Value, RuntimeError> {
+        let map = Rc::clone(self);
+        let method = match name.lexeme.as_str() {
+            "keys" => NativeFunction::new(
+                "keys",
+                0,
+                Box::new(move |interpreter, _, _| {
+                    let keys = map
+                        .entries
+                        .borrow()
+                        .iter()
+                        .map(|(key, _)| key.clone())
+                        .collect();
+                    Ok(Value::List(interpreter.heap().alloc(LoxList::new(keys))))
+                }),
+            ),
+            "has" => NativeFunction::new(
+                "has",
+                1,
+                Box::new(move |_, _, arguments| {
+                    let has = Key::new(&arguments[0])
+                        .is_some_and(|key| map.positions.borrow().contains_key(&key));
+                    Ok(Value::Boolean(has))
+                }),
+            ),
+            "remove" => NativeFunction::new(
+                "remove",
+                1,
+                Box::new(move |_, _, arguments| {
+                    Ok(map.remove(&arguments[0]).unwrap_or(Value::Nil))
+                }),
+            ),
+            _ => {
+                return Err(RuntimeError::new(
+                    name,
+                    &format!("Undefined property '{}'.", name.lexeme),
+                ))
+            }
+        };
+        Ok(Value::Callable(Rc::new(method)))
+    }
+
+    /// Show the map with each key and value shown by `show`, like
+    /// `{a: 1, b: 2}`. A map inside itself is shown as `{...}`
+    pub fn show(&self, show: impl Fn(&Value) -> String) -> String {
+        if self.showing.replace(true) {
+            return "{...}".to_string();
+        }
+        let entries: Vec<String> = self
+            .entries
+            .borrow()
+            .iter()
+            .map(|(key, value)| format!("{}: {}", show(key), show(value)))
+            .collect();
+        self.showing.set(false);
+        format!("{{{}}}", entries.join(", "))
+    }
+}
+
+impl Trace for LoxMap {
+    fn trace(&self, references: &mut Vec<usize>) -> bool {
+        let Ok(entries) = self.entries.try_borrow() else {
+            return false;
+        };
+        for (key, value) in entries.iter() {
+            gc::trace_value(key, references);
+            gc::trace_value(value, references);
+        }
+        true
+    }
+
+    fn clear(&self) {
+        if let (Ok(mut entries), Ok(mut positions)) = (
+            self.entries.try_borrow_mut(),
+            self.positions.try_borrow_mut(),
+        ) {
+            entries.clear();
+            positions.clear();
+        }
+    }
+}
+
+impl Display for LoxMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.show(Value::to_string))
+    }
+}
@@ -0,0 +1,54 @@
+/// Modules for the `Lox` interpreter, the files loaded with `import`
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::rc::Rc;
+
+use crate::environment::Environment;
+use crate::gc::{self, Trace};
+use crate::interpreter::{RuntimeError, Value};
+use crate::lexer::Token;
+
+/// A file run by an `import`, whose globals are read as `module.name`
+///
+/// Each module has globals of its own, so it only sees the natives and
+/// what it declares itself (or imports), whoever imports it
+#[derive(Debug)]
+pub struct LoxModule {
+    pub name: String,
+    globals: Rc<RefCell<Environment>>,
+}
+
+impl LoxModule {
+    pub fn new(name: &str, globals: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            name: name.to_string(),
+            globals,
+        }
+    }
+
+    /// Look up one of the globals the module declared
+    pub fn get(&self, name: &Token) -> Result<Value, RuntimeError> {
+        self.globals
+            .borrow()
+            .lookup_at(0, name.lexeme.symbol())
+            .ok_or_else(|| {
+                RuntimeError::new(name, &format!("Undefined property '{}'.", name.lexeme))
+            })
+    }
+}
+
+impl Trace for LoxModule {
+    fn trace(&self, references: &mut Vec<usize>) -> bool {
+        references.push(gc::address(&self.globals));
+        true
+    }
+
+    // The globals are cleared instead, if they are garbage too
+    fn clear(&self) {}
+}
+
+impl Display for LoxModule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<module {}>", self.name)
+    }
+}
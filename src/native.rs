@@ -0,0 +1,247 @@
+/// Functions provided by the interpreter itself, implemented in Rust
+use std::fmt::{Debug, Display};
+
+use crate::function::LoxCallable;
+use crate::interpreter::{format_number, Interpreter, RuntimeError, Value};
+use crate::lexer::Token;
+
+/// Implementation of a native function. It receives the closing parenthesis
+/// of the call to point errors at, and as many arguments as its arity
+pub type NativeFn = dyn Fn(&mut Interpreter, &Token, Vec<Value>) -> Result<Value, RuntimeError>;
+
+pub struct NativeFunction {
+    name: String,
+    arity: usize,
+    function: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new(name: &str, arity: usize, function: Box<NativeFn>) -> Self {
+        Self {
+            name: name.to_string(),
+            arity,
+            function,
+        }
+    }
+}
+
+impl Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LoxCallable for NativeFunction {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        (self.function)(interpreter, paren, arguments)
+    }
+}
+
+impl Display for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+/// `numberToString(n, digits)`: the text of `n` rounded to `digits`
+/// significant digits, regardless of the precision `print` is using
+pub fn number_to_string(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, RuntimeError> {
+    let (Value::Number(n), Value::Number(digits)) = (&arguments[0], &arguments[1]) else {
+        return Err(RuntimeError::new(paren, "Arguments must be numbers."));
+    };
+    if digits.fract() != 0.0 || *digits < 1.0 {
+        return Err(RuntimeError::new(
+            paren,
+            "Digits must be a positive integer.",
+        ));
+    }
+    Ok(Value::String(
+        format_number(*n, Some(*digits as usize)).into(),
+    ))
+}
+
+/// `assert(condition, message)`: stop with a runtime error showing the
+/// message unless the condition is truthy
+pub fn assert(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, RuntimeError> {
+    if arguments[0].is_truthy() {
+        return Ok(Value::Nil);
+    }
+    let message = interpreter.stringify(&arguments[1]);
+    // Name the function the assertion is in, unless it is at the top level
+    let frames = interpreter.frames();
+    let message = match frames.len().checked_sub(2) {
+        Some(caller) => format!("Assertion failed in {}: {}", frames[caller].name, message),
+        None => format!("Assertion failed: {}", message),
+    };
+    Err(RuntimeError::new(paren, &message))
+}
+
+/// `stackDepth()`: how many calls are being executed (0 at the top level)
+pub fn stack_depth(
+    interpreter: &mut Interpreter,
+    _paren: &Token,
+    _arguments: Vec<Value>,
+) -> Result<Value, RuntimeError> {
+    // Not counting the call to `stackDepth` itself
+    let depth = interpreter.frames().len() - 1;
+    Ok(Value::Number(depth as f64))
+}
+
+/// `callerName(n)`: the name of the function `n` calls up the stack from
+/// the one calling `callerName` (so 0 is that function itself), or `nil`
+/// past the top level
+pub fn caller_name(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, RuntimeError> {
+    let Value::Number(n) = arguments[0] else {
+        return Err(RuntimeError::new(paren, "Argument must be a number."));
+    };
+    if n.fract() != 0.0 || n < 0.0 {
+        return Err(RuntimeError::new(
+            paren,
+            "Argument must be a non-negative integer.",
+        ));
+    }
+    // Skipping the call to `callerName` itself
+    let frames = interpreter.frames();
+    let name = (frames.len() - 1)
+        .checked_sub((n as usize).saturating_add(1))
+        .map(|index| Value::String(frames[index].name.as_str().into()));
+    Ok(name.unwrap_or(Value::Nil))
+}
+
+/// `clock()`: seconds since the Unix epoch, to time things
+pub fn clock(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    _arguments: Vec<Value>,
+) -> Result<Value, RuntimeError> {
+    match interpreter.host().clock() {
+        Some(time) => Ok(Value::Number(time)),
+        None => Err(RuntimeError::new(paren, "There is no clock to read.")),
+    }
+}
+
+/// `str(value)`: the text `print` would show for the value
+pub fn str(
+    interpreter: &mut Interpreter,
+    _paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, RuntimeError> {
+    Ok(Value::String(interpreter.stringify(&arguments[0]).into()))
+}
+
+/// `num(value)`: the number written in a string (numbers are returned as
+/// they are)
+pub fn num(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, RuntimeError> {
+    match &arguments[0] {
+        Value::Number(n) => Ok(Value::Number(*n)),
+        Value::String(s) => s
+            .trim()
+            .parse()
+            .ok()
+            .filter(|n: &f64| n.is_finite())
+            .map(Value::Number)
+            .ok_or_else(|| {
+                RuntimeError::new(paren, &format!("Can't convert '{}' to a number.", s))
+            }),
+        _ => Err(RuntimeError::new(
+            paren,
+            "Argument must be a string or a number.",
+        )),
+    }
+}
+
+/// `len(value)`: the number of characters in a string, of elements in a
+/// list, or of entries in a map
+pub fn len(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, RuntimeError> {
+    match &arguments[0] {
+        Value::String(s) => Ok(Value::Number(s.chars().count() as f64)),
+        Value::List(list) => Ok(Value::Number(list.len() as f64)),
+        Value::Map(map) => Ok(Value::Number(map.len() as f64)),
+        _ => Err(RuntimeError::new(
+            paren,
+            "Argument must be a string, a list or a map.",
+        )),
+    }
+}
+
+/// `readLine()`: the next line from the standard input (or wherever the
+/// host reads them from) without the line break, or `nil` once there are
+/// no more
+pub fn read_line(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    _arguments: Vec<Value>,
+) -> Result<Value, RuntimeError> {
+    match interpreter.host().read_line() {
+        Ok(None) => Ok(Value::Nil),
+        Ok(Some(line)) => {
+            let line = line.strip_suffix('\n').unwrap_or(&line);
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            Ok(Value::String(line.into()))
+        }
+        Err(err) => Err(RuntimeError::new(
+            paren,
+            &format!("Could not read from the standard input: {}.", err),
+        )),
+    }
+}
+
+/// `type(value)`: the name of the type of the value
+pub fn type_of(
+    _interpreter: &mut Interpreter,
+    _paren: &Token,
+    arguments: Vec<Value>,
+) -> Result<Value, RuntimeError> {
+    let name = match &arguments[0] {
+        Value::Nil => "nil",
+        Value::Boolean(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Callable(_) => "function",
+        Value::Class(_) => "class",
+        Value::Instance(_) => "instance",
+        Value::List(_) => "list",
+        Value::Map(_) => "map",
+        Value::Enum(_) => "enum",
+        Value::Variant(..) => "variant",
+        Value::Module(_) => "module",
+        Value::Foreign(object) => object.type_name(),
+    };
+    Ok(Value::String(name.into()))
+}
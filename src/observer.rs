@@ -0,0 +1,177 @@
+/// Hooks into the execution of a program, for tools watching it run
+///
+/// An `Observer` set on the `Interpreter` (with `set_observer`) is told
+/// about every statement executed, every variable defined or assigned, and
+/// every call entered and left. It gets the interpreter too, to look at
+/// the call stack (`Interpreter::frames`) or format values.
+///
+/// `Tracer` is the observer behind `rinlox --trace`, logging all of it.
+use std::fmt::Debug;
+use std::io::Write;
+
+use crate::ast::to_source;
+use crate::interpreter::{Frame, Interpreter, RuntimeError, Value};
+use crate::lexer::Token;
+use crate::stmt::Stmt;
+
+/// What happens while a program runs. Every method does nothing by default,
+/// so observers only implement the ones they care about
+pub trait Observer {
+    /// A statement is about to be executed
+    fn statement(&mut self, _interpreter: &Interpreter, _stmt: &Stmt) {}
+
+    /// A variable was declared or assigned the `value`
+    fn assignment(&mut self, _interpreter: &Interpreter, _name: &Token, _value: &Value) {}
+
+    /// A call was entered, with its frame already on the call stack
+    fn call(&mut self, _interpreter: &Interpreter, _frame: &Frame, _arguments: &[Value]) {}
+
+    /// A call was left, with its frame still on the call stack
+    fn returned(
+        &mut self,
+        _interpreter: &Interpreter,
+        _frame: &Frame,
+        _result: &Result<Value, RuntimeError>,
+    ) {
+    }
+}
+
+impl Debug for dyn Observer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Observer")
+    }
+}
+
+/// Observer logging what a program does, indented by the depth of the
+/// call stack:
+///
+/// ```text
+/// var a = f(1);
+/// call f(1)
+///   return n + 1;
+/// f returned 2
+///   a = 2
+/// ```
+///
+/// If writing the log fails, the tracer stops logging and leaves the
+/// program running.
+pub struct Tracer {
+    /// Where the log goes, until writing it fails
+    out: Option<Box<dyn Write>>,
+}
+
+impl Tracer {
+    pub fn new(out: impl Write + 'static) -> Self {
+        Self {
+            out: Some(Box::new(out)),
+        }
+    }
+
+    fn log(&mut self, depth: usize, line: &str) {
+        if let Some(out) = &mut self.out {
+            if writeln!(out, "{}{}", "  ".repeat(depth), line).is_err() {
+                self.out = None;
+            }
+        }
+    }
+}
+
+impl Observer for Tracer {
+    fn statement(&mut self, interpreter: &Interpreter, stmt: &Stmt) {
+        // The statements in a block are logged on their own
+        if matches!(stmt, Stmt::Block { .. }) {
+            return;
+        }
+        let source = to_source(stmt);
+        let first_line = source.lines().next().unwrap_or_default();
+        self.log(interpreter.frames().len(), first_line);
+    }
+
+    fn assignment(&mut self, interpreter: &Interpreter, name: &Token, value: &Value) {
+        let line = format!("  {} = {}", name.lexeme, interpreter.stringify(value));
+        self.log(interpreter.frames().len(), &line);
+    }
+
+    fn call(&mut self, interpreter: &Interpreter, frame: &Frame, arguments: &[Value]) {
+        let arguments: Vec<_> = arguments
+            .iter()
+            .map(|argument| interpreter.stringify(argument))
+            .collect();
+        let line = format!("call {}({})", frame.name, arguments.join(", "));
+        self.log(interpreter.frames().len() - 1, &line);
+    }
+
+    fn returned(
+        &mut self,
+        interpreter: &Interpreter,
+        frame: &Frame,
+        result: &Result<Value, RuntimeError>,
+    ) {
+        let line = match result {
+            Ok(value) => format!("{} returned {}", frame.name, interpreter.stringify(value)),
+            Err(error) => format!("{} failed: {}", frame.name, error.message),
+        };
+        self.log(interpreter.frames().len() - 1, &line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::features::Features;
+
+    /// Writer keeping what is written to it
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn traces_statements_assignments_and_calls() {
+        let trace = Buffer::default();
+        let mut interpreter = Interpreter::default();
+        interpreter.set_output(std::io::sink());
+        interpreter.set_observer(Tracer::new(trace.clone()));
+        let source = "fun f(n) {\n  return n + 1;\n}\nvar a = f(1);\na = a * 2;\nprint a;";
+        crate::run(&mut interpreter, source, Features::default()).unwrap();
+
+        let trace = String::from_utf8(trace.0.take()).unwrap();
+        assert_eq!(
+            trace,
+            "\
+fun f(n) {
+var a = f(1);
+call f(1)
+  return n + 1;
+f returned 2
+  a = 2
+a = a * 2;
+  a = 4
+print a;
+"
+        );
+    }
+
+    #[test]
+    fn traces_failed_calls() {
+        let trace = Buffer::default();
+        let mut interpreter = Interpreter::default();
+        interpreter.set_observer(Tracer::new(trace.clone()));
+        let source = "fun f() { return nil + 1; } f();";
+        assert!(crate::run(&mut interpreter, source, Features::default()).is_err());
+
+        let trace = String::from_utf8(trace.0.take()).unwrap();
+        assert!(trace.ends_with("f failed: Operands must be two numbers or two strings.\n"));
+    }
+}
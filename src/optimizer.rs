@@ -0,0 +1,434 @@
+/// Optimizations of the syntax tree, run between the resolver and the
+/// interpreter (`--opt`)
+///
+/// Each optimization is a `Pass`, turning the statements of a program into
+/// others that do the same with less work. The `Optimizer` runs its passes
+/// one after the other: by default `ConstantFolding`, and then
+/// `DeadBranches` (which removes more code once the conditions are folded).
+///
+/// The passes run on programs the resolver accepted, so the code they
+/// remove still had its errors reported, and the variables they keep are
+/// still resolved.
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use crate::expr::{Expr, Object};
+use crate::internal;
+use crate::lexer::{Token, TokenType};
+use crate::stmt::{ClassBody, Stmt};
+
+/// A transformation of the syntax tree that keeps what the program does
+pub trait Pass: Debug {
+    fn run(&mut self, statements: Vec<Stmt>) -> Vec<Stmt>;
+}
+
+#[derive(Debug)]
+pub struct Optimizer {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Self::new()
+            .with_pass(ConstantFolding)
+            .with_pass(DeadBranches)
+    }
+}
+
+impl Optimizer {
+    /// Optimizer with no passes (see `with_pass`)
+    pub fn new() -> Self {
+        Self { passes: Vec::new() }
+    }
+
+    /// Run `pass` after the ones added before
+    pub fn with_pass(mut self, pass: impl Pass + 'static) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    pub fn optimize(&mut self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        self.passes
+            .iter_mut()
+            .fold(statements, |statements, pass| pass.run(statements))
+    }
+}
+
+/// Computes the operators whose operands are literals, like `2 * 3` or
+/// `!nil`, and the `and`, `or`, `?:` and `,` whose left operand is. The
+/// operations that would fail at runtime are left for it to report
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ConstantFolding;
+
+impl Pass for ConstantFolding {
+    fn run(&mut self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        Rewrite {
+            expr: fold,
+            stmt: |stmt| stmt,
+        }
+        .statements(statements)
+    }
+}
+
+fn fold(expr: Expr) -> Expr {
+    match expr {
+        Expr::Grouping { expression } if is_literal(&expression) => *expression,
+        Expr::Unary { operator, right } => match (&operator.typ, *right) {
+            (
+                TokenType::Minus,
+                Expr::Literal {
+                    value: Object::Number(n),
+                },
+            ) => Expr::literal(Object::Number(-n)),
+            (TokenType::Bang, Expr::Literal { value }) => {
+                Expr::literal(Object::Boolean(!is_truthy(&value)))
+            }
+            (_, right) => Expr::unary(operator, right),
+        },
+        // The left operand is only there for its effects, which a literal
+        // has none of
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        } if operator.typ == TokenType::Comma && is_literal(&left) => *right,
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        } => match (*left, *right) {
+            (Expr::Literal { value: l }, Expr::Literal { value: r }) => {
+                match binary(&operator, &l, &r) {
+                    Some(value) => Expr::literal(value),
+                    None => Expr::binary(Expr::literal(l), operator, Expr::literal(r)),
+                }
+            }
+            (left, right) => Expr::binary(left, operator, right),
+        },
+        Expr::Logical {
+            left,
+            operator,
+            right,
+        } => match *left {
+            Expr::Literal { value } => {
+                let short_circuits = match operator.typ {
+                    TokenType::Or => is_truthy(&value),
+                    _ => !is_truthy(&value),
+                };
+                if short_circuits {
+                    Expr::literal(value)
+                } else {
+                    *right
+                }
+            }
+            left => Expr::logical(left, operator, right),
+        },
+        Expr::Conditional {
+            condition,
+            then_branch,
+            else_branch,
+        } => match *condition {
+            Expr::Literal { value } if is_truthy(&value) => *then_branch,
+            Expr::Literal { .. } => *else_branch,
+            condition => Expr::conditional(condition, then_branch, else_branch),
+        },
+        expr => expr,
+    }
+}
+
+/// The value of the operation on two literals, if it doesn't fail
+fn binary(operator: &Token, left: &Object, right: &Object) -> Option<Object> {
+    let value = match (&operator.typ, left, right) {
+        (TokenType::Plus, Object::Number(l), Object::Number(r)) => Object::Number(l + r),
+        (TokenType::Plus, Object::String(l), Object::String(r)) => {
+            Object::String(format!("{}{}", l, r))
+        }
+        (TokenType::Minus, Object::Number(l), Object::Number(r)) => Object::Number(l - r),
+        (TokenType::Star, Object::Number(l), Object::Number(r)) => Object::Number(l * r),
+        (TokenType::Slash, Object::Number(l), Object::Number(r)) => Object::Number(l / r),
+        (TokenType::Percent, Object::Number(l), Object::Number(r)) => Object::Number(l % r),
+        (TokenType::Greater, Object::Number(l), Object::Number(r)) => Object::Boolean(l > r),
+        (TokenType::GreaterEqual, Object::Number(l), Object::Number(r)) => Object::Boolean(l >= r),
+        (TokenType::Less, Object::Number(l), Object::Number(r)) => Object::Boolean(l < r),
+        (TokenType::LessEqual, Object::Number(l), Object::Number(r)) => Object::Boolean(l <= r),
+        (TokenType::EqualEqual, l, r) => Object::Boolean(l == r),
+        (TokenType::BangEqual, l, r) => Object::Boolean(l != r),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Removes the branches of the `if` statements that can't run, and the
+/// loops whose condition is always false
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DeadBranches;
+
+impl Pass for DeadBranches {
+    fn run(&mut self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        Rewrite {
+            expr: |expr| expr,
+            stmt: prune,
+        }
+        .statements(statements)
+    }
+}
+
+fn prune(stmt: Stmt) -> Stmt {
+    match stmt {
+        Stmt::If {
+            condition: Expr::Literal { value },
+            then_branch,
+            else_branch,
+        } => {
+            if is_truthy(&value) {
+                *then_branch
+            } else {
+                else_branch.map_or_else(|| Stmt::block(Vec::new()), |branch| *branch)
+            }
+        }
+        Stmt::While {
+            condition: Expr::Literal { value },
+            ..
+        } if !is_truthy(&value) => Stmt::block(Vec::new()),
+        stmt => stmt,
+    }
+}
+
+fn is_literal(expr: &Expr) -> bool {
+    matches!(expr, Expr::Literal { .. })
+}
+
+/// `false` and `nil` are falsey, like the values they stand for
+fn is_truthy(value: &Object) -> bool {
+    !matches!(value, Object::Nil | Object::Boolean(false))
+}
+
+/// Rebuilds the tree from the bottom up, handing each expression and
+/// statement to `expr` and `stmt` once its children are rebuilt. Empty
+/// blocks (like the ones left where a statement was removed) are dropped
+/// from the lists of statements, as they do nothing
+struct Rewrite<E, S> {
+    expr: E,
+    stmt: S,
+}
+
+impl<E: FnMut(Expr) -> Expr, S: FnMut(Stmt) -> Stmt> Rewrite<E, S> {
+    fn statements(&mut self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        statements
+            .into_iter()
+            .map(|stmt| self.statement(stmt))
+            .filter(|stmt| !matches!(stmt, Stmt::Block { statements } if statements.is_empty()))
+            .collect()
+    }
+
+    fn body(&mut self, body: Rc<Vec<Stmt>>) -> Rc<Vec<Stmt>> {
+        let body = Rc::try_unwrap(body).unwrap_or_else(|body| (*body).clone());
+        Rc::new(self.statements(body))
+    }
+
+    fn class_body(&mut self, body: ClassBody) -> ClassBody {
+        ClassBody {
+            methods: self.statements(body.methods),
+            getters: self.statements(body.getters),
+            class_methods: self.statements(body.class_methods),
+        }
+    }
+
+    fn statement(&mut self, stmt: Stmt) -> Stmt {
+        let stmt = match stmt {
+            Stmt::Block { statements } => Stmt::block(self.statements(statements)),
+            Stmt::Class {
+                name,
+                superclass,
+                body,
+            } => Stmt::class(
+                name,
+                superclass.map(|superclass| self.expression(superclass)),
+                self.class_body(body),
+            ),
+            Stmt::Defer { keyword, body } => Stmt::defer(keyword, self.body(body)),
+            Stmt::Extend { class, body } => {
+                Stmt::extend(self.expression(class), self.class_body(body))
+            }
+            Stmt::Expression { expression } => Stmt::expression(self.expression(expression)),
+            Stmt::Function { name, params, body } => Stmt::function(name, params, self.body(body)),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => Stmt::if_else(
+                self.expression(condition),
+                self.statement(*then_branch),
+                else_branch.map(|branch| Box::new(self.statement(*branch))),
+            ),
+            Stmt::Print {
+                keyword,
+                expression,
+            } => Stmt::print(keyword, self.expression(expression)),
+            Stmt::Return { keyword, value } => {
+                Stmt::return_value(keyword, value.map(|value| self.expression(value)))
+            }
+            Stmt::Var { name, initializer } => {
+                Stmt::var(name, initializer.map(|value| self.expression(value)))
+            }
+            Stmt::While {
+                keyword,
+                condition,
+                body,
+                increment,
+            } => Stmt::while_loop(
+                keyword,
+                self.expression(condition),
+                self.statement(*body),
+                increment.map(|increment| self.expression(increment)),
+            ),
+            stmt @ (Stmt::Break { .. }
+            | Stmt::Continue { .. }
+            | Stmt::Enum { .. }
+            | Stmt::Import { .. }) => stmt,
+        };
+        (self.stmt)(stmt)
+    }
+
+    fn expression(&mut self, expr: Expr) -> Expr {
+        // Nested code can take more stack than the thread has
+        internal::grow_stack(|| {
+            let expr = match expr {
+                Expr::Assign {
+                    name,
+                    value,
+                    depth,
+                    slot,
+                } => Expr::Assign {
+                    name,
+                    value: Box::new(self.expression(*value)),
+                    depth,
+                    slot,
+                },
+                Expr::Binary {
+                    left,
+                    operator,
+                    right,
+                } => Expr::binary(self.expression(*left), operator, self.expression(*right)),
+                Expr::Call {
+                    callee,
+                    paren,
+                    arguments,
+                } => Expr::call(self.expression(*callee), paren, self.expressions(arguments)),
+                Expr::Conditional {
+                    condition,
+                    then_branch,
+                    else_branch,
+                } => Expr::conditional(
+                    self.expression(*condition),
+                    self.expression(*then_branch),
+                    self.expression(*else_branch),
+                ),
+                Expr::Get { object, name } => Expr::get(self.expression(*object), name),
+                Expr::Grouping { expression } => Expr::grouping(self.expression(*expression)),
+                Expr::Index {
+                    object,
+                    bracket,
+                    index,
+                } => Expr::index(self.expression(*object), bracket, self.expression(*index)),
+                Expr::Lambda {
+                    keyword,
+                    params,
+                    body,
+                } => Expr::lambda(keyword, params, self.body(body)),
+                Expr::List { bracket, elements } => Expr::list(bracket, self.expressions(elements)),
+                Expr::Logical {
+                    left,
+                    operator,
+                    right,
+                } => Expr::logical(self.expression(*left), operator, self.expression(*right)),
+                Expr::Map { brace, entries } => {
+                    let entries = entries
+                        .into_iter()
+                        .map(|(key, value)| (self.expression(key), self.expression(value)))
+                        .collect::<Vec<_>>();
+                    Expr::map(brace, entries)
+                }
+                Expr::Set {
+                    object,
+                    name,
+                    operator,
+                    value,
+                } => Expr::set(
+                    self.expression(*object),
+                    name,
+                    operator,
+                    self.expression(*value),
+                ),
+                Expr::SetIndex {
+                    object,
+                    bracket,
+                    index,
+                    operator,
+                    value,
+                } => Expr::set_index(
+                    self.expression(*object),
+                    bracket,
+                    self.expression(*index),
+                    operator,
+                    self.expression(*value),
+                ),
+                Expr::Unary { operator, right } => Expr::unary(operator, self.expression(*right)),
+                expr @ (Expr::Literal { .. }
+                | Expr::Super { .. }
+                | Expr::This { .. }
+                | Expr::Variable { .. }) => expr,
+            };
+            (self.expr)(expr)
+        })
+    }
+
+    fn expressions(&mut self, exprs: Vec<Expr>) -> Vec<Expr> {
+        exprs
+            .into_iter()
+            .map(|expr| self.expression(expr))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::program_to_source;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+
+    fn optimize(source: &str) -> String {
+        let statements = Parser::new(Scanner::new(source.to_string()))
+            .parse()
+            .expect("the source should parse");
+        program_to_source(&Optimizer::default().optimize(statements))
+    }
+
+    #[test]
+    fn folds_constant_expressions() {
+        assert_eq!(optimize("print 1 + 2 * 3;"), "print 7;\n");
+        assert_eq!(optimize("print -(2 - 4) >= 2;"), "print true;\n");
+        assert_eq!(optimize("print \"a\" + \"b\" == \"ab\";"), "print true;\n");
+        assert_eq!(optimize("print !nil and x;"), "print x;\n");
+        assert_eq!(optimize("print nil or 1 ? a : b;"), "print a;\n");
+        assert_eq!(optimize("print (1, f());"), "print (f());\n");
+        // Operations that fail are left for the interpreter to report
+        assert_eq!(optimize("print 1 + \"a\";"), "print 1 + \"a\";\n");
+        assert_eq!(optimize("print -x * 2;"), "print -x * 2;\n");
+    }
+
+    #[test]
+    fn removes_code_that_never_runs() {
+        assert_eq!(optimize("if (1 > 2) print 1; else print 2;"), "print 2;\n");
+        assert_eq!(optimize("if (nil) { print 1; }\nprint 2;"), "print 2;\n");
+        assert_eq!(
+            optimize("fun f() { while (!true) f(); return 1; }"),
+            "fun f() {\n    return 1;\n}\n"
+        );
+        assert_eq!(
+            optimize("for (var i = 0; false; i = i + 1) print i;"),
+            "{\n    var i = 0;\n}\n"
+        );
+    }
+}
@@ -0,0 +1,1478 @@
+/// Recursive descent parser for the `Lox` programming language
+///
+/// Statements are parsed by recursive descent, and expressions by
+/// precedence climbing (a Pratt parser, like `clox`) driven by the
+/// `PARSE_RULES` table, so adding an operator is adding an entry to it.
+///
+/// The grammar it accepts is kept as data in `GRAMMAR`, which `rinlox
+/// grammar` prints along with the operators in the table.
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::mem::discriminant;
+use std::path::Path;
+
+use crate::diagnostics::{error_header, header, Note, Severity};
+use crate::expr::{Expr, Object};
+use crate::features::Feature;
+use crate::internal;
+use crate::lexer::{ScanError, Scanner, Span, Token, TokenType};
+use crate::stmt::{ClassBody, Stmt};
+
+/// Rule of the grammar, written in the notation from the book
+#[derive(Debug)]
+pub struct GrammarRule {
+    pub name: &'static str,
+    pub body: &'static str,
+    /// Experimental feature the rule is part of, if any
+    pub feature: Option<Feature>,
+}
+
+const fn rule(name: &'static str, body: &'static str) -> GrammarRule {
+    GrammarRule {
+        name,
+        body,
+        feature: None,
+    }
+}
+
+const fn gated(feature: Feature, name: &'static str, body: &'static str) -> GrammarRule {
+    GrammarRule {
+        name,
+        body,
+        feature: Some(feature),
+    }
+}
+
+/// The grammar of Lox as accepted by this parser (keep it in sync!)
+pub static GRAMMAR: &[GrammarRule] = &[
+    rule("program", r#"declaration* EOF"#),
+    rule(
+        "declaration",
+        r#"classDecl | enumDecl | extendDecl | funDecl | importDecl | varDecl | statement"#,
+    ),
+    rule(
+        "classDecl",
+        r#""class" IDENTIFIER ( "<" IDENTIFIER )? "{" member* "}""#,
+    ),
+    // A getter has no parameter list, and a `class` method is called on the
+    // class itself
+    rule(
+        "member",
+        r#""class" function | function | IDENTIFIER block"#,
+    ),
+    gated(
+        Feature::Enum,
+        "enumDecl",
+        r#""enum" IDENTIFIER "{" ( IDENTIFIER ( "," IDENTIFIER )* ","? )? "}""#,
+    ),
+    gated(
+        Feature::Extend,
+        "extendDecl",
+        r#""extend" IDENTIFIER "{" member* "}""#,
+    ),
+    // A `fun` followed by `(` starts a lambda in an expression statement
+    rule("funDecl", r#""fun" function"#),
+    rule("function", r#"IDENTIFIER "(" parameters? ")" block"#),
+    rule("parameters", r#"IDENTIFIER ( "," IDENTIFIER )*"#),
+    rule("importDecl", r#""import" ( STRING | IDENTIFIER ) ";""#),
+    rule("varDecl", r#""var" IDENTIFIER ( "=" expression )? ";""#),
+    rule(
+        "statement",
+        r#"breakStmt | continueStmt | deferStmt | exprStmt | forStmt | ifStmt | printStmt | returnStmt | whileStmt | block"#,
+    ),
+    rule("breakStmt", r#""break" ";""#),
+    rule("continueStmt", r#""continue" ";""#),
+    gated(Feature::Defer, "deferStmt", r#""defer" block"#),
+    rule("exprStmt", r#"expression ";""#),
+    rule(
+        "forStmt",
+        r#""for" "(" ( varDecl | exprStmt | ";" ) expression? ";" expression? ")" statement"#,
+    ),
+    rule(
+        "ifStmt",
+        r#""if" "(" expression ")" statement ( "else" statement )?"#,
+    ),
+    rule("printStmt", r#""print" expression ";""#),
+    rule("returnStmt", r#""return" expression? ";""#),
+    rule("whileStmt", r#""while" "(" expression ")" statement"#),
+    rule("block", r#""{" declaration* "}""#),
+    rule("expression", r#"comma"#),
+    rule("comma", r#"assignment ( "," assignment )*"#),
+    rule(
+        "assignment",
+        r#"( call "." IDENTIFIER | call "[" expression "]" | IDENTIFIER ) ( "=" | "+=" | "-=" | "*=" | "/=" ) assignment | conditional"#,
+    ),
+    rule(
+        "conditional",
+        r#"logic_or ( "?" expression ":" conditional )?"#,
+    ),
+    rule("logic_or", r#"logic_and ( "or" logic_and )*"#),
+    rule("logic_and", r#"equality ( "and" equality )*"#),
+    rule("equality", r#"comparison ( ( "!=" | "==" ) comparison )*"#),
+    rule(
+        "comparison",
+        r#"term pipe* ( ( ">" | ">=" | "<" | "<=" ) term pipe* )*"#,
+    ),
+    gated(Feature::Pipe, "pipe", r#""|>" call"#),
+    rule("term", r#"factor ( ( "-" | "+" ) factor )*"#),
+    rule("factor", r#"unary ( ( "/" | "*" | "%" ) unary )*"#),
+    rule("unary", r#"( "!" | "-" ) unary | call"#),
+    rule(
+        "call",
+        r#"primary ( "(" arguments? ")" | "[" expression "]" | "." IDENTIFIER )*"#,
+    ),
+    rule("arguments", r#"assignment ( "," assignment )*"#),
+    rule(
+        "primary",
+        r#"NUMBER | STRING | "true" | "false" | "nil" | "this" | "(" expression ")" | IDENTIFIER | "super" "." IDENTIFIER | list | map | lambda"#,
+    ),
+    rule("list", r#""[" arguments? "]""#),
+    // A `{` starting a statement is always a block
+    rule("map", r#""{" ( entry ( "," entry )* )? "}""#),
+    rule("entry", r#"assignment ":" assignment"#),
+    rule("lambda", r#""fun" "(" parameters? ")" block"#),
+];
+
+/// Binding power of each kind of expression, from loosest to tightest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Precedence {
+    Comma,
+    Assignment,
+    Conditional,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Pipe,
+    Term,
+    Factor,
+    Unary,
+    Call,
+    Primary,
+}
+
+impl Precedence {
+    /// The next tighter level, used for the right operand of left
+    /// associative operators
+    pub fn next(self) -> Self {
+        match self {
+            Precedence::Comma => Precedence::Assignment,
+            Precedence::Assignment => Precedence::Conditional,
+            Precedence::Conditional => Precedence::Or,
+            Precedence::Or => Precedence::And,
+            Precedence::And => Precedence::Equality,
+            Precedence::Equality => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Pipe,
+            Precedence::Pipe => Precedence::Term,
+            Precedence::Term => Precedence::Factor,
+            Precedence::Factor => Precedence::Unary,
+            Precedence::Unary => Precedence::Call,
+            Precedence::Call | Precedence::Primary => Precedence::Primary,
+        }
+    }
+}
+
+/// How a token starts an expression
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Prefix {
+    Grouping,
+    /// `fun (params) { body }`
+    Lambda,
+    /// `[a, b, c]`
+    List,
+    Literal,
+    /// `{key: value, ...}`
+    Map,
+    Super,
+    This,
+    Unary,
+    Variable,
+}
+
+/// How a token continues the expression before it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Infix {
+    /// `=` and the compound assignments like `+=`
+    Assign,
+    Binary,
+    Call,
+    /// `,`, which evaluates both sides and produces the right one
+    Comma,
+    /// `condition ? then : else`
+    Conditional,
+    /// `.` followed by a property name
+    Get,
+    /// `[index]`, getting an element of a list or a map
+    Index,
+    /// `and` and `or`, which short-circuit
+    Logical,
+    /// `value |> f(...)`, calling `f` with `value` as its first argument
+    Pipe,
+}
+
+impl Infix {
+    /// Whether `a op b op c` is `a op (b op c)`
+    pub fn is_right_associative(self) -> bool {
+        matches!(self, Infix::Assign | Infix::Conditional)
+    }
+}
+
+/// Entry of the parse table: what a token does at the start of an
+/// expression and after one
+#[derive(Debug)]
+pub struct ParseRule {
+    /// Only its variant matters (not the value of literals)
+    pub token: TokenType,
+    /// How the token is written, to show the table
+    pub text: &'static str,
+    pub prefix: Option<Prefix>,
+    pub infix: Option<(Infix, Precedence)>,
+}
+
+const fn prefix(token: TokenType, text: &'static str, prefix: Prefix) -> ParseRule {
+    ParseRule {
+        token,
+        text,
+        prefix: Some(prefix),
+        infix: None,
+    }
+}
+
+const fn infix(
+    token: TokenType,
+    text: &'static str,
+    infix: Infix,
+    precedence: Precedence,
+) -> ParseRule {
+    ParseRule {
+        token,
+        text,
+        prefix: None,
+        infix: Some((infix, precedence)),
+    }
+}
+
+/// Tokens that can start or continue an expression (keep `GRAMMAR` in
+/// sync!)
+pub static PARSE_RULES: &[ParseRule] = &[
+    ParseRule {
+        token: TokenType::LeftParen,
+        text: "(",
+        prefix: Some(Prefix::Grouping),
+        infix: Some((Infix::Call, Precedence::Call)),
+    },
+    ParseRule {
+        token: TokenType::LeftBracket,
+        text: "[",
+        prefix: Some(Prefix::List),
+        infix: Some((Infix::Index, Precedence::Call)),
+    },
+    prefix(TokenType::LeftBrace, "{", Prefix::Map),
+    infix(TokenType::Dot, ".", Infix::Get, Precedence::Call),
+    ParseRule {
+        token: TokenType::Minus,
+        text: "-",
+        prefix: Some(Prefix::Unary),
+        infix: Some((Infix::Binary, Precedence::Term)),
+    },
+    infix(TokenType::Plus, "+", Infix::Binary, Precedence::Term),
+    infix(TokenType::Slash, "/", Infix::Binary, Precedence::Factor),
+    infix(TokenType::Star, "*", Infix::Binary, Precedence::Factor),
+    infix(TokenType::Percent, "%", Infix::Binary, Precedence::Factor),
+    prefix(TokenType::Bang, "!", Prefix::Unary),
+    infix(
+        TokenType::BangEqual,
+        "!=",
+        Infix::Binary,
+        Precedence::Equality,
+    ),
+    infix(
+        TokenType::EqualEqual,
+        "==",
+        Infix::Binary,
+        Precedence::Equality,
+    ),
+    infix(
+        TokenType::Greater,
+        ">",
+        Infix::Binary,
+        Precedence::Comparison,
+    ),
+    infix(
+        TokenType::GreaterEqual,
+        ">=",
+        Infix::Binary,
+        Precedence::Comparison,
+    ),
+    infix(TokenType::Less, "<", Infix::Binary, Precedence::Comparison),
+    infix(
+        TokenType::LessEqual,
+        "<=",
+        Infix::Binary,
+        Precedence::Comparison,
+    ),
+    infix(TokenType::Pipe, "|>", Infix::Pipe, Precedence::Pipe),
+    infix(TokenType::And, "and", Infix::Logical, Precedence::And),
+    infix(TokenType::Or, "or", Infix::Logical, Precedence::Or),
+    infix(
+        TokenType::Question,
+        "?",
+        Infix::Conditional,
+        Precedence::Conditional,
+    ),
+    infix(TokenType::Equal, "=", Infix::Assign, Precedence::Assignment),
+    infix(
+        TokenType::PlusEqual,
+        "+=",
+        Infix::Assign,
+        Precedence::Assignment,
+    ),
+    infix(
+        TokenType::MinusEqual,
+        "-=",
+        Infix::Assign,
+        Precedence::Assignment,
+    ),
+    infix(
+        TokenType::StarEqual,
+        "*=",
+        Infix::Assign,
+        Precedence::Assignment,
+    ),
+    infix(
+        TokenType::SlashEqual,
+        "/=",
+        Infix::Assign,
+        Precedence::Assignment,
+    ),
+    infix(TokenType::Comma, ",", Infix::Comma, Precedence::Comma),
+    prefix(TokenType::Identifier, "IDENTIFIER", Prefix::Variable),
+    prefix(TokenType::Number(0.0), "NUMBER", Prefix::Literal),
+    prefix(TokenType::String(String::new()), "STRING", Prefix::Literal),
+    prefix(TokenType::False, "false", Prefix::Literal),
+    prefix(TokenType::True, "true", Prefix::Literal),
+    prefix(TokenType::Nil, "nil", Prefix::Literal),
+    prefix(TokenType::This, "this", Prefix::This),
+    prefix(TokenType::Super, "super", Prefix::Super),
+    prefix(TokenType::Fun, "fun", Prefix::Lambda),
+];
+
+/// The entry of the parse table for the token type, if it is part of
+/// expressions
+pub fn parse_rule(typ: &TokenType) -> Option<&'static ParseRule> {
+    PARSE_RULES
+        .iter()
+        .find(|rule| discriminant(&rule.token) == discriminant(typ))
+}
+
+/// Precedence of the token as an infix operator, if it is one
+pub fn infix_precedence(typ: &TokenType) -> Option<Precedence> {
+    parse_rule(typ)?.infix.map(|(_, precedence)| precedence)
+}
+
+/// Maximum number of arguments in a call (and parameters in a function)
+const MAX_ARGUMENTS: usize = 255;
+
+/// Maximum depth of the statements and expressions nested in each other,
+/// so the passes walking the tree (and dropping it) don't overflow the
+/// stack
+pub(crate) const MAX_NESTING: usize = 256;
+
+/// Maximum number of operators chained one after the other (like the `+`
+/// in `a + b + c`) on the way to a node of the tree. They aren't nested in
+/// the source, but each one puts the operators before it a level deeper
+/// in the tree, so they still need a limit, if a much larger one (but not
+/// in WebAssembly, where the stack can't grow)
+pub(crate) const MAX_CHAINED: usize = if cfg!(target_arch = "wasm32") {
+    1_000
+} else {
+    10_000
+};
+
+/// Error found while parsing
+#[derive(Debug, Clone)]
+pub enum ParseError {
+    /// The scanner could not turn part of the source into a token
+    Scan(ScanError),
+    /// The tokens don't follow the grammar, found at `token`
+    Syntax { token: Token, message: String },
+    /// The keyword of an experimental feature was used without enabling it
+    /// (so it was scanned as a plain identifier)
+    FeatureGate { token: Token, feature: Feature },
+}
+
+impl ParseError {
+    /// Where the error was found
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::Scan(error) => error.span,
+            ParseError::Syntax { token, .. } | ParseError::FeatureGate { token, .. } => token.span,
+        }
+    }
+
+    /// What is wrong, without where (see `span`)
+    pub fn message(&self) -> String {
+        match self {
+            ParseError::Scan(error) => error.message.clone(),
+            ParseError::Syntax { message, .. } => message.clone(),
+            ParseError::FeatureGate { token, feature } => feature.gate_message(&token.lexeme),
+        }
+    }
+
+    /// Other places in the source the error is about
+    pub fn notes(&self) -> &[Note] {
+        match self {
+            ParseError::Scan(error) => &error.notes,
+            ParseError::Syntax { .. } | ParseError::FeatureGate { .. } => &[],
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Scan(error) => write!(f, "{}", error),
+            ParseError::Syntax { token, message } => write!(f, "{}", error_header(token, message)),
+            ParseError::FeatureGate { token, feature } => write!(
+                f,
+                "{}",
+                error_header(token, &feature.gate_message(&token.lexeme))
+            ),
+        }
+    }
+}
+
+type ParseResult<T> = Result<T, ParseError>;
+
+/// Code the parser accepted although it is not quite right, found at
+/// `token`
+#[derive(Debug, Clone)]
+pub struct Hint {
+    pub token: Token,
+    pub message: String,
+}
+
+impl Display for Hint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", header(Severity::Hint, &self.token, &self.message))
+    }
+}
+
+/// Parser pulling its tokens lazily from a scanner (or any other source of
+/// tokens), so they never need to be all in memory at once
+pub struct Parser<I>
+where
+    I: Iterator<Item = Result<Token, ScanError>>,
+{
+    tokens: I,
+    /// The next token to be consumed
+    current: Token,
+    /// The last token consumed, if any
+    previous: Option<Token>,
+    /// Every error found so far, including the ones that did not need to
+    /// unwind the parser (e.g. invalid assignment targets)
+    errors: RefCell<Vec<ParseError>>,
+    /// Whether a `;` missing at the end of a line is assumed there instead
+    /// of being an error
+    auto_semicolons: bool,
+    hints: Vec<Hint>,
+    /// How deep in the tree the node being parsed is (see `MAX_NESTING`)
+    nesting: usize,
+    /// How many operators chained are above it (see `MAX_CHAINED`)
+    chained: usize,
+}
+
+impl<I> Parser<I>
+where
+    I: Iterator<Item = Result<Token, ScanError>>,
+{
+    pub fn new(mut tokens: I) -> Self {
+        let errors = RefCell::new(Vec::new());
+        let current = next_token(&mut tokens, &errors);
+        Self {
+            tokens,
+            current,
+            previous: None,
+            errors,
+            auto_semicolons: false,
+            hints: Vec::new(),
+            nesting: 0,
+            chained: 0,
+        }
+    }
+
+    /// Assume a `;` at the end of a line where a statement is missing one,
+    /// with a hint instead of an error. The next line starts a new
+    /// statement only if it can't continue the one before: `a` followed by
+    /// a line with `(b)` is still the call `a(b)`
+    pub fn with_auto_semicolons(mut self) -> Self {
+        self.auto_semicolons = true;
+        self
+    }
+
+    /// The semicolons assumed so far
+    pub fn hints(&self) -> &[Hint] {
+        &self.hints
+    }
+
+    /// Parse the tokens into a list of statements, or return all the errors
+    /// found if there was any
+    ///
+    /// After an error the parser synchronizes and keeps going, so all the
+    /// syntax errors in the program are found in a single pass
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<ParseError>> {
+        let mut statements = Vec::new();
+        while !self.is_at_end() {
+            match self.declaration() {
+                Ok(stmt) => statements.push(stmt),
+                // Already recorded in `errors`
+                Err(_) => {
+                    // The errors unwind every level of the statement
+                    self.nesting = 0;
+                    self.chained = 0;
+                    self.synchronize();
+                }
+            }
+        }
+
+        let errors = self.errors.take();
+        if errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn declaration(&mut self) -> ParseResult<Stmt> {
+        if self.next_matches(&[TokenType::Class]) {
+            self.class_declaration()
+        } else if self.next_matches(&[TokenType::Enum]) {
+            self.enum_declaration()
+        } else if self.next_matches(&[TokenType::Extend]) {
+            self.extend_declaration()
+        } else if self.next_matches(&[TokenType::Fun]) {
+            // Without a name it is a lambda, starting an expression statement
+            if self.check(&TokenType::LeftParen) {
+                self.lambda_statement()
+            } else {
+                self.function("function")
+            }
+        } else if self.next_matches(&[TokenType::Import]) {
+            self.import_declaration()
+        } else if self.next_matches(&[TokenType::Var]) {
+            self.var_declaration()
+        } else {
+            self.statement()
+        }
+    }
+
+    fn class_declaration(&mut self) -> ParseResult<Stmt> {
+        let name = self
+            .consume(&TokenType::Identifier, "Expect class name.")?
+            .clone();
+
+        let superclass = if self.next_matches(&[TokenType::Less]) {
+            let name = self
+                .consume(&TokenType::Identifier, "Expect superclass name.")?
+                .clone();
+            Some(Expr::variable(name))
+        } else {
+            None
+        };
+
+        let body = self.class_body("class")?;
+
+        Ok(Stmt::class(name, superclass, body))
+    }
+
+    /// Parse an `enum` declaration (behind the `lox-enum` feature)
+    fn enum_declaration(&mut self) -> ParseResult<Stmt> {
+        let name = self
+            .consume(&TokenType::Identifier, "Expect enum name.")?
+            .clone();
+        self.consume(&TokenType::LeftBrace, "Expect '{' before enum body.")?;
+
+        let mut variants = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            variants.push(
+                self.consume(&TokenType::Identifier, "Expect variant name.")?
+                    .clone(),
+            );
+            if !self.next_matches(&[TokenType::Comma]) {
+                break;
+            }
+        }
+
+        self.consume(&TokenType::RightBrace, "Expect '}' after enum body.")?;
+        Ok(Stmt::enumeration(name, variants))
+    }
+
+    /// Parse an `extend` declaration (behind the `lox-extend` feature),
+    /// which adds methods to a class that already exists
+    fn extend_declaration(&mut self) -> ParseResult<Stmt> {
+        let name = self
+            .consume(&TokenType::Identifier, "Expect class name after 'extend'.")?
+            .clone();
+        let body = self.class_body("extension")?;
+
+        Ok(Stmt::extend(Expr::variable(name), body))
+    }
+
+    /// Parse the methods in the body of a class. `kind` describes what the
+    /// body belongs to, for the error messages
+    fn class_body(&mut self, kind: &str) -> ParseResult<ClassBody> {
+        self.consume(
+            &TokenType::LeftBrace,
+            &format!("Expect '{{' before {} body.", kind),
+        )?;
+
+        let mut body = ClassBody::default();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            let class_method = self.next_matches(&[TokenType::Class]);
+            let name = self
+                .consume(&TokenType::Identifier, "Expect method name.")?
+                .clone();
+            if !class_method && self.next_matches(&[TokenType::LeftBrace]) {
+                let getter = self.block()?;
+                body.getters.push(Stmt::function(name, Vec::new(), getter));
+                continue;
+            }
+
+            self.consume(&TokenType::LeftParen, "Expect '(' after method name.")?;
+            let (params, method) = self.function_rest("method")?;
+            let method = Stmt::function(name, params, method);
+            if class_method {
+                body.class_methods.push(method);
+            } else {
+                body.methods.push(method);
+            }
+        }
+
+        self.consume(
+            &TokenType::RightBrace,
+            &format!("Expect '}}' after {} body.", kind),
+        )?;
+        Ok(body)
+    }
+
+    /// Parse a function declaration, after the `fun` keyword. `kind`
+    /// describes what is being declared, for the error messages
+    fn function(&mut self, kind: &str) -> ParseResult<Stmt> {
+        let name = self
+            .consume(&TokenType::Identifier, &format!("Expect {} name.", kind))?
+            .clone();
+
+        self.consume(
+            &TokenType::LeftParen,
+            &format!("Expect '(' after {} name.", kind),
+        )?;
+        let (params, body) = self.function_rest(kind)?;
+        Ok(Stmt::function(name, params, body))
+    }
+
+    /// Parse the parameters and the body of a function, after the `(`
+    fn function_rest(&mut self, kind: &str) -> ParseResult<(Vec<Token>, Vec<Stmt>)> {
+        let mut params = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    self.error(
+                        self.peek(),
+                        &format!("Can't have more than {} parameters.", MAX_ARGUMENTS),
+                    );
+                }
+                params.push(
+                    self.consume(&TokenType::Identifier, "Expect parameter name.")?
+                        .clone(),
+                );
+                if !self.next_matches(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(&TokenType::RightParen, "Expect ')' after parameters.")?;
+
+        self.consume(
+            &TokenType::LeftBrace,
+            &format!("Expect '{{' before {} body.", kind),
+        )?;
+        let body = self.block()?;
+        Ok((params, body))
+    }
+
+    /// Parse an expression statement starting with a lambda, after its
+    /// `fun`
+    fn lambda_statement(&mut self) -> ParseResult<Stmt> {
+        let lambda = self.prefix(Prefix::Lambda)?;
+        let expression = self.infix_operators(lambda, Precedence::Comma)?;
+        self.end_statement("Expect ';' after expression.")?;
+        Ok(Stmt::expression(expression))
+    }
+
+    /// Parse an `import` of a module by its path (`import "lib/util.lox";`)
+    /// or by name (`import util;`, for `util.lox`). Either way it is bound
+    /// to a variable named after the file
+    fn import_declaration(&mut self) -> ParseResult<Stmt> {
+        let keyword = self.previous().clone();
+        let (path, name) = if self.next_matches(&[TokenType::Identifier]) {
+            let name = self.previous().clone();
+            (name.clone(), name)
+        } else {
+            let path = self
+                .consume(
+                    &TokenType::String(String::new()),
+                    "Expect module name or path after 'import'.",
+                )?
+                .clone();
+            let TokenType::String(literal) = &path.typ else {
+                unreachable!("the token was checked to be a string");
+            };
+            let stem = Path::new(literal)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            if !is_identifier(&stem) {
+                // NOTE(alvaro): Not in a confused state, so no need to
+                // synchronize
+                let message = format!("Can't name a module '{}', from its file name.", stem);
+                self.error(&path, &message);
+            }
+            let name = Token::new(TokenType::Identifier, &stem, path.span);
+            (path, name)
+        };
+
+        self.end_statement("Expect ';' after import.")?;
+        Ok(Stmt::import(keyword, path, name))
+    }
+
+    fn var_declaration(&mut self) -> ParseResult<Stmt> {
+        let name = self
+            .consume(&TokenType::Identifier, "Expect variable name.")?
+            .clone();
+
+        let initializer = if self.next_matches(&[TokenType::Equal]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.end_statement("Expect ';' after variable declaration.")?;
+        Ok(Stmt::var(name, initializer))
+    }
+
+    fn statement(&mut self) -> ParseResult<Stmt> {
+        self.nested(|parser| {
+            if parser.next_matches(&[TokenType::Break]) {
+                let keyword = parser.previous().clone();
+                parser.end_statement("Expect ';' after 'break'.")?;
+                Ok(Stmt::break_loop(keyword))
+            } else if parser.next_matches(&[TokenType::Continue]) {
+                let keyword = parser.previous().clone();
+                parser.end_statement("Expect ';' after 'continue'.")?;
+                Ok(Stmt::continue_loop(keyword))
+            } else if parser.next_matches(&[TokenType::Defer]) {
+                let keyword = parser.previous().clone();
+                parser.consume(&TokenType::LeftBrace, "Expect '{' after 'defer'.")?;
+                Ok(Stmt::defer(keyword, parser.block()?))
+            } else if parser.next_matches(&[TokenType::For]) {
+                parser.for_statement()
+            } else if parser.next_matches(&[TokenType::If]) {
+                parser.if_statement()
+            } else if parser.next_matches(&[TokenType::Print]) {
+                parser.print_statement()
+            } else if parser.next_matches(&[TokenType::Return]) {
+                parser.return_statement()
+            } else if parser.next_matches(&[TokenType::While]) {
+                parser.while_statement()
+            } else if parser.next_matches(&[TokenType::LeftBrace]) {
+                Ok(Stmt::block(parser.block()?))
+            } else {
+                parser.expression_statement()
+            }
+        })
+    }
+
+    /// Parse the declarations in a block, after the opening `{`
+    fn block(&mut self) -> ParseResult<Vec<Stmt>> {
+        self.nested(|parser| {
+            let mut statements = Vec::new();
+
+            while !parser.check(&TokenType::RightBrace) && !parser.is_at_end() {
+                statements.push(parser.declaration()?);
+            }
+
+            parser.consume(&TokenType::RightBrace, "Expect '}' after block.")?;
+            Ok(statements)
+        })
+    }
+
+    /// Parse a `for` loop, desugaring it into the equivalent `while` loop
+    /// (wrapped in a block for the initializer). The increment stays apart
+    /// from the body, so a `continue` in the body still runs it
+    fn for_statement(&mut self) -> ParseResult<Stmt> {
+        // The loop is a `while` in the tree, found where the `for` is
+        let keyword = Token::new(TokenType::While, "while", self.previous().span);
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'for'.")?;
+
+        let initializer = if self.next_matches(&[TokenType::SemiColon]) {
+            None
+        } else if self.next_matches(&[TokenType::Var]) {
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+
+        let condition = if self.check(&TokenType::SemiColon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(&TokenType::SemiColon, "Expect ';' after loop condition.")?;
+
+        let increment = if self.check(&TokenType::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(&TokenType::RightParen, "Expect ')' after for clauses.")?;
+
+        let body = self.statement()?;
+
+        let condition = condition.unwrap_or(Expr::literal(Object::Boolean(true)));
+        let mut body = Stmt::while_loop(keyword, condition, body, increment);
+
+        if let Some(initializer) = initializer {
+            body = Stmt::block(vec![initializer, body]);
+        }
+
+        Ok(body)
+    }
+
+    fn if_statement(&mut self) -> ParseResult<Stmt> {
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(&TokenType::RightParen, "Expect ')' after if condition.")?;
+
+        let then_branch = self.statement()?;
+        let else_branch = if self.next_matches(&[TokenType::Else]) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+
+        Ok(Stmt::if_else(condition, then_branch, else_branch))
+    }
+
+    fn while_statement(&mut self) -> ParseResult<Stmt> {
+        let keyword = self.previous().clone();
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(&TokenType::RightParen, "Expect ')' after condition.")?;
+        let body = self.statement()?;
+
+        Ok(Stmt::while_loop(keyword, condition, body, None))
+    }
+
+    fn print_statement(&mut self) -> ParseResult<Stmt> {
+        let keyword = self.previous().clone();
+        let expression = self.expression()?;
+        self.end_statement("Expect ';' after value.")?;
+        Ok(Stmt::print(keyword, expression))
+    }
+
+    fn return_statement(&mut self) -> ParseResult<Stmt> {
+        let keyword = self.previous().clone();
+        let value = if self.check(&TokenType::SemiColon) || self.assumes_semicolon() {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+
+        self.end_statement("Expect ';' after return value.")?;
+        Ok(Stmt::return_value(keyword, value))
+    }
+
+    fn expression_statement(&mut self) -> ParseResult<Stmt> {
+        let expression = self.expression()?;
+        self.end_statement("Expect ';' after expression.")?;
+        Ok(Stmt::expression(expression))
+    }
+
+    fn expression(&mut self) -> ParseResult<Expr> {
+        self.parse_precedence(Precedence::Comma)
+    }
+
+    /// Parse an expression made of operators that bind at least as tightly
+    /// as `min`: the prefix part first, and then every infix operator
+    /// after it that is tight enough
+    fn parse_precedence(&mut self, min: Precedence) -> ParseResult<Expr> {
+        self.nested(|parser| {
+            let Some(prefix) = parse_rule(&parser.peek().typ).and_then(|rule| rule.prefix) else {
+                return Err(parser.error(parser.peek(), "Expect expression."));
+            };
+            parser.advance();
+            let expr = parser.prefix(prefix)?;
+            parser.infix_operators(expr, min)
+        })
+    }
+
+    /// Parse the infix operators after `expr` that bind at least as tightly
+    /// as `min`
+    fn infix_operators(&mut self, mut expr: Expr, min: Precedence) -> ParseResult<Expr> {
+        // The chain is built in a loop, but each operator still puts the
+        // expression before it a level deeper, like `a + b` in `a + b + c`
+        let chained = self.chained;
+        while let Some((infix, precedence)) =
+            parse_rule(&self.peek().typ).and_then(|rule| rule.infix)
+        {
+            if precedence < min {
+                break;
+            }
+            self.chain()?;
+            self.advance();
+            expr = self.infix(infix, precedence, expr)?;
+        }
+
+        self.chained = chained;
+        Ok(expr)
+    }
+
+    /// Parse an expression starting with the token just consumed
+    fn prefix(&mut self, prefix: Prefix) -> ParseResult<Expr> {
+        let token = self.previous().clone();
+        match prefix {
+            Prefix::Grouping => {
+                let expr = self.expression()?;
+                self.consume(&TokenType::RightParen, "Expect ')' after expression.")?;
+                Ok(Expr::grouping(expr))
+            }
+            Prefix::Lambda => {
+                self.consume(&TokenType::LeftParen, "Expect '(' after 'fun'.")?;
+                let (params, body) = self.function_rest("lambda")?;
+                Ok(Expr::lambda(token, params, body))
+            }
+            Prefix::List => {
+                let mut elements = Vec::new();
+                if !self.check(&TokenType::RightBracket) {
+                    loop {
+                        // Like in the arguments of a call, commas separate
+                        // the elements
+                        elements.push(self.parse_precedence(Precedence::Assignment)?);
+                        if !self.next_matches(&[TokenType::Comma]) {
+                            break;
+                        }
+                    }
+                }
+                self.consume(&TokenType::RightBracket, "Expect ']' after list elements.")?;
+                Ok(Expr::list(token, elements))
+            }
+            Prefix::Literal => {
+                let value = match token.typ {
+                    TokenType::False => Object::Boolean(false),
+                    TokenType::True => Object::Boolean(true),
+                    TokenType::Number(n) => Object::Number(n),
+                    TokenType::String(s) => Object::String(s),
+                    _ => Object::Nil,
+                };
+                Ok(Expr::literal(value))
+            }
+            Prefix::Map => {
+                let mut entries = Vec::new();
+                if !self.check(&TokenType::RightBrace) {
+                    loop {
+                        let key = self.parse_precedence(Precedence::Assignment)?;
+                        self.consume(&TokenType::Colon, "Expect ':' after map key.")?;
+                        let value = self.parse_precedence(Precedence::Assignment)?;
+                        entries.push((key, value));
+                        if !self.next_matches(&[TokenType::Comma]) {
+                            break;
+                        }
+                    }
+                }
+                self.consume(&TokenType::RightBrace, "Expect '}' after map entries.")?;
+                Ok(Expr::map(token, entries))
+            }
+            Prefix::Super => {
+                self.consume(&TokenType::Dot, "Expect '.' after 'super'.")?;
+                let method = self
+                    .consume(&TokenType::Identifier, "Expect superclass method name.")?
+                    .clone();
+                Ok(Expr::super_method(token, method))
+            }
+            Prefix::This => Ok(Expr::this(token)),
+            Prefix::Unary => {
+                let right = self.parse_precedence(Precedence::Unary)?;
+                Ok(Expr::unary(token, right))
+            }
+            Prefix::Variable => Ok(Expr::variable(token)),
+        }
+    }
+
+    /// Parse the rest of an expression starting with `left`, after the
+    /// operator token just consumed
+    fn infix(&mut self, infix: Infix, precedence: Precedence, left: Expr) -> ParseResult<Expr> {
+        let operator = self.previous().clone();
+        // Right associative operators take another one of their own on the
+        // right, left associative ones only tighter ones
+        let right_precedence = if infix.is_right_associative() {
+            precedence
+        } else {
+            precedence.next()
+        };
+        match infix {
+            Infix::Assign => self.assignment(left, operator),
+            Infix::Binary | Infix::Comma => {
+                let right = self.parse_precedence(right_precedence)?;
+                Ok(Expr::binary(left, operator, right))
+            }
+            Infix::Call => self.finish_call(left),
+            Infix::Conditional => {
+                let then_branch = self.expression()?;
+                self.consume(
+                    &TokenType::Colon,
+                    "Expect ':' after then branch of conditional expression.",
+                )?;
+                let else_branch = self.parse_precedence(right_precedence)?;
+                Ok(Expr::conditional(left, then_branch, else_branch))
+            }
+            Infix::Get => {
+                let name = self
+                    .consume(&TokenType::Identifier, "Expect property name after '.'.")?
+                    .clone();
+                Ok(Expr::get(left, name))
+            }
+            Infix::Index => {
+                let index = self.expression()?;
+                let bracket = self
+                    .consume(&TokenType::RightBracket, "Expect ']' after index.")?
+                    .clone();
+                Ok(Expr::index(left, bracket, index))
+            }
+            Infix::Logical => {
+                let right = self.parse_precedence(right_precedence)?;
+                Ok(Expr::logical(left, operator, right))
+            }
+            Infix::Pipe => self.pipe(left, operator),
+        }
+    }
+
+    /// Parse the function `value` is piped into after `|>`, desugaring it
+    /// into a call: `value |> f` is `f(value)`, and `value |> f(a, b)` is
+    /// `f(value, a, b)`
+    fn pipe(&mut self, value: Expr, pipe: Token) -> ParseResult<Expr> {
+        match self.parse_precedence(Precedence::Call)? {
+            // The value goes first in the outermost call, so
+            // `value |> f(a)(b)` is `f(a)(value, b)`
+            Expr::Call {
+                callee,
+                paren,
+                mut arguments,
+            } => {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    self.error(
+                        &pipe,
+                        &format!("Can't have more than {} arguments.", MAX_ARGUMENTS),
+                    );
+                }
+                arguments.insert(0, value);
+                Ok(Expr::Call {
+                    callee,
+                    paren,
+                    arguments,
+                })
+            }
+            callee => Ok(Expr::call(callee, pipe, vec![value])),
+        }
+    }
+
+    /// Parse the value assigned to `target` after the `equals` (or a
+    /// compound assignment operator)
+    fn assignment(&mut self, target: Expr, equals: Token) -> ParseResult<Expr> {
+        let value = self.parse_precedence(Precedence::Assignment)?;
+
+        let operator = compound_operator(&equals);
+        match target {
+            // Compound assignments to variables like `a += b` are sugar for
+            // `a = a + b`, since evaluating `a` twice has no effects
+            Expr::Variable { name, .. } => {
+                let value = match operator {
+                    Some(operator) => Expr::binary(Expr::variable(name.clone()), operator, value),
+                    None => value,
+                };
+                Ok(Expr::assign(name, value))
+            }
+            Expr::Get { object, name } => Ok(Expr::set(object, name, operator, value)),
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => Ok(Expr::set_index(object, bracket, index, operator, value)),
+            target => {
+                // NOTE(alvaro): We report the error but don't need to
+                // synchronize, since the parser is not in a confused state
+                self.error(&equals, "Invalid assignment target.");
+                Ok(target)
+            }
+        }
+    }
+
+    /// Parse the arguments of a call to `callee`, after the opening `(`
+    fn finish_call(&mut self, callee: Expr) -> ParseResult<Expr> {
+        let mut arguments = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    self.error(
+                        self.peek(),
+                        &format!("Can't have more than {} arguments.", MAX_ARGUMENTS),
+                    );
+                }
+                // Commas here separate the arguments, so each one can't
+                // have a comma operator in it
+                arguments.push(self.parse_precedence(Precedence::Assignment)?);
+                if !self.next_matches(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        let paren = self
+            .consume(&TokenType::RightParen, "Expect ')' after arguments.")?
+            .clone();
+
+        Ok(Expr::call(callee, paren, arguments))
+    }
+
+    /// Consume the next token if it is of any of the given types
+    fn next_matches(&mut self, types: &[TokenType]) -> bool {
+        if types.iter().any(|typ| self.check(typ)) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consume the next token, which must be of the given type
+    fn consume(&mut self, typ: &TokenType, msg: &str) -> ParseResult<&Token> {
+        if self.check(typ) {
+            return Ok(self.advance());
+        }
+
+        Err(self.error(self.peek(), msg))
+    }
+
+    /// Consume the `;` ending a statement, or assume it if it is missing at
+    /// the end of a line with automatic semicolons
+    fn end_statement(&mut self, msg: &str) -> ParseResult<()> {
+        if self.next_matches(&[TokenType::SemiColon]) {
+            return Ok(());
+        }
+        if !self.assumes_semicolon() {
+            return Err(self.error(self.peek(), msg));
+        }
+        self.hints.push(Hint {
+            token: self.previous().clone(),
+            message: "Missing ';' at the end of the line.".to_string(),
+        });
+        Ok(())
+    }
+
+    /// Whether a missing `;` goes right before the next token: with
+    /// automatic semicolons, if it starts a new line (or there are no more)
+    fn assumes_semicolon(&self) -> bool {
+        self.auto_semicolons
+            && self.previous.as_ref().is_some_and(|previous| {
+                self.is_at_end() || self.peek().span.line > previous.span.end_line
+            })
+    }
+
+    /// Check if the next token is of the given type. Only the variant is
+    /// compared, so literal payloads are ignored
+    fn check(&self, typ: &TokenType) -> bool {
+        if self.is_at_end() {
+            return false;
+        }
+        discriminant(&self.peek().typ) == discriminant(typ)
+    }
+
+    fn advance(&mut self) -> &Token {
+        if !self.is_at_end() {
+            let next = next_token(&mut self.tokens, &self.errors);
+            self.previous = Some(std::mem::replace(&mut self.current, next));
+        }
+        self.previous()
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.peek().typ == TokenType::Eof
+    }
+
+    fn peek(&self) -> &Token {
+        &self.current
+    }
+
+    fn previous(&self) -> &Token {
+        self.previous
+            .as_ref()
+            .expect("a token should have been consumed")
+    }
+
+    /// Record a syntax error at the token, returning it to unwind the
+    /// parser if needed
+    fn error(&self, token: &Token, msg: &str) -> ParseError {
+        // Without its feature, something like `enum Color { ... }` starts
+        // with an identifier and fails right after it. Blame the gate for
+        // that instead of the code that follows
+        let gated = self
+            .previous
+            .as_ref()
+            .filter(|previous| {
+                previous.typ == TokenType::Identifier && token.span == self.peek().span
+            })
+            .and_then(|previous| Some((previous, Feature::of_lexeme(&previous.lexeme)?)));
+        let error = match gated {
+            Some((previous, feature)) => ParseError::FeatureGate {
+                token: previous.clone(),
+                feature,
+            },
+            None => ParseError::Syntax {
+                token: token.clone(),
+                message: msg.to_string(),
+            },
+        };
+        self.errors.borrow_mut().push(error.clone());
+        error
+    }
+
+    /// Parse a node of the tree a level deeper than the one being parsed
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        self.enter()?;
+        let node = internal::grow_stack(|| parse(self))?;
+        self.nesting -= 1;
+        Ok(node)
+    }
+
+    /// Go a level deeper in the tree, unless it is too deep already. On an
+    /// error the levels entered are left by `parse`
+    fn enter(&mut self) -> ParseResult<()> {
+        if self.nesting >= MAX_NESTING {
+            return Err(self.give_up("Too much nesting."));
+        }
+        self.nesting += 1;
+        Ok(())
+    }
+
+    /// Chain one more operator, unless there are too many already. Like
+    /// `enter`, the count is restored by the caller
+    fn chain(&mut self) -> ParseResult<()> {
+        if self.chained >= MAX_CHAINED {
+            return Err(self.give_up("Too many operators in a row."));
+        }
+        self.chained += 1;
+        Ok(())
+    }
+
+    /// Report an error about the shape of the whole tree at the next token
+    fn give_up(&mut self, message: &str) -> ParseError {
+        let error = self.error(self.peek(), message);
+        // There is no telling where the nesting ends, so the rest of the
+        // source is skipped instead of reporting every token closing it as
+        // an error
+        while !self.is_at_end() {
+            self.advance();
+        }
+        error
+    }
+
+    /// Discard tokens until we are (probably) at the start of the next
+    /// statement, so we can keep parsing after an error
+    fn synchronize(&mut self) {
+        self.advance();
+
+        while !self.is_at_end() {
+            if self.previous().typ == TokenType::SemiColon {
+                return;
+            }
+
+            match self.peek().typ {
+                TokenType::Break
+                | TokenType::Class
+                | TokenType::Continue
+                | TokenType::Defer
+                | TokenType::Enum
+                | TokenType::Extend
+                | TokenType::Fun
+                | TokenType::Import
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return => return,
+                _ => {}
+            }
+
+            self.advance();
+        }
+    }
+}
+
+/// Binary operator a compound assignment like `+=` applies, or `None` for a
+/// plain `=`
+fn compound_operator(equals: &Token) -> Option<Token> {
+    let (typ, lexeme) = match equals.typ {
+        TokenType::PlusEqual => (TokenType::Plus, "+"),
+        TokenType::MinusEqual => (TokenType::Minus, "-"),
+        TokenType::StarEqual => (TokenType::Star, "*"),
+        TokenType::SlashEqual => (TokenType::Slash, "/"),
+        _ => return None,
+    };
+    Some(Token::new(typ, lexeme, equals.span))
+}
+
+/// Whether `name` scans as a single identifier (a keyword doesn't)
+fn is_identifier(name: &str) -> bool {
+    let tokens = Scanner::new(name.to_string()).scan_all();
+    matches!(tokens.as_deref(), Ok([token, _]) if token.typ == TokenType::Identifier)
+}
+
+/// Pull the next token, recording any scanning errors before it. Those
+/// make the parse fail, but don't stop it from going on
+fn next_token<I>(tokens: &mut I, errors: &RefCell<Vec<ParseError>>) -> Token
+where
+    I: Iterator<Item = Result<Token, ScanError>>,
+{
+    loop {
+        match tokens.next().expect("the tokens should end with an `Eof`") {
+            Ok(token) => return token,
+            Err(err) => errors.borrow_mut().push(ParseError::Scan(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::program_to_source;
+    use crate::features::Features;
+
+    /// Parse with every feature enabled and print the tree back as source
+    fn desugared(source: &str) -> String {
+        let scanner = Scanner::new(source.to_string()).with_features(Features::all());
+        let statements = Parser::new(scanner).parse().expect("source should parse");
+        program_to_source(&statements).trim_end().to_string()
+    }
+
+    #[test]
+    fn limits_the_nesting() {
+        let parse = |source: String| Parser::new(Scanner::new(source)).parse();
+        let nested = |n| format!("print {}1{};", "(".repeat(n), ")".repeat(n));
+        assert!(parse(nested(200)).is_ok());
+        let errors = parse(nested(MAX_NESTING)).unwrap_err();
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["[line 1, col 262] Error at '(': Too much nesting."]
+        );
+        // Operators in a row aren't nested, however many there are
+        let chain = |n| format!("print 1{};", " + 1".repeat(n));
+        assert!(parse(chain(MAX_NESTING * 4)).is_ok());
+        assert!(parse(format!("print o{};", ".a".repeat(MAX_NESTING * 4))).is_ok());
+        let errors = parse(chain(MAX_CHAINED + 1)).unwrap_err();
+        assert_eq!(errors[0].message(), "Too many operators in a row.");
+        // Errors leave the levels they unwind
+        assert!(parse(nested(MAX_NESTING - 2)).is_ok());
+        let source = format!("print ((1 +));\n{}", nested(MAX_NESTING - 2));
+        assert_eq!(parse(source).unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn indexing_binds_like_calls() {
+        assert_eq!(desugared("-a[0] + [1, 2][b];"), "-a[0] + [1, 2][b];");
+        assert_eq!(desugared("a.b[c](d)[e] = f;"), "a.b[c](d)[e] = f;");
+        // Only the variables are desugared, so `a` is evaluated once
+        assert_eq!(desugared("a[0] += 1;"), "a[0] += 1;");
+        assert_eq!(desugared("a.b -= c = 1;"), "a.b -= c = 1;");
+        assert_eq!(desugared("a *= 2;"), "a = a * 2;");
+    }
+
+    #[test]
+    fn braces_are_maps_only_in_expressions() {
+        assert_eq!(
+            desugared(r#"print {"a": 1, b: c ? d : e}["a"];"#),
+            r#"print {"a": 1, b: c ? d : e}["a"];"#
+        );
+        assert_eq!(desugared("{ a; }"), "{\n    a;\n}");
+        assert_eq!(desugared("({})[a] = {};"), "({})[a] = {};");
+    }
+
+    #[test]
+    fn lambdas_are_expressions() {
+        assert_eq!(
+            desugared("var f = fun (a) { return a; };"),
+            "var f = fun (a) {\n    return a;\n};"
+        );
+        assert_eq!(desugared("fun () {}(1) + 2;"), "fun () {\n}(1) + 2;");
+        assert_eq!(desugared("fun f() {}"), "fun f() {\n}");
+    }
+
+    #[test]
+    fn pipes_desugar_into_calls() {
+        assert_eq!(desugared("x |> f;"), "f(x);");
+        assert_eq!(desugared("x |> f |> g(2, 3);"), "g(f(x), 2, 3);");
+        assert_eq!(desugared("x |> a.b(1);"), "a.b(x, 1);");
+        assert_eq!(desugared("x |> f(1)(2);"), "f(1)(x, 2);");
+    }
+
+    #[test]
+    fn pipes_bind_looser_than_arithmetic_and_tighter_than_comparison() {
+        assert_eq!(desugared("1 + 2 |> f;"), "f(1 + 2);");
+        assert_eq!(desugared("-x |> f;"), "f(-x);");
+        assert_eq!(desugared("a < b |> f;"), "a < f(b);");
+        assert_eq!(desugared("a |> f == b |> g;"), "f(a) == g(b);");
+        assert_eq!(desugared("y = x |> f;"), "y = f(x);");
+        assert_eq!(desugared("c ? a : b |> f;"), "c ? a : f(b);");
+    }
+
+    #[test]
+    fn pipes_need_their_feature() {
+        let errors = Parser::new(Scanner::new("x |> f;".to_string()))
+            .parse()
+            .expect_err("the pipe operator should be gated");
+        assert!(errors[0]
+            .to_string()
+            .contains("'|>' is behind the experimental feature 'lox-pipe'."));
+    }
+
+    #[test]
+    fn auto_semicolons_end_statements_at_line_ends() {
+        let source = "var a = 1\nprint a\n  + 2\nfun f() {\n  return\n}\nprint f()";
+        let mut parser = Parser::new(Scanner::new(source.to_string())).with_auto_semicolons();
+        let statements = parser.parse().expect("source should parse");
+        assert_eq!(
+            program_to_source(&statements).trim_end(),
+            "var a = 1;\nprint a + 2;\nfun f() {\n    return;\n}\nprint f();"
+        );
+        let hints: Vec<_> = parser.hints().iter().map(Hint::to_string).collect();
+        assert_eq!(
+            hints,
+            [
+                "[line 1, col 9] Hint at '1': Missing ';' at the end of the line.",
+                "[line 3, col 5] Hint at '2': Missing ';' at the end of the line.",
+                "[line 5, col 3] Hint at 'return': Missing ';' at the end of the line.",
+                "[line 7, col 9] Hint at ')': Missing ';' at the end of the line.",
+            ]
+        );
+    }
+
+    #[test]
+    fn auto_semicolons_still_need_them_within_a_line() {
+        let mut parser =
+            Parser::new(Scanner::new("print 1 print 2".to_string())).with_auto_semicolons();
+        let errors = parser.parse().expect_err("the ';' should be missing");
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1, col 9] Error at 'print': Expect ';' after value."
+        );
+    }
+}
@@ -0,0 +1,125 @@
+/// Native functions loaded from dynamic libraries (`rinlox --plugin`)
+///
+/// A plugin is a `cdylib` crate depending on `rinlox` (with the `plugins`
+/// feature), which defines its natives on the `Registry` it is given:
+///
+/// ```ignore
+/// fn register(registry: &mut rinlox::plugin::Registry) {
+///     registry.define_native("twice", 1, |_, _, arguments| match &arguments[0] {
+///         Value::Number(n) => Ok(Value::Number(n * 2.0)),
+///         _ => Ok(Value::Nil),
+///     });
+/// }
+///
+/// rinlox::declare_plugin!(register);
+/// ```
+///
+/// `declare_plugin!` exports the C-ABI functions `load` looks for, and
+/// makes the plugin share the interned names of the interpreter. The
+/// natives cross the library boundary as Rust values, so a plugin only
+/// works with the exact version of rinlox it was built against (which
+/// `load` checks), built by the same compiler. See `examples/plugin.rs`.
+use std::ffi::{c_char, CStr};
+use std::path::Path;
+
+use libloading::{Library, Symbol};
+
+use crate::interpreter::{Interpreter, RuntimeError, Value};
+use crate::lexer::Token;
+
+/// Version of rinlox plugins have to be built against, nul-terminated
+pub const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+
+/// Symbol of the function returning the `VERSION` a plugin was built with
+const VERSION_SYMBOL: &[u8] = b"rinlox_plugin_version";
+
+/// Symbol of the function defining the natives of a plugin
+const REGISTER_SYMBOL: &[u8] = b"rinlox_plugin_register";
+
+/// What a plugin can add to the interpreter loading it
+pub struct Registry<'a> {
+    interpreter: &'a mut Interpreter,
+}
+
+impl Registry<'_> {
+    /// Bind a native function as a global, like
+    /// `Interpreter::define_native`
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&mut Interpreter, &Token, Vec<Value>) -> Result<Value, RuntimeError> + 'static,
+    ) {
+        self.interpreter.define_native(name, arity, function);
+    }
+
+    /// The interner of the interpreter, which the plugin has to share for
+    /// its names to match (`declare_plugin!` does it)
+    pub fn interner(&self) -> &'static crate::symbol::Interner {
+        crate::symbol::interner()
+    }
+}
+
+/// Export the functions of a plugin, given the function registering its
+/// natives (taking a `&mut Registry`)
+#[macro_export]
+macro_rules! declare_plugin {
+    ($register:path) => {
+        #[no_mangle]
+        pub extern "C" fn rinlox_plugin_version() -> *const ::std::ffi::c_char {
+            $crate::plugin::VERSION.as_ptr().cast()
+        }
+
+        #[no_mangle]
+        pub extern "C" fn rinlox_plugin_register(registry: &mut $crate::plugin::Registry) {
+            $crate::symbol::share_interner(registry.interner());
+            $register(registry)
+        }
+    };
+}
+
+/// Load the plugin at `path`, defining its natives in the interpreter
+///
+/// The library stays loaded until the process ends, since the natives
+/// (and any value they return) can outlive the interpreter.
+pub fn load(interpreter: &mut Interpreter, path: &Path) -> Result<(), String> {
+    let error =
+        |message: String| format!("Could not load plugin '{}': {}", path.display(), message);
+
+    // SAFETY: Loading a library runs its initializers, and calling its
+    // functions trusts their signatures. Both are on whoever passes a
+    // library as a plugin, and the version check catches plugins built
+    // for another rinlox
+    unsafe {
+        let library = Library::new(path).map_err(|e| error(e.to_string()))?;
+        let version: Symbol<extern "C" fn() -> *const c_char> = library
+            .get(VERSION_SYMBOL)
+            .map_err(|_| error("it is not a rinlox plugin".to_string()))?;
+        let version = CStr::from_ptr(version()).to_string_lossy();
+        let expected = VERSION.trim_end_matches('\0');
+        if version != expected {
+            return Err(error(format!(
+                "it was built for rinlox {} instead of {}",
+                version, expected
+            )));
+        }
+
+        let register: Symbol<extern "C" fn(&mut Registry)> = library
+            .get(REGISTER_SYMBOL)
+            .map_err(|_| error("it is not a rinlox plugin".to_string()))?;
+        register(&mut Registry { interpreter });
+        std::mem::forget(library);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_libraries_are_reported() {
+        let error = load(&mut Interpreter::default(), Path::new("nope/libnope.so")).unwrap_err();
+        assert!(error.starts_with("Could not load plugin 'nope/libnope.so': "));
+    }
+}
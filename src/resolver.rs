@@ -0,0 +1,601 @@
+/// Static resolution pass for the `Lox` programming language
+///
+/// Walks the AST before it is executed, recording in each variable access
+/// how many scopes away its declaration is (see `expr::Depth`), so that
+/// closures always see the variable that was in scope where they were
+/// declared. It also finds the semantic errors that can be detected
+/// without running the program.
+///
+/// Tools like an editor can also have it record which declaration each
+/// variable used refers to (see `Resolver::with_references`).
+///
+/// Given the slots of the globals of a program (see `GlobalSlots`), it also
+/// records in each global used where the program keeps it.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::rc::Rc;
+
+use crate::diagnostics::{error_header, Note};
+use crate::expr::{Depth, Expr, Slot};
+use crate::internal;
+use crate::lexer::Token;
+use crate::stmt::{ClassBody, Stmt};
+use crate::symbol::Symbol;
+
+/// Semantic error found at `token`, possibly pointing at other related
+/// places in the source with the `notes`
+#[derive(Debug, Clone)]
+pub struct ResolveError {
+    pub token: Token,
+    pub message: String,
+    pub notes: Vec<Note>,
+}
+
+impl Display for ResolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", error_header(&self.token, &self.message))
+    }
+}
+
+/// A variable used in the program (read or assigned), and where the
+/// variable is declared
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub name: Token,
+    pub declaration: Token,
+}
+
+/// Slot of each global among the globals of a program, given out by the
+/// resolver the first time it sees the global
+///
+/// The interpreter keeps the same slots for as long as it keeps its
+/// globals (see `Interpreter::global_slots`), so each line typed in a
+/// prompt finds the globals the earlier ones declared where they left them.
+/// Cloning the slots shares them.
+#[derive(Debug, Clone, Default)]
+pub struct GlobalSlots(Rc<RefCell<HashMap<Symbol, usize>>>);
+
+impl GlobalSlots {
+    /// Slot of the global `name`, giving it the next free one if it has
+    /// none yet
+    pub fn slot(&self, name: Symbol) -> usize {
+        let mut slots = self.0.borrow_mut();
+        let next = slots.len();
+        *slots.entry(name).or_insert(next)
+    }
+
+    /// Slot of the global `name`, if it has one
+    pub fn get(&self, name: Symbol) -> Option<usize> {
+        self.0.borrow().get(&name).copied()
+    }
+
+    /// The globals with a slot and their slots
+    pub fn entries(&self) -> Vec<(Symbol, usize)> {
+        self.0
+            .borrow()
+            .iter()
+            .map(|(&name, &slot)| (name, slot))
+            .collect()
+    }
+}
+
+/// The declarations in scope at each point, to find the one each variable
+/// refers to
+#[derive(Debug, Default)]
+struct Index {
+    /// Names declared in each local scope, alongside `Resolver::scopes`
+    scopes: Vec<HashMap<Symbol, Token>>,
+    /// First declaration of each global
+    globals: HashMap<Symbol, Token>,
+    /// Variables resolved to a local scope so far
+    references: Vec<Reference>,
+    /// Variables not found in any local scope, which can refer to a global
+    /// declared anywhere in the program
+    globals_used: Vec<Token>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum FunctionType {
+    #[default]
+    None,
+    Function,
+    Initializer,
+    Method,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum ClassType {
+    #[default]
+    None,
+    Class,
+    Subclass,
+}
+
+#[derive(Debug, Default)]
+pub struct Resolver {
+    /// Stack of the local scopes being resolved, mapping each variable to
+    /// whether its initializer has been resolved yet
+    scopes: Vec<HashMap<Symbol, bool>>,
+    current_function: FunctionType,
+    current_class: ClassType,
+    /// Whether we are inside the body of a `defer` (of the current function)
+    in_defer: bool,
+    /// Whether we are inside the body of a loop (of the current function or
+    /// deferred block), where `break` and `continue` can be used
+    in_loop: bool,
+    errors: Vec<ResolveError>,
+    /// Only kept when asked for with `with_references`
+    index: Option<Index>,
+    references: Vec<Reference>,
+    /// Only given with `with_globals`
+    globals: Option<GlobalSlots>,
+}
+
+impl Resolver {
+    /// Also record the declaration each variable used refers to, which
+    /// `references` gives back after resolving
+    pub fn with_references(mut self) -> Self {
+        self.index = Some(Index::default());
+        self
+    }
+
+    /// Record in each global used its slot in `globals`, giving a slot to
+    /// the globals that have none yet
+    pub fn with_globals(mut self, globals: GlobalSlots) -> Self {
+        self.globals = Some(globals);
+        self
+    }
+
+    /// The variables used in the program last resolved and their
+    /// declarations, in the order they appear in the source. Globals that
+    /// are never declared, as well as `this` and `super`, are left out
+    pub fn references(&self) -> &[Reference] {
+        &self.references
+    }
+
+    /// Resolve all the variables in the program, returning every semantic
+    /// error found along the way if there was any
+    pub fn resolve(&mut self, statements: &[Stmt]) -> Result<(), Vec<ResolveError>> {
+        self.resolve_statements(statements);
+        if let Some(index) = &mut self.index {
+            let mut index = std::mem::take(index);
+            for name in index.globals_used {
+                if let Some(declaration) = index.globals.get(&name.lexeme.symbol()) {
+                    let declaration = declaration.clone();
+                    index.references.push(Reference { name, declaration });
+                }
+            }
+            index
+                .references
+                .sort_by_key(|reference| reference.name.span.start);
+            self.references = index.references;
+        }
+
+        let errors = std::mem::take(&mut self.errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn resolve_statements(&mut self, statements: &[Stmt]) {
+        for stmt in statements {
+            self.resolve_stmt(stmt);
+        }
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        // Nested code can take more stack than the thread has
+        internal::grow_stack(|| {
+            match stmt {
+                Stmt::Block { statements } => {
+                    self.begin_scope();
+                    self.resolve_statements(statements);
+                    self.end_scope();
+                }
+                Stmt::Class {
+                    name,
+                    superclass,
+                    body,
+                } => self.resolve_class(name, superclass.as_ref(), body),
+                Stmt::Break { keyword } | Stmt::Continue { keyword } => {
+                    if !self.in_loop {
+                        let message = format!("Can't use '{}' outside of a loop.", keyword.lexeme);
+                        self.error(keyword, &message);
+                    }
+                }
+                Stmt::Defer { body, .. } => {
+                    let enclosing_defer = std::mem::replace(&mut self.in_defer, true);
+                    let enclosing_loop = std::mem::replace(&mut self.in_loop, false);
+                    self.begin_scope();
+                    self.resolve_statements(body);
+                    self.end_scope();
+                    self.in_defer = enclosing_defer;
+                    self.in_loop = enclosing_loop;
+                }
+                Stmt::Enum { name, variants } => {
+                    self.declare(name);
+                    self.define(name);
+                    for (i, variant) in variants.iter().enumerate() {
+                        if let Some(previous) = variants[..i].iter().find(|v| *v == variant) {
+                            let note = Note::new(previous.span, "first declared here");
+                            self.error_with_notes(
+                                variant,
+                                "Already a variant with this name in this enum.",
+                                vec![note],
+                            );
+                        }
+                    }
+                }
+                Stmt::Extend { class, body } => {
+                    self.resolve_expr(class);
+
+                    // Extensions are resolved as a class body with no
+                    // superclass, so they can't use `super`
+                    let enclosing_class = self.current_class;
+                    self.current_class = ClassType::Class;
+                    self.begin_scope();
+                    self.define_name(Symbol::THIS);
+                    self.resolve_class_body(body);
+                    self.end_scope();
+                    self.current_class = enclosing_class;
+                }
+                Stmt::Expression { expression } | Stmt::Print { expression, .. } => {
+                    self.resolve_expr(expression)
+                }
+                Stmt::Import { keyword, name, .. } => {
+                    // Imports are relative to the file being run, which is only
+                    // known for sure at the top level
+                    if !self.scopes.is_empty() {
+                        self.error(keyword, "Can only import at the top level.");
+                    }
+                    self.declare(name);
+                    self.define(name);
+                }
+                Stmt::Function { name, params, body } => {
+                    // Define the name eagerly so the function can refer to itself
+                    self.declare(name);
+                    self.define(name);
+                    self.resolve_function(params, body, FunctionType::Function);
+                }
+                Stmt::If {
+                    condition,
+                    then_branch,
+                    else_branch,
+                } => {
+                    self.resolve_expr(condition);
+                    self.resolve_stmt(then_branch);
+                    if let Some(else_branch) = else_branch {
+                        self.resolve_stmt(else_branch);
+                    }
+                }
+                Stmt::Return { keyword, value } => {
+                    if self.current_function == FunctionType::None {
+                        self.error(keyword, "Can't return from top-level code.");
+                    } else if self.in_defer {
+                        self.error(keyword, "Can't return from a deferred block.");
+                    }
+                    if let Some(value) = value {
+                        if self.current_function == FunctionType::Initializer {
+                            self.error(keyword, "Can't return a value from an initializer.");
+                        }
+                        self.resolve_expr(value);
+                    }
+                }
+                Stmt::Var { name, initializer } => {
+                    self.declare(name);
+                    if let Some(initializer) = initializer {
+                        self.resolve_expr(initializer);
+                    }
+                    self.define(name);
+                }
+                Stmt::While {
+                    condition,
+                    body,
+                    increment,
+                    ..
+                } => {
+                    self.resolve_expr(condition);
+                    let enclosing_loop = std::mem::replace(&mut self.in_loop, true);
+                    self.resolve_stmt(body);
+                    self.in_loop = enclosing_loop;
+                    if let Some(increment) = increment {
+                        self.resolve_expr(increment);
+                    }
+                }
+            }
+        })
+    }
+
+    fn resolve_class(&mut self, name: &Token, superclass: Option<&Expr>, body: &ClassBody) {
+        let enclosing_class = self.current_class;
+        self.current_class = ClassType::Class;
+
+        self.declare(name);
+        self.define(name);
+
+        if let Some(superclass) = superclass {
+            if let Expr::Variable {
+                name: superclass_name,
+                ..
+            } = superclass
+            {
+                if superclass_name.lexeme == name.lexeme {
+                    self.error(superclass_name, "A class can't inherit from itself.");
+                }
+            }
+            self.current_class = ClassType::Subclass;
+            self.resolve_expr(superclass);
+
+            self.begin_scope();
+            self.define_name(Symbol::SUPER);
+        }
+
+        self.begin_scope();
+        self.define_name(Symbol::THIS);
+        self.resolve_class_body(body);
+        self.end_scope();
+        if superclass.is_some() {
+            self.end_scope();
+        }
+
+        self.current_class = enclosing_class;
+    }
+
+    /// Resolve the methods of a class body, inside the scope binding `this`
+    /// (which is the class itself in class methods)
+    fn resolve_class_body(&mut self, class_body: &ClassBody) {
+        for method in &class_body.methods {
+            let Stmt::Function { name, params, body } = method else {
+                unreachable!("class methods should be functions");
+            };
+            let typ = if name.lexeme == "init" {
+                FunctionType::Initializer
+            } else {
+                FunctionType::Method
+            };
+            self.resolve_function(params, body, typ);
+        }
+        for getter in &class_body.getters {
+            let Stmt::Function { name, body, .. } = getter else {
+                unreachable!("getters should be functions");
+            };
+            if name.lexeme == "init" {
+                self.error(name, "An initializer can't be a getter.");
+            }
+            self.resolve_function(&[], body, FunctionType::Method);
+        }
+        for method in &class_body.class_methods {
+            let Stmt::Function { params, body, .. } = method else {
+                unreachable!("class methods should be functions");
+            };
+            self.resolve_function(params, body, FunctionType::Method);
+        }
+    }
+
+    fn resolve_function(&mut self, params: &[Token], body: &[Stmt], typ: FunctionType) {
+        let enclosing_function = self.current_function;
+        self.current_function = typ;
+        let enclosing_defer = std::mem::replace(&mut self.in_defer, false);
+        let enclosing_loop = std::mem::replace(&mut self.in_loop, false);
+
+        self.begin_scope();
+        for param in params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.resolve_statements(body);
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+        self.in_defer = enclosing_defer;
+        self.in_loop = enclosing_loop;
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        // Nested code can take more stack than the thread has
+        internal::grow_stack(|| match expr {
+            Expr::Assign {
+                name,
+                value,
+                depth,
+                slot,
+            } => {
+                self.resolve_expr(value);
+                self.resolve_local(depth, name.lexeme.symbol());
+                self.resolve_slot(depth, slot, name.lexeme.symbol());
+                self.reference(name, depth);
+            }
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.resolve_expr(callee);
+                for argument in arguments {
+                    self.resolve_expr(argument);
+                }
+            }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_expr(then_branch);
+                self.resolve_expr(else_branch);
+            }
+            Expr::Get { object, .. } => self.resolve_expr(object),
+            Expr::Set { object, value, .. } => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+            }
+            Expr::Index { object, index, .. } => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+            Expr::List { elements, .. } => {
+                for element in elements {
+                    self.resolve_expr(element);
+                }
+            }
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.resolve_expr(key);
+                    self.resolve_expr(value);
+                }
+            }
+            Expr::Grouping { expression } => self.resolve_expr(expression),
+            Expr::Lambda { params, body, .. } => {
+                self.resolve_function(params, body, FunctionType::Function)
+            }
+            Expr::Literal { .. } => {}
+            Expr::Super { keyword, depth, .. } => {
+                match self.current_class {
+                    ClassType::None => self.error(keyword, "Can't use 'super' outside of a class."),
+                    ClassType::Class => {
+                        self.error(keyword, "Can't use 'super' in a class with no superclass.")
+                    }
+                    ClassType::Subclass => {}
+                }
+                self.resolve_local(depth, Symbol::SUPER);
+            }
+            Expr::This { keyword, depth } => {
+                if self.current_class == ClassType::None {
+                    self.error(keyword, "Can't use 'this' outside of a class.");
+                    return;
+                }
+                self.resolve_local(depth, Symbol::THIS);
+            }
+            Expr::Unary { right, .. } => self.resolve_expr(right),
+            Expr::Variable { name, depth, slot } => {
+                let declared_not_defined = self
+                    .scopes
+                    .last()
+                    .and_then(|scope| scope.get(&name.lexeme.symbol()))
+                    .map(|defined| !defined)
+                    .unwrap_or(false);
+                if declared_not_defined {
+                    self.error(name, "Can't read local variable in its own initializer.");
+                }
+                self.resolve_local(depth, name.lexeme.symbol());
+                self.resolve_slot(depth, slot, name.lexeme.symbol());
+                self.reference(name, depth);
+            }
+        })
+    }
+
+    /// Record how many scopes away the variable is declared. If it is not
+    /// found in any local scope it is assumed to be a global
+    fn resolve_local(&mut self, depth: &Depth, name: Symbol) {
+        let distance = self
+            .scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(&name));
+        depth.set(distance);
+    }
+
+    /// Record the slot of the variable resolved to `depth` if it is a global
+    /// and the globals have slots
+    fn resolve_slot(&self, depth: &Depth, slot: &Slot, name: Symbol) {
+        let globals = self.globals.as_ref().filter(|_| depth.get().is_none());
+        slot.set(globals.map(|globals| globals.slot(name)));
+    }
+
+    /// Record the declaration the variable resolved to `depth` refers to,
+    /// if keeping track of them
+    fn reference(&mut self, name: &Token, depth: &Depth) {
+        let Some(index) = &mut self.index else {
+            return;
+        };
+        match depth.get() {
+            Some(distance) => {
+                let scope = &index.scopes[index.scopes.len() - 1 - distance];
+                if let Some(declaration) = scope.get(&name.lexeme.symbol()) {
+                    index.references.push(Reference {
+                        name: name.clone(),
+                        declaration: declaration.clone(),
+                    });
+                }
+            }
+            None => index.globals_used.push(name.clone()),
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+        if let Some(index) = &mut self.index {
+            index.scopes.push(HashMap::new());
+        }
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+        if let Some(index) = &mut self.index {
+            index.scopes.pop();
+        }
+    }
+
+    /// Add the variable to the innermost scope, but mark it as not ready
+    /// to be used until its initializer is resolved
+    fn declare(&mut self, name: &Token) {
+        if let Some(index) = &mut self.index {
+            match index.scopes.last_mut() {
+                Some(scope) => {
+                    scope.insert(name.lexeme.symbol(), name.clone());
+                }
+                None => {
+                    index
+                        .globals
+                        .entry(name.lexeme.symbol())
+                        .or_insert_with(|| name.clone());
+                }
+            }
+        }
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+        if scope.contains_key(&name.lexeme.symbol()) {
+            self.error(name, "Already a variable with this name in this scope.");
+            return;
+        }
+        scope.insert(name.lexeme.symbol(), false);
+    }
+
+    fn define(&mut self, name: &Token) {
+        self.define_name(name.lexeme.symbol());
+    }
+
+    fn define_name(&mut self, name: Symbol) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, true);
+        }
+    }
+
+    fn error(&mut self, token: &Token, msg: &str) {
+        self.error_with_notes(token, msg, Vec::new());
+    }
+
+    fn error_with_notes(&mut self, token: &Token, msg: &str, notes: Vec<Note>) {
+        self.errors.push(ResolveError {
+            token: token.clone(),
+            message: msg.to_string(),
+            notes,
+        });
+    }
+}
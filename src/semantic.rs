@@ -0,0 +1,404 @@
+/// What each identifier of a program names, so editors can color user code
+/// by meaning rather than only as identifiers
+///
+/// Declarations are classified by how they are declared. Variables used
+/// are classified like the declaration the resolver found for them (see
+/// `Resolver::with_references`), and the ones declared nowhere in the
+/// program (like the natives) are globals. Names after a `.` are
+/// properties, or methods when they are called.
+///
+/// Enums are classified as classes and their variants as properties, which
+/// is how they are used.
+use std::collections::HashMap;
+
+use crate::expr::Expr;
+use crate::internal;
+use crate::lexer::Token;
+use crate::resolver::Resolver;
+use crate::stmt::{ClassBody, Stmt};
+
+/// What an identifier names
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Kind {
+    Parameter,
+    Local,
+    Global,
+    Function,
+    Class,
+    Method,
+    Property,
+}
+
+/// An identifier in the source and what it names
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SemanticToken {
+    pub name: Token,
+    pub kind: Kind,
+    /// Whether the identifier is where its variable or member is declared
+    pub declaration: bool,
+}
+
+/// The identifiers of the program, in the order they appear in the source
+///
+/// The program doesn't have to resolve without errors: the variables that
+/// can still be resolved are classified like their declarations.
+pub fn classify(statements: &[Stmt]) -> Vec<SemanticToken> {
+    let mut resolver = Resolver::default().with_references();
+    let _ = resolver.resolve(statements);
+    let declarations: HashMap<_, _> = resolver
+        .references()
+        .iter()
+        .map(|reference| (reference.name.span.start, reference.declaration.span.start))
+        .collect();
+
+    let mut classifier = Classifier::default();
+    classifier.statements(statements);
+    let mut tokens = classifier.tokens;
+    let kinds: HashMap<_, _> = tokens
+        .iter()
+        .filter(|token| token.declaration)
+        .map(|token| (token.name.span.start, token.kind))
+        .collect();
+    for token in tokens.iter_mut().filter(|token| token.kind == Kind::Global) {
+        let declared = declarations
+            .get(&token.name.span.start)
+            .and_then(|declaration| kinds.get(declaration));
+        if let (false, Some(&kind)) = (token.declaration, declared) {
+            token.kind = kind;
+        }
+    }
+    tokens.sort_by_key(|token| token.name.span.start);
+    // Desugared code (like `a += 1`) repeats the tokens it is made from
+    tokens.dedup_by_key(|token| token.name.span.start);
+    tokens
+}
+
+/// Walk through the program collecting the identifiers, with the variables
+/// used taken for globals until their declarations are known
+#[derive(Debug, Default)]
+struct Classifier {
+    /// Scopes the walk is in, with the top level at 0
+    depth: usize,
+    tokens: Vec<SemanticToken>,
+}
+
+impl Classifier {
+    fn statements(&mut self, statements: &[Stmt]) {
+        for stmt in statements {
+            self.stmt(stmt);
+        }
+    }
+
+    fn scope(&mut self, statements: &[Stmt]) {
+        self.depth += 1;
+        self.statements(statements);
+        self.depth -= 1;
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        // Nested code can take more stack than the thread has
+        internal::grow_stack(|| match stmt {
+            Stmt::Block { statements } => self.scope(statements),
+            Stmt::Class {
+                name,
+                superclass,
+                body,
+            } => {
+                self.declare(name, Kind::Class);
+                if let Some(superclass) = superclass {
+                    self.expr(superclass);
+                }
+                self.class_body(body);
+            }
+            Stmt::Break { .. } | Stmt::Continue { .. } => {}
+            Stmt::Defer { body, .. } => self.scope(body),
+            Stmt::Enum { name, variants } => {
+                self.declare(name, Kind::Class);
+                for variant in variants {
+                    self.declare(variant, Kind::Property);
+                }
+            }
+            Stmt::Extend { class, body } => {
+                self.expr(class);
+                self.class_body(body);
+            }
+            Stmt::Expression { expression } | Stmt::Print { expression, .. } => {
+                self.expr(expression)
+            }
+            Stmt::Function { name, params, body } => {
+                self.declare(name, Kind::Function);
+                self.function(params, body);
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(condition);
+                self.stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.stmt(else_branch);
+                }
+            }
+            Stmt::Import { name, .. } => self.declare(name, self.variable()),
+            Stmt::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            Stmt::Var { name, initializer } => {
+                self.declare(name, self.variable());
+                if let Some(initializer) = initializer {
+                    self.expr(initializer);
+                }
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+                ..
+            } => {
+                self.expr(condition);
+                self.stmt(body);
+                if let Some(increment) = increment {
+                    self.expr(increment);
+                }
+            }
+        })
+    }
+
+    fn class_body(&mut self, body: &ClassBody) {
+        let members = [
+            (&body.methods, Kind::Method),
+            (&body.getters, Kind::Property),
+            (&body.class_methods, Kind::Method),
+        ];
+        for (members, kind) in members {
+            for member in members {
+                let Stmt::Function { name, params, body } = member else {
+                    unreachable!("class members should be functions");
+                };
+                self.declare(name, kind);
+                self.function(params, body);
+            }
+        }
+    }
+
+    fn function(&mut self, params: &[Token], body: &[Stmt]) {
+        self.depth += 1;
+        for param in params {
+            self.declare(param, Kind::Parameter);
+        }
+        self.statements(body);
+        self.depth -= 1;
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        // Nested code can take more stack than the thread has
+        internal::grow_stack(|| match expr {
+            Expr::Assign { name, value, .. } => {
+                self.used(name);
+                self.expr(value);
+            }
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                match callee.as_ref() {
+                    Expr::Get { object, name } => {
+                        self.expr(object);
+                        self.member(name, Kind::Method);
+                    }
+                    callee => self.expr(callee),
+                }
+                for argument in arguments {
+                    self.expr(argument);
+                }
+            }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(condition);
+                self.expr(then_branch);
+                self.expr(else_branch);
+            }
+            Expr::Get { object, name } => {
+                self.expr(object);
+                self.member(name, Kind::Property);
+            }
+            Expr::Grouping { expression } => self.expr(expression),
+            Expr::Index { object, index, .. } => {
+                self.expr(object);
+                self.expr(index);
+            }
+            Expr::Lambda { params, body, .. } => self.function(params, body),
+            Expr::List { elements, .. } => {
+                for element in elements {
+                    self.expr(element);
+                }
+            }
+            Expr::Literal { .. } | Expr::This { .. } => {}
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+                ..
+            } => {
+                self.expr(object);
+                self.member(name, Kind::Property);
+                self.expr(value);
+            }
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.expr(object);
+                self.expr(index);
+                self.expr(value);
+            }
+            Expr::Super { method, .. } => self.member(method, Kind::Method),
+            Expr::Unary { right, .. } => self.expr(right),
+            Expr::Variable { name, .. } => self.used(name),
+        })
+    }
+
+    /// Kind of the variables declared at this point
+    fn variable(&self) -> Kind {
+        if self.depth == 0 {
+            Kind::Global
+        } else {
+            Kind::Local
+        }
+    }
+
+    fn declare(&mut self, name: &Token, kind: Kind) {
+        self.push(name, kind, true);
+    }
+
+    fn used(&mut self, name: &Token) {
+        self.push(name, Kind::Global, false);
+    }
+
+    fn member(&mut self, name: &Token, kind: Kind) {
+        self.push(name, kind, false);
+    }
+
+    fn push(&mut self, name: &Token, kind: Kind, declaration: bool) {
+        self.tokens.push(SemanticToken {
+            name: name.clone(),
+            kind,
+            declaration,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+
+    /// The lexeme, kind and whether it is a declaration of each identifier
+    fn classified(source: &str) -> Vec<(String, Kind, bool)> {
+        let statements = Parser::new(Scanner::new(source.to_string()))
+            .parse()
+            .expect("source should parse");
+        classify(&statements)
+            .into_iter()
+            .map(|token| (token.name.lexeme.to_string(), token.kind, token.declaration))
+            .collect()
+    }
+
+    fn found(lexeme: &str, kind: Kind, declaration: bool) -> (String, Kind, bool) {
+        (lexeme.to_string(), kind, declaration)
+    }
+
+    #[test]
+    fn classifies_variables_like_their_declarations() {
+        let source = "
+            var total = 0;
+            fun add(n) {
+                var next = total + n;
+                total = next;
+                return clock;
+            }";
+        assert_eq!(
+            classified(source),
+            [
+                found("total", Kind::Global, true),
+                found("add", Kind::Function, true),
+                found("n", Kind::Parameter, true),
+                found("next", Kind::Local, true),
+                found("total", Kind::Global, false),
+                found("n", Kind::Parameter, false),
+                found("total", Kind::Global, false),
+                found("next", Kind::Local, false),
+                found("clock", Kind::Global, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn classifies_classes_and_their_members() {
+        let source = "
+            class Point {
+                init(x) { this.x = x; }
+                norm { return this.x; }
+            }
+            var p = Point(1);
+            p.init(2);
+            print p.norm;
+            fun later() { return Later; }
+            class Later {}";
+        assert_eq!(
+            classified(source),
+            [
+                found("Point", Kind::Class, true),
+                found("init", Kind::Method, true),
+                found("x", Kind::Parameter, true),
+                found("x", Kind::Property, false),
+                found("x", Kind::Parameter, false),
+                found("norm", Kind::Property, true),
+                found("x", Kind::Property, false),
+                found("p", Kind::Global, true),
+                found("Point", Kind::Class, false),
+                found("p", Kind::Global, false),
+                found("init", Kind::Method, false),
+                found("p", Kind::Global, false),
+                found("norm", Kind::Property, false),
+                found("later", Kind::Function, true),
+                // Declared further down
+                found("Later", Kind::Class, false),
+                found("Later", Kind::Class, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_classifying_programs_with_errors() {
+        let source = "fun f(a) { var a = a; return a; } return f;";
+        let tokens = classified(source);
+        assert_eq!(tokens[0], found("f", Kind::Function, true));
+        assert_eq!(tokens[1], found("a", Kind::Parameter, true));
+        assert_eq!(tokens.last(), Some(&found("f", Kind::Function, false)));
+    }
+}
@@ -0,0 +1,64 @@
+/// AST definition for statements
+use std::rc::Rc;
+
+use crate::expr::Expr;
+use crate::lexer::Token;
+
+/// The body of a class (or of an extension of one), whose members are all
+/// `Stmt::Function`s
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ClassBody {
+    pub methods: Vec<Stmt>,
+    /// Declared with no parameter list, they run when the property is read
+    pub getters: Vec<Stmt>,
+    /// Declared after `class`, they are called on the class itself
+    pub class_methods: Vec<Stmt>,
+}
+
+// NOTE(alvaro): In `Stmt::Import`, `path` is the string or identifier
+// written after `import`, and `name` the variable the module is bound to
+// (the same token for an identifier)
+define_ast! {
+    pub enum Stmt: StmtVisitor {
+        Block(block, visit_block_stmt) { statements: Vec<Stmt> },
+        Break(break_loop, visit_break_stmt) { keyword: Token },
+        Class(class, visit_class_stmt) { name: Token, superclass: Option<Expr>, body: ClassBody },
+        Continue(continue_loop, visit_continue_stmt) { keyword: Token },
+        Defer(defer, visit_defer_stmt) { keyword: Token, body: Rc<Vec<Stmt>> },
+        Enum(enumeration, visit_enum_stmt) { name: Token, variants: Vec<Token> },
+        Extend(extend, visit_extend_stmt) { class: Expr, body: ClassBody },
+        Expression(expression, visit_expression_stmt) { expression: Expr },
+        Function(function, visit_function_stmt) { name: Token, params: Vec<Token>, body: Rc<Vec<Stmt>> },
+        If(if_else, visit_if_stmt) { condition: Expr, then_branch: Box<Stmt>, else_branch: Option<Box<Stmt>> },
+        Import(import, visit_import_stmt) { keyword: Token, path: Token, name: Token },
+        Print(print, visit_print_stmt) { keyword: Token, expression: Expr },
+        Return(return_value, visit_return_stmt) { keyword: Token, value: Option<Expr> },
+        Var(var, visit_var_stmt) { name: Token, initializer: Option<Expr> },
+        While(while_loop, visit_while_stmt) { keyword: Token, condition: Expr, body: Box<Stmt>, increment: Option<Expr> },
+    }
+}
+
+impl Stmt {
+    /// The first token of the statement that is in the tree, if any (an
+    /// expression statement made of literals has none)
+    pub fn first_token(&self) -> Option<&Token> {
+        match self {
+            Stmt::Block { statements } => statements.first().and_then(Stmt::first_token),
+            Stmt::Class { name, .. }
+            | Stmt::Enum { name, .. }
+            | Stmt::Function { name, .. }
+            | Stmt::Var { name, .. } => Some(name),
+            Stmt::Break { keyword }
+            | Stmt::Continue { keyword }
+            | Stmt::Defer { keyword, .. }
+            | Stmt::Import { keyword, .. }
+            | Stmt::Print { keyword, .. }
+            | Stmt::Return { keyword, .. }
+            | Stmt::While { keyword, .. } => Some(keyword),
+            Stmt::Extend { class, .. } => class.first_token(),
+            Stmt::Expression { expression } => expression.first_token(),
+            Stmt::If { condition, .. } => condition.first_token(),
+        }
+    }
+}
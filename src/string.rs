@@ -0,0 +1,131 @@
+/// Operations on the strings of the `Lox` interpreter: indexing with
+/// `string[index]`, and the methods looked up on them
+use std::rc::Rc;
+
+use crate::interpreter::{RuntimeError, Value};
+use crate::lexer::Token;
+use crate::list::LoxList;
+use crate::native::NativeFunction;
+
+/// The character at `index` of the string, as a string of its own. The
+/// `bracket` of the expression reading it points errors at
+pub fn get(bracket: &Token, string: &str, index: &Value) -> Result<Value, RuntimeError> {
+    let length = string.chars().count();
+    let position = as_integer(bracket, "String index", index)?;
+    if position < 0.0 || position >= length as f64 {
+        return Err(RuntimeError::new(
+            bracket,
+            &format!(
+                "String index {} out of range for a string of {} characters.",
+                position, length
+            ),
+        ));
+    }
+    let character = string
+        .chars()
+        .nth(position as usize)
+        .expect("it is in range");
+    Ok(Value::String(character.to_string().into()))
+}
+
+/// The value as an integer, or an error saying `what` must be one
+fn as_integer(token: &Token, what: &str, value: &Value) -> Result<f64, RuntimeError> {
+    let Value::Number(n) = *value else {
+        return Err(RuntimeError::new(
+            token,
+            &format!("{} must be a number.", what),
+        ));
+    };
+    if n.fract() != 0.0 {
+        return Err(RuntimeError::new(
+            token,
+            &format!("{} must be an integer.", what),
+        ));
+    }
+    Ok(n)
+}
+
+/// The value as a string, or an error saying `what` must be one
+fn as_string<'a>(token: &Token, what: &str, value: &'a Value) -> Result<&'a str, RuntimeError> {
+    match value {
+        Value::String(s) => Ok(s),
+        _ => Err(RuntimeError::new(
+            token,
+            &format!("{} must be a string.", what),
+        )),
+    }
+}
+
+/// Look up one of the methods by name, bound to the string. Positions count
+/// characters, like `len`:
+///
+/// - `length()` returns the number of characters.
+/// - `substring(start, end)` returns the characters from `start` up to (not
+///   including) `end`.
+/// - `split(separator)` returns a list of the parts between the
+///   separators, or of the characters if it is empty.
+/// - `toUpper()` and `toLower()` return the string in upper or lower case.
+pub fn method(string: &str, name: &Token) -> Result<Value, RuntimeError> {
+    let s: Rc<str> = string.into();
+    let method = match name.lexeme.as_str() {
+        "length" => NativeFunction::new(
+            "length",
+            0,
+            Box::new(move |_, _, _| Ok(Value::Number(s.chars().count() as f64))),
+        ),
+        "substring" => NativeFunction::new(
+            "substring",
+            2,
+            Box::new(move |_, paren, arguments| {
+                let start = as_integer(paren, "Substring start", &arguments[0])?;
+                let end = as_integer(paren, "Substring end", &arguments[1])?;
+                let length = s.chars().count();
+                if start < 0.0 || start > end || end > length as f64 {
+                    return Err(RuntimeError::new(
+                        paren,
+                        &format!(
+                            "Substring from {} to {} out of range for a string of {} characters.",
+                            start, end, length
+                        ),
+                    ));
+                }
+                let substring = s.chars().skip(start as usize).take((end - start) as usize);
+                Ok(Value::String(substring.collect::<String>().into()))
+            }),
+        ),
+        "split" => NativeFunction::new(
+            "split",
+            1,
+            Box::new(move |interpreter, paren, arguments| {
+                let separator = as_string(paren, "Separator", &arguments[0])?;
+                let parts = if separator.is_empty() {
+                    s.chars()
+                        .map(|c| Value::String(c.to_string().into()))
+                        .collect()
+                } else {
+                    s.split(separator)
+                        .map(|part| Value::String(part.into()))
+                        .collect()
+                };
+                Ok(Value::List(interpreter.heap().alloc(LoxList::new(parts))))
+            }),
+        ),
+        "toUpper" => NativeFunction::new(
+            "toUpper",
+            0,
+            Box::new(move |_, _, _| Ok(Value::String(s.to_uppercase().into()))),
+        ),
+        "toLower" => NativeFunction::new(
+            "toLower",
+            0,
+            Box::new(move |_, _, _| Ok(Value::String(s.to_lowercase().into()))),
+        ),
+        _ => {
+            return Err(RuntimeError::new(
+                name,
+                &format!("Undefined property '{}'.", name.lexeme),
+            ))
+        }
+    };
+    Ok(Value::Callable(Rc::new(method)))
+}
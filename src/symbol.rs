@@ -0,0 +1,210 @@
+/// Interned strings for the names in Lox code
+///
+/// The identifiers and keywords of the code are stored once in a table
+/// shared by the whole process, and their tokens refer to them by their
+/// position there. A `Symbol` is then as cheap to copy, compare and hash as
+/// a number, which is what the scopes and the classes of the interpreter
+/// look names up by.
+///
+/// The strings are never freed: the table only grows with the distinct
+/// names of the code run, which is small next to the code itself (literals
+/// are not interned, see `Lexeme`). Interning takes a lock, but reading a
+/// symbol doesn't: the strings are appended to segments that are never
+/// moved nor overwritten, so they can be read while others are added.
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::ops::Deref;
+use std::sync::{Mutex, OnceLock};
+
+/// A string in the interner, by its position there
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+/// The names the interpreter binds itself, interned up front (in this
+/// order) so they don't have to be looked up
+const PREDEFINED: [&str; 3] = ["this", "super", "init"];
+
+impl Symbol {
+    pub const THIS: Symbol = Symbol(0);
+    pub const SUPER: Symbol = Symbol(1);
+    pub const INIT: Symbol = Symbol(2);
+
+    /// The symbol of the string, adding it to the interner if it is new
+    pub fn intern(string: &str) -> Self {
+        interner().intern(string)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        interner().get(self)
+    }
+}
+
+/// Strings in the first segment of the interner, each segment after it
+/// being twice as big as the one before
+const FIRST_SEGMENT: usize = 64;
+
+/// Enough segments for every `u32` symbol
+const SEGMENTS: usize = 27;
+
+type Segment = Box<[OnceLock<&'static str>]>;
+
+pub struct Interner {
+    /// Symbol of each string, only used (and locked) to intern
+    symbols: Mutex<HashMap<&'static str, Symbol>>,
+    /// String of each symbol, written while holding the lock of `symbols`
+    /// but read without it
+    segments: [OnceLock<Segment>; SEGMENTS],
+}
+
+impl Interner {
+    fn new() -> Self {
+        let interner = Interner {
+            symbols: Mutex::new(HashMap::new()),
+            segments: [const { OnceLock::new() }; SEGMENTS],
+        };
+        for string in PREDEFINED {
+            interner.intern(string);
+        }
+        interner
+    }
+
+    fn intern(&self, string: &str) -> Symbol {
+        let mut symbols = self.symbols.lock().expect("the interner is never poisoned");
+        if let Some(&symbol) = symbols.get(string) {
+            return symbol;
+        }
+        let string: &'static str = Box::leak(string.into());
+        let symbol = Symbol(symbols.len() as u32);
+        let (segment, slot) = locate(symbol);
+        let segment = self.segments[segment].get_or_init(|| {
+            (0..FIRST_SEGMENT << segment)
+                .map(|_| OnceLock::new())
+                .collect()
+        });
+        segment[slot]
+            .set(string)
+            .expect("symbols are only added once");
+        symbols.insert(string, symbol);
+        symbol
+    }
+
+    fn get(&self, symbol: Symbol) -> &'static str {
+        let (segment, slot) = locate(symbol);
+        self.segments[segment]
+            .get()
+            .and_then(|segment| segment[slot].get())
+            .expect("symbols are only made by interning")
+    }
+}
+
+impl Debug for Interner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Interner").finish_non_exhaustive()
+    }
+}
+
+/// Segment of the interner holding the string of the symbol, and its slot
+/// in the segment
+fn locate(symbol: Symbol) -> (usize, usize) {
+    let index = symbol.0 as usize;
+    let segment = (index / FIRST_SEGMENT + 1).ilog2() as usize;
+    (segment, index - FIRST_SEGMENT * ((1 << segment) - 1))
+}
+
+static INTERNER: OnceLock<&'static Interner> = OnceLock::new();
+
+/// The interner of the process, created the first time it is needed
+pub fn interner() -> &'static Interner {
+    INTERNER.get_or_init(|| Box::leak(Box::new(Interner::new())))
+}
+
+/// Use the interner of the program loading this copy of the crate (a
+/// plugin), so the symbols mean the same on both sides. It has to be called
+/// before anything is interned
+pub fn share_interner(interner: &'static Interner) {
+    let shared = INTERNER.get_or_init(|| interner);
+    assert!(
+        std::ptr::eq(*shared, interner),
+        "the interner should be shared before it is used"
+    );
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(string: &str) -> Self {
+        Symbol::intern(string)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(string: String) -> Self {
+        Symbol::intern(&string)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interns_each_string_once() {
+        let a = Symbol::intern("symbol_test");
+        assert_eq!(Symbol::intern(&String::from("symbol_test")), a);
+        assert_ne!(Symbol::intern("symbol_test_2"), a);
+        assert_eq!(a.as_str(), "symbol_test");
+        assert_eq!(Symbol::intern("this"), Symbol::THIS);
+        assert_eq!(Symbol::INIT, "init");
+    }
+
+    #[test]
+    fn reads_symbols_across_segments() {
+        let interner = Interner::new();
+        let symbols: Vec<_> = (0..1000)
+            .map(|i| interner.intern(&format!("segment_test_{i}")))
+            .collect();
+        for (i, symbol) in symbols.into_iter().enumerate() {
+            assert_eq!(interner.get(symbol), format!("segment_test_{i}"));
+        }
+        assert_eq!(locate(Symbol(63)), (0, 63));
+        assert_eq!(locate(Symbol(64)), (1, 0));
+        assert_eq!(locate(Symbol(192)), (2, 0));
+    }
+}
@@ -0,0 +1,226 @@
+/// Comparison of token dumps (`rinlox tokenize --baseline=dump`)
+///
+/// A dump is what `rinlox tokenize` prints: one token per line, with its
+/// position first, like `[line 1, col 5] Identifier a`. Saving the dump of
+/// a script before changing the scanner and comparing it with the tokens
+/// after shows exactly what the change did to them.
+///
+/// The tokens are matched by their type and lexeme, so a token inserted in
+/// the middle only reports that token. A matched token whose position is
+/// different, or a removed token where another one was added, is reported
+/// as changed.
+use std::fmt::Display;
+
+use crate::lexer::Token;
+
+/// Token of a dump, as its position and the rest of its line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpedToken {
+    pub position: String,
+    pub token: String,
+}
+
+impl DumpedToken {
+    pub fn new(token: &Token) -> Self {
+        Self {
+            position: token.span.to_string(),
+            token: token.to_string().trim_end().to_string(),
+        }
+    }
+}
+
+impl Display for DumpedToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}", self.position, self.token)
+    }
+}
+
+/// Read a dump, or return the line that is not a token
+pub fn parse_dump(dump: &str) -> Result<Vec<DumpedToken>, String> {
+    dump.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            line.strip_prefix('[')
+                .and_then(|line| line.split_once("] "))
+                .map(|(position, token)| DumpedToken {
+                    position: position.to_string(),
+                    token: token.trim_end().to_string(),
+                })
+                .ok_or_else(|| format!("line {} is not a token: {}", i + 1, line))
+        })
+        .collect()
+}
+
+/// Difference between the old and the new tokens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added(DumpedToken),
+    Removed(DumpedToken),
+    Changed { old: DumpedToken, new: DumpedToken },
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Added(new) => write!(f, "+ {}", new),
+            Change::Removed(old) => write!(f, "- {}", old),
+            Change::Changed { old, new } => write!(f, "~ {}\n  {}", old, new),
+        }
+    }
+}
+
+/// Compare the tokens, returning the changes in the order of the tokens
+pub fn diff(old: &[DumpedToken], new: &[DumpedToken]) -> Vec<Change> {
+    // Only the tokens between the common start and end need aligning
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(old, new)| old.token == new.token)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old.token == new.token)
+        .count();
+
+    let mut changes = Vec::new();
+    for (old, new) in old[..prefix].iter().zip(&new[..prefix]) {
+        changes.extend(moved(old, new));
+    }
+    changes.extend(align(
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    ));
+    for (old, new) in old[old.len() - suffix..]
+        .iter()
+        .zip(&new[new.len() - suffix..])
+    {
+        changes.extend(moved(old, new));
+    }
+    changes
+}
+
+/// The same token, if it is somewhere else now
+fn moved(old: &DumpedToken, new: &DumpedToken) -> Option<Change> {
+    (old.position != new.position).then(|| Change::Changed {
+        old: old.clone(),
+        new: new.clone(),
+    })
+}
+
+/// Match the tokens along their longest common subsequence. The tokens in
+/// between are paired up as changed, and the ones left over are added or
+/// removed
+fn align(old: &[DumpedToken], new: &[DumpedToken]) -> Vec<Change> {
+    // Length of the longest common subsequence of `old[i..]` and `new[j..]`
+    let mut lengths = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i].token == new[j].token {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i].token == new[j].token {
+            changes.extend(moved(&old[i], &new[j]));
+            i += 1;
+            j += 1;
+            continue;
+        }
+
+        // Take the tokens up to the next match
+        let (start_i, start_j) = (i, j);
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old[i].token == new[j].token {
+                break;
+            }
+            if j == new.len() || (i < old.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        let (removed, added) = (&old[start_i..i], &new[start_j..j]);
+        for (old, new) in removed.iter().zip(added) {
+            changes.push(Change::Changed {
+                old: old.clone(),
+                new: new.clone(),
+            });
+        }
+        let paired = removed.len().min(added.len());
+        changes.extend(removed[paired..].iter().cloned().map(Change::Removed));
+        changes.extend(added[paired..].iter().cloned().map(Change::Added));
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Scanner;
+
+    fn tokens(source: &str) -> Vec<DumpedToken> {
+        Scanner::new(source.to_string())
+            .map(|token| DumpedToken::new(&token.unwrap()))
+            .collect()
+    }
+
+    fn dump(tokens: &[DumpedToken]) -> String {
+        tokens.iter().map(|token| format!("{}\n", token)).collect()
+    }
+
+    #[test]
+    fn reads_back_what_tokenize_prints() {
+        let tokens = tokens("var a = \"x\";\nprint a;");
+        assert_eq!(parse_dump(&dump(&tokens)), Ok(tokens));
+        assert_eq!(
+            parse_dump("[line 1, col 1] Var var\nnope"),
+            Err("line 2 is not a token: nope".to_string())
+        );
+    }
+
+    #[test]
+    fn finds_no_changes_in_the_same_tokens() {
+        let tokens = tokens("fun f(a) { return a + 1; }");
+        assert_eq!(diff(&tokens, &tokens), []);
+    }
+
+    #[test]
+    fn reports_added_removed_and_changed_tokens() {
+        let old = tokens("print a + b;\nprint c;");
+        let new = tokens("print a - b * 2;\nprint c;");
+        let changes: Vec<String> = diff(&old, &new).iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            changes,
+            [
+                "~ [line 1, col 9] Plus +\n  [line 1, col 9] Minus -",
+                "+ [line 1, col 13] Star *",
+                "+ [line 1, col 15] Number(2.0) 2",
+                "~ [line 1, col 12] SemiColon ;\n  [line 1, col 16] SemiColon ;",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_tokens_moved_elsewhere() {
+        let old = tokens("print 1;");
+        let new = tokens("print  1;");
+        let changes: Vec<String> = diff(&old, &new).iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            changes,
+            [
+                "~ [line 1, col 7] Number(1.0) 1\n  [line 1, col 8] Number(1.0) 1",
+                "~ [line 1, col 8] SemiColon ;\n  [line 1, col 9] SemiColon ;",
+                "~ [line 1, col 9] Eof\n  [line 1, col 10] Eof",
+            ]
+        );
+    }
+}
@@ -0,0 +1,652 @@
+/// Translation of Lox programs into standalone Rust programs
+///
+/// The generated program starts with a small runtime (`PRELUDE`) with the
+/// Lox values and operations, and each construct of the program becomes the
+/// equivalent Rust code on top of it:
+///
+/// - Local variables live in shared cells, so closures see the assignments
+///   made after they were created
+/// - Globals live in a table, since functions can refer to them before they
+///   are declared
+/// - Functions are Rust closures capturing the cells of the locals in scope.
+///   Lambdas are made right before the statement they are in
+///
+/// Classes, lists, maps, imports and the experimental features are not
+/// supported yet.
+use std::rc::Rc;
+
+use crate::expr::{Expr, Object};
+use crate::internal;
+use crate::lexer::{Span, Token, TokenType};
+use crate::stmt::Stmt;
+
+/// Error for a part of the program the transpiler can't translate
+#[derive(Debug)]
+pub struct TranspileError {
+    pub token: Token,
+    pub message: String,
+}
+
+impl TranspileError {
+    fn new(token: &Token, message: &str) -> Self {
+        Self {
+            token: token.clone(),
+            message: message.to_string(),
+        }
+    }
+}
+
+type TranspileResult<T> = Result<T, TranspileError>;
+
+const INDENT: &str = "    ";
+
+#[derive(Debug, Default)]
+pub struct Transpiler {
+    out: String,
+    /// Indentation level of the lines being written
+    depth: usize,
+    /// Stack of the local scopes, mapping each Lox variable to the Rust
+    /// name of its cell
+    scopes: Vec<Vec<(String, String)>>,
+    /// Number of locals declared so far, to give each one a unique name
+    locals: usize,
+    /// Stack of the loops being written, with the number of the labels of
+    /// the ones that have them (see `while_loop`)
+    loops: Vec<Option<usize>>,
+}
+
+impl Transpiler {
+    /// Translate the (already resolved) program into the source of a Rust
+    /// program doing the same
+    pub fn transpile(mut self, statements: &[Stmt]) -> TranspileResult<String> {
+        self.out.push_str(PRELUDE);
+        self.out.push_str("\nfn run() -> Result<()> {\n");
+        self.depth = 1;
+        for stmt in statements {
+            self.statement(stmt)?;
+        }
+        self.line("Ok(())");
+        self.out.push_str("}\n");
+        Ok(self.out)
+    }
+
+    /// Write a line of code at the current indentation
+    fn line(&mut self, code: &str) {
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+        self.out.push_str(code);
+        self.out.push('\n');
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Declare a local in the innermost scope, returning the name of its
+    /// cell
+    fn declare(&mut self, name: &Token) -> String {
+        self.locals += 1;
+        let cell = format!("v{}_{}", self.locals, name.lexeme);
+        self.scopes
+            .last_mut()
+            .expect("locals should be declared inside a scope")
+            .push((name.lexeme.to_string(), cell.clone()));
+        cell
+    }
+
+    /// Name of the cell of the local variable, or `None` for a global
+    fn local(&self, name: &Token) -> Option<&str> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(lox_name, _)| *lox_name == *name.lexeme)
+            .map(|(_, cell)| cell.as_str())
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> TranspileResult<()> {
+        match stmt {
+            Stmt::Block { statements } => {
+                self.line("{");
+                self.nested(statements)?;
+                self.line("}");
+            }
+            Stmt::Break { .. } => match self.loops.last().copied().flatten() {
+                Some(label) => self.line(&format!("break 'loop{};", label)),
+                None => self.line("break;"),
+            },
+            Stmt::Continue { .. } => match self.loops.last().copied().flatten() {
+                Some(label) => self.line(&format!("break 'body{};", label)),
+                None => self.line("continue;"),
+            },
+            Stmt::Class { name, .. } => {
+                return Err(TranspileError::new(
+                    name,
+                    "Classes are not supported by the transpiler yet.",
+                ))
+            }
+            Stmt::Defer { keyword: name, .. }
+            | Stmt::Enum { name, .. }
+            | Stmt::Extend {
+                class: Expr::Variable { name, .. },
+                ..
+            } => {
+                return Err(TranspileError::new(
+                    name,
+                    "Experimental features are not supported by the transpiler yet.",
+                ))
+            }
+            Stmt::Extend { .. } => unreachable!("extended class should be a variable"),
+            Stmt::Import { keyword, .. } => {
+                return Err(TranspileError::new(
+                    keyword,
+                    "Imports are not supported by the transpiler yet.",
+                ))
+            }
+            Stmt::Expression { expression } => {
+                let expression = self.expression(expression)?;
+                self.line(&format!("{};", expression));
+            }
+            Stmt::Function { name, params, body } => self.function(name, params, body)?,
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let condition = self.expression(condition)?;
+                self.line(&format!("if truthy(&{}) {{", condition));
+                self.nested(std::slice::from_ref(then_branch))?;
+                if let Some(else_branch) = else_branch {
+                    self.line("} else {");
+                    self.nested(std::slice::from_ref(else_branch))?;
+                }
+                self.line("}");
+            }
+            Stmt::Print { expression, .. } => {
+                let expression = self.expression(expression)?;
+                self.line(&format!("println!(\"{{}}\", {});", expression));
+            }
+            Stmt::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => self.expression(value)?,
+                    None => "Value::Nil".to_string(),
+                };
+                self.line(&format!("return Ok({});", value));
+            }
+            Stmt::Var { name, initializer } => {
+                let value = match initializer {
+                    Some(initializer) => self.expression(initializer)?,
+                    None => "Value::Nil".to_string(),
+                };
+                if self.scopes.is_empty() {
+                    self.line(&format!("define_global({:?}, {});", name.lexeme, value));
+                } else {
+                    let cell = self.declare(name);
+                    self.line(&format!("let {} = var({});", cell, value));
+                }
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+                ..
+            } => self.while_loop(condition, body, increment.as_ref())?,
+        }
+        Ok(())
+    }
+
+    /// Write a loop. The body of one with an increment is a labeled block,
+    /// so a `continue` can leave it and still run the increment, and the
+    /// loop itself is labeled, so a `break` can leave it from that block
+    fn while_loop(
+        &mut self,
+        condition: &Expr,
+        body: &Stmt,
+        increment: Option<&Expr>,
+    ) -> TranspileResult<()> {
+        let condition = self.expression(condition)?;
+        let Some(increment) = increment else {
+            self.line(&format!("while truthy(&{}) {{", condition));
+            self.loops.push(None);
+            let result = self.nested(std::slice::from_ref(body));
+            self.loops.pop();
+            result?;
+            self.line("}");
+            return Ok(());
+        };
+
+        self.locals += 1;
+        let label = self.locals;
+        self.line(&format!("'loop{}: while truthy(&{}) {{", label, condition));
+        self.depth += 1;
+        self.line(&format!("'body{}: {{", label));
+        self.loops.push(Some(label));
+        let result = self.nested(std::slice::from_ref(body));
+        self.loops.pop();
+        result?;
+        self.line("}");
+        let increment = self.expression(increment)?;
+        self.line(&format!("{};", increment));
+        self.depth -= 1;
+        self.line("}");
+        Ok(())
+    }
+
+    /// Write the statements one level deeper, in a scope of their own
+    fn nested(&mut self, statements: &[Stmt]) -> TranspileResult<()> {
+        self.depth += 1;
+        self.begin_scope();
+        let result = statements.iter().try_for_each(|stmt| self.statement(stmt));
+        self.end_scope();
+        self.depth -= 1;
+        result
+    }
+
+    /// Write a function declaration as a closure stored in its variable,
+    /// which is declared first so the function can call itself
+    fn function(
+        &mut self,
+        name: &Token,
+        params: &[Token],
+        body: &Rc<Vec<Stmt>>,
+    ) -> TranspileResult<()> {
+        let cell = if self.scopes.is_empty() {
+            None
+        } else {
+            let cell = self.declare(name);
+            self.line(&format!("let {} = var(Value::Nil);", cell));
+            Some(cell)
+        };
+
+        self.closure("function", &name.lexeme, params, body)?;
+        match cell {
+            Some(cell) => self.line(&format!("*{}.borrow_mut() = function;", cell)),
+            None => self.line(&format!("define_global({:?}, function);", name.lexeme)),
+        }
+        Ok(())
+    }
+
+    /// Bind the closure of a function to the Rust `variable`, capturing the
+    /// cells of every local in scope
+    fn closure(
+        &mut self,
+        variable: &str,
+        name: &str,
+        params: &[Token],
+        body: &[Stmt],
+    ) -> TranspileResult<()> {
+        self.line(&format!("let {} = {{", variable));
+        self.depth += 1;
+        let captured: Vec<String> = self
+            .scopes
+            .iter()
+            .flatten()
+            .map(|(_, cell)| cell.clone())
+            .collect();
+        for cell in captured {
+            self.line(&format!("let {0} = Rc::clone(&{0});", cell));
+        }
+        self.line(&format!(
+            "closure({:?}, {}, move |arguments: Vec<Value>| {{",
+            name,
+            params.len()
+        ));
+
+        self.depth += 1;
+        self.begin_scope();
+        let loops = std::mem::take(&mut self.loops);
+        self.line("let mut arguments = arguments.into_iter();");
+        for param in params {
+            let cell = self.declare(param);
+            self.line(&format!("let {} = var(arguments.next().unwrap());", cell));
+        }
+        let result = body.iter().try_for_each(|stmt| self.statement(stmt));
+        self.line("Ok(Value::Nil)");
+        self.loops = loops;
+        self.end_scope();
+        self.depth -= 1;
+        result?;
+
+        self.line("})");
+        self.depth -= 1;
+        self.line("};");
+        Ok(())
+    }
+
+    /// Translate the expression into a Rust expression producing its
+    /// `Value` (propagating runtime errors with `?`)
+    fn expression(&mut self, expr: &Expr) -> TranspileResult<String> {
+        // Nested code can take more stack than the thread has
+        internal::grow_stack(|| {
+            let code = match expr {
+                Expr::Assign { name, value, .. } => {
+                    let value = self.expression(value)?;
+                    match self.local(name) {
+                        Some(cell) => format!(
+                            "{{ let value = {}; *{}.borrow_mut() = value.clone(); value }}",
+                            value, cell
+                        ),
+                        None => format!(
+                            "assign_global({:?}, {}, {})?",
+                            name.lexeme,
+                            value,
+                            location(&name.span)
+                        ),
+                    }
+                }
+                Expr::Binary {
+                    left,
+                    operator,
+                    right,
+                } => {
+                    let left = self.expression(left)?;
+                    let right = self.expression(right)?;
+                    let at = location(&operator.span);
+                    let numeric = |result: &str| {
+                        format!("numeric({}, {}, {}, |l, r| {})?", left, right, at, result)
+                    };
+                    match operator.typ {
+                        TokenType::Comma => format!("{{ {}; {} }}", left, right),
+                        TokenType::Plus => format!("add({}, {}, {})?", left, right, at),
+                        TokenType::Minus => numeric("Value::Number(l - r)"),
+                        TokenType::Star => numeric("Value::Number(l * r)"),
+                        TokenType::Slash => numeric("Value::Number(l / r)"),
+                        TokenType::Percent => numeric("Value::Number(l % r)"),
+                        TokenType::Greater => numeric("Value::Boolean(l > r)"),
+                        TokenType::GreaterEqual => numeric("Value::Boolean(l >= r)"),
+                        TokenType::Less => numeric("Value::Boolean(l < r)"),
+                        TokenType::LessEqual => numeric("Value::Boolean(l <= r)"),
+                        TokenType::EqualEqual => format!("Value::Boolean({} == {})", left, right),
+                        TokenType::BangEqual => format!("Value::Boolean({} != {})", left, right),
+                        _ => unreachable!("invalid binary operator {}", operator),
+                    }
+                }
+                Expr::Call {
+                    callee,
+                    paren,
+                    arguments,
+                } => {
+                    let callee = self.expression(callee)?;
+                    let arguments = arguments
+                        .iter()
+                        .map(|argument| self.expression(argument))
+                        .collect::<TranspileResult<Vec<_>>>()?;
+                    format!(
+                        "call({}, vec![{}], {})?",
+                        callee,
+                        arguments.join(", "),
+                        location(&paren.span)
+                    )
+                }
+                Expr::Conditional {
+                    condition,
+                    then_branch,
+                    else_branch,
+                } => format!(
+                    "if truthy(&{}) {{ {} }} else {{ {} }}",
+                    self.expression(condition)?,
+                    self.expression(then_branch)?,
+                    self.expression(else_branch)?
+                ),
+                Expr::Grouping { expression } => self.expression(expression)?,
+                // Making a closure has no side effects, so it can be made
+                // before the statement the lambda is in
+                Expr::Lambda { params, body, .. } => {
+                    self.locals += 1;
+                    let lambda = format!("lambda{}", self.locals);
+                    self.closure(&lambda, "lambda", params, body)?;
+                    format!("{}.clone()", lambda)
+                }
+                Expr::Literal { value } => match value {
+                    Object::Nil => "Value::Nil".to_string(),
+                    Object::Boolean(b) => format!("Value::Boolean({})", b),
+                    Object::Number(n) => format!("Value::Number({:?})", n),
+                    Object::String(s) => format!("Value::string({:?})", s),
+                },
+                Expr::Logical {
+                    left,
+                    operator,
+                    right,
+                } => {
+                    // The left operand decides the result if it is truthy for
+                    // `or` and falsey for `and`
+                    let negate = if operator.typ == TokenType::And {
+                        "!"
+                    } else {
+                        ""
+                    };
+                    format!(
+                        "{{ let left = {}; if {}truthy(&left) {{ left }} else {{ {} }} }}",
+                        self.expression(left)?,
+                        negate,
+                        self.expression(right)?
+                    )
+                }
+                Expr::Unary { operator, right } => {
+                    let right = self.expression(right)?;
+                    match operator.typ {
+                        TokenType::Bang => format!("Value::Boolean(!truthy(&{}))", right),
+                        TokenType::Minus => {
+                            format!("negate({}, {})?", right, location(&operator.span))
+                        }
+                        _ => unreachable!("invalid unary operator {}", operator),
+                    }
+                }
+                Expr::Variable { name, .. } => match self.local(name) {
+                    Some(cell) => format!("{}.borrow().clone()", cell),
+                    None => format!("get_global({:?}, {})?", name.lexeme, location(&name.span)),
+                },
+                Expr::Get { name, .. } | Expr::Set { name, .. } => {
+                    return Err(TranspileError::new(
+                        name,
+                        "Properties are not supported by the transpiler yet.",
+                    ))
+                }
+                Expr::Super { keyword, .. } | Expr::This { keyword, .. } => {
+                    return Err(TranspileError::new(
+                        keyword,
+                        "Classes are not supported by the transpiler yet.",
+                    ))
+                }
+                Expr::Index { bracket, .. }
+                | Expr::List { bracket, .. }
+                | Expr::SetIndex { bracket, .. } => {
+                    return Err(TranspileError::new(
+                        bracket,
+                        "Lists are not supported by the transpiler yet.",
+                    ))
+                }
+                Expr::Map { brace, .. } => {
+                    return Err(TranspileError::new(
+                        brace,
+                        "Maps are not supported by the transpiler yet.",
+                    ))
+                }
+            };
+            Ok(code)
+        })
+    }
+}
+
+/// Location of the token as the `(line, column)` the runtime reports errors
+/// at
+fn location(span: &Span) -> String {
+    format!("({}, {})", span.line, span.column)
+}
+
+/// Runtime of the generated programs, mirroring how the interpreter
+/// behaves (including its error messages)
+const PRELUDE: &str = r#"// Generated by `rinlox transpile`
+#![allow(unused)]
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Clone)]
+enum Value {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(Rc<str>),
+    Function(Rc<Function>),
+}
+
+impl Value {
+    fn string(s: &str) -> Value {
+        Value::String(s.into())
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Boolean(l), Value::Boolean(r)) => l == r,
+            (Value::Number(l), Value::Number(r)) => l == r,
+            (Value::String(l), Value::String(r)) => l == r,
+            (Value::Function(l), Value::Function(r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Function(function) => write!(f, "<fn {}>", function.name),
+        }
+    }
+}
+
+struct Function {
+    name: &'static str,
+    arity: usize,
+    body: Box<dyn Fn(Vec<Value>) -> Result<Value>>,
+}
+
+fn closure(
+    name: &'static str,
+    arity: usize,
+    body: impl Fn(Vec<Value>) -> Result<Value> + 'static,
+) -> Value {
+    Value::Function(Rc::new(Function {
+        name,
+        arity,
+        body: Box::new(body),
+    }))
+}
+
+/// Line and column in the Lox source
+type Location = (usize, usize);
+
+struct Error {
+    message: String,
+    location: Location,
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+fn error<T>(message: &str, location: Location) -> Result<T> {
+    Err(Error {
+        message: message.to_string(),
+        location,
+    })
+}
+
+/// Cell of a local variable, shared with the closures capturing it
+type Var = Rc<RefCell<Value>>;
+
+fn var(value: Value) -> Var {
+    Rc::new(RefCell::new(value))
+}
+
+thread_local! {
+    static GLOBALS: RefCell<HashMap<&'static str, Value>> = RefCell::new(HashMap::new());
+}
+
+fn define_global(name: &'static str, value: Value) {
+    GLOBALS.with(|globals| globals.borrow_mut().insert(name, value));
+}
+
+fn get_global(name: &'static str, location: Location) -> Result<Value> {
+    match GLOBALS.with(|globals| globals.borrow().get(name).cloned()) {
+        Some(value) => Ok(value),
+        None => error(&format!("Undefined variable '{}'.", name), location),
+    }
+}
+
+fn assign_global(name: &'static str, value: Value, location: Location) -> Result<Value> {
+    GLOBALS.with(|globals| match globals.borrow_mut().get_mut(name) {
+        Some(slot) => {
+            *slot = value.clone();
+            Ok(value)
+        }
+        None => error(&format!("Undefined variable '{}'.", name), location),
+    })
+}
+
+fn truthy(value: &Value) -> bool {
+    !matches!(value, Value::Nil | Value::Boolean(false))
+}
+
+fn add(left: Value, right: Value, location: Location) -> Result<Value> {
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l + r)),
+        (Value::String(l), Value::String(r)) => Ok(Value::string(&format!("{}{}", l, r))),
+        _ => error("Operands must be two numbers or two strings.", location),
+    }
+}
+
+fn numeric(
+    left: Value,
+    right: Value,
+    location: Location,
+    operation: impl Fn(f64, f64) -> Value,
+) -> Result<Value> {
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => Ok(operation(l, r)),
+        _ => error("Operands must be numbers.", location),
+    }
+}
+
+fn negate(value: Value, location: Location) -> Result<Value> {
+    match value {
+        Value::Number(n) => Ok(Value::Number(-n)),
+        _ => error("Operand must be a number.", location),
+    }
+}
+
+fn call(callee: Value, arguments: Vec<Value>, location: Location) -> Result<Value> {
+    let Value::Function(function) = callee else {
+        return error("Can only call functions and classes.", location);
+    };
+    if arguments.len() != function.arity {
+        let message = format!(
+            "Expected {} arguments but got {}.",
+            function.arity,
+            arguments.len()
+        );
+        return error(&message, location);
+    }
+    (function.body)(arguments)
+}
+
+fn main() {
+    if let Err(err) = run() {
+        let (line, column) = err.location;
+        println!("{}\n[line {}, col {}]", err.message, line, column);
+        std::process::exit(70);
+    }
+}
+"#;
@@ -0,0 +1,373 @@
+/// Stack-based virtual machine running the bytecode from the `compiler`,
+/// a second backend next to the tree-walking `Interpreter`
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::chunk::{Chunk, OpCode};
+use crate::interpreter::{format_number, Output, RuntimeError, Value};
+
+type RuntimeResult<T> = Result<T, RuntimeError>;
+
+/// Virtual machine state that persists across runs (e.g. REPL lines)
+#[derive(Debug)]
+pub struct Vm {
+    /// Temporaries and locals of the code being run
+    stack: Vec<Value>,
+    globals: HashMap<String, Value>,
+    /// Significant digits `print` uses for non-integral numbers
+    precision: Option<usize>,
+    output: Output,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self {
+            stack: Vec::new(),
+            globals: HashMap::new(),
+            precision: None,
+            output: Output(Box::new(std::io::stdout())),
+        }
+    }
+}
+
+impl Vm {
+    pub fn set_precision(&mut self, precision: Option<usize>) {
+        self.precision = precision;
+    }
+
+    /// Make `print` write to `output` instead of the standard output
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Output(Box::new(output));
+    }
+
+    /// Run the chunk until its `Return`
+    pub fn interpret(&mut self, chunk: &Chunk) -> RuntimeResult<()> {
+        // Whatever code that failed (or panicked) left on the stack is of
+        // no use
+        self.stack.clear();
+        self.run(chunk)
+    }
+
+    fn run(&mut self, chunk: &Chunk) -> RuntimeResult<()> {
+        let mut ip = 0;
+        loop {
+            let offset = ip;
+            let op = OpCode::from_byte(chunk.code[ip]).expect("the compiler only emits opcodes");
+            ip += 1;
+            let error = |message: &str| RuntimeError::new(&chunk.tokens[offset], message);
+
+            match op {
+                OpCode::Constant => {
+                    let constant = chunk.constants[chunk.code[ip] as usize].clone();
+                    ip += 1;
+                    self.push(constant);
+                }
+                OpCode::Nil => self.push(Value::Nil),
+                OpCode::True => self.push(Value::Boolean(true)),
+                OpCode::False => self.push(Value::Boolean(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = chunk.code[ip] as usize;
+                    ip += 1;
+                    self.push(self.stack[slot].clone());
+                }
+                OpCode::SetLocal => {
+                    let slot = chunk.code[ip] as usize;
+                    ip += 1;
+                    // Assignment is an expression, so the value stays on
+                    // the stack
+                    self.stack[slot] = self.peek().clone();
+                }
+                OpCode::GetGlobal => {
+                    let name = global_name(chunk, ip);
+                    ip += 1;
+                    let value = self
+                        .globals
+                        .get(name)
+                        .cloned()
+                        .ok_or_else(|| error(&format!("Undefined variable '{}'.", name)))?;
+                    self.push(value);
+                }
+                OpCode::DefineGlobal => {
+                    let name = global_name(chunk, ip);
+                    ip += 1;
+                    let value = self.pop();
+                    self.globals.insert(name.to_string(), value);
+                }
+                OpCode::SetGlobal => {
+                    let name = global_name(chunk, ip);
+                    ip += 1;
+                    let value = self.peek().clone();
+                    match self.globals.get_mut(name) {
+                        Some(global) => *global = value,
+                        None => return Err(error(&format!("Undefined variable '{}'.", name))),
+                    }
+                }
+                OpCode::Equal => {
+                    let (left, right) = self.pop_pair();
+                    self.push(Value::Boolean(left == right));
+                }
+                OpCode::Greater => self.comparison(|l, r| l > r).map_err(error)?,
+                OpCode::Less => self.comparison(|l, r| l < r).map_err(error)?,
+                OpCode::Add => match self.pop_pair() {
+                    (Value::Number(l), Value::Number(r)) => self.push(Value::Number(l + r)),
+                    (Value::String(l), Value::String(r)) => {
+                        self.push(Value::String(format!("{}{}", l, r).into()))
+                    }
+                    _ => return Err(error("Operands must be two numbers or two strings.")),
+                },
+                OpCode::Subtract => self.arithmetic(|l, r| l - r).map_err(error)?,
+                OpCode::Multiply => self.arithmetic(|l, r| l * r).map_err(error)?,
+                OpCode::Divide => self.arithmetic(|l, r| l / r).map_err(error)?,
+                OpCode::Modulo => self.arithmetic(|l, r| l % r).map_err(error)?,
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.push(Value::Boolean(!value.is_truthy()));
+                }
+                OpCode::Negate => match self.pop() {
+                    Value::Number(n) => self.push(Value::Number(-n)),
+                    _ => return Err(error("Operand must be a number.")),
+                },
+                OpCode::Print => {
+                    let text = match self.pop() {
+                        Value::Number(n) => format_number(n, self.precision),
+                        value => value.to_string(),
+                    };
+                    writeln!(self.output.0, "{}", text)
+                        .map_err(|err| RuntimeError::output_failed(&chunk.tokens[offset], &err))?;
+                }
+                OpCode::Jump => {
+                    ip += read_jump(chunk, ip) + 2;
+                }
+                OpCode::JumpIfFalse => {
+                    let jump = read_jump(chunk, ip);
+                    ip += 2;
+                    if !self.peek().is_truthy() {
+                        ip += jump;
+                    }
+                }
+                OpCode::Loop => {
+                    ip = ip + 2 - read_jump(chunk, ip);
+                }
+                OpCode::Return => return Ok(()),
+            }
+        }
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("the stack should not underflow")
+    }
+
+    /// Pop the operands of a binary operator, left one first
+    fn pop_pair(&mut self) -> (Value, Value) {
+        let right = self.pop();
+        let left = self.pop();
+        (left, right)
+    }
+
+    fn peek(&self) -> &Value {
+        self.stack.last().expect("the stack should not be empty")
+    }
+
+    fn arithmetic(&mut self, op: fn(f64, f64) -> f64) -> Result<(), &'static str> {
+        let (l, r) = self.number_operands()?;
+        self.push(Value::Number(op(l, r)));
+        Ok(())
+    }
+
+    fn comparison(&mut self, op: fn(f64, f64) -> bool) -> Result<(), &'static str> {
+        let (l, r) = self.number_operands()?;
+        self.push(Value::Boolean(op(l, r)));
+        Ok(())
+    }
+
+    fn number_operands(&mut self) -> Result<(f64, f64), &'static str> {
+        match self.pop_pair() {
+            (Value::Number(l), Value::Number(r)) => Ok((l, r)),
+            _ => Err("Operands must be numbers."),
+        }
+    }
+}
+
+/// Name of the global in the constant referred to by the operand at `ip`
+fn global_name(chunk: &Chunk, ip: usize) -> &str {
+    match &chunk.constants[chunk.code[ip] as usize] {
+        Value::String(name) => name,
+        value => unreachable!("global names should be strings, not {}", value),
+    }
+}
+
+/// The 16 bit offset of the jump with its operands at `ip`
+fn read_jump(chunk: &Chunk, ip: usize) -> usize {
+    u16::from_be_bytes([chunk.code[ip], chunk.code[ip + 1]]) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::compiler::Compiler;
+    use crate::lexer::Scanner;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Compile and run the source, returning what it printed
+    fn run(source: &str) -> Result<String, String> {
+        let chunk = Compiler::new(Scanner::new(source.to_string()))
+            .compile()
+            .map_err(|errors| errors[0].to_string())?;
+        let output = Buffer::default();
+        let mut vm = Vm::default();
+        vm.set_output(output.clone());
+        vm.interpret(&chunk).map_err(|error| error.message)?;
+        let printed = output.0.borrow().clone();
+        Ok(String::from_utf8(printed).expect("output should be UTF-8"))
+    }
+
+    #[test]
+    fn evaluates_expressions() {
+        assert_eq!(run("print 1 + 2 * 3 - 4 / 2;"), Ok("5\n".to_string()));
+        assert_eq!(run("print -(1 + 2) % 2;"), Ok("-1\n".to_string()));
+        assert_eq!(run("print \"a\" + \"b\";"), Ok("ab\n".to_string()));
+        assert_eq!(
+            run("print 1 < 2 == !(2 <= 1); print 1 != 1; print 2 >= 3;"),
+            Ok("true\nfalse\nfalse\n".to_string())
+        );
+        assert_eq!(
+            run("print nil or \"or\"; print 1 and 2; print false and x;"),
+            Ok("or\n2\nfalse\n".to_string())
+        );
+        assert_eq!(run("print true ? 1 : 2, 3;"), Ok("3\n".to_string()));
+    }
+
+    #[test]
+    fn keeps_globals_and_locals() {
+        let source = "
+            var a = 1;
+            a += 2;
+            {
+                var b = a * 2;
+                { var b = a; }
+                var c;
+                b = c = b + 1;
+                print b;
+                print c;
+            }
+            print a;";
+        assert_eq!(run(source), Ok("7\n7\n3\n".to_string()));
+    }
+
+    #[test]
+    fn runs_control_flow() {
+        let source = "
+            var total = 0;
+            for (var i = 0; i < 5; i = i + 1) {
+                if (i == 2) total = total + 10; else total = total + i;
+            }
+            while (total > 10) total = total - 3;
+            print total;";
+        assert_eq!(run(source), Ok("9\n".to_string()));
+    }
+
+    #[test]
+    fn breaks_and_continues_loops() {
+        let source = "
+            for (var i = 0; i < 10; i = i + 1) {
+                var square = i * i;
+                if (square == 4) continue;
+                if (square > 10) break;
+                print i;
+            }
+            var j = 0;
+            while (true) {
+                var next = j + 1;
+                j = next;
+                { var a = j; if (a < 3) continue; }
+                if (j == 4) break;
+                print j;
+            }";
+        assert_eq!(run(source), Ok("0\n1\n3\n3\n".to_string()));
+        assert_eq!(
+            run("{ break; }"),
+            Err(
+                "[line 1, col 3] Error at 'break': Can't use 'break' outside of a loop."
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(
+            run("print -\"a\";"),
+            Err("Operand must be a number.".to_string())
+        );
+        assert_eq!(
+            run("print 1 + nil;"),
+            Err("Operands must be two numbers or two strings.".to_string())
+        );
+        assert_eq!(run("x = 1;"), Err("Undefined variable 'x'.".to_string()));
+        assert_eq!(
+            run("{ var a = a; }"),
+            Err(
+                "[line 1, col 11] Error at 'a': Can't read local variable in its own initializer."
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            run("var a; a + 1 = 2;"),
+            Err("[line 1, col 14] Error at '=': Invalid assignment target.".to_string())
+        );
+        assert_eq!(
+            run("fun f() { return 1; } print 1;"),
+            Err(
+                "[line 1, col 1] Error at 'fun': 'fun' is not supported by the bytecode \
+                 backend yet."
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn reports_runtime_errors_where_the_interpreter_does() {
+        let sources = [
+            "print 1 < \"a\";",
+            "print 2 >= nil;",
+            "print -\"a\";",
+            "print 1 +\n  nil;",
+            "var x = \"a\"; x -= 1;",
+        ];
+        for source in sources {
+            let chunk = Compiler::new(Scanner::new(source.to_string()))
+                .compile()
+                .expect("source should compile");
+            let error = Vm::default().interpret(&chunk).unwrap_err();
+            let expected = crate::run_source(source).unwrap_err();
+            assert_eq!(
+                crate::Error::Runtime(error).to_string(),
+                expected.to_string(),
+                "in {:?}",
+                source
+            );
+        }
+    }
+}
@@ -0,0 +1,119 @@
+/// Bindings to run Lox in a browser, e.g. for a playground
+///
+/// Build the library for `wasm32-unknown-unknown` with the `wasm` feature,
+/// and generate the JavaScript glue for it with `wasm-bindgen`:
+///
+/// ```text
+/// cargo rustc --lib --release --target wasm32-unknown-unknown --features wasm --crate-type cdylib
+/// wasm-bindgen --target web --out-dir playground target/wasm32-unknown-unknown/release/rinlox.wasm
+/// ```
+///
+/// The page then runs programs on a `Playground`, which keeps its globals
+/// from one run to the next like the REPL does:
+///
+/// ```text
+/// const playground = new Playground();
+/// playground.run('print "Hello, world!";', (line) => console.log(line));
+/// ```
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use wasm_bindgen::prelude::*;
+
+use crate::diagnostics::Diagnostics;
+use crate::host::Host;
+use crate::{Features, Interpreter, Limits};
+
+/// How many calls can be nested in a browser. Its stack can't grow, so this
+/// is a lot less than on the command line
+const MAX_CALL_DEPTH: usize = 500;
+
+/// The host of a page: there is no input to read or files to import, and
+/// the time comes from JavaScript
+#[derive(Debug)]
+struct Browser;
+
+impl Host for Browser {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn clock(&self) -> Option<f64> {
+        Some(js_sys::Date::now() / 1000.0)
+    }
+
+    fn now(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f64(js_sys::Date::now() / 1000.0))
+    }
+
+    fn resolve(&self, _path: &Path) -> io::Result<PathBuf> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "there are no files in the browser",
+        ))
+    }
+
+    fn load(&mut self, _path: &Path) -> io::Result<String> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "there are no files in the browser",
+        ))
+    }
+}
+
+/// An interpreter for a page to run programs on
+#[wasm_bindgen]
+pub struct Playground {
+    interpreter: Interpreter,
+    diagnostics: Diagnostics,
+}
+
+#[wasm_bindgen]
+impl Playground {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        let mut interpreter = Interpreter::default();
+        interpreter.set_host(Browser);
+        interpreter.set_max_call_depth(MAX_CALL_DEPTH);
+        Self {
+            interpreter,
+            diagnostics: Diagnostics::new(false),
+        }
+    }
+
+    /// Run the program in `source`, calling `print` with each line it
+    /// prints. If it fails, the error is thrown as the text the command
+    /// line would show for it
+    pub fn run(&mut self, source: &str, print: js_sys::Function) -> Result<(), JsValue> {
+        self.interpreter.set_print(move |line| {
+            // Whatever the callback throws has nowhere to go
+            let _ = print.call1(&JsValue::NULL, &JsValue::from_str(line));
+        });
+        self.diagnostics.set_source(source);
+        crate::run(&mut self.interpreter, source, Features::default())
+            .map(|_| ())
+            .map_err(|error| JsValue::from_str(&error.render(&self.diagnostics)))
+    }
+
+    /// Stop the runs from now on after `operations` statements and
+    /// expressions, so a program that never ends doesn't hang the page
+    #[wasm_bindgen(js_name = setMaxOperations)]
+    pub fn set_max_operations(&mut self, operations: u64) {
+        self.interpreter.set_limits(Limits {
+            operations: Some(operations),
+            time: None,
+        });
+    }
+
+    /// Forget every global defined so far
+    pub fn reset(&mut self) {
+        self.interpreter.reset();
+    }
+}
+
+impl Default for Playground {
+    fn default() -> Self {
+        Self::new()
+    }
+}
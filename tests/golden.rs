@@ -0,0 +1,135 @@
+//! Runs the Lox programs in `tests/cases` and compares what they do with
+//! the expectations written in their comments, in the style of the test
+//! suite from "Crafting Interpreters":
+//!
+//! ```lox
+//! print 1 + 2; // expect: 3
+//! print a;     // expect runtime error: Undefined variable 'a'.
+//! var 1;       // Error at '1': Expect variable name.
+//! // [line 5] Error at end: Expect ';' after value.
+//! ```
+//!
+//! Each program is run by the `rinlox` binary, as a user would run it, and
+//! what it writes to the standard output and error is compared line by
+//! line. Errors are compared without their column, and without the source
+//! lines quoted under them or the stack trace. The exit code is checked
+//! too: 65 for errors in the code, 70 for runtime errors, and 0 otherwise.
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// What a program prints and how it ends
+#[derive(Debug, Default, PartialEq)]
+struct Outcome {
+    stdout: Vec<String>,
+    stderr: Vec<String>,
+    exit_code: i32,
+}
+
+/// The outcome the comments in the source expect
+fn expected(source: &str) -> Outcome {
+    let mut outcome = Outcome::default();
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        if let Some((_, output)) = line.split_once("// expect: ") {
+            outcome.stdout.push(output.to_string());
+        } else if let Some((_, message)) = line.split_once("// expect runtime error: ") {
+            outcome.stderr.push(message.to_string());
+            outcome.stderr.push(format!("[line {}]", line_number));
+            outcome.exit_code = 70;
+        } else if let Some((_, error)) = line.split_once("// [line ") {
+            outcome.stderr.push(format!("[line {}", error));
+            outcome.exit_code = 65;
+        } else if let Some((_, error)) = line.split_once("// Error") {
+            outcome
+                .stderr
+                .push(format!("[line {}] Error{}", line_number, error));
+            outcome.exit_code = 65;
+        }
+    }
+    outcome
+}
+
+/// Drop the column from the location of an error, `[line 1, col 2]`
+fn without_column(error: &str) -> String {
+    match (error.find(", col "), error.find(']')) {
+        (Some(start), Some(end)) if start < end => {
+            format!("{}{}", &error[..start], &error[end..])
+        }
+        _ => error.to_string(),
+    }
+}
+
+/// Whether a line of the errors only gives context to the one before it:
+/// a line of the source quoted (`12 | print a;`), the mark under it, or a
+/// call of the stack trace
+fn is_context(line: &str) -> bool {
+    let quoted = line
+        .split_once('|')
+        .is_some_and(|(gutter, _)| gutter.trim().chars().all(|c| c.is_ascii_digit()));
+    quoted || line.starts_with("  at ") || line.starts_with("  ... repeated ")
+}
+
+/// Run the program in the file `path` with the `rinlox` binary
+fn run(path: &Path) -> Outcome {
+    let output = Command::new(env!("CARGO_BIN_EXE_rinlox"))
+        .arg("--no-color")
+        .arg(path)
+        .output()
+        .expect("rinlox should run");
+    let stdout = String::from_utf8(output.stdout).expect("output should be UTF-8");
+    let stderr = String::from_utf8(output.stderr).expect("errors should be UTF-8");
+    Outcome {
+        stdout: stdout.lines().map(str::to_string).collect(),
+        stderr: stderr
+            .lines()
+            .filter(|line| !is_context(line))
+            .map(without_column)
+            .collect(),
+        exit_code: output.status.code().expect("rinlox should exit on its own"),
+    }
+}
+
+fn find_cases(dir: &Path, cases: &mut Vec<PathBuf>) {
+    let entries = std::fs::read_dir(dir).expect("the cases directory should be readable");
+    for entry in entries {
+        let path = entry.expect("the cases should be readable").path();
+        if path.is_dir() {
+            find_cases(&path, cases);
+        } else if path.extension().is_some_and(|extension| extension == "lox") {
+            cases.push(path);
+        }
+    }
+}
+
+#[test]
+fn golden_files() {
+    let mut cases = Vec::new();
+    find_cases(
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cases"),
+        &mut cases,
+    );
+    cases.sort();
+    assert!(!cases.is_empty(), "there should be test cases");
+
+    let mut failures = Vec::new();
+    for case in &cases {
+        let source = std::fs::read_to_string(case).expect("the case should be readable");
+        let expected = expected(&source);
+        let actual = run(case);
+        if actual != expected {
+            failures.push(format!(
+                "{}\nexpected: {:#?}\nactual: {:#?}",
+                case.display(),
+                expected,
+                actual
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} cases failed:\n\n{}",
+        failures.len(),
+        cases.len(),
+        failures.join("\n\n")
+    );
+}
@@ -0,0 +1,173 @@
+//! Feeds malformed programs to every stage, checking each is rejected with
+//! a diagnostic instead of a panic
+//!
+//! Besides the programs below, every prefix of the programs in
+//! `tests/cases` is scanned, parsed and resolved (but not run, since a
+//! prefix of a loop could run forever).
+use std::path::{Path, PathBuf};
+
+use rinlox::{internal, Error, Features, Interpreter, Parser, Resolver, Scanner};
+
+/// Programs with an error in each stage, and inputs the stages could choke
+/// on
+const CORPUS: &[&str] = &[
+    "",
+    ";",
+    "\0",
+    "\"",
+    "\"\\",
+    "/*",
+    "/* /* */",
+    "@#^&`~",
+    "é = 1;",
+    "1.",
+    ".1",
+    "1e",
+    "99999999999999999999999999999999999999999 * 1e308 * 10;",
+    "(((((((((((1",
+    "1))))))",
+    "{{{{{{{{{{",
+    "}}}}",
+    "var",
+    "var = 1;",
+    "var 1 = 2;",
+    "fun",
+    "fun f(",
+    "fun f(a, a) {}",
+    "fun f() { return; } f(1, 2, 3);",
+    "class",
+    "class A < A {}",
+    "class A { init() { return 1; } }",
+    "class A {} A.b;",
+    "super.x;",
+    "this;",
+    "return;",
+    "a = b = ;",
+    "1 = 2;",
+    "a.b.c = ;",
+    "true ? : 1;",
+    "1 ? 2 :",
+    "1, , 2;",
+    "!;",
+    "-\"a\";",
+    "\"a\" - 1;",
+    "nil();",
+    "\"a\"();",
+    "1 / 0;",
+    "0 % 0;",
+    "len(1);",
+    "num(\"abc\");",
+    "var a = a;",
+    "{ var a = 1; var a = 2; }",
+    "for (;;",
+    "for (var i = 0; i < ; ) {}",
+    "while",
+    "if (true",
+    "if () {} else",
+    "print",
+    "print 1",
+    "defer {}",
+    "enum E { }",
+    "a |> b;",
+];
+
+/// How deep the programs nested too deep for the parser go, far deeper than
+/// the stack of a thread fits if it recursed through each level
+const DEEP: usize = 100_000;
+
+/// Programs nesting each kind of statement and expression `DEEP` levels
+/// (or chaining as many operators), with the error the parser gives them
+fn deeply_nested() -> Vec<(String, &'static str)> {
+    let nested = [
+        format!("print {}1{};", "(".repeat(DEEP), ")".repeat(DEEP)),
+        format!("print {}1;", "-".repeat(DEEP)),
+        format!("var a; a{} = 1;", " = a".repeat(DEEP)),
+        format!("print [{}];", "[".repeat(DEEP)),
+        format!("{}{}", "{".repeat(DEEP), "}".repeat(DEEP)),
+        format!("{}print 1;", "if (true) ".repeat(DEEP)),
+        format!("{}{}", "fun f() {".repeat(DEEP), "}".repeat(DEEP)),
+    ];
+    let chained = [
+        format!("print 1{};", " + 1".repeat(DEEP)),
+        format!("print a{};", ".b".repeat(DEEP)),
+    ];
+    let nested = nested
+        .into_iter()
+        .map(|source| (source, "Too much nesting."));
+    let chained = chained
+        .into_iter()
+        .map(|source| (source, "Too many operators in a row."));
+    nested.chain(chained).collect()
+}
+
+#[test]
+fn the_corpus_is_rejected_without_panicking() {
+    let deep = deeply_nested();
+    for source in CORPUS
+        .iter()
+        .copied()
+        .chain(deep.iter().map(|(source, _)| source.as_str()))
+    {
+        let result = run(source);
+        assert!(
+            !matches!(result, Err(Error::Internal(_))),
+            "{:?} made rinlox panic: {}",
+            source,
+            result.unwrap_err()
+        );
+    }
+    for (source, message) in &deep {
+        let Err(Error::Parse(errors)) = run(source) else {
+            panic!("{:.20}... should be nested too deep", source);
+        };
+        assert_eq!(errors[0].message(), *message);
+    }
+}
+
+#[test]
+fn prefixes_of_the_cases_are_rejected_without_panicking() {
+    for path in lox_files(Path::new("tests/cases")) {
+        let source = std::fs::read_to_string(&path).expect("the cases should be readable");
+        let cuts = source.char_indices().map(|(i, _)| i);
+        for cut in cuts.chain([source.len()]) {
+            let prefix = &source[..cut];
+            let result = internal::catch(|| {
+                let scanner = Scanner::new(prefix.to_string()).with_features(Features::all());
+                let statements = Parser::new(scanner).parse().map_err(Error::Parse)?;
+                Resolver::default()
+                    .resolve(&statements)
+                    .map_err(Error::Resolve)
+            });
+            if let Err(Error::Internal(error)) = result {
+                panic!(
+                    "{} cut at byte {} made rinlox panic: {}",
+                    path.display(),
+                    cut,
+                    error
+                );
+            }
+        }
+    }
+}
+
+/// Run the source with every feature enabled, catching any panic
+fn run(source: &str) -> Result<(), Error> {
+    let mut interpreter = Interpreter::default();
+    interpreter.set_output(std::io::sink());
+    internal::catch(|| rinlox::run(&mut interpreter, source, Features::all()).map(|_| ()))
+}
+
+/// The `.lox` files in the directory and the ones within it, in order
+fn lox_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).expect("the cases should be readable") {
+        let path = entry.expect("the cases should be readable").path();
+        if path.is_dir() {
+            files.extend(lox_files(&path));
+        } else if path.extension().is_some_and(|extension| extension == "lox") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files
+}
@@ -0,0 +1,246 @@
+//! Property tests of the front end: arbitrary input never makes the scanner,
+//! the parser or the resolver panic, and random valid programs survive
+//! being printed back as source and being formatted
+//!
+//! A failure is shrunk by `proptest` to a minimal input before it is
+//! reported. More cases are run with e.g. `PROPTEST_CASES=10000`.
+use proptest::prelude::*;
+
+use rinlox::ast::program_to_source;
+use rinlox::{formatter, internal, Error, Features, Parser, Resolver, Scanner, Stmt};
+
+/// Tokens (and pieces of tokens) of every feature, for the soups of tokens
+/// to get past the scanner and into the parser more often than random text
+const TOKENS: &[&str] = &[
+    "(", ")", "{", "}", "[", "]", ",", ".", ";", ":", "?", "-", "+", "*", "/", "%", "!", "!=", "=",
+    "==", "<", "<=", ">", ">=", "|>", "and", "or", "var", "fun", "class", "enum", "return", "if",
+    "else", "while", "for", "break", "continue", "defer", "import", "print", "this", "super",
+    "true", "false", "nil", "a", "b", "init", "1", "2.5", "1.", ".5", "\"s\"", "\"", "/*", "*/",
+    "//", "\n", "é",
+];
+
+/// Names for the random programs, none of them a keyword
+const NAMES: &[&str] = &["a", "b", "c", "count", "name_2", "_x"];
+
+const BINARY_OPERATORS: &[&str] = &[
+    "+", "-", "*", "/", "<", "<=", ">", ">=", "==", "!=", "and", "or",
+];
+
+/// Operators (and what follows them) to chain one after the other
+const CHAIN_LINKS: &[&str] = &[
+    " + \"x\"", " - 1", " * a", " / 2", " < b", " == c", " and d", " or e", ".a", "(1)", "[0]",
+];
+
+/// What goes before and after a statement to nest it in another one
+const STATEMENT_LAYERS: &[(&str, &str)] = &[
+    ("{", "}"),
+    ("if (a) ", ""),
+    ("if (a) {} else ", ""),
+    ("while (a) ", ""),
+    ("for (;;) ", ""),
+    ("fun f() {", "}"),
+    ("class C { m() {", "}}"),
+];
+
+/// What goes before and after an expression to nest it in another one
+const EXPRESSION_LAYERS: &[(&str, &str)] = &[
+    ("(", ")"),
+    ("-", ""),
+    ("!", ""),
+    ("[", "]"),
+    ("f(", ")"),
+    ("a[", "]"),
+    ("a = ", ""),
+    ("1 + ", ""),
+    ("a ? 1 : ", ""),
+    ("fun () { return ", "; }"),
+];
+
+proptest! {
+    #[test]
+    fn any_text_is_rejected_without_panicking(source in any::<String>()) {
+        check_front_end(&source)?;
+    }
+
+    #[test]
+    fn any_bytes_are_rejected_without_panicking(bytes in any::<Vec<u8>>()) {
+        check_front_end(&String::from_utf8_lossy(&bytes))?;
+    }
+
+    #[test]
+    fn any_tokens_are_rejected_without_panicking(
+        tokens in prop::collection::vec(prop::sample::select(TOKENS), 0..64)
+    ) {
+        check_front_end(&tokens.join(" "))?;
+        check_front_end(&tokens.concat())?;
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected_without_panicking(source in deeply_nested()) {
+        check_front_end(&source)?;
+    }
+
+    #[test]
+    fn long_flat_chains_parse(source in flat_chain()) {
+        let printed = program_to_source(&parse(&source)?);
+        prop_assert_eq!(program_to_source(&parse(&printed)?), printed);
+        check_front_end(&source)?;
+    }
+
+    #[test]
+    fn printed_programs_parse_back_the_same(source in program()) {
+        let printed = program_to_source(&parse(&source)?);
+        let reprinted = program_to_source(&parse(&printed)?);
+        prop_assert_eq!(reprinted, printed);
+    }
+
+    #[test]
+    fn formatting_is_idempotent_and_keeps_the_program(source in program()) {
+        let formatted = format(&source)?;
+        prop_assert_eq!(&format(&formatted)?, &formatted);
+        prop_assert_eq!(
+            program_to_source(&parse(&formatted)?),
+            program_to_source(&parse(&source)?)
+        );
+    }
+}
+
+/// Scan, parse and resolve the source with every feature enabled, failing
+/// if any of them panics
+fn check_front_end(source: &str) -> Result<(), TestCaseError> {
+    let result = internal::catch(|| {
+        let scanner = Scanner::new(source.to_string()).with_features(Features::all());
+        let statements = Parser::new(scanner).parse().map_err(Error::Parse)?;
+        Resolver::default()
+            .resolve(&statements)
+            .map_err(Error::Resolve)
+    });
+    if let Err(Error::Internal(error)) = result {
+        return Err(TestCaseError::fail(format!(
+            "{:?} made rinlox panic: {}",
+            source, error
+        )));
+    }
+    Ok(())
+}
+
+fn parse(source: &str) -> Result<Vec<Stmt>, TestCaseError> {
+    let scanner = Scanner::new(source.to_string());
+    Parser::new(scanner)
+        .parse()
+        .map_err(|errors| TestCaseError::fail(format!("{:?} should parse: {:?}", source, errors)))
+}
+
+fn format(source: &str) -> Result<String, TestCaseError> {
+    formatter::format(source, Features::default())
+        .map_err(|errors| TestCaseError::fail(format!("{:?} should format: {:?}", source, errors)))
+}
+
+/// Source of a random program, valid for the parser (though not always for
+/// the resolver, and seldom for the interpreter)
+fn program() -> impl Strategy<Value = String> {
+    prop::collection::vec(statement(), 0..8).prop_map(|statements| statements.concat())
+}
+
+/// Source of a statement nested in a random mix of statements, around an
+/// expression nested in a random mix of expressions, often far deeper than
+/// the parser allows
+fn deeply_nested() -> impl Strategy<Value = String> {
+    let layers = |layers| prop::collection::vec(prop::sample::select(layers), 0..1000);
+    (layers(STATEMENT_LAYERS), layers(EXPRESSION_LAYERS)).prop_map(|(statements, expressions)| {
+        let mut source = String::new();
+        statements
+            .iter()
+            .for_each(|(before, _)| source.push_str(before));
+        source.push_str("print ");
+        expressions
+            .iter()
+            .for_each(|(before, _)| source.push_str(before));
+        source.push('1');
+        expressions
+            .iter()
+            .rev()
+            .for_each(|(_, after)| source.push_str(after));
+        source.push(';');
+        statements
+            .iter()
+            .rev()
+            .for_each(|(_, after)| source.push_str(after));
+        source
+    })
+}
+
+/// Source of an expression with thousands of operators in a row, which
+/// aren't nested however many they are
+fn flat_chain() -> impl Strategy<Value = String> {
+    prop::collection::vec(prop::sample::select(CHAIN_LINKS), 0..2000)
+        .prop_map(|links| format!("print a{};", links.concat()))
+}
+
+fn name() -> impl Strategy<Value = String> {
+    prop::sample::select(NAMES).prop_map(str::to_string)
+}
+
+fn expression() -> impl Strategy<Value = String> {
+    let literal = prop_oneof![
+        (0u32..1000).prop_map(|n| n.to_string()),
+        (0u32..1000, 0u32..100).prop_map(|(n, fraction)| format!("{}.{}", n, fraction)),
+        "[a-z ]{0,8}".prop_map(|s| format!("\"{}\"", s)),
+        prop::sample::select(&["true", "false", "nil"][..]).prop_map(str::to_string),
+        name(),
+    ];
+    literal.prop_recursive(4, 32, 4, |inner| {
+        prop_oneof![
+            (
+                inner.clone(),
+                prop::sample::select(BINARY_OPERATORS),
+                inner.clone()
+            )
+                .prop_map(|(left, operator, right)| format!("({} {} {})", left, operator, right)),
+            (prop::sample::select(&["-", "!"][..]), inner.clone())
+                .prop_map(|(operator, operand)| format!("{}({})", operator, operand)),
+            (name(), prop::collection::vec(inner.clone(), 0..3))
+                .prop_map(|(callee, arguments)| format!("{}({})", callee, arguments.join(", "))),
+            (name(), inner).prop_map(|(name, value)| format!("({} = {})", name, value)),
+        ]
+    })
+}
+
+fn statement() -> impl Strategy<Value = String> {
+    let simple = prop_oneof![
+        (name(), expression()).prop_map(|(name, value)| format!("var {} = {};\n", name, value)),
+        name().prop_map(|name| format!("var {};\n", name)),
+        expression().prop_map(|value| format!("print {};\n", value)),
+        expression().prop_map(|value| format!("{};\n", value)),
+    ];
+    simple.prop_recursive(3, 24, 4, |inner| {
+        // Every branch is a block, so an `else` can't be taken by an `if`
+        // nested in the branch before it
+        let block = prop::collection::vec(inner, 0..4)
+            .prop_map(|statements| format!("{{\n{}}}\n", statements.concat()));
+        prop_oneof![
+            block.clone(),
+            (expression(), block.clone(), prop::option::of(block.clone())).prop_map(
+                |(condition, then, otherwise)| match otherwise {
+                    Some(otherwise) => format!("if ({}) {} else {}", condition, then, otherwise),
+                    None => format!("if ({}) {}", condition, then),
+                }
+            ),
+            (expression(), block.clone())
+                .prop_map(|(condition, body)| format!("while ({}) {}", condition, body)),
+            (
+                name(),
+                prop::collection::vec(name(), 0..3),
+                block,
+                expression()
+            )
+                .prop_map(|(name, parameters, body, value)| format!(
+                    "fun {}({}) {{\n{}return {};\n}}\n",
+                    name,
+                    parameters.join(", "),
+                    body,
+                    value
+                )),
+        ]
+    })
+}